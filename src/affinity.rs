@@ -0,0 +1,197 @@
+//CPU pinning and NUMA-aware partitioning of hashing work for large multi-socket machines
+use indicatif::{ParallelProgressIterator, ProgressBar};
+use rayon::prelude::*;
+use std::error::Error;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+static PINNING: AtomicBool = AtomicBool::new(false);
+static PIN_FAILURES: AtomicUsize = AtomicUsize::new(0);
+static NODE_POOLS: OnceLock<Vec<NodePool>> = OnceLock::new();
+
+// One rayon pool whose workers are pinned to the CPUs of a single NUMA node
+pub struct NodePool {
+    pub node: usize,
+    pub cpus: Vec<usize>,
+    pool: rayon::ThreadPool,
+}
+
+// Parse a sysfs cpulist such as "0-3,8-11"
+pub fn parse_cpulist(list: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                if let (Ok(start), Ok(end)) = (start.parse::<usize>(), end.parse::<usize>()) {
+                    cpus.extend(start..=end);
+                }
+            }
+            None => cpus.extend(part.parse::<usize>().ok()),
+        }
+    }
+    cpus
+}
+
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Option<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return None;
+        }
+        Some(
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|cpu| libc::CPU_ISSET(*cpu, &set))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cpus() -> Option<Vec<usize>> {
+    None
+}
+
+/*  CPUs per NUMA node from /sys/devices/system/node, limited to the CPUs this process may run on.
+Machines without that topology (or other systems) are a single node holding every CPU.
+*/
+pub fn numa_nodes() -> Vec<(usize, Vec<usize>)> {
+    let allowed = allowed_cpus();
+    let mut nodes = Vec::new();
+
+    if let Ok(entries) = fs::read_dir("/sys/devices/system/node") {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(node) = name.strip_prefix("node").and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            let Ok(list) = fs::read_to_string(entry.path().join("cpulist")) else {
+                continue;
+            };
+            let cpus: Vec<usize> = parse_cpulist(&list)
+                .into_iter()
+                .filter(|cpu| allowed.as_ref().is_none_or(|a| a.contains(cpu)))
+                .collect();
+            if !cpus.is_empty() {
+                nodes.push((node, cpus));
+            }
+        }
+    }
+
+    if nodes.is_empty() {
+        let cpus = allowed.unwrap_or_else(|| (0..num_cpus::get()).collect());
+        nodes.push((0, cpus));
+    }
+    nodes.sort();
+    nodes
+}
+
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cpu: usize) -> std::io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cpu: usize) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "thread pinning is only supported on Linux",
+    ))
+}
+
+fn pin_or_count(cpu: usize) {
+    if pin_current_thread(cpu).is_err() {
+        PIN_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/*  Build one pinned pool per NUMA node (worker i of a node runs on its i-th CPU) and switch the
+hashing stages to partitioned mode. Returns (nodes, workers).
+*/
+pub fn enable_pinning() -> Result<(usize, usize), Box<dyn Error>> {
+    let mut pools = Vec::new();
+    for (node, cpus) in numa_nodes() {
+        let worker_cpus = cpus.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(cpus.len())
+            .thread_name(move |i| format!("rdedupe-node{}-{}", node, i))
+            .start_handler(move |i| pin_or_count(worker_cpus[i % worker_cpus.len()]))
+            .build()?;
+        pools.push(NodePool { node, cpus, pool });
+    }
+
+    let workers = pools.iter().map(|p| p.cpus.len()).sum();
+    let nodes = pools.len();
+    NODE_POOLS
+        .set(pools)
+        .map_err(|_| "thread pinning is already enabled")?;
+    PINNING.store(true, Ordering::Relaxed);
+
+    Ok((nodes, workers))
+}
+
+pub fn pinning_enabled() -> bool {
+    PINNING.load(Ordering::Relaxed)
+}
+
+// Workers whose CPU could not be set, e.g. because a cgroup forbids it
+pub fn pin_failures() -> usize {
+    PIN_FAILURES.load(Ordering::Relaxed)
+}
+
+/*  Map items in parallel, giving each NUMA node one contiguous share (sized by its CPU count) to
+work through in its own pinned pool, so a node's workers stay on their own memory. Results keep
+the order of items. Without pinning this is the plain rayon map on the global pool.
+*/
+pub fn map_partitioned<T, R, F>(items: &[T], progress: ProgressBar, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    let pools = match NODE_POOLS.get() {
+        Some(pools) if pinning_enabled() => pools,
+        _ => {
+            return items.par_iter().progress_with(progress).map(f).collect();
+        }
+    };
+
+    let total_cpus: usize = pools.iter().map(|p| p.cpus.len()).sum();
+    let mut shares = Vec::with_capacity(pools.len());
+    let mut start = 0;
+    for (i, pool) in pools.iter().enumerate() {
+        let end = if i + 1 == pools.len() {
+            items.len()
+        } else {
+            start + items.len() * pool.cpus.len() / total_cpus
+        };
+        shares.push(&items[start..end]);
+        start = end;
+    }
+
+    let f = &f;
+    let results: Vec<Vec<R>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = pools
+            .iter()
+            .zip(shares)
+            .map(|(pool, share)| {
+                let progress = progress.clone();
+                scope.spawn(move || {
+                    pool.pool
+                        .install(|| share.par_iter().progress_with(progress).map(f).collect())
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    results.into_iter().flatten().collect()
+}
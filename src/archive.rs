@@ -0,0 +1,347 @@
+//deduplicating archiver: stores scanned trees as content-addressed chunks plus a snapshot index
+use crate::chunking::{self, ChunkerParams};
+use crate::diagnostics;
+use indicatif::{ParallelProgressIterator, ProgressStyle};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepositoryConfig {
+    pub version: u32,
+    pub chunker: ChunkerParams,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotFile {
+    // Relative to the archived root, always with / separators
+    pub path: String,
+    pub size_bytes: u64,
+    pub mode: Option<u32>,
+    pub modified: Option<u64>,
+    // blake3 chunk ids in file order
+    pub chunks: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub name: String,
+    pub created: u64,
+    pub source: String,
+    pub files: Vec<SnapshotFile>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveStats {
+    pub files: usize,
+    pub chunks: u64,
+    pub total_bytes: u64,
+    // Bytes written as new chunks; the rest was already in the repository
+    pub stored_bytes: u64,
+    // Files that couldn't be read, reported through diagnostics and left out of the snapshot
+    pub skipped: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    total_bytes: AtomicU64,
+    stored_bytes: AtomicU64,
+    chunks: AtomicU64,
+}
+
+pub struct Repository {
+    root: PathBuf,
+    config: RepositoryConfig,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Write through a temporary file and rename, so readers never see partial content
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension(format!(
+        "tmp-{}-{:?}",
+        std::process::id(),
+        std::thread::current().id()
+    ));
+    let mut file = File::create(&temporary)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+impl Repository {
+    // Create a new empty repository, or open it if it already exists
+    pub fn init(root: &Path) -> Result<Self, Box<dyn Error>> {
+        if root.join("config.json").exists() {
+            return Self::open(root);
+        }
+
+        fs::create_dir_all(root.join("chunks"))?;
+        fs::create_dir_all(root.join("snapshots"))?;
+
+        let config = RepositoryConfig {
+            version: FORMAT_VERSION,
+            chunker: ChunkerParams::default(),
+        };
+        write_atomically(
+            &root.join("config.json"),
+            &serde_json::to_vec_pretty(&config)?,
+        )?;
+
+        Ok(Repository {
+            root: root.to_path_buf(),
+            config,
+        })
+    }
+
+    pub fn open(root: &Path) -> Result<Self, Box<dyn Error>> {
+        let config: RepositoryConfig = serde_json::from_slice(&fs::read(root.join("config.json"))?)
+            .map_err(|e| format!("{} is not an rdedupe repository: {}", root.display(), e))?;
+
+        if config.version != FORMAT_VERSION {
+            return Err(format!("unsupported repository version {}", config.version).into());
+        }
+
+        Ok(Repository {
+            root: root.to_path_buf(),
+            config,
+        })
+    }
+
+    // Chunk ids are blake3 hex digests; anything else an index holds never becomes a path
+    fn chunk_path(&self, id: &str) -> io::Result<PathBuf> {
+        if id.len() != 64 || !id.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid chunk id '{}'", id),
+            ));
+        }
+        Ok(self.root.join("chunks").join(&id[..2]).join(id))
+    }
+
+    // A snapshot name is a single file name under snapshots/
+    fn snapshot_path(&self, name: &str) -> Result<PathBuf, String> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(format!("invalid snapshot name '{}'", name));
+        }
+        Ok(self.root.join("snapshots").join(format!("{}.json", name)))
+    }
+
+    // Store a chunk unless it is already present; returns true when it was new
+    fn put_chunk(&self, id: &str, data: &[u8]) -> io::Result<bool> {
+        let path = self.chunk_path(id)?;
+        if path.exists() {
+            return Ok(false);
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+        write_atomically(&path, data)?;
+        Ok(true)
+    }
+
+    // Read a chunk back, checking it still hashes to its id
+    fn get_chunk(&self, id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = fs::read(self.chunk_path(id)?)?;
+        if blake3::hash(&data).to_hex().as_str() != id {
+            return Err(format!("chunk {} is corrupt", id).into());
+        }
+        Ok(data)
+    }
+
+    fn archive_file(
+        &self,
+        root: &Path,
+        path: &str,
+        counters: &Counters,
+    ) -> Result<SnapshotFile, Box<dyn Error + Send + Sync>> {
+        let metadata = fs::metadata(path)?;
+        let mut chunks = Vec::new();
+        let (mut total, mut stored) = (0, 0);
+
+        chunking::chunk_stream(File::open(path)?, &self.config.chunker, |chunk| {
+            let id = blake3::hash(chunk).to_hex().to_string();
+            if self.put_chunk(&id, chunk)? {
+                stored += chunk.len() as u64;
+            }
+            total += chunk.len() as u64;
+            chunks.push(id);
+            Ok(())
+        })?;
+        // Counted once the whole file made it, so a file skipped halfway adds nothing
+        counters.total_bytes.fetch_add(total, Ordering::Relaxed);
+        counters.stored_bytes.fetch_add(stored, Ordering::Relaxed);
+        counters
+            .chunks
+            .fetch_add(chunks.len() as u64, Ordering::Relaxed);
+
+        let relative = Path::new(path)
+            .strip_prefix(root)
+            .unwrap_or(Path::new(path));
+        let relative: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        Ok(SnapshotFile {
+            path: relative.join("/"),
+            size_bytes: metadata.len(),
+            mode: file_mode(&metadata),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            chunks,
+        })
+    }
+
+    // Chunk every file under source into the repository and record a snapshot of those it could read
+    pub fn create_snapshot(
+        &self,
+        source: &str,
+        name: Option<&str>,
+    ) -> Result<(Snapshot, ArchiveStats), Box<dyn Error>> {
+        let name = name
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| format!("snapshot-{}", now()));
+        let snapshot_path = self.snapshot_path(&name)?;
+        if snapshot_path.exists() {
+            return Err(format!("snapshot '{}' already exists", name).into());
+        }
+
+        let mut files = crate::walk(&[source])?;
+
+        // Archiving a tree that contains the repository must not chunk the repository itself
+        if let (Ok(source_root), Ok(repository)) =
+            (Path::new(source).canonicalize(), self.root.canonicalize())
+        {
+            if let Ok(inside) = repository.strip_prefix(&source_root) {
+                let inside = Path::new(source).join(inside);
+                files.retain(|file| !Path::new(file).starts_with(&inside));
+            }
+        }
+
+        println!(
+            "Archiving {} files from {} as '{}'",
+            files.len(),
+            source,
+            name
+        );
+
+        let pb = crate::progress::multi().add(indicatif::ProgressBar::new(files.len() as u64));
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(
+                    "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})",
+                )
+                .unwrap(),
+        );
+
+        let counters = Counters::default();
+        let root = Path::new(source);
+        let entries: Vec<Option<SnapshotFile>> = files
+            .par_iter()
+            .progress_with(pb)
+            .map(|file| diagnostics::skipped(file, self.archive_file(root, file, &counters)))
+            .collect();
+
+        let skipped = entries.iter().filter(|entry| entry.is_none()).count();
+        let mut snapshot_files: Vec<SnapshotFile> = entries.into_iter().flatten().collect();
+        snapshot_files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let snapshot = Snapshot {
+            name,
+            created: now(),
+            source: source.to_string(),
+            files: snapshot_files,
+        };
+        write_atomically(&snapshot_path, &serde_json::to_vec_pretty(&snapshot)?)?;
+
+        let stats = ArchiveStats {
+            files: snapshot.files.len(),
+            total_bytes: counters.total_bytes.into_inner(),
+            stored_bytes: counters.stored_bytes.into_inner(),
+            chunks: counters.chunks.into_inner(),
+            skipped,
+        };
+
+        Ok((snapshot, stats))
+    }
+
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, Box<dyn Error>> {
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(self.root.join("snapshots"))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                snapshots.push(serde_json::from_slice::<Snapshot>(&fs::read(&path)?)?);
+            }
+        }
+        snapshots.sort_by_key(|s| s.created);
+        Ok(snapshots)
+    }
+
+    pub fn snapshot(&self, name: &str) -> Result<Snapshot, Box<dyn Error>> {
+        let data = fs::read(self.snapshot_path(name)?)
+            .map_err(|e| format!("snapshot '{}': {}", name, e))?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    // Restore a snapshot under dest, verifying every chunk on the way
+    pub fn extract(&self, name: &str, dest: &Path) -> Result<usize, Box<dyn Error>> {
+        let snapshot = self.snapshot(name)?;
+
+        for file in &snapshot.files {
+            // Never write outside dest, whatever the index says
+            let relative = Path::new(&file.path);
+            if relative
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+            {
+                return Err(format!("refusing unsafe path '{}'", file.path).into());
+            }
+
+            let target = dest.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut out = File::create(&target)?;
+            for id in &file.chunks {
+                out.write_all(&self.get_chunk(id)?)?;
+            }
+
+            if let Some(modified) = file.modified {
+                out.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(modified))?;
+            }
+            #[cfg(unix)]
+            if let Some(mode) = file.mode {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&target, fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        Ok(snapshot.files.len())
+    }
+}
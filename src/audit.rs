@@ -0,0 +1,251 @@
+//append-only, hash-chained log of every action that changed the filesystem
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Chain value before the first entry of a log
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+static LOG: OnceLock<Mutex<AuditLog>> = OnceLock::new();
+
+// Why an action was taken: the content hash both copies share and the policy that chose the victim
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    pub hash: &'a str,
+    pub policy: &'a str,
+}
+
+/*  One line of the log. chain is blake3(prev chain, entry without chain), so editing, dropping or
+reordering any line but the newest breaks every chain value after it.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Entry {
+    pub time: u64,
+    pub action: String,
+    pub path: String,
+    // The kept copy a link or move points at
+    pub target: Option<String>,
+    pub hash: String,
+    pub size_bytes: u64,
+    pub policy: String,
+    pub chain: String,
+}
+
+struct AuditLog {
+    file: File,
+    last: String,
+}
+
+fn chain_of(previous: &str, entry: &Entry) -> Result<String, Box<dyn Error>> {
+    let unchained = Entry {
+        chain: String::new(),
+        ..entry.clone()
+    };
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(previous.as_bytes());
+    hasher.update(serde_json::to_string(&unchained)?.as_bytes());
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// Every entry of a log, in order
+pub fn read_log(path: &Path) -> Result<Vec<Entry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), number + 1, e))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+// Index of the first entry whose chain doesn't match, if any
+pub fn verify(entries: &[Entry]) -> Result<Option<usize>, Box<dyn Error>> {
+    let mut previous = GENESIS.to_string();
+    for (index, entry) in entries.iter().enumerate() {
+        if chain_of(&previous, entry)? != entry.chain {
+            return Ok(Some(index));
+        }
+        previous = entry.chain.clone();
+    }
+    Ok(None)
+}
+
+/*  Append this run's actions to path. The existing chain is checked first, so a run never extends
+a log that was already tampered with.
+*/
+pub fn open(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut last = GENESIS.to_string();
+    if path.exists() {
+        let entries = read_log(path)?;
+        if let Some(index) = verify(&entries)? {
+            return Err(format!(
+                "audit log {} fails verification at entry {}",
+                path.display(),
+                index + 1
+            )
+            .into());
+        }
+        if let Some(entry) = entries.last() {
+            last = entry.chain.clone();
+        }
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    LOG.set(Mutex::new(AuditLog { file, last }))
+        .map_err(|_| "audit log is already open")?;
+    Ok(())
+}
+
+// Log one completed action; a no-op without an open log
+pub fn record(
+    action: &str,
+    path: &Path,
+    target: Option<&Path>,
+    size_bytes: u64,
+    context: &Context,
+) -> Result<(), Box<dyn Error>> {
+    let Some(log) = LOG.get() else {
+        return Ok(());
+    };
+    let mut log = log.lock().unwrap();
+
+    let mut entry = Entry {
+        time: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        action: action.to_string(),
+        path: path.to_string_lossy().to_string(),
+        target: target.map(|t| t.to_string_lossy().to_string()),
+        hash: context.hash.to_string(),
+        size_bytes,
+        policy: context.policy.to_string(),
+        chain: String::new(),
+    };
+    entry.chain = chain_of(&log.last, &entry)?;
+
+    let line = format!("{}\n", serde_json::to_string(&entry)?);
+    log.file.write_all(line.as_bytes())?;
+    log.file.sync_data()?;
+    log.last = entry.chain;
+    Ok(())
+}
+
+// Days since 1970-01-01 to (year, month, day), after Howard Hinnant's civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// UTC timestamp such as 2024-03-05 14:07:09
+pub fn format_time(time: u64) -> String {
+    let (days, seconds) = ((time / 86_400) as i64, time % 86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// A YYYY-MM-DD date as the unix time of its first second, UTC
+pub fn parse_date(date: &str) -> Result<u64, String> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let invalid = || format!("invalid date '{}' (expected YYYY-MM-DD)", date);
+    let [year, month, day] = parts[..] else {
+        return Err(invalid());
+    };
+    let (year, month, day): (i64, u32, u32) = (
+        year.parse().map_err(|_| invalid())?,
+        month.parse().map_err(|_| invalid())?,
+        day.parse().map_err(|_| invalid())?,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || year < 1970 {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day) as u64 * 86_400)
+}
+
+/*  Print the entries in [since, until) and check the chain. Returns false when the log fails
+verification.
+*/
+pub fn print_log(
+    path: &Path,
+    since: Option<u64>,
+    until: Option<u64>,
+) -> Result<bool, Box<dyn Error>> {
+    let entries = read_log(path)?;
+    let broken = verify(&entries)?;
+
+    let mut shown = 0;
+    let mut bytes = 0;
+    for entry in &entries {
+        if since.is_some_and(|s| entry.time < s) || until.is_some_and(|u| entry.time >= u) {
+            continue;
+        }
+        let target = entry
+            .target
+            .as_deref()
+            .map(|t| format!(" -> {}", t))
+            .unwrap_or_default();
+        println!(
+            "{} {} {}{} ({:.2} MB, {}, {})",
+            format_time(entry.time),
+            entry.action,
+            entry.path,
+            target,
+            entry.size_bytes as f64 / 1_048_576.0,
+            entry.hash,
+            entry.policy
+        );
+        shown += 1;
+        bytes += entry.size_bytes;
+    }
+
+    println!(
+        "{} action(s), {:.2} MB, out of {} logged",
+        shown,
+        bytes as f64 / 1_048_576.0,
+        entries.len()
+    );
+    match broken {
+        Some(index) => {
+            println!(
+                "Warning: the log fails verification at entry {}, it was modified after being written",
+                index + 1
+            );
+            Ok(false)
+        }
+        None => {
+            println!("Chain verified: the log is intact");
+            Ok(true)
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//verifies a backup tree against its source using the same hashing pipeline as dedupe
+use crate::{collect_file_info, walk, FileInfo};
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupStatus {
+    // Same relative path, same content
+    Ok,
+    // Content is in the backup, but under a different path
+    Relocated,
+    // Same relative path exists in the backup with different content
+    Corrupt,
+    // Content not found anywhere in the backup
+    Missing,
+}
+
+impl BackupStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupStatus::Ok => "ok",
+            BackupStatus::Relocated => "relocated",
+            BackupStatus::Corrupt => "corrupt",
+            BackupStatus::Missing => "missing",
+        }
+    }
+
+    // Missing and corrupt entries mean the backup can't restore the source
+    pub fn is_problem(&self) -> bool {
+        matches!(self, BackupStatus::Corrupt | BackupStatus::Missing)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BackupEntry {
+    pub source: String,
+    pub status: BackupStatus,
+    // Matching (or conflicting, for corrupt entries) file in the backup
+    pub backup: Option<String>,
+    pub size_bytes: u64,
+}
+
+fn relative(root: &str, path: &str) -> String {
+    Path::new(path)
+        .strip_prefix(root)
+        .unwrap_or(Path::new(path))
+        .to_string_lossy()
+        .to_string()
+}
+
+// Check that every file under source has a content-identical counterpart under backup
+pub fn verify_backup(source: &str, backup: &str) -> Result<Vec<BackupEntry>, Box<dyn Error>> {
+    println!("Hashing source tree: {}", source);
+    let source_infos = collect_file_info(walk(&[source])?)?;
+
+    println!("Hashing backup tree: {}", backup);
+    let backup_infos = collect_file_info(walk(&[backup])?)?;
+
+    let mut by_relative: HashMap<String, &FileInfo> = HashMap::new();
+    let mut by_hash: HashMap<&str, &FileInfo> = HashMap::new();
+    for info in &backup_infos {
+        by_relative.insert(relative(backup, &info.path), info);
+        by_hash.entry(info.hash.as_str()).or_insert(info);
+    }
+
+    let mut entries: Vec<BackupEntry> = source_infos
+        .iter()
+        .map(|info| {
+            let (status, counterpart) = match by_relative.get(&relative(source, &info.path)) {
+                Some(copy) if copy.hash == info.hash => (BackupStatus::Ok, Some(*copy)),
+                Some(copy) => match by_hash.get(info.hash.as_str()) {
+                    Some(moved) => (BackupStatus::Relocated, Some(*moved)),
+                    None => (BackupStatus::Corrupt, Some(*copy)),
+                },
+                None => match by_hash.get(info.hash.as_str()) {
+                    Some(moved) => (BackupStatus::Relocated, Some(*moved)),
+                    None => (BackupStatus::Missing, None),
+                },
+            };
+
+            BackupEntry {
+                source: info.path.clone(),
+                status,
+                backup: counterpart.map(|c| c.path.clone()),
+                size_bytes: info.size_bytes,
+            }
+        })
+        .collect();
+
+    entries.sort_by(|a, b| a.source.cmp(&b.source));
+
+    Ok(entries)
+}
+
+// One row per source file with its verification status
+pub fn backup_dataframe(entries: &[BackupEntry]) -> Result<DataFrame, Box<dyn Error>> {
+    Ok(df! [
+        "source_path" => entries.iter().map(|e| e.source.clone()).collect::<Vec<_>>(),
+        "status" => entries.iter().map(|e| e.status.as_str()).collect::<Vec<_>>(),
+        "backup_path" => entries.iter().map(|e| e.backup.clone()).collect::<Vec<_>>(),
+        "size_bytes" => entries.iter().map(|e| e.size_bytes).collect::<Vec<_>>(),
+    ]?)
+}
+
+pub fn write_backup_csv(entries: &[BackupEntry], output_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut df = backup_dataframe(entries)?;
+    crate::report::write_csv(&mut df, Path::new(output_path))
+}
+
+// Print a summary plus every missing/corrupt entry; returns the number of problems
+pub fn print_backup_report(entries: &[BackupEntry]) -> usize {
+    let count = |status: BackupStatus| entries.iter().filter(|e| e.status == status).count();
+
+    println!("\n=== Backup Verification ===");
+    println!("Source files checked: {}", entries.len());
+    println!("  ok:        {}", count(BackupStatus::Ok));
+    println!("  relocated: {}", count(BackupStatus::Relocated));
+    println!("  corrupt:   {}", count(BackupStatus::Corrupt));
+    println!("  missing:   {}", count(BackupStatus::Missing));
+
+    let problems: Vec<&BackupEntry> = entries.iter().filter(|e| e.status.is_problem()).collect();
+    for entry in &problems {
+        match &entry.backup {
+            Some(copy) => println!(
+                "{}: {} (backup copy {} differs)",
+                entry.status.as_str(),
+                entry.source,
+                copy
+            ),
+            None => println!("{}: {}", entry.status.as_str(), entry.source),
+        }
+    }
+
+    problems.len()
+}
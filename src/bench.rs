@@ -0,0 +1,196 @@
+//built-in benchmark: hash throughput per algorithm and thread count, plus IO throughput on a volume
+use rayon::prelude::*;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::Instant;
+
+pub const ALGORITHMS: [&str; 2] = ["md5", "blake3"];
+
+// Work unit handed to each thread, roughly a typical file
+const PIECE: usize = 1_048_576;
+
+#[derive(Debug, Clone)]
+pub struct HashResult {
+    pub algorithm: &'static str,
+    pub threads: usize,
+    pub mb_per_sec: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchReport {
+    pub size_bytes: usize,
+    pub hashes: Vec<HashResult>,
+    pub write_mb_per_sec: f64,
+    pub read_mb_per_sec: f64,
+    pub algorithm: &'static str,
+    pub threads: usize,
+}
+
+// Incompressible pseudo-random bytes (xorshift), the same on every run
+pub fn synthetic_data(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed | 1;
+    let mut data = Vec::with_capacity(len + 8);
+    while data.len() < len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.extend_from_slice(&state.to_le_bytes());
+    }
+    data.truncate(len);
+    data
+}
+
+fn hash_piece(algorithm: &str, piece: &[u8]) {
+    match algorithm {
+        "md5" => {
+            std::hint::black_box(md5::compute(piece));
+        }
+        _ => {
+            std::hint::black_box(blake3::hash(piece));
+        }
+    }
+}
+
+fn mb_per_sec(bytes: usize, started: Instant) -> f64 {
+    bytes as f64 / 1_048_576.0 / started.elapsed().as_secs_f64().max(1e-9)
+}
+
+// 1, 2, 4, ... up to the number of cores, always including the core count itself
+pub fn thread_counts() -> Vec<usize> {
+    let cores = num_cpus::get().max(1);
+    let mut counts: Vec<usize> = std::iter::successors(Some(1), |n| Some(n * 2))
+        .take_while(|n| *n < cores)
+        .collect();
+    counts.push(cores);
+    counts
+}
+
+pub fn bench_hashes(data: &[u8]) -> Result<Vec<HashResult>, Box<dyn Error>> {
+    let mut results = Vec::new();
+
+    for threads in thread_counts() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+
+        for algorithm in ALGORITHMS {
+            let started = Instant::now();
+            pool.install(|| {
+                data.par_chunks(PIECE)
+                    .for_each(|piece| hash_piece(algorithm, piece))
+            });
+            results.push(HashResult {
+                algorithm,
+                threads,
+                mb_per_sec: mb_per_sec(data.len(), started),
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+/*  Write the data to a scratch file under dir (synced to disk), then read it back. The read is
+likely served from the page cache, so it is an upper bound for warm trees.
+*/
+pub fn bench_io(dir: &Path, data: &[u8]) -> Result<(f64, f64), Box<dyn Error>> {
+    let path = dir.join(format!(".rdedupe-bench-{}", std::process::id()));
+
+    let result = (|| -> Result<(f64, f64), Box<dyn Error>> {
+        let started = Instant::now();
+        let mut file = File::create(&path)?;
+        for piece in data.chunks(PIECE) {
+            file.write_all(piece)?;
+        }
+        file.sync_all()?;
+        let write = mb_per_sec(data.len(), started);
+
+        let started = Instant::now();
+        let mut file = File::open(&path)?;
+        let mut buffer = vec![0u8; PIECE];
+        let mut read = 0;
+        loop {
+            let n = file.read(&mut buffer)?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        Ok((write, mb_per_sec(read, started)))
+    })();
+
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/*  Pick the fastest algorithm, then the fewest threads reaching 90% of its best throughput. Past
+the volume's read speed extra hashing threads only wait on IO, so the bar is capped there.
+*/
+fn recommend(hashes: &[HashResult], read_mb_per_sec: f64) -> (&'static str, usize) {
+    let best = hashes
+        .iter()
+        .max_by(|a, b| a.mb_per_sec.total_cmp(&b.mb_per_sec))
+        .expect("at least one hash result");
+
+    let target = (best.mb_per_sec * 0.9).min(read_mb_per_sec);
+    let threads = hashes
+        .iter()
+        .filter(|r| r.algorithm == best.algorithm && r.mb_per_sec >= target)
+        .map(|r| r.threads)
+        .min()
+        .unwrap_or(best.threads);
+
+    (best.algorithm, threads)
+}
+
+pub fn run_bench(dir: &Path, size_bytes: usize) -> Result<BenchReport, Box<dyn Error>> {
+    println!(
+        "Benchmarking with {:.2} MB of synthetic data on {}",
+        size_bytes as f64 / 1_048_576.0,
+        dir.display()
+    );
+    let data = synthetic_data(size_bytes, 0x5eed);
+
+    let hashes = bench_hashes(&data)?;
+    let (write_mb_per_sec, read_mb_per_sec) = bench_io(dir, &data)?;
+    let (algorithm, threads) = recommend(&hashes, read_mb_per_sec);
+
+    Ok(BenchReport {
+        size_bytes,
+        hashes,
+        write_mb_per_sec,
+        read_mb_per_sec,
+        algorithm,
+        threads,
+    })
+}
+
+pub fn print_bench_report(report: &BenchReport) {
+    println!("\n=== Hash Throughput ===");
+    println!("{:<8} {:>7} {:>12}", "algo", "threads", "MB/s");
+    for result in &report.hashes {
+        println!(
+            "{:<8} {:>7} {:>12.2}",
+            result.algorithm, result.threads, result.mb_per_sec
+        );
+    }
+
+    println!("\n=== IO Throughput ===");
+    println!("write (synced): {:.2} MB/s", report.write_mb_per_sec);
+    println!("read:           {:.2} MB/s", report.read_mb_per_sec);
+
+    println!(
+        "\nRecommendation: {} with {} thread(s)",
+        report.algorithm, report.threads
+    );
+    let fastest_hash = report
+        .hashes
+        .iter()
+        .map(|r| r.mb_per_sec)
+        .fold(0.0, f64::max);
+    if report.read_mb_per_sec < fastest_hash {
+        println!("Scans on this volume are IO-bound: reading is slower than hashing.");
+    }
+}
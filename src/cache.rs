@@ -0,0 +1,524 @@
+//checksum cache between runs: a local file by default, or a Redis server shared by every host of a filer
+use crate::diagnostics::{self, Diagnostic};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+/*  Files modified this recently are not cached: a write in the same timestamp tick could change
+the content without changing the key (git's racy-clean problem).
+*/
+const RACY: Duration = Duration::from_secs(2);
+// Local cache entries are split by key over this many locks, so hashing threads rarely meet
+const SHARDS: usize = 64;
+
+static BACKEND: OnceLock<Arc<dyn HashCache>> = OnceLock::new();
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+
+// Where content hashes are remembered between runs, keyed by key()
+pub trait HashCache: Send + Sync {
+    fn get(&self, key: &str) -> Option<String>;
+    fn put(&self, key: &str, hash: &str);
+    // One line for the end-of-run summary
+    fn describe(&self) -> String;
+}
+
+impl std::fmt::Debug for dyn HashCache + '_ {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/*  A Redis (or any RESP speaking) server, given as redis://[:password@]host[:port][/db]. Connections
+are pooled; a server that stops answering is switched off for the rest of the run, so a broken
+cache only costs one timeout, never the scan.
+*/
+pub struct RedisCache {
+    address: String,
+    password: Option<String>,
+    db: u32,
+    pool: Mutex<Vec<Connection>>,
+    broken: AtomicBool,
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+}
+
+enum Reply {
+    Bulk(Option<Vec<u8>>),
+    // Status and integer replies, whose value isn't needed
+    Done,
+}
+
+impl Connection {
+    fn command(&mut self, args: &[&[u8]]) -> io::Result<Reply> {
+        let mut request = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            request.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            request.extend_from_slice(arg);
+            request.extend_from_slice(b"\r\n");
+        }
+        self.stream.write_all(&request)?;
+        self.reply()
+    }
+
+    fn reply(&mut self) -> io::Result<Reply> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(protocol("server closed the connection"));
+        }
+        let line = line.trim_end();
+        let (kind, rest) = line.split_at_checked(1).unwrap_or(("", ""));
+        match kind {
+            "+" | ":" => Ok(Reply::Done),
+            "-" => Err(protocol(rest)),
+            "$" => {
+                let len: i64 = rest.parse().map_err(|_| protocol("bad bulk length"))?;
+                if len < 0 {
+                    return Ok(Reply::Bulk(None));
+                }
+                let mut data = vec![0u8; len as usize + 2];
+                io::Read::read_exact(&mut self.reader, &mut data)?;
+                data.truncate(len as usize);
+                Ok(Reply::Bulk(Some(data)))
+            }
+            _ => Err(protocol("unexpected reply")),
+        }
+    }
+}
+
+fn protocol(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl RedisCache {
+    pub fn open(url: &str) -> Result<Self, Box<dyn Error>> {
+        let rest = url
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("unsupported cache URL '{}' (expected redis://...)", url))?;
+        let (credentials, rest) = match rest.rsplit_once('@') {
+            Some((credentials, rest)) => (Some(credentials), rest),
+            None => (None, rest),
+        };
+        let (host, db) = match rest.split_once('/') {
+            Some((host, "")) => (host, 0),
+            Some((host, db)) => (
+                host,
+                db.parse()
+                    .map_err(|_| format!("bad database number in '{}'", url))?,
+            ),
+            None => (rest, 0),
+        };
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:6379", host)
+        };
+        let password = credentials.map(|c| c.rsplit(':').next().unwrap_or(c).to_string());
+
+        let cache = RedisCache {
+            address,
+            password,
+            db,
+            pool: Mutex::new(Vec::new()),
+            broken: AtomicBool::new(false),
+        };
+        // Fail up front on a wrong address or password rather than silently skipping the cache
+        let connection = cache.connect()?;
+        cache.pool.lock().unwrap().push(connection);
+        Ok(cache)
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let address = std::net::ToSocketAddrs::to_socket_addrs(&self.address)?
+            .next()
+            .ok_or_else(|| protocol("address did not resolve"))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        };
+
+        if let Some(password) = &self.password {
+            connection.command(&[b"AUTH", password.as_bytes()])?;
+        }
+        if self.db != 0 {
+            connection.command(&[b"SELECT", self.db.to_string().as_bytes()])?;
+        }
+        Ok(connection)
+    }
+
+    // Run one command on a pooled connection; any failure switches the cache off
+    fn with_connection<T>(&self, f: impl FnOnce(&mut Connection) -> io::Result<T>) -> Option<T> {
+        if self.broken.load(Ordering::Relaxed) {
+            return None;
+        }
+        let pooled = self.pool.lock().unwrap().pop();
+        let result = match pooled {
+            Some(connection) => Ok(connection),
+            None => self.connect(),
+        }
+        .and_then(|mut connection| f(&mut connection).map(|value| (connection, value)));
+
+        match result {
+            Ok((connection, value)) => {
+                self.pool.lock().unwrap().push(connection);
+                Some(value)
+            }
+            Err(e) => {
+                if !self.broken.swap(true, Ordering::Relaxed) {
+                    crate::progress::message(&format!(
+                        "Warning: shared cache {} failed, continuing without it: {}",
+                        self.address, e
+                    ));
+                    diagnostics::report(Diagnostic::CacheUnavailable {
+                        reason: format!("{}: {}", self.address, e),
+                    });
+                }
+                None
+            }
+        }
+    }
+}
+
+impl HashCache for RedisCache {
+    fn get(&self, key: &str) -> Option<String> {
+        match self.with_connection(|c| c.command(&[b"GET", key.as_bytes()]))? {
+            Reply::Bulk(Some(data)) => String::from_utf8(data).ok(),
+            _ => None,
+        }
+    }
+
+    fn put(&self, key: &str, hash: &str) {
+        self.with_connection(|c| c.command(&[b"SET", key.as_bytes(), hash.as_bytes()]));
+    }
+
+    fn describe(&self) -> String {
+        format!("redis at {}", self.address)
+    }
+}
+
+// One line of the local cache file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Line {
+    key: String,
+    hash: String,
+}
+
+/*  An append-only file of key/hash lines, loaded once when opened. Every new hash is appended as it
+is computed, so a crash or an early exit loses nothing; later lines win, and a torn last line is
+ignored. prune() rewrites the file without the entries of changed or deleted files.
+*/
+pub struct LocalCache {
+    path: PathBuf,
+    entries: Shards,
+    log: Mutex<Option<fs::File>>,
+    // Set once the file can't be appended to, new hashes then stay in memory only
+    broken: AtomicBool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pruned {
+    pub kept: usize,
+    pub removed: usize,
+}
+
+// key -> hash, each key in the shard its hash picks
+struct Shards {
+    state: RandomState,
+    shards: Vec<RwLock<HashMap<String, String>>>,
+}
+
+impl Shards {
+    fn new(entries: HashMap<String, String>) -> Self {
+        let shards = Shards {
+            state: RandomState::new(),
+            shards: (0..SHARDS).map(|_| RwLock::default()).collect(),
+        };
+        for (key, hash) in entries {
+            shards.shard(&key).write().unwrap().insert(key, hash);
+        }
+        shards
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, String>> {
+        &self.shards[self.state.hash_one(key) as usize % SHARDS]
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+}
+
+// The path and modification time (ns since the epoch) a key was made from
+fn key_parts(key: &str) -> Option<(&str, u128)> {
+    let mut parts = key.splitn(7, ':');
+    let (_, _, _, modified, _, _, path) = (
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    );
+    Some((path, modified.parse().ok()?))
+}
+
+fn algorithm_of(key: &str) -> Option<&str> {
+    key.split(':').nth(1)
+}
+
+impl LocalCache {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut entries = HashMap::new();
+        match fs::File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    if let Ok(line) = serde_json::from_str::<Line>(&line?) {
+                        entries.insert(line.key, line.hash);
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        }
+        Ok(LocalCache {
+            path: path.to_path_buf(),
+            entries: Shards::new(entries),
+            log: Mutex::new(None),
+            broken: AtomicBool::new(false),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn append(&self, line: &Line) -> io::Result<()> {
+        let mut log = self.log.lock().unwrap();
+        if log.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            *log = Some(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+        let text = format!("{}\n", serde_json::to_string(line)?);
+        log.as_mut()
+            .expect("opened above")
+            .write_all(text.as_bytes())
+    }
+
+    // Drop every entry whose file is gone or no longer has the size and mtime it was hashed at
+    pub fn prune(&self) -> Result<Pruned, Box<dyn Error>> {
+        let mut entries: Vec<_> = self
+            .entries
+            .shards
+            .iter()
+            .map(|s| s.write().unwrap())
+            .collect();
+        let before: usize = entries.iter().map(|shard| shard.len()).sum();
+        for shard in entries.iter_mut() {
+            shard.retain(|entry, _| {
+                algorithm_of(entry)
+                    .zip(key_parts(entry))
+                    .and_then(|(algorithm, (path, _))| key(path, algorithm))
+                    .is_some_and(|current| current == *entry)
+            });
+        }
+        let kept: usize = entries.iter().map(|shard| shard.len()).sum();
+
+        crate::report::write_atomic(&self.path, |writer| {
+            for (key, hash) in entries.iter().flat_map(|shard| shard.iter()) {
+                let line = Line {
+                    key: key.clone(),
+                    hash: hash.clone(),
+                };
+                writeln!(writer, "{}", serde_json::to_string(&line)?)?;
+            }
+            Ok(())
+        })?;
+        // Appends go to the rewritten file from now on
+        *self.log.lock().unwrap() = None;
+        Ok(Pruned {
+            kept,
+            removed: before - kept,
+        })
+    }
+}
+
+impl HashCache for LocalCache {
+    fn get(&self, key: &str) -> Option<String> {
+        self.entries.shard(key).read().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, hash: &str) {
+        let racy = key_parts(key).is_none_or(|(_, modified)| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            now.as_nanos() < modified + RACY.as_nanos()
+        });
+        if racy {
+            return;
+        }
+        self.entries
+            .shard(key)
+            .write()
+            .unwrap()
+            .insert(key.to_string(), hash.to_string());
+        let line = Line {
+            key: key.to_string(),
+            hash: hash.to_string(),
+        };
+        if self.broken.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = self.append(&line) {
+            self.broken.store(true, Ordering::Relaxed);
+            crate::progress::message(&format!(
+                "Warning: checksum cache {} can't be written, continuing without saving: {}",
+                self.path.display(),
+                e
+            ));
+            diagnostics::report(Diagnostic::CacheUnavailable {
+                reason: format!("{}: {}", self.path.display(), e),
+            });
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("local {}", self.path.display())
+    }
+}
+
+// RDEDUPE_CACHE, else checksums.jsonl under $XDG_CACHE_HOME/rdedupe or ~/.cache/rdedupe
+pub fn default_local_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RDEDUPE_CACHE") {
+        return Some(PathBuf::from(path));
+    }
+
+    let cache_home = std::env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| Path::new(&home).join(".cache")))
+        .ok()?;
+
+    Some(cache_home.join("rdedupe").join("checksums.jsonl"))
+}
+
+// Only the first backend of a run is used
+pub fn set_backend(backend: Box<dyn HashCache>) {
+    let _ = BACKEND.set(Arc::from(backend));
+}
+
+// The backend of the run, for callers such as scan::ScanOptions that carry their own
+pub fn backend() -> Option<Arc<dyn HashCache>> {
+    BACKEND.get().cloned()
+}
+
+pub fn global() -> Option<&'static dyn HashCache> {
+    BACKEND.get().map(|backend| backend.as_ref())
+}
+
+pub fn open(url: &str) -> Result<Box<dyn HashCache>, Box<dyn Error>> {
+    Ok(Box::new(RedisCache::open(url)?))
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/*  A file is looked up by algorithm, size, modification time, device, inode and canonical path, so
+a rewritten or replaced file misses even if its path is unchanged, and "./a" and "a" share an
+entry. Hosts must mount the filer at the same path, and see the same device numbers, to share
+entries.
+*/
+pub fn key(path: &str, algorithm: &str) -> Option<String> {
+    let canonical = fs::canonicalize(path).ok()?;
+    let metadata = fs::metadata(&canonical).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let (device, inode) = file_id(&metadata);
+    Some(format!(
+        "rdedupe:{}:{}:{}:{}:{}:{}",
+        algorithm,
+        metadata.len(),
+        modified.as_nanos(),
+        device,
+        inode,
+        canonical.to_str()?
+    ))
+}
+
+// Hash through backend, computing and storing on a miss; without one, just compute
+pub fn cached(
+    backend: Option<&dyn HashCache>,
+    path: &str,
+    algorithm: &str,
+    compute: impl FnOnce() -> io::Result<String>,
+) -> io::Result<String> {
+    let Some(backend) = backend else {
+        return compute();
+    };
+    let Some(key) = key(path, algorithm) else {
+        return compute();
+    };
+
+    if let Some(hash) = backend.get(&key) {
+        HITS.fetch_add(1, Ordering::Relaxed);
+        return Ok(hash);
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    diagnostics::report(Diagnostic::CacheMiss {
+        path: path.to_string(),
+    });
+    let hash = compute()?;
+    backend.put(&key, &hash);
+    Ok(hash)
+}
+
+pub fn describe() -> Option<String> {
+    BACKEND.get().map(|b| b.describe())
+}
+
+pub fn stats() -> CacheStats {
+    CacheStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    }
+}
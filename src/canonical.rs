@@ -0,0 +1,70 @@
+//which copy of each duplicate group is canonical, as a JSON mapping for sync and backup tooling
+use crate::profile::{self, KeepPolicy};
+use crate::report;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Election {
+    pub group: String,
+    pub canonical: String,
+    // The other copies, which resolve to canonical
+    pub duplicates: Vec<String>,
+    pub size_bytes: u64,
+    pub policy: String,
+}
+
+/*  Elect one canonical copy per group. A DataFrame from run_profile carries the profile's keepers
+(whichever copy its keep policy elected, or would elect on a dry run); any other DataFrame uses the
+default policy, first path in sorted order.
+*/
+pub fn elect(df: &DataFrame, policy: KeepPolicy) -> Result<Vec<Election>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+    let keepers = match df.column("keep") {
+        Ok(_) => profile::keepers(df)?,
+        Err(_) => Default::default(),
+    };
+
+    let mut members: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for index in 0..df.height() {
+        if let (Some(group), Some(path)) = (groups.get(index), paths.get(index)) {
+            members
+                .entry(group)
+                .or_default()
+                .push((path, sizes.get(index).unwrap_or(0)));
+        }
+    }
+
+    let mut elections = Vec::with_capacity(members.len());
+    for (group, mut copies) in members {
+        copies.sort();
+        let canonical = keepers.get(group).copied().unwrap_or(copies[0].0);
+        elections.push(Election {
+            group: group.to_string(),
+            canonical: canonical.to_string(),
+            duplicates: copies
+                .iter()
+                .map(|(path, _)| path.to_string())
+                .filter(|path| path != canonical)
+                .collect(),
+            size_bytes: copies[0].1,
+            policy: policy.as_str().to_string(),
+        });
+    }
+    Ok(elections)
+}
+
+// A JSON array, one object per group, ordered by group ID
+pub fn write_mapping(elections: &[Election], path: &Path) -> Result<(), Box<dyn Error>> {
+    report::write_atomic(path, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, elections)?;
+        writeln!(writer)?;
+        Ok(())
+    })
+}
@@ -0,0 +1,103 @@
+//content-defined chunking (FastCDC style gear hash) so shifted content still produces shared chunks
+use std::io::{self, Read};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ChunkerParams {
+    pub min_size: usize,
+    pub avg_size: usize,
+    pub max_size: usize,
+}
+
+impl Default for ChunkerParams {
+    fn default() -> Self {
+        ChunkerParams {
+            min_size: 16 * 1024,
+            avg_size: 64 * 1024,
+            max_size: 256 * 1024,
+        }
+    }
+}
+
+// Deterministic gear table (splitmix64), it must never change or stored chunks stop matching
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+// Mask over the top bits of the rolling hash; more bits make a cut less likely
+fn mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits)
+}
+
+/*  Length of the next chunk at the start of data. Normalized chunking: a stricter mask before
+the average size and a looser one after it keeps chunk sizes close to the average.
+*/
+pub fn cut_point(data: &[u8], params: &ChunkerParams) -> usize {
+    if data.len() <= params.min_size {
+        return data.len();
+    }
+
+    let bits = params.avg_size.max(2).ilog2();
+    let (mask_small, mask_large) = (mask(bits + 1), mask(bits.saturating_sub(1).max(1)));
+    let end = data.len().min(params.max_size);
+    let normal = params.avg_size.min(end);
+
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(end).skip(params.min_size) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal { mask_small } else { mask_large };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+
+    end
+}
+
+// Split a stream into content-defined chunks, calling on_chunk for each one in order
+pub fn chunk_stream<R, F>(mut reader: R, params: &ChunkerParams, mut on_chunk: F) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(&[u8]) -> io::Result<()>,
+{
+    let mut buffer = vec![0u8; params.max_size];
+    let mut filled = 0;
+    let mut eof = false;
+
+    loop {
+        while !eof && filled < buffer.len() {
+            let read = reader.read(&mut buffer[filled..])?;
+            if read == 0 {
+                eof = true;
+            }
+            filled += read;
+        }
+
+        if filled == 0 {
+            return Ok(());
+        }
+
+        // Without more input coming, whatever is left is the final chunk
+        let cut = if eof && filled <= params.min_size {
+            filled
+        } else {
+            cut_point(&buffer[..filled], params)
+        };
+
+        on_chunk(&buffer[..cut])?;
+        buffer.copy_within(cut..filled, 0);
+        filled -= cut;
+    }
+}
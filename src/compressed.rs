@@ -0,0 +1,85 @@
+//transparent decompression while hashing, so a file and its compressed copy group together
+use crate::hasher::Hasher;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Format {
+    // By extension; the content is trusted to match and falls back to raw hashing if it doesn't
+    pub fn of(path: &str) -> Option<Format> {
+        match Path::new(path).extension()?.to_str()? {
+            "gz" => Some(Format::Gzip),
+            "zst" => Some(Format::Zstd),
+            "bz2" => Some(Format::Bzip2),
+            _ => None,
+        }
+    }
+
+    // Cache key algorithm, so a decompressed hash is never served to a raw scan or another hash
+    pub fn algorithm(self, hasher: Hasher) -> String {
+        let tool = match self {
+            Format::Gzip => "gunzip",
+            Format::Zstd => "unzstd",
+            Format::Bzip2 => "bunzip2",
+        };
+        format!("{}-{}", hasher.as_str(), tool)
+    }
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// The format to decompress path with, if decompression is on for the run and path is compressed
+pub fn decompressing(path: &str) -> Option<Format> {
+    enabled().then(|| Format::of(path)).flatten()
+}
+
+// Concatenated gzip and bzip2 members are read as one stream, like zcat and bzcat do
+pub fn open(path: &str, format: Format) -> io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match format {
+        Format::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+        Format::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+        Format::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(file)),
+    })
+}
+
+/*  Hash of the decompressed content. A file that turns out not to be valid in its format (a
+truncated download, a misnamed file) is hashed as it is on disk instead of being dropped.
+*/
+pub fn hash_content(path: &str, format: Format, hasher: Hasher) -> io::Result<Vec<u8>> {
+    match hasher.hash_reader(open(path, format)?) {
+        Ok(digest) => Ok(digest),
+        Err(_) => hasher.hash_file(path),
+    }
+}
+
+/*  The whole decompressed content, with the same raw fallback as hash_content. None once it grows
+past limit bytes, so a small file that inflates enormously (a gzip bomb) is never held in memory.
+*/
+pub fn read(path: &str, format: Format, limit: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut content = Vec::new();
+    match open(path, format)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut content)
+    {
+        Ok(_) if content.len() as u64 > limit => Ok(None),
+        Ok(_) => Ok(Some(content)),
+        Err(_) => crate::throttle::read(path).map(Some),
+    }
+}
@@ -0,0 +1,121 @@
+//non-fatal conditions reported to library consumers, so embedders can show them in their own UI
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::OnceLock;
+
+static SINK: OnceLock<Box<dyn DiagnosticSink>> = OnceLock::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    // A file the walk found but that could not be hashed or stat'ed; it is left out of the results
+    SkippedFile { path: String, reason: String },
+    // A symlink pointing back at one of its ancestors; the walk does not descend into it
+    SymlinkLoop { path: String, ancestor: String },
+    // A directory or entry below a walk root that could not be read; the walk carries on without it
+    UnreadablePath { path: String, reason: String },
+    CacheMiss { path: String },
+    // A checksum cache failed and is off (or read-only) for the rest of the run
+    CacheUnavailable { reason: String },
+    // A remote worker failed; its files are hashed locally
+    WorkerFailed { address: String, reason: String },
+}
+
+impl Diagnostic {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Diagnostic::SkippedFile { .. } => "skipped-file",
+            Diagnostic::SymlinkLoop { .. } => "symlink-loop",
+            Diagnostic::UnreadablePath { .. } => "unreadable-path",
+            Diagnostic::CacheMiss { .. } => "cache-miss",
+            Diagnostic::CacheUnavailable { .. } => "cache-unavailable",
+            Diagnostic::WorkerFailed { .. } => "worker-failed",
+        }
+    }
+}
+
+// Called from the hashing threads, so it must be cheap and thread safe
+pub trait DiagnosticSink: Send + Sync {
+    fn report(&self, diagnostic: Diagnostic);
+}
+
+// Forwards every diagnostic into a channel; a dropped receiver just discards them
+pub struct ChannelSink(Sender<Diagnostic>);
+
+impl DiagnosticSink for ChannelSink {
+    fn report(&self, diagnostic: Diagnostic) {
+        let _ = self.0.send(diagnostic);
+    }
+}
+
+pub fn channel() -> (ChannelSink, Receiver<Diagnostic>) {
+    let (sender, receiver) = mpsc::channel();
+    (ChannelSink(sender), receiver)
+}
+
+// Only the first sink of a run is used; without one diagnostics are dropped
+pub fn set_sink(sink: Box<dyn DiagnosticSink>) {
+    let _ = SINK.set(sink);
+}
+
+pub fn report(diagnostic: Diagnostic) {
+    if let Some(sink) = SINK.get() {
+        sink.report(diagnostic);
+    }
+}
+
+// Report a file left out of the results, passing the result through
+pub fn skipped<T, E: std::fmt::Display>(path: &str, result: Result<T, E>) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(e) => {
+            report(Diagnostic::SkippedFile {
+                path: path.to_string(),
+                reason: e.to_string(),
+            });
+            None
+        }
+    }
+}
+
+// A walkdir error that only means a symlink loop was cut; other errors are the caller's
+pub fn symlink_loop(error: &walkdir::Error) -> bool {
+    let (Some(ancestor), Some(path)) = (error.loop_ancestor(), error.path()) else {
+        return false;
+    };
+    report(Diagnostic::SymlinkLoop {
+        path: path.to_string_lossy().to_string(),
+        ancestor: ancestor.to_string_lossy().to_string(),
+    });
+    true
+}
+
+// A path the scan had to leave out, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    pub path: String,
+    pub reason: String,
+}
+
+/*  Settle a walkdir error: symlink loops are cut, anything below the root is reported as an
+unreadable path and returned so the walk can carry on. Only an unreadable root is an error.
+*/
+pub fn walk_error(error: walkdir::Error) -> Result<Option<PathError>, walkdir::Error> {
+    if symlink_loop(&error) {
+        return Ok(None);
+    }
+    if error.depth() == 0 {
+        return Err(error);
+    }
+    let path = error
+        .path()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let reason = match error.io_error() {
+        Some(e) => e.to_string(),
+        None => error.to_string(),
+    };
+    report(Diagnostic::UnreadablePath {
+        path: path.clone(),
+        reason: reason.clone(),
+    });
+    Ok(Some(PathError { path, reason }))
+}
@@ -0,0 +1,145 @@
+//compare the duplicate groups of two saved scans, to track what a cleanup campaign achieved
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Appeared,
+    Disappeared,
+    Grew,
+    Shrank,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Appeared => "appeared",
+            Change::Disappeared => "disappeared",
+            Change::Grew => "grew",
+            Change::Shrank => "shrank",
+        }
+    }
+}
+
+// Copies of one group in a scan and the bytes all but one of them waste
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupStats {
+    pub copies: usize,
+    pub wasted_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDiff {
+    pub group: String,
+    pub change: Change,
+    pub before: GroupStats,
+    pub after: GroupStats,
+}
+
+// Duplicate groups of a scan keyed by group ID; files outside a group are ignored
+pub fn group_stats(df: &DataFrame) -> Result<BTreeMap<String, GroupStats>, Box<dyn Error>> {
+    let groups = df.column("duplicate_group")?.cast(&DataType::Utf8)?;
+    let groups = groups.utf8()?;
+    let sizes = df.column("size_bytes")?.cast(&DataType::UInt64)?;
+    let sizes = sizes.u64()?;
+
+    let mut copies: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for (group, size) in groups.into_iter().zip(sizes) {
+        if let Some(group) = group.filter(|g| !g.is_empty()) {
+            copies
+                .entry(group.to_string())
+                .or_default()
+                .push(size.unwrap_or(0));
+        }
+    }
+
+    Ok(copies
+        .into_iter()
+        .filter(|(_, sizes)| sizes.len() > 1)
+        .map(|(group, sizes)| {
+            let largest = sizes.iter().copied().max().unwrap_or(0);
+            let stats = GroupStats {
+                copies: sizes.len(),
+                wasted_bytes: sizes.iter().sum::<u64>() - largest,
+            };
+            (group, stats)
+        })
+        .collect())
+}
+
+// Groups whose copy count changed between two scans, ordered by change then group
+pub fn diff_scans(before: &DataFrame, after: &DataFrame) -> Result<Vec<GroupDiff>, Box<dyn Error>> {
+    let before = group_stats(before)?;
+    let after = group_stats(after)?;
+
+    let mut diffs = Vec::new();
+    for (group, old) in &before {
+        let new = after.get(group).copied().unwrap_or_default();
+        let change = match new.copies {
+            0 => Change::Disappeared,
+            n if n > old.copies => Change::Grew,
+            n if n < old.copies => Change::Shrank,
+            _ => continue,
+        };
+        diffs.push(GroupDiff {
+            group: group.clone(),
+            change,
+            before: *old,
+            after: new,
+        });
+    }
+    for (group, new) in &after {
+        if !before.contains_key(group) {
+            diffs.push(GroupDiff {
+                group: group.clone(),
+                change: Change::Appeared,
+                before: GroupStats::default(),
+                after: *new,
+            });
+        }
+    }
+
+    diffs.sort_by(|a, b| (a.change, &a.group).cmp(&(b.change, &b.group)));
+    Ok(diffs)
+}
+
+pub fn print_diff(
+    diffs: &[GroupDiff],
+    before: &DataFrame,
+    after: &DataFrame,
+) -> Result<(), Box<dyn Error>> {
+    println!("=== Duplicate Groups Diff ===");
+    for diff in diffs {
+        println!(
+            "{:<11} {} ({} -> {} copies, {:.2} -> {:.2} MB wasted)",
+            diff.change.as_str(),
+            diff.group,
+            diff.before.copies,
+            diff.after.copies,
+            diff.before.wasted_bytes as f64 / 1_048_576.0,
+            diff.after.wasted_bytes as f64 / 1_048_576.0
+        );
+    }
+
+    let count = |change: Change| diffs.iter().filter(|d| d.change == change).count();
+    println!(
+        "{} appeared, {} disappeared, {} grew, {} shrank",
+        count(Change::Appeared),
+        count(Change::Disappeared),
+        count(Change::Grew),
+        count(Change::Shrank)
+    );
+
+    let wasted = |df: &DataFrame| -> Result<u64, Box<dyn Error>> {
+        Ok(group_stats(df)?.values().map(|s| s.wasted_bytes).sum())
+    };
+    let (old, new) = (wasted(before)?, wasted(after)?);
+    println!(
+        "Wasted space: {:.2} MB -> {:.2} MB ({:+.2} MB)",
+        old as f64 / 1_048_576.0,
+        new as f64 / 1_048_576.0,
+        (new as f64 - old as f64) / 1_048_576.0
+    );
+    Ok(())
+}
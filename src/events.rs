@@ -0,0 +1,159 @@
+//machine-readable scan output: one JSON object per line for every file, group and error as it happens
+use crate::diagnostics::{Diagnostic, DiagnosticSink};
+use crate::progress::{ProgressSink, Stage};
+use crate::scan::{DedupeReport, DuplicateGroup};
+use serde::Serialize;
+use std::io::Write;
+
+// Exit codes of a --format ndjson scan, so scripts needn't parse the output to branch on it
+pub const NO_DUPLICATES: i32 = 0;
+pub const DUPLICATES_FOUND: i32 = 1;
+pub const SCAN_ERRORS: i32 = 2;
+
+/*  Every line names its kind in "event". Phase, file and error events stream while the scan runs,
+the group events and one summary follow once it is done, largest waste first:
+
+    {"event":"phase","phase":"hashing","total":2}
+    {"event":"file","path":"./a.jpg","hash":"9e10..."}
+    {"event":"error","kind":"skipped-file","path":"./b.jpg","reason":"Permission denied (os error 13)"}
+    {"event":"group","hash":"9e10...","size_bytes":10240,"wasted_bytes":10240,"files":[...]}
+    {"event":"summary","files":3,"groups":1,"wasted_bytes":10240,"errors":1,"exit_code":2}
+
+Only files that had to be read get a file event; a file whose size no other file shares is
+counted in the summary but never hashed. Consumers should ignore fields and events they don't
+know, so both can be added.
+*/
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    Phase {
+        phase: &'a str,
+        total: Option<u64>,
+    },
+    File {
+        path: &'a str,
+        hash: &'a str,
+    },
+    Error {
+        // diagnostics::Diagnostic::kind, or "scan" when the scan itself failed
+        kind: &'a str,
+        path: Option<&'a str>,
+        reason: &'a str,
+    },
+    Group {
+        hash: &'a str,
+        size_bytes: u64,
+        wasted_bytes: u64,
+        files: Vec<GroupFile<'a>>,
+    },
+    Summary {
+        files: usize,
+        groups: usize,
+        wasted_bytes: u64,
+        errors: usize,
+        elapsed_ms: u128,
+        exit_code: i32,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupFile<'a> {
+    pub path: &'a str,
+    pub root: &'a str,
+}
+
+// One line on stdout; the lock keeps lines from the hashing threads whole
+pub fn emit(event: &Event) {
+    let Ok(line) = serde_json::to_string(event) else {
+        return;
+    };
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+fn group_event(group: &DuplicateGroup) -> Event<'_> {
+    Event::Group {
+        hash: &group.hash,
+        size_bytes: group.size_bytes,
+        wasted_bytes: group.wasted_bytes(),
+        files: group
+            .files
+            .iter()
+            .map(|file| GroupFile {
+                path: &file.path,
+                root: &file.root,
+            })
+            .collect(),
+    }
+}
+
+// Scan errors outrank duplicates: a partial scan can't promise it found them all
+pub fn exit_code(report: &DedupeReport) -> i32 {
+    if !report.errors.is_empty() {
+        SCAN_ERRORS
+    } else if !report.groups.is_empty() {
+        DUPLICATES_FOUND
+    } else {
+        NO_DUPLICATES
+    }
+}
+
+// The events of a finished scan: every group, then the summary
+pub fn emit_report(report: &DedupeReport) {
+    for group in &report.groups {
+        emit(&group_event(group));
+    }
+    emit(&Event::Summary {
+        files: report.stats.files,
+        groups: report.groups.len(),
+        wasted_bytes: report.wasted_bytes,
+        errors: report.errors.len(),
+        elapsed_ms: report.stats.elapsed.as_millis(),
+        exit_code: exit_code(report),
+    });
+}
+
+// A scan that failed as a whole, such as on an unreadable root
+pub fn emit_failure(error: &str) {
+    emit(&Event::Error {
+        kind: "scan",
+        path: None,
+        reason: error,
+    });
+}
+
+/*  The sink for both progress and diagnostics, turning them into events as the scan goes. Cache
+misses and symlink loops are routine and left out; the errors that make a scan partial, and
+failed caches and workers, are kept.
+*/
+pub struct Ndjson;
+
+impl ProgressSink for Ndjson {
+    fn on_phase_change(&self, stage: Stage, total: Option<u64>) {
+        emit(&Event::Phase {
+            phase: stage.label(),
+            total,
+        });
+    }
+
+    fn on_file_hashed(&self, path: &str, hash: &str) {
+        emit(&Event::File { path, hash });
+    }
+}
+
+impl DiagnosticSink for Ndjson {
+    fn report(&self, diagnostic: Diagnostic) {
+        let kind = diagnostic.kind();
+        let (path, reason) = match &diagnostic {
+            Diagnostic::SkippedFile { path, reason }
+            | Diagnostic::UnreadablePath { path, reason } => (Some(path.as_str()), reason.as_str()),
+            Diagnostic::CacheUnavailable { reason } => (None, reason.as_str()),
+            Diagnostic::WorkerFailed { address, reason } => {
+                (Some(address.as_str()), reason.as_str())
+            }
+            Diagnostic::SymlinkLoop { .. } | Diagnostic::CacheMiss { .. } => return,
+        };
+        emit(&Event::Error { kind, path, reason });
+    }
+}
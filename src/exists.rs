@@ -0,0 +1,116 @@
+//early-exit duplicate check for scripts: stop as soon as enough duplicates are confirmed
+use crate::diagnostics;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    // Copies of this one file anywhere in the walk
+    File(String),
+    // Duplicates among the files whose path contains this pattern
+    Pattern(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    pub path: String,
+    // The earlier file (or the target file) it is a copy of
+    pub original: String,
+}
+
+// A file seen so far, hashed only once another file of its size turns up
+struct Seen {
+    path: String,
+    hash: Option<String>,
+}
+
+fn hash_of(seen: &mut Seen) -> Option<&str> {
+    if seen.hash.is_none() {
+        seen.hash = diagnostics::skipped(&seen.path, crate::content_hash(&seen.path));
+    }
+    seen.hash.as_deref()
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/*  Walk order, stopping at the limit'th duplicate. Only files sharing a size are hashed, so a tree
+where the target's size is unique costs a walk and a stat per file and nothing more.
+*/
+pub fn find_duplicates(
+    files: &[String],
+    target: &Target,
+    limit: usize,
+) -> Result<Vec<Found>, Box<dyn Error>> {
+    let mut found = Vec::new();
+
+    let mut sizes: HashMap<u64, Vec<Seen>> = HashMap::new();
+    let candidates: Vec<&String> = match target {
+        Target::File(file) => {
+            let size = fs::metadata(file)
+                .map_err(|e| format!("{}: {}", file, e))?
+                .len();
+            sizes.entry(size).or_default().push(Seen {
+                path: file.clone(),
+                hash: Some(crate::content_hash(file).map_err(|e| format!("{}: {}", file, e))?),
+            });
+            files.iter().filter(|path| !same_file(path, file)).collect()
+        }
+        Target::Pattern(pattern) => files.iter().filter(|path| path.contains(pattern)).collect(),
+    };
+
+    for path in candidates {
+        let Some(size) = diagnostics::skipped(path, fs::symlink_metadata(path)).map(|m| m.len())
+        else {
+            continue;
+        };
+        let bucket = sizes.entry(size).or_default();
+        let mut current = Seen {
+            path: path.clone(),
+            hash: None,
+        };
+
+        if !bucket.is_empty() {
+            let Some(hash) = hash_of(&mut current).map(str::to_string) else {
+                continue;
+            };
+            let original = bucket
+                .iter_mut()
+                .find_map(|seen| (hash_of(seen) == Some(hash.as_str())).then(|| seen.path.clone()));
+            if let Some(original) = original {
+                found.push(Found {
+                    path: path.clone(),
+                    original,
+                });
+                if found.len() >= limit {
+                    break;
+                }
+                // A copy adds nothing new to compare against
+                continue;
+            }
+        }
+        // Copies of the target are all that counts in file mode
+        if matches!(target, Target::Pattern(_)) {
+            bucket.push(current);
+        }
+    }
+    Ok(found)
+}
+
+pub fn print_found(found: &[Found], limit: usize) {
+    for item in found {
+        println!("Duplicate: {} (same as {})", item.path, item.original);
+    }
+    if found.is_empty() {
+        println!("No duplicates found");
+    } else if found.len() >= limit {
+        println!("Found {} duplicate(s), stopped early", found.len());
+    } else {
+        println!("Found only {} of {} duplicate(s)", found.len(), limit);
+    }
+}
@@ -0,0 +1,213 @@
+//composable file filters applied during the walk: pattern, size, age, owner or any closure
+use std::fmt;
+use std::fs::Metadata;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/*  Decides whether the walk keeps a file, given its path and the metadata the walk already has
+(symlinks are not followed). Closures taking (&str, &Metadata) are filters too.
+*/
+pub trait FileFilter: Send + Sync {
+    fn accept(&self, path: &str, metadata: &Metadata) -> bool;
+
+    // For --help style listings and Debug output
+    fn describe(&self) -> String {
+        "custom filter".to_string()
+    }
+}
+
+impl<F> FileFilter for F
+where
+    F: Fn(&str, &Metadata) -> bool + Send + Sync,
+{
+    fn accept(&self, path: &str, metadata: &Metadata) -> bool {
+        self(path, metadata)
+    }
+}
+
+// The path contains this text; an empty pattern keeps everything, like find()
+#[derive(Debug, Clone)]
+pub struct Pattern(pub String);
+
+impl FileFilter for Pattern {
+    fn accept(&self, path: &str, _metadata: &Metadata) -> bool {
+        path.contains(&self.0)
+    }
+
+    fn describe(&self) -> String {
+        format!("pattern={}", self.0)
+    }
+}
+
+// Sizes in bytes; either bound may be open
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Size {
+    pub larger_than: Option<u64>,
+    pub smaller_than: Option<u64>,
+}
+
+impl Size {
+    // Inclusive bounds, as --min-size and --max-size take them; None when both are open
+    pub fn within(min: Option<u64>, max: Option<u64>) -> Option<Self> {
+        let larger_than = min.filter(|min| *min > 0).map(|min| min - 1);
+        let smaller_than = max.map(|max| max.saturating_add(1));
+        (larger_than.is_some() || smaller_than.is_some()).then_some(Size {
+            larger_than,
+            smaller_than,
+        })
+    }
+}
+
+impl FileFilter for Size {
+    fn accept(&self, _path: &str, metadata: &Metadata) -> bool {
+        let len = metadata.len();
+        self.larger_than.is_none_or(|min| len > min)
+            && self.smaller_than.is_none_or(|max| len < max)
+    }
+
+    fn describe(&self) -> String {
+        match (self.larger_than, self.smaller_than) {
+            (Some(min), Some(max)) => format!("size>{} and size<{}", min, max),
+            (Some(min), None) => format!("size>{}", min),
+            (None, Some(max)) => format!("size<{}", max),
+            (None, None) => "any size".to_string(),
+        }
+    }
+}
+
+// By modification time; a file without one is kept
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Age {
+    pub older_than: Option<Duration>,
+    pub newer_than: Option<Duration>,
+}
+
+impl FileFilter for Age {
+    fn accept(&self, _path: &str, metadata: &Metadata) -> bool {
+        let Some(age) = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        else {
+            return true;
+        };
+        self.older_than.is_none_or(|min| age > min) && self.newer_than.is_none_or(|max| age < max)
+    }
+
+    fn describe(&self) -> String {
+        let days = |d: Duration| d.as_secs() / 86_400;
+        match (self.older_than, self.newer_than) {
+            (Some(min), Some(max)) => format!("age>{}d and age<{}d", days(min), days(max)),
+            (Some(min), None) => format!("age>{}d", days(min)),
+            (None, Some(max)) => format!("age<{}d", days(max)),
+            (None, None) => "any age".to_string(),
+        }
+    }
+}
+
+// Files owned by this user id; always false where ownership isn't known
+#[derive(Debug, Clone, Copy)]
+pub struct Owner(pub u32);
+
+impl FileFilter for Owner {
+    #[cfg(unix)]
+    fn accept(&self, _path: &str, metadata: &Metadata) -> bool {
+        std::os::unix::fs::MetadataExt::uid(metadata) == self.0
+    }
+
+    #[cfg(not(unix))]
+    fn accept(&self, _path: &str, _metadata: &Metadata) -> bool {
+        false
+    }
+
+    fn describe(&self) -> String {
+        format!("owner={}", self.0)
+    }
+}
+
+// Every filter must accept a file for the walk to keep it
+#[derive(Clone, Default)]
+pub struct FilterChain(Vec<Arc<dyn FileFilter>>);
+
+impl fmt::Debug for FilterChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|filter| filter.describe()))
+            .finish()
+    }
+}
+
+impl FilterChain {
+    pub fn push(&mut self, filter: Arc<dyn FileFilter>) {
+        self.0.push(filter);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn accepts(&self, path: &str, metadata: &Metadata) -> bool {
+        self.0.iter().all(|filter| filter.accept(path, metadata))
+    }
+
+    // Only stats the entry when there is something to check; entries that can't be stat'ed are dropped
+    pub fn accepts_entry(&self, path: &str, entry: &walkdir::DirEntry) -> bool {
+        self.is_empty() || entry.metadata().is_ok_and(|m| self.accepts(path, &m))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn user_id(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    (!entry.is_null()).then(|| unsafe { (*entry).pw_uid })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn user_id(_name: &str) -> Option<u32> {
+    None
+}
+
+/*  One --filter expression: pattern=TEXT, size>4k, size<1g, age>90d, age<2w or owner=NAME (or a
+numeric user id). Sizes use binary suffixes and ages the d/w/y units of profiles.
+*/
+pub fn parse_filter(value: &str) -> Result<Arc<dyn FileFilter>, String> {
+    let invalid = || {
+        format!(
+            "invalid filter '{}' (expected pattern=TEXT, size>SIZE, size<SIZE, age>AGE, age<AGE or owner=USER)",
+            value
+        )
+    };
+    let split = value.find(['=', '>', '<']).ok_or_else(invalid)?;
+    let (field, rest) = value.split_at(split);
+    let (operator, operand) = rest.split_at(1);
+    let age = |operand: &str| crate::profile::parse_age(operand).map(Duration::from_secs);
+
+    Ok(match (field.trim(), operator) {
+        ("pattern", "=") => Arc::new(Pattern(operand.to_string())),
+        ("size", ">") => Arc::new(Size {
+            larger_than: Some(crate::generate::parse_size(operand)?),
+            smaller_than: None,
+        }),
+        ("size", "<") => Arc::new(Size {
+            larger_than: None,
+            smaller_than: Some(crate::generate::parse_size(operand)?),
+        }),
+        ("age", ">") => Arc::new(Age {
+            older_than: Some(age(operand)?),
+            newer_than: None,
+        }),
+        ("age", "<") => Arc::new(Age {
+            older_than: None,
+            newer_than: Some(age(operand)?),
+        }),
+        ("owner", "=") => {
+            let uid = match operand.parse() {
+                Ok(uid) => uid,
+                Err(_) => user_id(operand).ok_or_else(|| format!("unknown user '{}'", operand))?,
+            };
+            Arc::new(Owner(uid))
+        }
+        _ => return Err(invalid()),
+    })
+}
@@ -0,0 +1,156 @@
+//reproducible synthetic trees with a controlled share of duplicate files
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Files per generated directory, and directories per parent
+const FILES_PER_DIR: u64 = 100;
+const DIRS_PER_PARENT: u64 = 64;
+// Room for the file index, which keeps every unique file unique
+const MIN_SIZE: u64 = 16;
+
+#[derive(Debug, Clone)]
+pub struct TreeSpec {
+    pub files: u64,
+    // Share of files that are copies of another generated file
+    pub dupe_ratio: f64,
+    // Average file size; sizes vary between half and one and a half times this
+    pub avg_size: u64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TreeSummary {
+    pub files: u64,
+    pub unique: u64,
+    pub duplicates: u64,
+    pub groups: u64,
+    pub total_bytes: u64,
+}
+
+// splitmix64: tiny, fast and identical on every platform, which is all a fixture or a sample needs
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+fn parse_scaled(value: &str, unit: u64) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, scale) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], unit),
+        Some((i, 'm' | 'M')) => (&value[..i], unit * unit),
+        Some((i, 'g' | 'G')) => (&value[..i], unit * unit * unit),
+        _ => (value, 1),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid number '{}'", value))?;
+    if number < 0.0 {
+        return Err(format!("'{}' must not be negative", value));
+    }
+    Ok((number * scale as f64).round() as u64)
+}
+
+// Counts use decimal suffixes: 100k = 100,000
+pub fn parse_count(value: &str) -> Result<u64, String> {
+    parse_scaled(value, 1000)
+}
+
+// Sizes use binary suffixes: 4k = 4,096 bytes; a trailing B or iB is allowed too (10MB, 1GiB, 512B)
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let trimmed = value.trim();
+    let number = match trimmed.strip_suffix(['B', 'b']) {
+        Some(rest) => rest.strip_suffix('i').unwrap_or(rest),
+        None => trimmed,
+    };
+    parse_scaled(number, 1024)
+}
+
+// Where file number index goes: dNNN/sNN/, FILES_PER_DIR files to a directory
+fn file_path(root: &Path, index: u64) -> std::path::PathBuf {
+    let dir = index / FILES_PER_DIR;
+    root.join(format!("d{:03}", dir / DIRS_PER_PARENT))
+        .join(format!("s{:02}", dir % DIRS_PER_PARENT))
+        .join(format!("f{:07}.bin", index))
+}
+
+fn file_content(seed: u64, index: u64, size: u64) -> Vec<u8> {
+    let mut rng = Rng(seed ^ index.wrapping_mul(0xA24B_AED4_963E_E407));
+    let mut content = Vec::with_capacity(size as usize + 8);
+    content.extend_from_slice(&index.to_le_bytes());
+    while (content.len() as u64) < size {
+        content.extend_from_slice(&rng.next().to_le_bytes());
+    }
+    content.truncate(size as usize);
+    content
+}
+
+/*  Write spec.files files under root. The same spec always produces the same tree: file i either
+holds content of its own or, for the duplicate share, a copy of an earlier unique file.
+*/
+pub fn generate_tree(root: &Path, spec: &TreeSpec) -> Result<TreeSummary, Box<dyn Error>> {
+    if !(0.0..1.0).contains(&spec.dupe_ratio) {
+        return Err(format!("--dupe-ratio must be in [0, 1), got {}", spec.dupe_ratio).into());
+    }
+    if root.exists() && fs::read_dir(root)?.next().is_some() {
+        return Err(format!("{} is not empty", root.display()).into());
+    }
+
+    let duplicates = (spec.files as f64 * spec.dupe_ratio).round() as u64;
+    let unique = spec.files - duplicates;
+    if unique == 0 && spec.files > 0 {
+        return Err("--dupe-ratio leaves no unique file to copy".into());
+    }
+
+    let mut rng = Rng(spec.seed);
+    let avg_size = spec.avg_size.max(MIN_SIZE);
+    let mut summary = TreeSummary {
+        files: spec.files,
+        unique,
+        duplicates,
+        ..Default::default()
+    };
+
+    // Which unique file each position copies; None means it is a unique file itself
+    let mut sources: Vec<Option<u64>> = (0..spec.files).map(|_| None).collect();
+    let mut unique_ids: Vec<u64> = Vec::with_capacity(unique as usize);
+    let mut positions: Vec<u64> = (0..spec.files).collect();
+    // Fisher-Yates, so duplicates end up scattered across the tree
+    for i in (1..positions.len()).rev() {
+        positions.swap(i, rng.below(i as u64 + 1) as usize);
+    }
+    unique_ids.extend(positions[..unique as usize].iter().copied());
+    unique_ids.sort();
+    for position in &positions[unique as usize..] {
+        sources[*position as usize] = Some(unique_ids[rng.below(unique) as usize]);
+    }
+
+    let mut copied = std::collections::HashSet::new();
+    for index in 0..spec.files {
+        let source = sources[index as usize].unwrap_or(index);
+        if source != index {
+            copied.insert(source);
+        }
+
+        // Sizes come from their own stream so they don't depend on the generation order
+        let size = avg_size / 2 + Rng(spec.seed ^ source).below(avg_size + 1);
+        let path = file_path(root, index);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, file_content(spec.seed, source, size))?;
+        summary.total_bytes += size;
+    }
+    summary.groups = copied.len() as u64;
+
+    Ok(summary)
+}
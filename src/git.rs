@@ -0,0 +1,67 @@
+//git awareness: locating repositories and files that match their committed version
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Nearest enclosing repository (a directory holding a .git entry), if any
+pub fn repository_root(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/*  Destructive actions must check this and refuse to touch files inside a
+repository unless the user explicitly forces it
+*/
+pub fn inside_repository(path: &Path) -> bool {
+    repository_root(path).is_some()
+}
+
+fn git_paths(repo: &Path, args: &[&str]) -> Result<Vec<String>, Box<dyn Error>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed in {}: {}",
+            args.join(" "),
+            repo.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(output
+        .stdout
+        .split(|b| *b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).to_string())
+        .collect())
+}
+
+/*  Absolute paths of files in the repository that are identical to their committed (HEAD)
+version. Those can always be restored with git, so they're not worth reporting.
+*/
+pub fn reproducible_files(repo: &Path) -> Result<HashSet<PathBuf>, Box<dyn Error>> {
+    let repo = repo.canonicalize()?;
+
+    // A fresh repository without commits has nothing reproducible
+    let tracked = match git_paths(&repo, &["ls-tree", "-r", "-z", "--name-only", "HEAD"]) {
+        Ok(tracked) => tracked,
+        Err(_) => return Ok(HashSet::new()),
+    };
+    let modified: HashSet<String> = git_paths(&repo, &["diff", "-z", "--name-only", "HEAD"])?
+        .into_iter()
+        .collect();
+
+    Ok(tracked
+        .into_iter()
+        .filter(|path| !modified.contains(path))
+        .map(|path| repo.join(path))
+        .collect())
+}
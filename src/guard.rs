@@ -0,0 +1,92 @@
+//allowlisted roots every destructive action must stay inside
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+static GUARD: OnceLock<Guard> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct Guard {
+    allowed: Vec<PathBuf>,
+    scanned: Vec<PathBuf>,
+    // Acknowledges acting inside an allowed root but outside the scanned directories
+    force: bool,
+}
+
+impl Guard {
+    // Roots are resolved once, so later symlink or .. tricks in victim paths can't widen them
+    pub fn new(
+        allowed: &[PathBuf],
+        scanned: &[String],
+        force: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut resolved = Vec::new();
+        for root in allowed {
+            let canonical = root
+                .canonicalize()
+                .map_err(|e| format!("allowed root {}: {}", root.display(), e))?;
+            if !canonical.is_dir() {
+                return Err(format!("allowed root {} is not a directory", root.display()).into());
+            }
+            resolved.push(canonical);
+        }
+
+        Ok(Guard {
+            allowed: resolved,
+            scanned: scanned
+                .iter()
+                .filter_map(|root| Path::new(root).canonicalize().ok())
+                .collect(),
+            force,
+        })
+    }
+
+    // Why path must not be changed, if it must not
+    pub fn refusal(&self, path: &Path) -> Option<String> {
+        let Some(resolved) = resolve(path) else {
+            return Some("cannot resolve its directory".to_string());
+        };
+        if !self.allowed.iter().any(|root| resolved.starts_with(root)) {
+            return Some("outside the allowed roots".to_string());
+        }
+        if !self.force && !self.scanned.iter().any(|root| resolved.starts_with(root)) {
+            return Some("outside the scanned directories, use --force".to_string());
+        }
+        None
+    }
+}
+
+/*  The path with its directory canonicalized. The file itself is not followed: acting on a
+symlink replaces the link, not what it points to.
+*/
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    Some(parent.canonicalize().ok()?.join(name))
+}
+
+// Only the first guard of a run counts
+pub fn install(guard: Guard) {
+    let _ = GUARD.set(guard);
+}
+
+// Fail before any change is made when no allowed roots were given
+pub fn require() -> Result<(), Box<dyn Error>> {
+    match GUARD.get() {
+        Some(_) => Ok(()),
+        None => Err(
+            "refusing to change files without --allow-root; name the directories rdedupe may modify"
+                .into(),
+        ),
+    }
+}
+
+pub fn check(path: &Path) -> Result<(), String> {
+    match GUARD.get() {
+        None => Err("no --allow-root given".to_string()),
+        Some(guard) => guard.refusal(path).map_or(Ok(()), Err),
+    }
+}
@@ -0,0 +1,159 @@
+//content hash algorithms a scan can group by, every file streamed in fixed-size chunks
+use crate::{progress, throttle};
+use sha2::Digest as _;
+use std::fs;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static SELECTED: AtomicU8 = AtomicU8::new(Hasher::Md5 as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Hasher {
+    // The historical default, fast but open to crafted collisions
+    #[default]
+    Md5,
+    Blake3,
+    Sha256,
+    // Not cryptographic: only for trees nobody is trying to fool
+    Xxhash64,
+}
+
+// The running state of one file's hash
+enum State {
+    Md5(md5::Context),
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+    Xxhash64(xxhash_rust::xxh64::Xxh64),
+}
+
+impl State {
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            State::Md5(context) => context.consume(chunk),
+            State::Blake3(hasher) => {
+                hasher.update(chunk);
+            }
+            State::Sha256(hasher) => hasher.update(chunk),
+            State::Xxhash64(hasher) => hasher.update(chunk),
+        }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        match self {
+            State::Md5(context) => context.compute().0.to_vec(),
+            State::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+            State::Sha256(hasher) => hasher.finalize().to_vec(),
+            State::Xxhash64(hasher) => hasher.digest().to_be_bytes().to_vec(),
+        }
+    }
+}
+
+impl Hasher {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Hasher::Md5 => "md5",
+            Hasher::Blake3 => "blake3",
+            Hasher::Sha256 => "sha256",
+            Hasher::Xxhash64 => "xxhash64",
+        }
+    }
+
+    // As printed in reports
+    pub fn label(self) -> &'static str {
+        match self {
+            Hasher::Md5 => "MD5",
+            Hasher::Blake3 => "BLAKE3",
+            Hasher::Sha256 => "SHA-256",
+            Hasher::Xxhash64 => "xxHash64",
+        }
+    }
+
+    fn start(self) -> State {
+        match self {
+            Hasher::Md5 => State::Md5(md5::Context::new()),
+            Hasher::Blake3 => State::Blake3(Box::default()),
+            Hasher::Sha256 => State::Sha256(sha2::Sha256::new()),
+            Hasher::Xxhash64 => State::Xxhash64(xxhash_rust::xxh64::Xxh64::new(0)),
+        }
+    }
+
+    pub fn hash_bytes(self, bytes: &[u8]) -> Vec<u8> {
+        let mut state = self.start();
+        state.update(bytes);
+        state.finish()
+    }
+
+    /*  Hash path in throttle::CHUNK sized blocks, so memory stays flat whatever the file size.
+    Large files get a byte progress line of their own.
+    */
+    pub fn hash_file(self, path: &str) -> io::Result<Vec<u8>> {
+        let len = fs::metadata(path)?.len();
+        let bar = (len >= progress::LARGE_FILE).then(|| progress::file_bytes_bar(path, len));
+        let mut state = self.start();
+        let result = throttle::read_chunks(path, |chunk| {
+            state.update(chunk);
+            if let Some(bar) = &bar {
+                bar.inc(chunk.len() as u64);
+            }
+        });
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
+        }
+        result?;
+        Ok(state.finish())
+    }
+
+    // Hash everything reader yields, such as a decompressed stream, in throttle::CHUNK sized blocks
+    pub fn hash_reader(self, mut reader: impl Read) -> io::Result<Vec<u8>> {
+        let mut state = self.start();
+        let mut buffer = vec![0u8; throttle::CHUNK];
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => break,
+                read => state.update(&buffer[..read]),
+            }
+        }
+        Ok(state.finish())
+    }
+}
+
+pub fn parse_hasher(value: &str) -> Result<Hasher, String> {
+    match value {
+        "md5" => Ok(Hasher::Md5),
+        "blake3" => Ok(Hasher::Blake3),
+        "sha256" => Ok(Hasher::Sha256),
+        "xxhash64" => Ok(Hasher::Xxhash64),
+        _ => Err(format!(
+            "unknown hash '{}' (expected md5, blake3, sha256 or xxhash64)",
+            value
+        )),
+    }
+}
+
+// The algorithm every content hash of the run uses
+pub fn set_hasher(hasher: Hasher) {
+    SELECTED.store(hasher as u8, Ordering::Relaxed);
+}
+
+pub fn selected() -> Hasher {
+    match SELECTED.load(Ordering::Relaxed) {
+        1 => Hasher::Blake3,
+        2 => Hasher::Sha256,
+        3 => Hasher::Xxhash64,
+        _ => Hasher::Md5,
+    }
+}
+
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
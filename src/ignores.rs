@@ -0,0 +1,133 @@
+//ignore files and hidden files during the walk: .gitignore and .rdedupeignore rules, dotfiles
+use crate::WalkOptions;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Read in every walked directory, in this order, so .rdedupeignore rules win over .gitignore ones
+pub const FILES: [&str; 2] = [".gitignore", ".rdedupeignore"];
+
+#[derive(Debug, Clone)]
+struct Rule {
+    regex: Regex,
+    // A leading ! re-includes what an earlier rule ignored
+    negate: bool,
+    // A trailing / only matches directories
+    dir_only: bool,
+}
+
+/*  The rules of one directory's ignore files, in gitignore syntax: # comments, ! to negate, a
+trailing / for directories only. A pattern with a / before its end is relative to the directory,
+any other matches at every depth below it. * and ? stay within one path component, ** spans any
+number of them.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreFile {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreFile {
+    // Lines that aren't valid globs are skipped
+    pub fn parse(text: &str) -> Self {
+        let mut rules = Vec::new();
+        for line in text.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negate, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dir_only, pattern) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            if pattern.is_empty() {
+                continue;
+            }
+            // Matched against the path below the directory with a leading /, see is_ignored
+            let anchored = match pattern.contains('/') && !pattern.starts_with('/') {
+                true => format!("/{}", pattern),
+                false => pattern.to_string(),
+            };
+            if let Ok(regex) = crate::matcher::glob_regex(&anchored) {
+                rules.push(Rule {
+                    regex,
+                    negate,
+                    dir_only,
+                });
+            }
+        }
+        IgnoreFile { rules }
+    }
+
+    // Some(true) if the last rule matching relative ignores it, Some(false) if it re-includes it
+    fn decide(&self, relative: &str, is_dir: bool) -> Option<bool> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.regex.is_match(relative))
+            .map(|rule| !rule.negate)
+    }
+}
+
+/*  The ignore files met so far in one walk, by directory. Only files in the walk root and below
+are read, not those of the directories above it. A deeper file's rules win over a shallower
+one's, and within a file the last matching rule wins.
+*/
+#[derive(Debug, Default)]
+pub struct Ignores {
+    files: HashMap<PathBuf, IgnoreFile>,
+}
+
+impl Ignores {
+    fn load(&mut self, dir: &Path) {
+        let mut ignore = IgnoreFile::default();
+        for name in FILES {
+            if let Ok(text) = fs::read_to_string(dir.join(name)) {
+                ignore.rules.extend(IgnoreFile::parse(&text).rules);
+            }
+        }
+        if !ignore.rules.is_empty() {
+            self.files.insert(dir.to_path_buf(), ignore);
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut dir = path.parent();
+        while let Some(base) = dir {
+            if let Some(ignore) = self.files.get(base) {
+                if let Ok(relative) = path.strip_prefix(base) {
+                    let relative = format!("/{}", relative.to_string_lossy());
+                    if let Some(ignored) = ignore.decide(&relative, is_dir) {
+                        return ignored;
+                    }
+                }
+            }
+            dir = base.parent();
+        }
+        false
+    }
+
+    /*  For filter_entry: whether to leave out entry, and so everything below it, under the hidden
+    and ignore file options. A directory that is kept has its ignore files read before its
+    entries are filtered. .git directories are left to include_git.
+    */
+    pub fn skips(&mut self, entry: &walkdir::DirEntry, options: &WalkOptions) -> bool {
+        if entry.depth() > 0 {
+            let name = entry.file_name().to_string_lossy();
+            if options.skip_hidden && name.starts_with('.') && name != ".git" {
+                return true;
+            }
+            if options.ignore_files && self.is_ignored(entry.path(), entry.file_type().is_dir()) {
+                return true;
+            }
+        }
+        if options.ignore_files && entry.file_type().is_dir() {
+            self.load(entry.path());
+        }
+        false
+    }
+}
@@ -0,0 +1,342 @@
+//compact checksum index: binary digests and prefix-interned paths instead of hex strings and owned paths
+use std::collections::HashMap;
+use std::io;
+use std::path::MAIN_SEPARATOR;
+
+pub type PathId = u32;
+
+// The longest digest a hasher::Hasher produces (BLAKE3 and SHA-256)
+const MAX_DIGEST: usize = 32;
+
+// A content digest of any hasher::Hasher, kept inline at its full length so hash maps stay flat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    len: u8,
+    bytes: [u8; MAX_DIGEST],
+}
+
+impl Digest {
+    pub fn from_bytes(bytes: &[u8]) -> Option<Digest> {
+        if bytes.len() > MAX_DIGEST {
+            return None;
+        }
+        let mut digest = Digest {
+            len: bytes.len() as u8,
+            bytes: [0u8; MAX_DIGEST],
+        };
+        digest.bytes[..bytes.len()].copy_from_slice(bytes);
+        Some(digest)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+// The content hash of path under the selected algorithm, as crate::content_hash but in binary
+pub fn digest(path: &str) -> io::Result<Digest> {
+    let hasher = crate::hasher::selected();
+    let bytes = if crate::cache::describe().is_some()
+        || crate::normalize::enabled()
+        || crate::compressed::decompressing(path).is_some()
+    {
+        crate::hasher::from_hex(&crate::content_hash(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed cached digest"))?
+    } else {
+        hasher.hash_file(path)?
+    };
+    Digest::from_bytes(&bytes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "digest too long"))
+}
+
+pub fn to_hex(digest: &Digest) -> String {
+    crate::hasher::to_hex(digest.as_bytes())
+}
+
+pub fn from_hex(hex: &str) -> Option<Digest> {
+    Digest::from_bytes(&crate::hasher::from_hex(hex)?)
+}
+
+const NO_DIR: u32 = u32::MAX;
+
+// One name in the table: its bytes in the shared buffer and the directory it sits in
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    start: usize,
+    len: u32,
+    parent: u32,
+}
+
+/*  Paths stored as an interned directory plus a file name. Each directory is kept once, as its own
+name under its parent, so a deep tree costs one name per path instead of the full path every time.
+Ids are u32; directory lookups are cached for the last directory since walks visit files in
+directory order.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct PathTable {
+    names: String,
+    dirs: Vec<Node>,
+    files: Vec<Node>,
+    // (parent directory, name) -> directory id
+    lookup: HashMap<(u32, Box<str>), u32>,
+    lookup_bytes: usize,
+    last_dir: Option<(String, u32)>,
+}
+
+impl PathTable {
+    fn push_name(&mut self, name: &str, parent: u32) -> Node {
+        let node = Node {
+            start: self.names.len(),
+            len: name.len() as u32,
+            parent,
+        };
+        self.names.push_str(name);
+        node
+    }
+
+    fn name(&self, node: &Node) -> &str {
+        &self.names[node.start..node.start + node.len as usize]
+    }
+
+    // Intern a directory path (e.g. a walk root) and return its id for push_dir/push_file
+    pub fn intern_dir(&mut self, dir: &str) -> u32 {
+        if let Some((last, id)) = &self.last_dir {
+            if last == dir {
+                return *id;
+            }
+        }
+
+        let (parent, name) = match dir.rsplit_once(MAIN_SEPARATOR) {
+            Some((parent, name)) => (self.intern_dir(parent), name),
+            None => (NO_DIR, dir),
+        };
+
+        let key = (parent, Box::from(name));
+        let id = match self.lookup.get(&key) {
+            Some(id) => *id,
+            None => {
+                let node = self.push_name(name, parent);
+                self.dirs.push(node);
+                let id = (self.dirs.len() - 1) as u32;
+                self.lookup_bytes += name.len();
+                self.lookup.insert(key, id);
+                id
+            }
+        };
+
+        self.last_dir = Some((dir.to_string(), id));
+        id
+    }
+
+    pub fn intern(&mut self, path: &str) -> PathId {
+        match path.rsplit_once(MAIN_SEPARATOR) {
+            Some((dir, name)) => {
+                let dir = self.intern_dir(dir);
+                self.push_file(Some(dir), name)
+            }
+            None => self.push_file(None, path),
+        }
+    }
+
+    /*  Add a directory by name under an already known parent. Used by walks, which visit parents
+    first and so never need the full path; the lookup is skipped, so each directory must be
+    pushed once.
+    */
+    pub fn push_dir(&mut self, parent: u32, name: &str) -> u32 {
+        let node = self.push_name(name, parent);
+        self.dirs.push(node);
+        (self.dirs.len() - 1) as u32
+    }
+
+    pub fn push_file(&mut self, dir: Option<u32>, name: &str) -> PathId {
+        let node = self.push_name(name, dir.unwrap_or(NO_DIR));
+        self.files.push(node);
+        (self.files.len() - 1) as PathId
+    }
+
+    fn write_dir(&self, dir: u32, out: &mut String) {
+        let node = &self.dirs[dir as usize];
+        if node.parent != NO_DIR {
+            self.write_dir(node.parent, out);
+            out.push(MAIN_SEPARATOR);
+        }
+        out.push_str(self.name(node));
+    }
+
+    // Write the full path behind an id into out, replacing its content, so callers can reuse one buffer
+    pub fn write_path(&self, id: PathId, out: &mut String) {
+        out.clear();
+        let node = &self.files[id as usize];
+        if node.parent != NO_DIR {
+            self.write_dir(node.parent, out);
+            out.push(MAIN_SEPARATOR);
+        }
+        out.push_str(self.name(node));
+    }
+
+    // Rebuild the full path behind an id
+    pub fn get(&self, id: PathId) -> String {
+        let mut path = String::new();
+        self.write_path(id, &mut path);
+        path
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn directories(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        let slot = std::mem::size_of::<(u32, Box<str>)>() + std::mem::size_of::<u32>() + 1;
+        self.names.capacity()
+            + (self.dirs.capacity() + self.files.capacity()) * std::mem::size_of::<Node>()
+            + self.lookup.capacity() * slot
+            + self.lookup_bytes
+    }
+}
+
+/*  Paths grouped by content digest. Most files are unique, so the first path of every digest is
+stored inline and only digests seen again get a vector of further paths.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumIndex {
+    paths: PathTable,
+    first: HashMap<Digest, PathId>,
+    more: HashMap<Digest, Vec<PathId>>,
+    // Paths held in the overflow lists
+    overflow: usize,
+}
+
+impl ChecksumIndex {
+    // An index over paths collected up front, e.g. by crate::walk_table; add them with insert_id
+    pub fn from_paths(paths: PathTable) -> Self {
+        ChecksumIndex {
+            paths,
+            ..Default::default()
+        }
+    }
+
+    pub fn paths(&self) -> &PathTable {
+        &self.paths
+    }
+
+    // Tie groups built with insert_id to the table their ids point into
+    pub fn set_paths(&mut self, paths: PathTable) {
+        self.paths = paths;
+    }
+
+    pub fn insert(&mut self, digest: Digest, path: &str) {
+        let id = self.paths.intern(path);
+        self.insert_id(digest, id);
+    }
+
+    pub fn insert_id(&mut self, digest: Digest, id: PathId) {
+        if let Some(existing) = self.first.insert(digest, id) {
+            // Keep the first path inline, the newcomer joins the overflow list
+            self.first.insert(digest, existing);
+            self.more.entry(digest).or_default().push(id);
+            self.overflow += 1;
+        }
+    }
+
+    // Put every group back in path id order, whatever order insert_id saw its paths in
+    pub fn sort_groups(&mut self) {
+        for (digest, more) in self.more.iter_mut() {
+            let Some(first) = self.first.get_mut(digest) else {
+                continue;
+            };
+            more.push(*first);
+            more.sort_unstable();
+            *first = more.remove(0);
+        }
+    }
+
+    // Number of paths indexed
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    // Number of distinct digests
+    pub fn unique(&self) -> usize {
+        self.first.len()
+    }
+
+    pub fn path(&self, id: PathId) -> String {
+        self.paths.get(id)
+    }
+
+    // All paths holding this content, in insertion order
+    pub fn get(&self, digest: &Digest) -> Vec<String> {
+        let mut paths = Vec::new();
+        if let Some(first) = self.first.get(digest) {
+            paths.push(self.path(*first));
+            for id in self.more.get(digest).into_iter().flatten() {
+                paths.push(self.path(*id));
+            }
+        }
+        paths
+    }
+
+    // Every digest held by more than one path, with those paths
+    pub fn duplicates(&self) -> impl Iterator<Item = (&Digest, Vec<String>)> + '_ {
+        self.more
+            .keys()
+            .map(move |digest| (digest, self.get(digest)))
+    }
+
+    // The duplicate groups on their own, for callers that outlive the index
+    pub fn duplicate_groups(&self) -> Vec<Vec<String>> {
+        self.duplicates().map(|(_, paths)| paths).collect()
+    }
+
+    // Approximate heap use, counting hash map slots at their key and value size; cheap enough per insert
+    pub fn memory_bytes(&self) -> usize {
+        self.paths.memory_bytes() + self.groups_memory_bytes()
+    }
+
+    // Heap use of the digest groups alone, without the path table
+    pub fn groups_memory_bytes(&self) -> usize {
+        let slot = std::mem::size_of::<Digest>() + std::mem::size_of::<PathId>() + 1;
+        let more_slot = std::mem::size_of::<Digest>() + std::mem::size_of::<Vec<PathId>>() + 1;
+        self.first.capacity() * slot
+            + self.more.capacity() * more_slot
+            + self.overflow * std::mem::size_of::<PathId>()
+    }
+
+    pub fn clear(&mut self) {
+        *self = ChecksumIndex::default();
+    }
+
+    // Forget every digest but keep the path table, so ids stay valid
+    pub fn clear_groups(&mut self) {
+        self.first = HashMap::new();
+        self.more = HashMap::new();
+        self.overflow = 0;
+    }
+
+    // Every (digest, path id) pair, grouped by digest
+    pub fn entry_ids(&self) -> impl Iterator<Item = (&Digest, PathId)> + '_ {
+        self.first.iter().flat_map(move |(digest, first)| {
+            std::iter::once(*first)
+                .chain(self.more.get(digest).into_iter().flatten().copied())
+                .map(move |id| (digest, id))
+        })
+    }
+
+    // Every (digest, path) pair, grouped by digest
+    pub fn entries(&self) -> impl Iterator<Item = (&Digest, String)> + '_ {
+        self.entry_ids()
+            .map(move |(digest, id)| (digest, self.path(id)))
+    }
+}
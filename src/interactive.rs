@@ -0,0 +1,387 @@
+//terminal interface for picking the copies to keep in each duplicate group and queueing the rest
+use crate::audit;
+use crate::rules::{Disposition, PlannedAction};
+use crate::scan::{DedupeReport, DuplicateGroup, FileEntry};
+use crate::tui::Choice;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::error::Error;
+use std::io::IsTerminal;
+use std::time::UNIX_EPOCH;
+
+const HELP: &str = "↑/↓ move  tab switch list  space keep/drop copy  d delete  h hardlink  s symlink  x leave group  a apply  q quit";
+
+// Recorded as the rule of every queued action, in the audit log and plan output
+const RULE: &str = "interactive";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pane {
+    Groups,
+    Files,
+}
+
+// A group under review: the copies that stay and what happens to the others
+struct Marked {
+    group: DuplicateGroup,
+    kept: Vec<bool>,
+    // Keep leaves the whole group alone
+    action: Disposition,
+}
+
+impl Marked {
+    fn keeper(&self) -> Option<&FileEntry> {
+        self.group
+            .files
+            .iter()
+            .zip(&self.kept)
+            .find(|(_, kept)| **kept)
+            .map(|(file, _)| file)
+    }
+
+    fn dropped(&self) -> impl Iterator<Item = &FileEntry> {
+        self.group
+            .files
+            .iter()
+            .zip(&self.kept)
+            .filter(|(_, kept)| !**kept)
+            .map(|(file, _)| file)
+    }
+}
+
+pub struct GroupReview {
+    groups: Vec<Marked>,
+    group_state: ListState,
+    file_state: ListState,
+    pane: Pane,
+    // A choice waiting for y/n
+    pending: Option<Choice>,
+}
+
+fn colored(line: String, action: Disposition) -> ListItem<'static> {
+    match action {
+        Disposition::Delete => ListItem::new(line).red(),
+        Disposition::Hardlink => ListItem::new(line).yellow(),
+        Disposition::Symlink => ListItem::new(line).cyan(),
+        Disposition::Keep => ListItem::new(line),
+    }
+}
+
+fn modified(file: &FileEntry) -> String {
+    file.modified
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(
+            || "unknown".to_string(),
+            |d| audit::format_time(d.as_secs()),
+        )
+}
+
+impl GroupReview {
+    // Every group starts left alone, keeping its first copy by path
+    pub fn new(report: DedupeReport) -> Self {
+        let groups: Vec<Marked> = report
+            .groups
+            .into_iter()
+            .map(|group| {
+                let mut kept = vec![false; group.files.len()];
+                kept[0] = true;
+                Marked {
+                    group,
+                    kept,
+                    action: Disposition::Keep,
+                }
+            })
+            .collect();
+        let selected = (!groups.is_empty()).then_some(0);
+        GroupReview {
+            groups,
+            group_state: ListState::default().with_selected(selected),
+            file_state: ListState::default().with_selected(selected),
+            pane: Pane::Groups,
+            pending: None,
+        }
+    }
+
+    pub fn pane(&self) -> Pane {
+        self.pane
+    }
+
+    // (group, file) under the cursor
+    pub fn selected(&self) -> Option<(usize, usize)> {
+        Some((self.group_state.selected()?, self.file_state.selected()?))
+    }
+
+    /*  The action queue: every copy not kept in a group with an action, against the group's first
+    kept copy, ready for rules::apply.
+    */
+    pub fn queue(&self) -> Vec<PlannedAction> {
+        let mut queue = Vec::new();
+        for marked in self.groups.iter().filter(|m| m.action != Disposition::Keep) {
+            let Some(keeper) = marked.keeper() else {
+                continue;
+            };
+            queue.extend(marked.dropped().map(|file| PlannedAction {
+                group: marked.group.hash.clone(),
+                keep: keeper.path.clone(),
+                path: file.path.clone(),
+                action: marked.action,
+                size_bytes: file.size_bytes,
+                rule: RULE.to_string(),
+            }));
+        }
+        queue
+    }
+
+    fn current(&mut self) -> Option<&mut Marked> {
+        self.group_state
+            .selected()
+            .and_then(|index| self.groups.get_mut(index))
+    }
+
+    fn set_action(&mut self, action: Disposition) {
+        if let Some(marked) = self.current() {
+            marked.action = action;
+        }
+    }
+
+    // The last kept copy of a group can't be dropped
+    fn toggle_kept(&mut self) {
+        let Some(file) = self.file_state.selected() else {
+            return;
+        };
+        if let Some(marked) = self.current() {
+            let kept = marked.kept.iter().filter(|kept| **kept).count();
+            if let Some(flag) = marked.kept.get_mut(file) {
+                if !*flag || kept > 1 {
+                    *flag = !*flag;
+                }
+            }
+        }
+    }
+
+    fn confirm(&mut self, choice: Choice) -> Option<Choice> {
+        // Nothing queued: quitting loses nothing and applying has nothing to do
+        if self.queue().is_empty() {
+            return (choice == Choice::Quit).then_some(Choice::Quit);
+        }
+        self.pending = Some(choice);
+        None
+    }
+
+    fn move_cursor(&mut self, key: KeyCode) {
+        let (state, last) = match self.pane {
+            Pane::Groups => (&mut self.group_state, self.groups.len()),
+            Pane::Files => {
+                let files = self
+                    .group_state
+                    .selected()
+                    .and_then(|index| self.groups.get(index))
+                    .map_or(0, |marked| marked.group.files.len());
+                (&mut self.file_state, files)
+            }
+        };
+        let before = state.selected();
+        match key {
+            KeyCode::Down | KeyCode::Char('j') => state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => state.select_previous(),
+            KeyCode::PageDown => state.scroll_down_by(10),
+            KeyCode::PageUp => state.scroll_up_by(10),
+            KeyCode::Home => state.select_first(),
+            KeyCode::End => state.select(Some(last.saturating_sub(1))),
+            _ => {}
+        }
+        // select_next can run one past the end until the list is rendered
+        if state.selected().is_some_and(|index| index >= last) {
+            state.select(last.checked_sub(1));
+        }
+        if self.pane == Pane::Groups && self.group_state.selected() != before {
+            self.file_state.select(Some(0));
+        }
+    }
+
+    // Returns the user's choice once one is made
+    pub fn handle(&mut self, key: KeyCode) -> Option<Choice> {
+        if let Some(choice) = self.pending.take() {
+            return matches!(key, KeyCode::Char('y') | KeyCode::Char('Y')).then_some(choice);
+        }
+
+        match key {
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                self.pane = match self.pane {
+                    Pane::Groups => Pane::Files,
+                    Pane::Files => Pane::Groups,
+                }
+            }
+            KeyCode::Char(' ') if self.pane == Pane::Files => self.toggle_kept(),
+            KeyCode::Char('d') => self.set_action(Disposition::Delete),
+            KeyCode::Char('h') => self.set_action(Disposition::Hardlink),
+            KeyCode::Char('s') => self.set_action(Disposition::Symlink),
+            KeyCode::Char('x') => self.set_action(Disposition::Keep),
+            KeyCode::Char('a') => return self.confirm(Choice::Apply),
+            KeyCode::Char('q') | KeyCode::Esc => return self.confirm(Choice::Quit),
+            key => self.move_cursor(key),
+        }
+        None
+    }
+
+    fn group_item(marked: &Marked) -> ListItem<'static> {
+        let group = &marked.group;
+        let line = format!(
+            "{:<8} {} x {:.2} MB  {}",
+            match marked.action {
+                Disposition::Keep => "-",
+                action => action.as_str(),
+            },
+            group.files.len(),
+            group.size_bytes as f64 / 1_048_576.0,
+            group.files[0].path
+        );
+        colored(line, marked.action)
+    }
+
+    fn file_items(marked: &Marked) -> Vec<ListItem<'static>> {
+        marked
+            .group
+            .files
+            .iter()
+            .zip(&marked.kept)
+            .map(|(file, kept)| match (kept, marked.action) {
+                (true, _) => ListItem::new(format!("keep     {}", file.path)).bold(),
+                (false, action) => colored(
+                    format!(
+                        "{:<8} {}",
+                        match action {
+                            Disposition::Keep => "drop",
+                            action => action.as_str(),
+                        },
+                        file.path
+                    ),
+                    action,
+                ),
+            })
+            .collect()
+    }
+
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some((group, file)) = self.selected() else {
+            return vec![Line::from("No duplicate groups")];
+        };
+        let marked = &self.groups[group];
+        let entry = &marked.group.files[file];
+        vec![
+            Line::from(format!("file:     {}", entry.path)),
+            Line::from(format!(
+                "size:     {:.2} MB, modified {}",
+                entry.size_bytes as f64 / 1_048_576.0,
+                modified(entry)
+            )),
+            Line::from(format!("group:    {}", marked.group.hash)),
+            Line::from(format!(
+                "copies:   {} of {} kept, {:.2} MB wasted",
+                marked.kept.iter().filter(|kept| **kept).count(),
+                marked.group.files.len(),
+                marked.group.wasted_bytes() as f64 / 1_048_576.0
+            )),
+        ]
+    }
+
+    fn status(&self) -> String {
+        let queue = self.queue();
+        let count = |disposition: Disposition| {
+            queue
+                .iter()
+                .filter(|action| action.action == disposition)
+                .count()
+        };
+        let reclaimed: u64 = queue.iter().map(|action| action.size_bytes).sum();
+        match self.pending {
+            Some(Choice::Apply) => format!(
+                "Apply {} delete(s), {} hardlink(s) and {} symlink(s) now? y/n",
+                count(Disposition::Delete),
+                count(Disposition::Hardlink),
+                count(Disposition::Symlink)
+            ),
+            Some(_) => format!("Discard {} queued action(s) and quit? y/n", queue.len()),
+            None => format!(
+                "{} to delete, {} to hardlink, {} to symlink, {:.2} MB to reclaim",
+                count(Disposition::Delete),
+                count(Disposition::Hardlink),
+                count(Disposition::Symlink),
+                reclaimed as f64 / 1_048_576.0
+            ),
+        }
+    }
+
+    pub fn render(&mut self, frame: &mut Frame) {
+        let [lists_area, details_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(6),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+        let [groups_area, files_area] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(lists_area);
+
+        let focused = |pane: Pane, title: String| match self.pane == pane {
+            true => Block::bordered().title(title).bold(),
+            false => Block::bordered().title(title),
+        };
+        let groups = List::new(self.groups.iter().map(Self::group_item).collect::<Vec<_>>())
+            .block(focused(
+                Pane::Groups,
+                format!(" {} duplicate group(s) ", self.groups.len()),
+            ))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        let files = self
+            .group_state
+            .selected()
+            .map(|index| Self::file_items(&self.groups[index]))
+            .unwrap_or_default();
+        let files = List::new(files)
+            .block(focused(Pane::Files, " Copies ".to_string()))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(groups, groups_area, &mut self.group_state);
+        frame.render_stateful_widget(files, files_area, &mut self.file_state);
+
+        frame.render_widget(
+            Paragraph::new(self.details()).block(Block::bordered().title(" Selected ")),
+            details_area,
+        );
+        frame.render_widget(
+            Paragraph::new(vec![Line::from(self.status()), Line::from(HELP).dim()]),
+            status_area,
+        );
+    }
+}
+
+// Run the review until the user applies or quits; returns the queued actions
+pub fn review_groups(report: DedupeReport) -> Result<(Choice, Vec<PlannedAction>), Box<dyn Error>> {
+    if !std::io::stdout().is_terminal() {
+        return Err("interactive mode needs an interactive terminal".into());
+    }
+
+    let mut review = GroupReview::new(report);
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<Choice, Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| review.render(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(choice) = review.handle(key.code) {
+                    return Ok(choice);
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+
+    Ok((result?, review.queue()))
+}
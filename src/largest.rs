@@ -0,0 +1,66 @@
+//the biggest files and directories of a walk, no hashing needed
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default)]
+pub struct Largest {
+    pub files: Vec<(String, u64)>,
+    // Directories under the root (the root included) by the total size of the files below them
+    pub directories: Vec<(String, u64)>,
+    pub total_bytes: u64,
+}
+
+fn top(sizes: impl IntoIterator<Item = (String, u64)>, count: usize) -> Vec<(String, u64)> {
+    let mut sizes: Vec<(String, u64)> = sizes.into_iter().collect();
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sizes.truncate(count);
+    sizes
+}
+
+// Files that vanished or can't be read since the walk are left out
+pub fn largest(files: &[String], root: &str, count: usize) -> Largest {
+    let mut sizes = Vec::with_capacity(files.len());
+    let mut directories: HashMap<&Path, u64> = HashMap::new();
+    let root = Path::new(root);
+
+    for file in files {
+        let Ok(metadata) = fs::symlink_metadata(file) else {
+            continue;
+        };
+        let size = metadata.len();
+        sizes.push((file.clone(), size));
+
+        for directory in Path::new(file).ancestors().skip(1) {
+            *directories.entry(directory).or_default() += size;
+            if directory == root {
+                break;
+            }
+        }
+    }
+
+    let total_bytes = sizes.iter().map(|(_, size)| size).sum();
+    let directories = directories
+        .into_iter()
+        .map(|(directory, size)| (directory.to_string_lossy().to_string(), size));
+    Largest {
+        files: top(sizes, count),
+        directories: top(directories, count),
+        total_bytes,
+    }
+}
+
+pub fn print_largest(largest: &Largest) {
+    println!("\n=== Largest Files ===");
+    for (path, size) in &largest.files {
+        println!("{:>12.2} MB  {}", *size as f64 / 1_048_576.0, path);
+    }
+    println!("\n=== Largest Directories ===");
+    for (path, size) in &largest.directories {
+        println!("{:>12.2} MB  {}", *size as f64 / 1_048_576.0, path);
+    }
+    println!(
+        "\nTotal: {:.2} MB",
+        largest.total_bytes as f64 / 1_048_576.0
+    );
+}
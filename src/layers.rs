@@ -0,0 +1,365 @@
+//container image layer analysis: finds files duplicated across layers and images
+use polars::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
+use walkdir::WalkDir;
+
+// One regular file stored in a layer
+#[derive(Debug, Clone)]
+pub struct LayerFile {
+    pub image: String,
+    pub layer: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub hash: String,
+}
+
+// A file content stored in more than one layer
+#[derive(Debug, Clone)]
+pub struct LayerDuplicate {
+    pub hash: String,
+    pub size_bytes: u64,
+    pub copies: Vec<LayerFile>,
+}
+
+impl LayerDuplicate {
+    // Bytes that could be saved by storing the content once
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * (self.copies.len() as u64 - 1)
+    }
+}
+
+// A stream with its first bytes read ahead and put back in front
+type Peeked<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+// Read the first bytes of a stream without losing them
+fn peek<R: Read>(mut reader: R, len: usize) -> io::Result<(Vec<u8>, Peeked<R>)> {
+    let mut head = Vec::with_capacity(len);
+    (&mut reader).take(len as u64).read_to_end(&mut head)?;
+    Ok((head.clone(), Cursor::new(head).chain(reader)))
+}
+
+fn is_gzip(head: &[u8]) -> bool {
+    head.starts_with(&[0x1f, 0x8b])
+}
+
+fn is_tar(head: &[u8]) -> bool {
+    head.len() >= 262 && &head[257..262] == b"ustar"
+}
+
+enum Sniffed<'a> {
+    Tar(Box<dyn Read + 'a>),
+    // Not an archive; the stream still yields the entry content
+    Other(Box<dyn Read + 'a>),
+}
+
+// Transparently gunzip, then check for a tar header
+fn sniff_tar<'a, R: Read + 'a>(reader: R) -> io::Result<Sniffed<'a>> {
+    let (head, reader) = peek(reader, 2)?;
+    let reader: Box<dyn Read + 'a> = if is_gzip(&head) {
+        Box::new(flate2::read::GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    let (head, reader) = peek(reader, 512)?;
+    if !is_tar(&head) {
+        return Ok(Sniffed::Other(Box::new(reader)));
+    }
+
+    Ok(Sniffed::Tar(Box::new(reader)))
+}
+
+fn open_tar<'a, R: Read + 'a>(reader: R) -> io::Result<Option<Box<dyn Read + 'a>>> {
+    match sniff_tar(reader)? {
+        Sniffed::Tar(reader) => Ok(Some(reader)),
+        Sniffed::Other(_) => Ok(None),
+    }
+}
+
+fn clean_path(path: &str) -> String {
+    path.trim_start_matches("./")
+        .trim_start_matches('/')
+        .to_string()
+}
+
+// Passes reads through, counting the bytes
+struct Counted<R> {
+    inner: R,
+    bytes: u64,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes += read as u64;
+        Ok(read)
+    }
+}
+
+// Size and content hash under the selected algorithm, the same as for files on disk
+fn hash_stream<R: Read>(reader: R) -> io::Result<(u64, String)> {
+    let mut counted = Counted {
+        inner: reader,
+        bytes: 0,
+    };
+    let digest = crate::hasher::selected().hash_reader(&mut counted)?;
+    Ok((counted.bytes, crate::hasher::to_hex(&digest)))
+}
+
+// Nested archives that image tarballs use for their layers
+fn looks_like_layer(name: &str) -> bool {
+    name.ends_with("layer.tar") || name.starts_with("blobs/")
+}
+
+const IMAGE_MARKERS: [&str; 3] = ["manifest.json", "index.json", "oci-layout"];
+
+// Hash every regular file of a layer tar stream
+fn scan_layer_stream<R: Read>(
+    reader: R,
+    image: &str,
+    layer: &str,
+    files: &mut Vec<LayerFile>,
+) -> Result<(), Box<dyn Error>> {
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = clean_path(&entry.path()?.to_string_lossy());
+
+        // Whiteouts mark deletions from lower layers, they carry no data
+        let name = Path::new(&path).file_name().unwrap_or_default();
+        if name.to_string_lossy().starts_with(".wh.") {
+            continue;
+        }
+
+        let (size_bytes, hash) = hash_stream(entry)?;
+        files.push(LayerFile {
+            image: image.to_string(),
+            layer: layer.to_string(),
+            path,
+            size_bytes,
+            hash,
+        });
+    }
+
+    Ok(())
+}
+
+/*  Scan a tarball that is either a single layer or a whole image (docker save / OCI layout),
+in which case every nested layer tar becomes its own layer
+*/
+fn scan_tarball(path: &Path, files: &mut Vec<LayerFile>) -> Result<(), Box<dyn Error>> {
+    let image = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let reader = match open_tar(BufReader::new(File::open(path)?))? {
+        Some(reader) => reader,
+        None => return Err(format!("{} is not a tar archive", path.display()).into()),
+    };
+
+    let mut outer = Vec::new();
+    let mut is_image = false;
+    let mut archive = tar::Archive::new(reader);
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let name = clean_path(&entry.path()?.to_string_lossy());
+        if IMAGE_MARKERS.contains(&name.as_str()) {
+            is_image = true;
+        }
+
+        let content: Box<dyn Read> = if looks_like_layer(&name) {
+            match sniff_tar(entry)? {
+                Sniffed::Tar(layer) => {
+                    scan_layer_stream(layer, &image, &name, files)?;
+                    continue;
+                }
+                Sniffed::Other(content) => content,
+            }
+        } else {
+            Box::new(entry)
+        };
+
+        let (size_bytes, hash) = hash_stream(content)?;
+        outer.push(LayerFile {
+            image: image.clone(),
+            layer: image.clone(),
+            path: name,
+            size_bytes,
+            hash,
+        });
+    }
+
+    // In an image tarball the top-level files are metadata, not layer content
+    if !is_image {
+        files.extend(outer);
+    }
+
+    Ok(())
+}
+
+// Scan a directory: an extracted OCI layout, or one extracted layer per subdirectory
+fn scan_directory(path: &Path, files: &mut Vec<LayerFile>) -> Result<(), Box<dyn Error>> {
+    let image = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+
+    let is_layout = IMAGE_MARKERS.iter().any(|m| path.join(m).is_file());
+    if is_layout {
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry?;
+            let relative = entry
+                .path()
+                .strip_prefix(path)?
+                .to_string_lossy()
+                .to_string();
+            if !entry.file_type().is_file() || !looks_like_layer(&relative) {
+                continue;
+            }
+            if let Some(layer) = open_tar(BufReader::new(File::open(entry.path())?))? {
+                scan_layer_stream(layer, &image, &relative, files)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut children: Vec<_> = fs::read_dir(path)?.collect::<Result<_, _>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let child_path = child.path();
+        let layer = child.file_name().to_string_lossy().to_string();
+
+        if child_path.is_dir() {
+            let layer_files = crate::walk(&[&child_path])?;
+            for info in crate::collect_file_info(layer_files)? {
+                let relative = Path::new(&info.path).strip_prefix(&child_path)?;
+                files.push(LayerFile {
+                    image: image.clone(),
+                    layer: layer.clone(),
+                    path: relative.to_string_lossy().to_string(),
+                    size_bytes: info.size_bytes,
+                    hash: info.hash,
+                });
+            }
+        } else if let Some(reader) = open_tar(BufReader::new(File::open(&child_path)?))? {
+            scan_layer_stream(reader, &image, &layer, files)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Collect the files of every layer found under the given directories and tarballs
+pub fn scan_layers(paths: &[String]) -> Result<Vec<LayerFile>, Box<dyn Error>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        let path = Path::new(path);
+        println!("Scanning layers in {}", path.display());
+
+        if path.is_dir() {
+            scan_directory(path, &mut files)?;
+        } else {
+            scan_tarball(path, &mut files)?;
+        }
+    }
+
+    Ok(files)
+}
+
+// Group identical non-empty files stored in more than one layer, largest waste first
+pub fn find_layer_duplicates(files: Vec<LayerFile>) -> Vec<LayerDuplicate> {
+    let mut groups: HashMap<String, Vec<LayerFile>> = HashMap::new();
+    for file in files.into_iter().filter(|f| f.size_bytes > 0) {
+        groups.entry(file.hash.clone()).or_default().push(file);
+    }
+
+    let mut duplicates: Vec<LayerDuplicate> = groups
+        .into_iter()
+        .filter(|(_, copies)| {
+            let layers: HashSet<(&str, &str)> = copies
+                .iter()
+                .map(|c| (c.image.as_str(), c.layer.as_str()))
+                .collect();
+            layers.len() > 1
+        })
+        .map(|(hash, copies)| LayerDuplicate {
+            hash,
+            size_bytes: copies[0].size_bytes,
+            copies,
+        })
+        .collect();
+
+    duplicates.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then(a.hash.cmp(&b.hash))
+    });
+
+    duplicates
+}
+
+// One row per copy of each duplicated content
+pub fn layer_dataframe(duplicates: &[LayerDuplicate]) -> Result<DataFrame, Box<dyn Error>> {
+    let copies: Vec<(&LayerDuplicate, &LayerFile)> = duplicates
+        .iter()
+        .flat_map(|d| d.copies.iter().map(move |c| (d, c)))
+        .collect();
+
+    Ok(df! [
+        "image" => copies.iter().map(|(_, c)| c.image.clone()).collect::<Vec<_>>(),
+        "layer" => copies.iter().map(|(_, c)| c.layer.clone()).collect::<Vec<_>>(),
+        "file_path" => copies.iter().map(|(_, c)| c.path.clone()).collect::<Vec<_>>(),
+        "size_bytes" => copies.iter().map(|(_, c)| c.size_bytes).collect::<Vec<_>>(),
+        "content_hash" => copies.iter().map(|(d, _)| d.hash.clone()).collect::<Vec<_>>(),
+        "copies" => copies.iter().map(|(d, _)| d.copies.len() as u32).collect::<Vec<_>>(),
+    ]?)
+}
+
+pub fn print_layer_report(duplicates: &[LayerDuplicate]) {
+    let wasted: u64 = duplicates.iter().map(|d| d.wasted_bytes()).sum();
+
+    println!("\n=== Files Duplicated Across Layers ===");
+    println!(
+        "{} duplicated file(s), {:.2} MB could be saved",
+        duplicates.len(),
+        wasted as f64 / 1_048_576.0
+    );
+
+    for duplicate in duplicates {
+        println!(
+            "{} ({} bytes x {} copies)",
+            duplicate.hash,
+            duplicate.size_bytes,
+            duplicate.copies.len()
+        );
+        for copy in &duplicate.copies {
+            println!("  {} :: {} :: {}", copy.image, copy.layer, copy.path);
+        }
+    }
+}
+
+pub fn write_layer_csv(
+    duplicates: &[LayerDuplicate],
+    output_path: &str,
+) -> Result<(), Box<dyn Error>> {
+    let mut df = layer_dataframe(duplicates)?;
+    crate::report::write_csv(&mut df, Path::new(output_path))
+}
@@ -1,18 +1,80 @@
 //walks a filesystem and finds duplicate files
-use indicatif::{ParallelProgressIterator, ProgressStyle};
+pub mod affinity;
+pub mod archive;
+pub mod audit;
+pub mod backup;
+pub mod bench;
+pub mod cache;
+pub mod canonical;
+pub mod chunking;
+pub mod compressed;
+pub mod diagnostics;
+pub mod diff;
+pub mod events;
+pub mod exists;
+pub mod filter;
+pub mod generate;
+pub mod git;
+pub mod guard;
+pub mod hasher;
+pub mod ignores;
+pub mod index;
+pub mod interactive;
+pub mod largest;
+pub mod layers;
+pub mod linkfarm;
+pub mod maildir;
+pub mod matcher;
+pub mod normalize;
+pub mod paranoia;
+pub mod partition;
+pub mod patterns;
+#[cfg(feature = "perceptual")]
+pub mod perceptual;
+pub mod photos;
+pub mod pipeline;
+pub mod plan;
+pub mod preflight;
+pub mod profile;
+pub mod progress;
+pub mod remote;
+pub mod report;
+pub mod resolve;
+pub mod roots;
+pub mod rules;
+pub mod sample;
+pub mod savings;
+pub mod scan;
+pub mod snapshot;
+pub mod space;
+pub mod spill;
+pub mod tags;
+pub mod throttle;
+pub mod timebox;
+pub mod trees;
+pub mod tui;
+pub mod verify;
+pub mod vfs;
+pub mod waste;
+
+use index::{ChecksumIndex, PathTable};
+use indicatif::ParallelProgressIterator;
 use polars::prelude::*;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs;
 use std::path::Path;
 use walkdir::WalkDir;
 
+// Digests in flight from the hashing threads to the thread grouping them, see checksum_table
+const CHECKSUM_CHANNEL: usize = 4096;
+
 // Function to display threading information
 pub fn display_thread_info() {
     let num_cpus = num_cpus::get();
     let rayon_threads = rayon::current_num_threads();
-    
+
     println!("💻 CPU cores: {}", num_cpus);
     println!("🧵 Rayon thread pool size: {}", rayon_threads);
 }
@@ -24,7 +86,7 @@ pub struct FileInfo {
     pub extension: String,
     pub size_bytes: u64,
     pub size_mb: f64,
-    pub md5_hash: String,
+    pub hash: String,
     pub is_duplicate: bool,
     pub duplicate_group: Option<String>,
     pub created: Option<String>,
@@ -33,9 +95,33 @@ pub struct FileInfo {
 
 impl FileInfo {
     pub fn new(path: &str) -> Result<Self, Box<dyn Error>> {
-        let path_obj = Path::new(path);
+        let hash = content_hash(path)?;
+        Self::with_hash(path, hash)
+    }
+
+    // Build the file info around a content hash computed by the caller
+    pub fn with_hash(path: &str, hash: String) -> Result<Self, Box<dyn Error>> {
         let metadata = fs::metadata(path)?;
 
+        Ok(Self::with_metadata(
+            path,
+            hash,
+            metadata.len(),
+            metadata.created().ok(),
+            metadata.modified().ok(),
+        ))
+    }
+
+    // Same as with_hash, with the metadata supplied too (e.g. by a vfs::Vfs)
+    pub fn with_metadata(
+        path: &str,
+        hash: String,
+        size_bytes: u64,
+        created: Option<std::time::SystemTime>,
+        modified: Option<std::time::SystemTime>,
+    ) -> Self {
+        let path_obj = Path::new(path);
+
         let name = path_obj
             .file_name()
             .unwrap_or_default()
@@ -48,54 +134,387 @@ impl FileInfo {
             .to_string_lossy()
             .to_string();
 
-        let size_bytes = metadata.len();
         let size_mb = size_bytes as f64 / 1_048_576.0; // Convert bytes to MB
 
-        let file_content = fs::read(path)?;
-        let md5_hash = format!("{:x}", md5::compute(&file_content));
-
-        let created = metadata
-            .created()
-            .ok()
+        let created = created
             .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
             .map(|duration| format!("{}", duration.as_secs()));
 
-        let modified = metadata
-            .modified()
-            .ok()
+        let modified = modified
             .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
             .map(|duration| format!("{}", duration.as_secs()));
 
-        Ok(FileInfo {
+        FileInfo {
             path: path.to_string(),
             name,
             extension,
             size_bytes,
             size_mb,
-            md5_hash,
+            hash,
             is_duplicate: false,
             duplicate_group: None,
             created,
             modified,
+        }
+    }
+}
+
+// Hash of the whole file content under the run's settings (MD5 unless --hash), as a hex string
+pub fn content_hash(path: &str) -> std::io::Result<String> {
+    HashSettings::global().content_hash(path)
+}
+
+/*  Everything a content hash depends on besides the file itself. content_hash uses the run's, as
+the command line set them; scan::ScanOptions carries its own, so two scans in one process can
+hash differently.
+*/
+#[derive(Debug, Clone, Copy)]
+pub struct HashSettings<'a> {
+    pub hasher: hasher::Hasher,
+    // Hash compressed files by their decompressed content, see compressed
+    pub decompress: bool,
+    pub normalizers: &'a [std::sync::Arc<dyn normalize::Normalizer>],
+    pub cache: Option<&'a dyn cache::HashCache>,
+}
+
+impl HashSettings<'static> {
+    // From hasher::set_hasher, compressed::set_enabled, normalize::install and cache::set_backend
+    pub fn global() -> Self {
+        HashSettings {
+            hasher: hasher::selected(),
+            decompress: compressed::enabled(),
+            normalizers: normalize::installed(),
+            cache: cache::global(),
+        }
+    }
+}
+
+impl HashSettings<'_> {
+    pub fn content_hash(&self, path: &str) -> std::io::Result<String> {
+        progress::file_started(path);
+        let hash = self.cached_hash(path)?;
+        progress::file_hashed(path, &hash);
+        Ok(hash)
+    }
+
+    fn cached_hash(&self, path: &str) -> std::io::Result<String> {
+        // With --decompress, compressed files are hashed by their decompressed content
+        let format = self
+            .decompress
+            .then(|| compressed::Format::of(path))
+            .flatten();
+        let hasher = self.hasher;
+        if !self.normalizers.is_empty() {
+            let algorithm = normalize::algorithm(format, hasher, self.normalizers);
+            return cache::cached(self.cache, path, &algorithm, || {
+                normalize::hash_normalized(path, format, hasher, self.normalizers)
+            });
+        }
+        if let Some(format) = format {
+            return cache::cached(self.cache, path, &format.algorithm(hasher), || {
+                Ok(hasher::to_hex(&compressed::hash_content(
+                    path, format, hasher,
+                )?))
+            });
+        }
+        cache::cached(self.cache, path, hasher.as_str(), || {
+            Ok(hasher::to_hex(&hasher.hash_file(path)?))
         })
     }
 }
 
-pub fn walk(path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+// Options controlling which files the walk returns
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    // Descend into .git directories (skipped by default)
+    pub include_git: bool,
+    // Drop files identical to their committed version in an enclosing git repository
+    pub skip_reproducible: bool,
+    // Checked for every file during the walk, see filter::FileFilter
+    pub filters: filter::FilterChain,
+    // Files and directories matching any of these are skipped; excluded directories aren't entered
+    pub exclude: Vec<matcher::Matcher>,
+    // Descend into symlinked directories and return symlinked files, cutting symlink loops
+    pub follow_links: bool,
+    // Return one name per file hardlinked to others, so names of one inode aren't duplicates
+    pub skip_hardlinks: bool,
+    // Stop walking once this many files are accepted
+    pub max_files: Option<usize>,
+    // Skip what .gitignore and .rdedupeignore files in the walked tree ignore, see ignores
+    pub ignore_files: bool,
+    // Skip files and directories whose name starts with a dot, but .git (see include_git)
+    pub skip_hidden: bool,
+}
+
+impl WalkOptions {
+    // Builder style: WalkOptions::default().filter(filter::Pattern(".log".into())).filter(...)
+    pub fn filter(mut self, filter: impl filter::FileFilter + 'static) -> Self {
+        self.filters.push(std::sync::Arc::new(filter));
+        self
+    }
+
+    pub fn exclude(mut self, matcher: matcher::Matcher) -> Self {
+        self.exclude.push(matcher);
+        self
+    }
+
+    // Whether found accepted files use up max_files
+    pub fn capped(&self, found: usize) -> bool {
+        self.max_files.is_some_and(|max| found >= max)
+    }
+
+    pub fn report_cap(&self, found: usize) {
+        if self.capped(found) {
+            progress::message(&format!(
+                "File cap reached: stopped walking after {} file(s)",
+                found
+            ));
+        }
+    }
+
+    // For filter_entry: the walk roots themselves are never excluded
+    pub fn excludes(&self, entry: &walkdir::DirEntry) -> bool {
+        entry.depth() > 0
+            && !self.exclude.is_empty()
+            && self
+                .exclude
+                .iter()
+                .any(|matcher| matcher.is_match(&entry.path().to_string_lossy()))
+    }
+}
+
+/*  (device, inode) of the files a walk already returned. With skip_hardlinks the later names of a
+hardlinked file are dropped; when following symlinks every name is checked, since a symlink and
+its target are one file.
+*/
+#[derive(Default)]
+pub struct SeenInodes(std::collections::HashSet<(u64, u64)>);
+
+impl SeenInodes {
+    pub fn first_name(&mut self, entry: &walkdir::DirEntry, options: &WalkOptions) -> bool {
+        if !options.skip_hardlinks && !options.follow_links {
+            return true;
+        }
+        match inode(entry, options) {
+            Some(key) => self.0.insert(key),
+            None => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn inode(entry: &walkdir::DirEntry, options: &WalkOptions) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = entry.metadata().ok()?;
+    (options.follow_links || metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_entry: &walkdir::DirEntry, _options: &WalkOptions) -> Option<(u64, u64)> {
+    None
+}
+
+pub fn walk<P: AsRef<Path>>(roots: &[P]) -> Result<Vec<String>, Box<dyn Error>> {
+    walk_roots(roots, &WalkOptions::default())
+}
+
+// Every root in the order given, max_files counting across all of them
+pub fn walk_roots<P: AsRef<Path>>(
+    roots: &[P],
+    options: &WalkOptions,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    let mut walk = options.clone();
+    for root in roots {
+        walk.max_files = options.max_files.map(|max| max.saturating_sub(files.len()));
+        if walk.capped(0) {
+            break;
+        }
+        files.extend(walk_collecting(
+            &root.as_ref().to_string_lossy(),
+            &walk,
+            &mut Vec::new(),
+        )?);
+    }
+    options.report_cap(files.len());
+    Ok(files)
+}
+
+// Unreadable paths below the root are reported through diagnostics and left out
+pub fn walk_with_options(path: &str, options: &WalkOptions) -> Result<Vec<String>, Box<dyn Error>> {
+    let files = walk_collecting(path, options, &mut Vec::new())?;
+    options.report_cap(files.len());
+    Ok(files)
+}
+
+// walk_with_options(), also pushing every path the walk couldn't read onto errors
+pub fn walk_collecting(
+    path: &str,
+    options: &WalkOptions,
+    errors: &mut Vec<diagnostics::PathError>,
+) -> Result<Vec<String>, Box<dyn Error>> {
     let mut files = Vec::new();
+    let mut repositories = Vec::new();
+    let mut inodes = SeenInodes::default();
+    let mut ignores = ignores::Ignores::default();
+
+    let walker = WalkDir::new(path).follow_links(options.follow_links);
+    let walker = walker.into_iter().filter_entry(|entry| {
+        if options.excludes(entry) || ignores.skips(entry, options) {
+            return false;
+        }
+        if entry.depth() > 0 && entry.file_name() == ".git" {
+            if let Some(parent) = entry.path().parent() {
+                repositories.push(parent.to_path_buf());
+            }
+            return options.include_git;
+        }
+        true
+    });
 
-    for entry in WalkDir::new(path) {
-        let entry = entry?;
+    let spinner = progress::walk_spinner();
+    for entry in walker {
+        if options.capped(files.len()) {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.extend(diagnostics::walk_error(e)?);
+                continue;
+            }
+        };
 
         if entry.file_type().is_file() {
-            files.push(entry.path().to_str().unwrap().to_string());
+            let file = entry.path().to_string_lossy().to_string();
+            if options.filters.accepts_entry(&file, &entry) && inodes.first_name(&entry, options) {
+                files.push(file);
+            }
+            spinner.inc(1);
         }
     }
+    spinner.finish_and_clear();
+
+    if options.skip_reproducible {
+        files = drop_reproducible(path, files, repositories)?;
+    }
 
     Ok(files)
 }
 
-//Find files matching a pattern
+/*  Walk straight into a PathTable: names are copied from the walkdir entries into the table's
+arena under their parent directory id, instead of allocating an owned String per file. Only
+files whose path contains pattern are kept.
+*/
+pub fn walk_table(
+    path: &str,
+    pattern: &str,
+    options: &WalkOptions,
+) -> Result<PathTable, Box<dyn Error>> {
+    let mut table = PathTable::default();
+
+    // Dropping reproducible files needs the full path list, so take the String walk for that
+    if options.skip_reproducible {
+        for file in find(walk_with_options(path, options)?, pattern) {
+            table.intern(&file);
+        }
+        return Ok(table);
+    }
+
+    // Directory ids by depth; entry n's parent is dirs[n - 1]
+    let mut dirs: Vec<u32> = Vec::new();
+    let mut inodes = SeenInodes::default();
+    let mut ignores = ignores::Ignores::default();
+    let walker = WalkDir::new(path).follow_links(options.follow_links);
+    let walker = walker.into_iter().filter_entry(|entry| {
+        (options.include_git || entry.depth() == 0 || entry.file_name() != ".git")
+            && !options.excludes(entry)
+            && !ignores.skips(entry, options)
+    });
+
+    let spinner = progress::walk_spinner();
+    let mut found = 0;
+    for entry in walker {
+        if options.capped(found) {
+            break;
+        }
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                diagnostics::walk_error(e)?;
+                continue;
+            }
+        };
+        let depth = entry.depth();
+
+        if entry.file_type().is_dir() {
+            dirs.truncate(depth);
+            let id = match depth {
+                0 => table.intern_dir(path.trim_end_matches(std::path::MAIN_SEPARATOR)),
+                _ => table.push_dir(dirs[depth - 1], &entry.file_name().to_string_lossy()),
+            };
+            dirs.push(id);
+        } else if entry.file_type().is_file() {
+            let file = entry.path().to_string_lossy();
+            if !pattern.is_empty() && !file.contains(pattern) {
+                continue;
+            }
+            if !options.filters.accepts_entry(&file, &entry) || !inodes.first_name(&entry, options)
+            {
+                continue;
+            }
+            match depth {
+                0 => table.intern(path),
+                _ => table.push_file(Some(dirs[depth - 1]), &entry.file_name().to_string_lossy()),
+            };
+            found += 1;
+            spinner.inc(1);
+        }
+    }
+    spinner.finish_and_clear();
+    options.report_cap(found);
+
+    Ok(table)
+}
+
+// Remove files that git can restore byte-for-byte from HEAD
+fn drop_reproducible(
+    root: &str,
+    files: Vec<String>,
+    mut repositories: Vec<std::path::PathBuf>,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    repositories.extend(git::repository_root(Path::new(root)));
+
+    let mut reproducible = std::collections::HashSet::new();
+    for repository in &repositories {
+        reproducible.extend(git::reproducible_files(repository)?);
+    }
+
+    if reproducible.is_empty() {
+        return Ok(files);
+    }
+
+    let canonical_root = Path::new(root).canonicalize()?;
+    let before = files.len();
+    let files: Vec<String> = files
+        .into_iter()
+        .filter(|file| {
+            let relative = Path::new(file)
+                .strip_prefix(root)
+                .unwrap_or(Path::new(file));
+            !reproducible.contains(&canonical_root.join(relative))
+        })
+        .collect();
+
+    progress::message(&format!(
+        "Skipped {} file(s) identical to their committed version",
+        before - files.len()
+    ));
+
+    Ok(files)
+}
+
+//Find files matching a pattern, for a file list already in hand; walks take a filter::FilterChain
 pub fn find(files: Vec<String>, pattern: &str) -> Vec<String> {
     let mut matches = Vec::new();
 
@@ -110,68 +529,86 @@ pub fn find(files: Vec<String>, pattern: &str) -> Vec<String> {
 
 // New function to collect detailed file information - TRUE PARALLEL VERSION
 pub fn collect_file_info(files: Vec<String>) -> Result<Vec<FileInfo>, Box<dyn Error>> {
+    collect_file_info_with(files, content_hash)
+}
+
+// Same as collect_file_info, but with a caller supplied content hash
+pub fn collect_file_info_with(
+    files: Vec<String>,
+    hash: fn(&str) -> std::io::Result<String>,
+) -> Result<Vec<FileInfo>, Box<dyn Error>> {
     if files.is_empty() {
         return Ok(Vec::new());
     }
 
-    println!("\nAnalyzing {} files with {} threads...", files.len(), rayon::current_num_threads());
-    
-    let pb = indicatif::ProgressBar::new(files.len() as u64);
-    let sty = ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}")
-        .unwrap()
-        .progress_chars("##-");
+    progress::message(&format!(
+        "\nAnalyzing {} files with {} threads...",
+        files.len(),
+        rayon::current_num_threads()
+    ));
 
-    pb.set_style(sty);
+    let pb = progress::stage_bar(progress::Stage::Hash, files.len() as u64);
     pb.set_message("Computing MD5 hashes...");
-    
+    let current = progress::current_file_line();
+
     // Enable steady tick to ensure spinner is visible
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
 
     // TRUE PARALLEL: Each thread processes files independently, no shared mutex
-    let file_infos: Vec<Option<FileInfo>> = files
-        .par_iter()
-        .progress_with(pb.clone())
-        .map(|file_path| FileInfo::new(file_path).ok())
-        .collect();
+    // With --pin-threads each NUMA node hashes its own share on its own pinned workers
+    let file_infos: Vec<Option<FileInfo>> =
+        affinity::map_partitioned(&files, pb.clone(), |file_path| {
+            if timebox::out_of_time(file_path) {
+                return None;
+            }
+            current.set_message(file_path.clone());
+            diagnostics::skipped(file_path, hash(file_path)).and_then(|hash| {
+                diagnostics::skipped(file_path, FileInfo::with_hash(file_path, hash))
+            })
+        });
 
+    current.finish_and_clear();
     pb.finish_with_message("✓ File analysis complete!");
-    println!();
+    progress::message("");
 
     // Filter out failed files
     let valid_infos: Vec<FileInfo> = file_infos.into_iter().flatten().collect();
-    
+
     Ok(valid_infos)
 }
 
-// Create Polars DataFrame from file information
-pub fn create_dataframe(mut file_infos: Vec<FileInfo>) -> Result<DataFrame, Box<dyn Error>> {
+// Create Polars DataFrame from file information, labelled with the run's hash and paranoia level
+pub fn create_dataframe(file_infos: Vec<FileInfo>) -> Result<DataFrame, Box<dyn Error>> {
+    create_dataframe_with(file_infos, hasher::selected(), paranoia::level())
+}
+
+// create_dataframe for hashes made with hasher at paranoia level, as by scan::ScanOptions
+pub fn create_dataframe_with(
+    mut file_infos: Vec<FileInfo>,
+    hasher: hasher::Hasher,
+    paranoia: paranoia::Paranoia,
+) -> Result<DataFrame, Box<dyn Error>> {
     // Group files by hash to identify duplicates
     let mut hash_groups: HashMap<String, Vec<usize>> = HashMap::new();
 
     for (index, file_info) in file_infos.iter().enumerate() {
         hash_groups
-            .entry(file_info.md5_hash.clone())
+            .entry(file_info.hash.clone())
             .or_default()
             .push(index);
     }
 
     // Mark duplicates and assign group IDs - ONLY for files that actually have duplicates
-    let mut duplicate_count = 0;
     for (hash, indices) in &hash_groups {
         if indices.len() > 1 {
             let group_id = hash.clone();
-            duplicate_count += indices.len();
-            
+
             for &index in indices {
                 file_infos[index].is_duplicate = true;
                 file_infos[index].duplicate_group = Some(group_id.clone());
             }
         }
     }
-    
-    println!("Found {} files in {} duplicate groups", duplicate_count, 
-             hash_groups.iter().filter(|(_, v)| v.len() > 1).count());
 
     // Extract data for DataFrame columns
     let paths: Vec<String> = file_infos.iter().map(|f| f.path.clone()).collect();
@@ -179,7 +616,7 @@ pub fn create_dataframe(mut file_infos: Vec<FileInfo>) -> Result<DataFrame, Box<
     let extensions: Vec<String> = file_infos.iter().map(|f| f.extension.clone()).collect();
     let sizes_bytes: Vec<u64> = file_infos.iter().map(|f| f.size_bytes).collect();
     let sizes_mb: Vec<f64> = file_infos.iter().map(|f| f.size_mb).collect();
-    let hashes: Vec<String> = file_infos.iter().map(|f| f.md5_hash.clone()).collect();
+    let hashes: Vec<String> = file_infos.iter().map(|f| f.hash.clone()).collect();
     let is_duplicate: Vec<bool> = file_infos.iter().map(|f| f.is_duplicate).collect();
     let duplicate_groups: Vec<Option<String>> = file_infos
         .iter()
@@ -192,14 +629,34 @@ pub fn create_dataframe(mut file_infos: Vec<FileInfo>) -> Result<DataFrame, Box<
         "extension" => extensions,
         "size_bytes" => sizes_bytes,
         "size_mb" => sizes_mb,
-        "md5_hash" => hashes,
+        "content_hash" => hashes,
+        // The algorithm behind content_hash, see hasher::Hasher
+        "hash_algorithm" => vec![hasher.as_str(); file_infos.len()],
         "is_duplicate" => is_duplicate,
         "duplicate_group" => duplicate_groups,
+        // How the groups were established, see paranoia::Paranoia
+        "paranoia" => vec![paranoia.as_str(); file_infos.len()],
     ]?;
 
     Ok(df)
 }
 
+// DataFrame with the report columns and no rows
+pub fn empty_dataframe() -> Result<DataFrame, Box<dyn Error>> {
+    Ok(df! [
+        "file_path" => Vec::<String>::new(),
+        "file_name" => Vec::<String>::new(),
+        "extension" => Vec::<String>::new(),
+        "size_bytes" => Vec::<u64>::new(),
+        "size_mb" => Vec::<f64>::new(),
+        "content_hash" => Vec::<String>::new(),
+        "hash_algorithm" => Vec::<String>::new(),
+        "is_duplicate" => Vec::<bool>::new(),
+        "duplicate_group" => Vec::<Option<String>>::new(),
+        "paranoia" => Vec::<String>::new(),
+    ]?)
+}
+
 // Generate file statistics summary
 pub fn generate_statistics(df: &DataFrame) -> Result<DataFrame, Box<dyn Error>> {
     let total_files = df.height();
@@ -225,40 +682,81 @@ pub fn generate_statistics(df: &DataFrame) -> Result<DataFrame, Box<dyn Error>>
     Ok(stats_df)
 }
 
+// Paths listed per group by collect_statistics
+const EXAMPLE_PATHS: usize = 3;
+
+/*  One row per duplicate group of an analyzed DataFrame, largest waste first: the group hash, file
+count, file size, bytes freed by keeping a single copy and up to EXAMPLE_PATHS of its paths.
+*/
+pub fn collect_statistics(df: &DataFrame) -> Result<DataFrame, PolarsError> {
+    let groups = report::duplicate_groups(df)?;
+    let examples: Vec<String> = groups
+        .iter()
+        .map(|group| {
+            group
+                .files
+                .iter()
+                .take(EXAMPLE_PATHS)
+                .cloned()
+                .collect::<Vec<_>>()
+                .join("; ")
+        })
+        .collect();
+
+    df! [
+        "group_hash" => groups.iter().map(|group| group.group.as_str()).collect::<Vec<_>>(),
+        "file_count" => groups.iter().map(|group| group.count as u64).collect::<Vec<_>>(),
+        "size_bytes" => groups.iter().map(|group| group.size_bytes).collect::<Vec<_>>(),
+        "wasted_bytes" => groups.iter().map(|group| group.wasted_bytes).collect::<Vec<_>>(),
+        "example_paths" => examples,
+    ]
+}
+
+// Totals of an analyzed DataFrame and its collect_statistics groups, as metric,value rows
+pub fn collect_summary(df: &DataFrame, groups: &DataFrame) -> Result<DataFrame, PolarsError> {
+    let duplicate_files = groups.column("file_count")?.u64()?.sum().unwrap_or(0);
+    let reclaimable_bytes = groups.column("wasted_bytes")?.u64()?.sum().unwrap_or(0);
+
+    df! [
+        "metric" => ["total_files", "duplicate_files", "duplicate_groups", "reclaimable_bytes"],
+        "value" => [df.height() as u64, duplicate_files, groups.height() as u64, reclaimable_bytes],
+    ]
+}
+
 // Validate duplicate detection logic
 pub fn validate_duplicates(df: &DataFrame) -> Result<(), Box<dyn Error>> {
     println!("\n=== Duplicate Detection Validation ===");
-    
+
     // Group by hash and check consistency
     let duplicates = df
         .clone()
         .lazy()
         .filter(col("is_duplicate").eq(lit(true)))
         .collect()?;
-    
+
     if duplicates.height() == 0 {
         println!("✓ No duplicates found - validation passed");
         return Ok(());
     }
-    
+
     // Group duplicates by their hash to verify consistency
     let grouped = duplicates
         .clone()
         .lazy()
-        .group_by([col("md5_hash")])
+        .group_by([col("content_hash")])
         .agg([
             col("file_path").count().alias("file_count"),
-            col("duplicate_group").first().alias("group_id")
+            col("duplicate_group").first().alias("group_id"),
         ])
         .collect()?;
-    
+
     println!("Duplicate groups found:");
     for row in 0..grouped.height() {
-        let hash = grouped.column("md5_hash")?.get(row)?;
+        let hash = grouped.column("content_hash")?.get(row)?;
         let count = grouped.column("file_count")?.get(row)?;
         println!("  Hash: {} -> {} files", hash, count);
     }
-    
+
     println!("✓ Duplicate detection validation completed");
     Ok(())
 }
@@ -271,18 +769,20 @@ pub fn generate_csv_report(df: &mut DataFrame, output_path: &str) -> Result<(),
         .lazy()
         .filter(col("is_duplicate").eq(lit(true)))
         .collect()?;
-    
+
     if duplicates_only.height() == 0 {
         println!("No duplicates found - CSV report not generated");
         return Ok(());
     }
 
-    let mut file = std::fs::File::create(output_path)?;
     let mut duplicates_df = duplicates_only;
-    
-    CsvWriter::new(&mut file).include_header(true).finish(&mut duplicates_df)?;
+    report::write_csv(&mut duplicates_df, Path::new(output_path))?;
 
-    println!("CSV report generated: {} ({} duplicate files)", output_path, duplicates_df.height());
+    println!(
+        "CSV report generated: {} ({} duplicate files)",
+        output_path,
+        duplicates_df.height()
+    );
 
     Ok(())
 }
@@ -292,39 +792,158 @@ pub fn run_with_dataframe(
     path: &str,
     pattern: &str,
     output_csv: Option<&str>,
+    walk_options: &WalkOptions,
+    verify: bool,
 ) -> Result<DataFrame, Box<dyn Error>> {
-    println!("Scanning directory: {}", path);
+    run_with_patterns(
+        &[path.to_string()],
+        &[pattern.to_string()],
+        output_csv,
+        walk_options,
+        verify,
+    )
+}
 
-    let files = walk(path)?;
-    let files = find(files, pattern);
+/*  Like run_with_dataframe for files matching any of several patterns. With more than one, the
+report gains a "pattern" column and the matches are broken down per pattern; with more than one
+root, likewise a "root" column and a breakdown per root.
+*/
+pub fn run_with_patterns(
+    roots: &[String],
+    patterns: &[String],
+    output_csv: Option<&str>,
+    walk_options: &WalkOptions,
+    verify: bool,
+) -> Result<DataFrame, Box<dyn Error>> {
+    let df = analyze(roots, patterns, walk_options, verify)?;
+    if df.height() == 0 {
+        return Ok(df);
+    }
+    if patterns.len() > 1 {
+        patterns::print_breakdown(&patterns::breakdown(&df, patterns)?);
+    }
+    if roots.len() > 1 {
+        roots::print_breakdown(&roots::breakdown(&df, roots)?);
+    }
 
-    println!("Found {} files matching pattern '{}'", files.len(), pattern);
-    
-    if files.is_empty() {
-        println!("No files found to analyze.");
-        return Ok(df! [
-            "file_path" => Vec::<String>::new(),
-            "file_name" => Vec::<String>::new(),
-            "extension" => Vec::<String>::new(),
-            "size_bytes" => Vec::<u64>::new(),
-            "size_mb" => Vec::<f64>::new(),
-            "md5_hash" => Vec::<String>::new(),
-            "is_duplicate" => Vec::<bool>::new(),
-            "duplicate_group" => Vec::<Option<String>>::new(),
-        ]?);
-    }
-
-    let file_infos = collect_file_info(files)?;
-    let df = create_dataframe(file_infos)?;
+    print_report(&df, output_csv)?;
+
+    Ok(df)
+}
+
+/*  The scan behind every report: walk, hash and group the files matching any of patterns into
+the analyzed DataFrame, without printing the report. Several patterns add the "pattern" column,
+several roots the "root" column; duplicates are found across all roots.
+*/
+pub fn analyze(
+    roots: &[String],
+    patterns: &[String],
+    walk_options: &WalkOptions,
+    verify: bool,
+) -> Result<DataFrame, Box<dyn Error>> {
+    for root in roots {
+        progress::message(&format!("Scanning directory: {}", root));
+    }
+    let pattern = patterns.join("', '");
+
+    // Hashing starts while the walk is still discovering files, unless workers elsewhere hash
+    let mut file_infos = if remote::enabled() {
+        // Workers resolve paths relative to the one root they serve
+        let [path] = roots else {
+            return Err("remote hashing scans a single root".into());
+        };
+        let files: Vec<String> = walk_with_options(path, walk_options)?
+            .into_iter()
+            .filter(|file| patterns::matches_any(patterns, file))
+            .collect();
+        let (file_infos, stats) = remote::collect_file_info(files, path)?;
+        progress::message(&format!(
+            "Remote hashing: {} file(s) on {} worker(s), {} hashed locally",
+            stats.remote, stats.workers, stats.local
+        ));
+        file_infos
+    } else if let Some(edge) = pipeline::staged() {
+        // Only files colliding on size and edges are hashed, so only those are in the report
+        let files: Vec<String> = walk_roots(roots, walk_options)?
+            .into_iter()
+            .filter(|file| patterns::matches_any(patterns, file))
+            .collect();
+        let total = files.len();
+        let staged = pipeline::same_edges(pipeline::group_by_size(files), edge, hasher::selected());
+        progress::message(&format!(
+            "Staged comparison: {} of {} file(s) collide on size and their first and last {} KB ({} compared by edges), only those are hashed in full",
+            staged.files.len(),
+            total,
+            edge / 1024,
+            staged.edge_hashed
+        ));
+        collect_file_info_with(staged.files, paranoia::level().hash())?
+    } else {
+        pipeline::walk_and_hash(
+            roots,
+            walk_options,
+            |file| patterns::matches_any(patterns, file),
+            paranoia::level().hash(),
+        )?
+    };
+
+    progress::message(&format!(
+        "Found {} files matching pattern '{}'",
+        file_infos.len(),
+        pattern
+    ));
+
+    if file_infos.is_empty() {
+        progress::message("No files found to analyze.");
+        return empty_dataframe();
+    }
+
+    if verify {
+        verify::report_split(verify::split_mismatches(&mut file_infos));
+    }
+
+    let mut df = create_dataframe(file_infos)?;
+    if patterns.len() > 1 {
+        df = patterns::label(&df, patterns)?;
+    }
+    if roots.len() > 1 {
+        df = roots::label(&df, roots)?;
+    }
+
+    Ok(df)
+}
+
+// The generate_statistics summary of an analyzed DataFrame, as a metric,value CSV
+pub fn write_statistics(df: &DataFrame, output_path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut stats = generate_statistics(df)?;
+    report::write_csv(&mut stats, output_path)
+}
+
+// Size the global rayon pool; must run before anything else uses it
+pub fn set_threads(threads: usize) -> Result<(), Box<dyn Error>> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()?;
+    Ok(())
+}
+
+// Print statistics and duplicates for an analyzed DataFrame, optionally writing the CSV report
+pub fn print_report(df: &DataFrame, output_csv: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let groups = report::duplicate_groups(df)?;
+    println!(
+        "Found {} files in {} duplicate groups",
+        groups.iter().map(|group| group.count).sum::<usize>(),
+        groups.len()
+    );
 
     // Print summary statistics
-    let stats = generate_statistics(&df)?;
+    let stats = generate_statistics(df)?;
 
     println!("\n=== File Analysis Summary ===");
     println!("{}", stats);
 
     // Validate duplicate detection
-    validate_duplicates(&df)?;
+    validate_duplicates(df)?;
 
     // Show duplicate information
     let duplicates = df
@@ -347,74 +966,91 @@ pub fn run_with_dataframe(
         generate_csv_report(&mut df_copy, csv_path)?;
     }
 
-    Ok(df)
+    Ok(())
 }
 
 /*  TRUE PARALLEL version of checksum using rayon with no mutex contention
-Uses indicatif to show a progress bar
+Uses indicatif to show a progress bar. The paths are interned up front, see checksum_table.
 */
-pub fn checksum(files: Vec<String>) -> Result<HashMap<String, Vec<String>>, Box<dyn Error>> {
-    println!("Computing checksums with {} threads...", rayon::current_num_threads());
-    
-    let pb = indicatif::ProgressBar::new(files.len() as u64);
-    let sty = ProgressStyle::default_bar()
-        .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
-        .unwrap();
-
-    pb.set_style(sty);
-
-    // TRUE PARALLEL: Each thread computes checksums independently
-    let file_checksums: Vec<(String, String)> = files
-        .par_iter()
-        .progress_with(pb)
-        .filter_map(|file| {
-            if let Ok(content) = std::fs::read(file) {
-                let checksum = format!("{:x}", md5::compute(&content));
-                Some((checksum, file.clone()))
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    // Sequential grouping (this part must be sequential anyway)
-    let mut checksums: HashMap<String, Vec<String>> = HashMap::new();
-    for (hash, file_path) in file_checksums {
-        checksums.entry(hash).or_default().push(file_path);
+pub fn checksum(files: Vec<String>) -> Result<ChecksumIndex, Box<dyn Error>> {
+    let mut paths = PathTable::default();
+    for file in files {
+        paths.intern(&file);
     }
-
-    Ok(checksums)
+    checksum_table(paths)
 }
 
-/*
-Find all the files with more than one entry in the HashMap
+/*  checksum() over a walk_table result, reusing one path buffer per thread. Hashing threads share
+no lock: each sends its (digest, path id) down a bounded channel, and this thread groups them into
+the index while hashing goes on, so no list of results is ever collected. Groups come back in path
+order, as if hashed one by one.
 */
-pub fn find_duplicates(checksums: HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
-    let mut duplicates = Vec::new();
-
-    for (_checksum, files) in checksums {
-        if files.len() > 1 {
-            duplicates.push(files);
+pub fn checksum_table(paths: PathTable) -> Result<ChecksumIndex, Box<dyn Error>> {
+    progress::message(&format!(
+        "Computing checksums with {} threads...",
+        rayon::current_num_threads()
+    ));
+
+    let pb = progress::stage_bar(progress::Stage::Hash, paths.len() as u64);
+    let (sender, receiver) = std::sync::mpsc::sync_channel(CHECKSUM_CHANNEL);
+
+    let mut checksums = std::thread::scope(|scope| {
+        let paths = &paths;
+        scope.spawn(move || {
+            (0..paths.len() as index::PathId)
+                .into_par_iter()
+                .progress_with(pb)
+                .for_each_init(
+                    || (String::new(), sender.clone()),
+                    |(buffer, sender), id| {
+                        paths.write_path(id, buffer);
+                        if let Ok(digest) = index::digest(buffer) {
+                            let _ = sender.send((digest, id));
+                        }
+                    },
+                );
+        });
+
+        // Ends once every hashing thread has dropped its sender
+        let mut checksums = ChecksumIndex::default();
+        for (digest, id) in receiver {
+            checksums.insert_id(digest, id);
         }
-    }
+        checksums
+    });
 
-    duplicates
+    checksums.set_paths(paths);
+    checksums.sort_groups();
+    Ok(checksums)
 }
 
-// invoke the actions along with the path and pattern and progress bar
-pub fn run(path: &str, pattern: &str) -> Result<(), Box<dyn Error>> {
-    let files = walk(path)?;
-    let files = find(files, pattern);
-
-    println!("Found {} files matching {}", files.len(), pattern);
-
-    let checksums = checksum(files)?;
-    let duplicates = find_duplicates(checksums);
-
-    println!("Found {} duplicate(s)", duplicates.len());
+pub fn find_duplicates(checksums: ChecksumIndex) -> Vec<Vec<String>> {
+    checksums.duplicate_groups()
+}
 
-    for duplicate in duplicates {
-        println!("{:?}", duplicate);
+// invoke the actions along with the roots and pattern and progress bar
+pub fn run(roots: &[std::path::PathBuf], pattern: &str) -> Result<(), Box<dyn Error>> {
+    let mut options = scan::ScanOptions::for_run(".");
+    options.roots = roots::distinct(roots);
+    options.walk = WalkOptions::default().filter(filter::Pattern(pattern.to_string()));
+    let report = scan::scan(&options)?;
+
+    println!("Found {} files matching {}", report.stats.files, pattern);
+    println!(
+        "Size pre-pass: {} of {} file(s) share a size, only those are read",
+        report.stats.same_size, report.stats.files
+    );
+    println!(
+        "Edge pass: {} large file(s) compared by their first and last {} KB, {} hashed in full",
+        report.stats.edge_hashed,
+        pipeline::EDGE_BYTES / 1024,
+        report.stats.hashed
+    );
+    println!("Found {} duplicate(s)", report.groups.len());
+
+    for group in &report.groups {
+        let files: Vec<&str> = group.files.iter().map(|f| f.path.as_str()).collect();
+        println!("{:?}", files);
     }
 
     Ok(())
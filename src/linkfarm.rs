@@ -0,0 +1,262 @@
+//audits hardlink farms (rsnapshot, rsync --link-dest) where unchanged files share one inode across snapshots
+use crate::{collect_file_info, walk, FileInfo};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+// Identity of the inode behind a path; copies sharing it are hardlinked
+#[cfg(unix)]
+fn inode(path: &str) -> io::Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_path: &str) -> io::Result<(u64, u64)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "inode identity is only available on unix",
+    ))
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FarmStatus {
+    // Same inode in both snapshots, the sharing is intact
+    Linked,
+    // Separate inodes with identical content, the link was lost or never made
+    Unlinked,
+    // Same size and mtime but different content: the copy was modified in place or rotted
+    Diverged,
+    // The file legitimately changed between the snapshots
+    Changed,
+}
+
+impl FarmStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FarmStatus::Linked => "linked",
+            FarmStatus::Unlinked => "unlinked",
+            FarmStatus::Diverged => "diverged",
+            FarmStatus::Changed => "changed",
+        }
+    }
+}
+
+// The same relative path in two consecutive snapshots
+#[derive(Debug, Clone)]
+pub struct FarmPair {
+    pub relative: String,
+    pub older: String,
+    pub newer: String,
+    pub status: FarmStatus,
+    pub size_bytes: u64,
+}
+
+// Identical content stored in more than one inode anywhere in the farm
+#[derive(Debug, Clone)]
+pub struct LinkOpportunity {
+    pub hash: String,
+    pub size_bytes: u64,
+    // One path per separate inode, the first is the one to keep
+    pub paths: Vec<String>,
+}
+
+impl LinkOpportunity {
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * (self.paths.len() as u64 - 1)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FarmAudit {
+    pub snapshots: Vec<String>,
+    pub files: usize,
+    pub pairs: Vec<FarmPair>,
+    pub opportunities: Vec<LinkOpportunity>,
+}
+
+impl FarmAudit {
+    pub fn count(&self, status: FarmStatus) -> usize {
+        self.pairs.iter().filter(|p| p.status == status).count()
+    }
+}
+
+// Every directory directly under root is one snapshot, in name order
+fn snapshot_dirs(root: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            snapshots.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    snapshots.sort();
+
+    if snapshots.len() < 2 {
+        return Err(format!("{} needs at least two snapshot directories", root).into());
+    }
+
+    Ok(snapshots)
+}
+
+fn classify(older: &FileInfo, newer: &FileInfo) -> Result<FarmStatus, Box<dyn Error>> {
+    if inode(&older.path)? == inode(&newer.path)? {
+        return Ok(FarmStatus::Linked);
+    }
+    if older.hash == newer.hash {
+        return Ok(FarmStatus::Unlinked);
+    }
+    // rsync treats same size and mtime as unchanged, so different bytes here were never a real change
+    if older.size_bytes == newer.size_bytes && modified(&older.path) == modified(&newer.path) {
+        return Ok(FarmStatus::Diverged);
+    }
+    Ok(FarmStatus::Changed)
+}
+
+/*  Audit a directory of snapshots: compare each relative path against the previous snapshot
+holding it, and collect content stored in several inodes anywhere in the farm (including files
+that moved between snapshots) as linking opportunities.
+*/
+pub fn audit_farm(root: &str) -> Result<FarmAudit, Box<dyn Error>> {
+    let snapshots = snapshot_dirs(root)?;
+
+    let mut files = Vec::new();
+    for snapshot in &snapshots {
+        files.extend(walk(&[snapshot])?);
+    }
+    println!(
+        "Hashing {} files in {} snapshots under {}",
+        files.len(),
+        snapshots.len(),
+        root
+    );
+    let infos = collect_file_info(files)?;
+
+    // relative path -> (snapshot index, file) in snapshot order
+    let mut by_relative: BTreeMap<String, Vec<(usize, &FileInfo)>> = BTreeMap::new();
+    for info in &infos {
+        let (index, snapshot) = snapshots
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| Path::new(&info.path).starts_with(s))
+            .max_by_key(|(_, s)| s.len())
+            .ok_or_else(|| format!("{} is outside every snapshot", info.path))?;
+        let relative = Path::new(&info.path)
+            .strip_prefix(snapshot)?
+            .to_string_lossy()
+            .to_string();
+        by_relative.entry(relative).or_default().push((index, info));
+    }
+
+    let mut pairs = Vec::new();
+    for (relative, copies) in &mut by_relative {
+        copies.sort_by_key(|(index, _)| *index);
+        for window in copies.windows(2) {
+            let ((_, older), (_, newer)) = (window[0], window[1]);
+            pairs.push(FarmPair {
+                relative: relative.clone(),
+                older: older.path.clone(),
+                newer: newer.path.clone(),
+                status: classify(older, newer)?,
+                size_bytes: newer.size_bytes,
+            });
+        }
+    }
+
+    // One representative path per inode, grouped by content
+    let mut by_hash: HashMap<&str, BTreeMap<(u64, u64), &FileInfo>> = HashMap::new();
+    for info in &infos {
+        by_hash
+            .entry(info.hash.as_str())
+            .or_default()
+            .entry(inode(&info.path)?)
+            .or_insert(info);
+    }
+
+    let mut opportunities: Vec<LinkOpportunity> = by_hash
+        .into_iter()
+        .filter(|(_, inodes)| inodes.len() > 1)
+        .map(|(hash, inodes)| {
+            let mut paths: Vec<String> = inodes.values().map(|i| i.path.clone()).collect();
+            paths.sort();
+            LinkOpportunity {
+                hash: hash.to_string(),
+                size_bytes: inodes.values().next().map(|i| i.size_bytes).unwrap_or(0),
+                paths,
+            }
+        })
+        .filter(|o| o.size_bytes > 0)
+        .collect();
+    opportunities.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.paths.cmp(&b.paths))
+    });
+
+    Ok(FarmAudit {
+        snapshots,
+        files: infos.len(),
+        pairs,
+        opportunities,
+    })
+}
+
+// Print the summary, every diverged file and every linking opportunity; returns the diverged count
+pub fn print_farm_report(audit: &FarmAudit) -> usize {
+    let wasted: u64 = audit.opportunities.iter().map(|o| o.wasted_bytes()).sum();
+
+    println!("\n=== Hardlink Farm Audit ===");
+    println!(
+        "Snapshots: {}, files: {}, paths compared across snapshots: {}",
+        audit.snapshots.len(),
+        audit.files,
+        audit.pairs.len()
+    );
+    for status in [
+        FarmStatus::Linked,
+        FarmStatus::Unlinked,
+        FarmStatus::Diverged,
+        FarmStatus::Changed,
+    ] {
+        println!(
+            "  {:<9} {}",
+            format!("{}:", status.as_str()),
+            audit.count(status)
+        );
+    }
+
+    for pair in audit
+        .pairs
+        .iter()
+        .filter(|p| p.status == FarmStatus::Diverged)
+    {
+        println!(
+            "diverged: {} ({} and {} have the same size and mtime but different content)",
+            pair.relative, pair.older, pair.newer
+        );
+    }
+
+    println!(
+        "Linking opportunities: {} group(s), {:.2} MB reclaimable",
+        audit.opportunities.len(),
+        wasted as f64 / 1_048_576.0
+    );
+    for opportunity in &audit.opportunities {
+        println!(
+            "unlinked: {} ({:.2} MB in {} separate inodes)",
+            opportunity.paths.join(" == "),
+            opportunity.size_bytes as f64 / 1_048_576.0,
+            opportunity.paths.len()
+        );
+    }
+
+    audit.count(FarmStatus::Diverged)
+}
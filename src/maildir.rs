@@ -0,0 +1,149 @@
+//maildir-aware dedupe: groups identical messages across folders and hardlinks them
+use crate::audit;
+use crate::resolve::{self, LinkSummary};
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// A message file parsed from its maildir name: <unique>:2,<flags>
+#[derive(Debug, Clone)]
+pub struct MaildirMessage {
+    pub path: String,
+    // Folder relative to the maildir root, INBOX for the root itself
+    pub folder: String,
+    pub unique: String,
+    pub flags: String,
+    pub size_bytes: u64,
+}
+
+// Identical messages; every copy in links is replaced by a hardlink to keep
+#[derive(Debug, Clone)]
+pub struct MaildirGroup {
+    pub hash: String,
+    pub keep: MaildirMessage,
+    pub links: Vec<MaildirMessage>,
+}
+
+/*  Split a maildir file name into its unique part and flags. The info separator is ':' but
+some filesystems force '!' or ';' instead, so all three are accepted.
+*/
+pub fn split_name(name: &str) -> (&str, &str) {
+    for separator in [":2,", "!2,", ";2,"] {
+        if let Some(index) = name.rfind(separator) {
+            return (&name[..index], &name[index + separator.len()..]);
+        }
+    }
+    (name, "")
+}
+
+// Parse a message path; only files directly inside cur/ or new/ are messages
+pub fn parse_message(root: &Path, path: &str) -> Option<MaildirMessage> {
+    let path_obj = Path::new(path);
+    let parent = path_obj.parent()?;
+    let state = parent.file_name()?.to_str()?;
+    if state != "cur" && state != "new" {
+        return None;
+    }
+
+    let name = path_obj.file_name()?.to_str()?;
+    if name.starts_with('.') {
+        return None;
+    }
+
+    let folder = parent
+        .parent()
+        .and_then(|f| f.strip_prefix(root).ok())
+        .map(|f| f.to_string_lossy().to_string())
+        .filter(|f| !f.is_empty())
+        .unwrap_or_else(|| "INBOX".to_string());
+
+    let (unique, flags) = split_name(name);
+
+    Some(MaildirMessage {
+        path: path.to_string(),
+        folder,
+        unique: unique.to_string(),
+        flags: flags.to_string(),
+        size_bytes: std::fs::metadata(path).ok()?.len(),
+    })
+}
+
+// Find identical messages anywhere under the maildir root
+pub fn scan_maildir(root: &str) -> Result<Vec<MaildirGroup>, Box<dyn Error>> {
+    let root_path = Path::new(root);
+    let messages: HashMap<String, MaildirMessage> = crate::walk(&[root])?
+        .iter()
+        .filter_map(|path| parse_message(root_path, path))
+        .map(|message| (message.path.clone(), message))
+        .collect();
+
+    println!("Found {} messages in {}", messages.len(), root);
+
+    let paths: Vec<String> = messages.keys().cloned().collect();
+    let checksums = crate::checksum(paths)?;
+
+    let mut groups: Vec<MaildirGroup> = checksums
+        .duplicates()
+        .map(|(digest, mut paths)| {
+            // Keep the first copy in path order so reruns pick the same one
+            paths.sort();
+            let mut copies: Vec<MaildirMessage> =
+                paths.iter().map(|p| messages[p].clone()).collect();
+            let keep = copies.remove(0);
+            MaildirGroup {
+                hash: crate::index::to_hex(digest),
+                keep,
+                links: copies,
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| a.keep.path.cmp(&b.keep.path));
+
+    Ok(groups)
+}
+
+// Hardlink every duplicate to its group's kept copy, see resolve::link_copy for the checks
+pub fn link_groups(groups: &[MaildirGroup], dry_run: bool) -> Result<LinkSummary, Box<dyn Error>> {
+    if !dry_run {
+        crate::guard::require()?;
+    }
+    let mut summary = LinkSummary::default();
+
+    for group in groups {
+        let keep = PathBuf::from(&group.keep.path);
+
+        let context = audit::Context {
+            hash: &group.hash,
+            policy: "maildir: keep the first copy in path order",
+        };
+
+        for copy in &group.links {
+            resolve::link_copy(
+                &keep,
+                Path::new(&copy.path),
+                copy.size_bytes,
+                dry_run,
+                &context,
+                &mut summary,
+            )?;
+        }
+    }
+
+    Ok(summary)
+}
+
+pub fn print_maildir_report(groups: &[MaildirGroup]) {
+    println!("\n=== Duplicate Messages ===");
+    println!("Found {} message(s) stored more than once", groups.len());
+
+    for group in groups {
+        println!(
+            "{} [{}] flags={}",
+            group.keep.unique, group.keep.folder, group.keep.flags
+        );
+        for copy in &group.links {
+            println!("  also in [{}] flags={}", copy.folder, copy.flags);
+        }
+    }
+}
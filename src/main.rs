@@ -12,8 +12,249 @@ use clap::Parser;
 struct Cli {
     #[clap(subcommand)]
     command: Option<Commands>,
+    #[clap(
+        long,
+        global = true,
+        help = "Draw no progress bars and print no progress messages (bars are also off when stderr isn't a terminal)"
+    )]
+    quiet: bool,
+    #[clap(
+        long,
+        global = true,
+        help = "Read with every worker thread instead of adapting readers to the observed IO latency"
+    )]
+    static_io: bool,
+    #[clap(
+        long,
+        global = true,
+        help = "Pin hashing workers to CPUs and split hashing work per NUMA node"
+    )]
+    pin_threads: bool,
+    #[clap(
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Worker threads for walking and hashing [default: one per CPU]"
+    )]
+    threads: Option<u64>,
+    #[clap(
+        long,
+        global = true,
+        default_value = "md5",
+        value_parser = rdedupe::hasher::parse_hasher,
+        help = "Content hash: md5, blake3, sha256 or xxhash64 (fast, not collision resistant)"
+    )]
+    hash: rdedupe::hasher::Hasher,
+    #[clap(
+        long,
+        global = true,
+        value_name = "URL",
+        help = "Share checksums with other hosts through a cache server, e.g. redis://cache:6379/0"
+    )]
+    shared_cache: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "FILE",
+        conflicts_with = "shared_cache",
+        help = "Local checksum cache, so files unchanged since an earlier run aren't hashed again [default: ~/.cache/rdedupe/checksums.jsonl]"
+    )]
+    cache: Option<std::path::PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        conflicts_with_all = ["cache", "shared_cache"],
+        help = "Hash every file, without reading or filling the local checksum cache"
+    )]
+    no_cache: bool,
+    #[clap(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "Append every link or delete to this tamper-evident audit log"
+    )]
+    audit_log: Option<std::path::PathBuf>,
 }
 
+//walk options shared by the scanning commands
+#[derive(clap::Args)]
+struct WalkArgs {
+    #[clap(long, help = "Descend into .git directories")]
+    include_git: bool,
+    #[clap(
+        long,
+        help = "Skip files identical to their committed version in a git repository"
+    )]
+    skip_reproducible: bool,
+    #[clap(
+        long,
+        value_name = "EXPR",
+        value_parser = rdedupe::filter::parse_filter,
+        help = "Only walk files matching pattern=TEXT, size>4k, size<1g, age>90d, age<2w or owner=USER (repeatable, all must match)"
+    )]
+    filter: Vec<std::sync::Arc<dyn rdedupe::filter::FileFilter>>,
+    #[clap(
+        long,
+        value_name = "SIZE",
+        value_parser = rdedupe::generate::parse_size,
+        help = "Skip files smaller than this, e.g. 4k or 10MB"
+    )]
+    min_size: Option<u64>,
+    #[clap(
+        long,
+        value_name = "SIZE",
+        value_parser = rdedupe::generate::parse_size,
+        help = "Skip files larger than this, e.g. 1g or 500MB"
+    )]
+    max_size: Option<u64>,
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Stop walking after this many files"
+    )]
+    max_files: Option<u64>,
+    #[clap(
+        long,
+        value_name = "MATCH",
+        value_parser = rdedupe::matcher::parse_matcher,
+        help = "Only walk files matching this: text, a glob such as **/*.jpg, or re:REGEX (repeatable, any may match)"
+    )]
+    include: Vec<rdedupe::matcher::Matcher>,
+    #[clap(
+        long,
+        value_name = "MATCH",
+        value_parser = rdedupe::matcher::parse_matcher,
+        help = "Skip files and directories matching this, without descending into them (repeatable)"
+    )]
+    exclude: Vec<rdedupe::matcher::Matcher>,
+    #[clap(
+        long,
+        help = "Descend into symlinked directories and hash symlinked files, each file once whatever name reaches it"
+    )]
+    follow_symlinks: bool,
+    #[clap(
+        long,
+        help = "Walk one name of files hardlinked together, so they aren't reported as duplicates"
+    )]
+    skip_hardlinks: bool,
+    #[clap(
+        long,
+        help = "Walk what .gitignore and .rdedupeignore files in the scanned tree ignore"
+    )]
+    no_ignore: bool,
+    #[clap(
+        long,
+        help = "Walk hidden files and directories, whose name starts with a dot"
+    )]
+    hidden: bool,
+}
+
+impl WalkArgs {
+    fn options(&self) -> rdedupe::WalkOptions {
+        let mut filters = rdedupe::filter::FilterChain::default();
+        for filter in &self.filter {
+            filters.push(filter.clone());
+        }
+        if let Some(size) = rdedupe::filter::Size::within(self.min_size, self.max_size) {
+            filters.push(std::sync::Arc::new(size));
+        }
+        if !self.include.is_empty() {
+            filters.push(std::sync::Arc::new(rdedupe::matcher::AnyOf(
+                self.include.clone(),
+            )));
+        }
+        rdedupe::WalkOptions {
+            include_git: self.include_git,
+            skip_reproducible: self.skip_reproducible,
+            filters,
+            exclude: self.exclude.clone(),
+            follow_links: self.follow_symlinks,
+            skip_hardlinks: self.skip_hardlinks,
+            max_files: self.max_files.map(|max| max as usize),
+            ignore_files: !self.no_ignore,
+            skip_hidden: !self.hidden,
+        }
+    }
+
+    // The walk options with --pattern as one more filter
+    fn options_matching(&self, pattern: &str) -> rdedupe::WalkOptions {
+        match pattern {
+            "" => self.options(),
+            _ => self
+                .options()
+                .filter(rdedupe::filter::Pattern(pattern.to_string())),
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+enum ArchiveCommands {
+    //chunk a tree into the repository as a new snapshot
+    Create {
+        #[clap(long)]
+        repo: String,
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(long, help = "Snapshot name [default: snapshot-<unix time>]")]
+        name: Option<String>,
+    },
+    //restore a snapshot into a directory
+    Extract {
+        #[clap(long)]
+        repo: String,
+        #[clap(long)]
+        snapshot: String,
+        #[clap(long)]
+        dest: String,
+    },
+    //list the snapshots in a repository
+    List {
+        #[clap(long)]
+        repo: String,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum CacheCommands {
+    //drop the entries of files deleted or changed since they were hashed
+    Prune,
+}
+
+#[derive(clap::Subcommand)]
+enum TagCommands {
+    //tag a duplicate group by its ID (the content_hash/duplicate_group of reports)
+    Add {
+        group: String,
+        #[clap(required = true, value_parser = parse_tag)]
+        tags: Vec<String>,
+        #[clap(long, help = "Tag store [default: ~/.local/share/rdedupe/tags.json]")]
+        store: Option<std::path::PathBuf>,
+    },
+    //take tags off a group
+    Remove {
+        group: String,
+        #[clap(required = true)]
+        tags: Vec<String>,
+        #[clap(long, help = "Tag store [default: ~/.local/share/rdedupe/tags.json]")]
+        store: Option<std::path::PathBuf>,
+    },
+    //list tagged groups
+    List {
+        #[clap(long, help = "Only groups with this tag")]
+        tag: Option<String>,
+        #[clap(long, help = "Tag store [default: ~/.local/share/rdedupe/tags.json]")]
+        store: Option<std::path::PathBuf>,
+    },
+}
+
+fn parse_tag(tag: &str) -> Result<String, String> {
+    rdedupe::tags::validate_tag(tag).map(|_| tag.to_string())
+}
+
+// Parsed once per run, so the size of the dedupe variant doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Parser)]
 enum Commands {
     Search {
@@ -21,34 +262,892 @@ enum Commands {
         path: String,
         #[clap(long, default_value = "")]
         pattern: String,
+        #[clap(flatten)]
+        walk: WalkArgs,
     },
 
     Dedupe {
+        #[clap(
+            value_name = "ROOT",
+            conflicts_with = "path",
+            help = "Directories to scan, duplicates are found across all of them [default: ., or the profile's cache directories]"
+        )]
+        roots: Vec<String>,
+        #[clap(long, help = "Directory to scan, like a single ROOT")]
+        path: Option<String>,
+        #[clap(
+            long,
+            help = "Only files whose path contains this (repeat to count and label several patterns)"
+        )]
+        pattern: Vec<String>,
+        #[clap(long, help = "Generate detailed CSV report")]
+        csv: Option<String>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Save the analyzed scan as Parquet, e.g. for `rdedupe diff`"
+        )]
+        parquet: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["memory_budget", "staged"],
+            help = "Save every file's path, size, hash and mtime as a snapshot, e.g. for `rdedupe diff`"
+        )]
+        snapshot: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Write the duplicates here: rows as CSV or Parquet, or one JSON object per group"
+        )]
+        output: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_parser = rdedupe::report::parse_format,
+            help = "Format of --output: csv, json, parquet or ndjson [default: by extension, else csv]; ndjson without --output streams the scan's events to stdout and exits 0 with no duplicates, 1 with some, 2 on scan errors"
+        )]
+        format: Option<rdedupe::report::Format>,
+        #[clap(
+            long,
+            help = "Break reclaimable space down by directory depth and top-level directory"
+        )]
+        waste: bool,
+        #[clap(
+            long,
+            help = "Report space already saved by hardlinks and shared extents next to what remains"
+        )]
+        savings: bool,
+        #[clap(
+            long,
+            conflicts_with_all = ["memory_budget", "staged"],
+            help = "Report whole duplicated directory trees, only the highest ones"
+        )]
+        dirs: bool,
+        #[clap(
+            long,
+            value_name = "HASH",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "dhash",
+            value_parser = ["dhash", "phash"],
+            conflicts_with_all = ["memory_budget", "staged"],
+            help = "Also report JPEG and PNG images that look alike, by perceptual hash (dhash or phash) [needs the perceptual feature]"
+        )]
+        perceptual: Option<String>,
+        #[clap(
+            long,
+            value_name = "BITS",
+            requires = "perceptual",
+            help = "Most bits two perceptual hashes may differ by to count as near-duplicates [default: 10]"
+        )]
+        perceptual_distance: Option<u32>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Write each group's canonical copy under the keep policy as JSON"
+        )]
+        canonical_map: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            help = "Write one CSV per top-level home directory plus a global report into this directory"
+        )]
+        per_home: Option<String>,
+        #[clap(
+            long,
+            help = "Use a preset profile (built-in or from the config file, see `rdedupe profiles`)"
+        )]
+        profile: Option<String>,
+        #[clap(long, help = "Config file with user presets")]
+        config: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["profile", "memory_budget"],
+            help = "TOML rules deciding per group which copies to delete or hardlink"
+        )]
+        rules: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_parser = rdedupe::rules::parse_action,
+            conflicts_with_all = ["profile", "rules", "memory_budget"],
+            help = "Resolve every group: delete, hardlink or symlink the copies that are not kept"
+        )]
+        action: Option<rdedupe::rules::Disposition>,
+        #[clap(
+            long,
+            requires = "action",
+            value_parser = rdedupe::resolve::parse_keep,
+            help = "Copy --action keeps: first, oldest, newest or shortest-path [default: first]"
+        )]
+        keep: Option<rdedupe::resolve::Keep>,
+        #[clap(
+            long,
+            value_name = "DIR",
+            requires = "action",
+            help = "Keep the copy under this directory whenever a group has one [default: the copy under the earliest ROOT]"
+        )]
+        prefer_root: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "AGE",
+            help = "Only act on copies untouched for this long, e.g. 2y or 90d (overrides the profile)"
+        )]
+        older_than: Option<String>,
+        #[clap(
+            long,
+            requires = "older_than",
+            value_parser = rdedupe::profile::parse_age_field,
+            help = "Age by modified, accessed or both times [default: modified]"
+        )]
+        age_by: Option<rdedupe::profile::AgeField>,
+        #[clap(
+            long,
+            value_name = "MB",
+            conflicts_with_all = [
+                "csv",
+                "parquet",
+                "output",
+                "waste",
+                "savings",
+                "canonical_map",
+                "per_home",
+                "profile"
+            ],
+            help = "Cap the checksum index at this many MB, spilling the rest to temporary files"
+        )]
+        memory_budget: Option<usize>,
+        #[clap(
+            long,
+            help = "Print the planned profile, rules or --action changes without touching any file"
+        )]
+        dry_run: bool,
+        #[clap(
+            long,
+            help = "Allow profile actions inside git repositories and outside the scanned directories"
+        )]
+        force: bool,
+        #[clap(
+            long,
+            value_name = "DIR",
+            help = "Directory profile, rules and --action changes may modify (repeatable, required for actions)"
+        )]
+        allow_root: Vec<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_parser = parse_tag,
+            help = "Only act on duplicate groups carrying this tag (see `rdedupe tag`)"
+        )]
+        only_tag: Option<String>,
+        #[clap(long, help = "Tag store [default: ~/.local/share/rdedupe/tags.json]")]
+        tag_store: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            help = "Compare hash-equal files byte-for-byte before reporting them as duplicates"
+        )]
+        verify: bool,
+        #[clap(
+            long,
+            value_parser = rdedupe::paranoia::parse_level,
+            conflicts_with = "verify",
+            help = "Detection rigor: quick (size only), standard (size and hash) or paranoid (hash and byte compare) [default: standard, paranoid with --verify]"
+        )]
+        paranoia: Option<rdedupe::paranoia::Paranoia>,
+        #[clap(
+            long,
+            value_name = "HOST:PORT",
+            conflicts_with_all = ["profile", "memory_budget", "decompress", "normalize"],
+            help = "Hash on this `rdedupe worker` instead of locally (repeat for several workers)"
+        )]
+        remote: Vec<String>,
+        #[clap(
+            long,
+            value_name = "TOKEN",
+            requires = "remote",
+            help = "Token the workers were started with [default: $RDEDUPE_TOKEN]"
+        )]
+        remote_token: Option<String>,
+        #[clap(
+            long,
+            value_name = "DURATION",
+            value_parser = rdedupe::timebox::parse_duration,
+            conflicts_with_all = ["profile", "rules", "action", "memory_budget", "remote"],
+            help = "Stop hashing after this long, e.g. 90s, 30m or 2h, and report the duplicates found so far"
+        )]
+        max_duration: Option<std::time::Duration>,
+        #[clap(
+            long,
+            conflicts_with_all = ["profile", "rules", "action", "verify", "memory_budget", "remote"],
+            help = "Hash .gz, .zst and .bz2 files by their decompressed content (report only)"
+        )]
+        decompress: bool,
+        #[clap(
+            long,
+            value_name = "NAME",
+            value_delimiter = ',',
+            value_parser = rdedupe::normalize::parse_builtin,
+            conflicts_with_all = ["profile", "rules", "action", "verify", "memory_budget", "remote"],
+            help = "Normalize content before hashing: eol, encoding, image-metadata (comma separated, report only)"
+        )]
+        normalize: Vec<String>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Checkpoint to continue from, written when --max-duration runs out and removed once a scan completes"
+        )]
+        resume: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "SIZE",
+            num_args = 0..=1,
+            default_missing_value = "64k",
+            value_parser = rdedupe::generate::parse_size,
+            conflicts_with_all = ["profile", "memory_budget", "remote", "max_duration", "resume", "decompress", "normalize"],
+            help = "Compare same-size files by their first and last SIZE [default: 64k] and hash only those still colliding in full; the report lists only those"
+        )]
+        staged: Option<u64>,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //scan and write a reviewable, signed plan of per-file actions for `rdedupe apply`
+    Plan {
         #[clap(long, default_value = ".")]
         path: String,
         #[clap(long, default_value = "")]
         pattern: String,
-        #[clap(long, help = "Generate detailed CSV report")]
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "TOML rules deciding each group's actions"
+        )]
+        rules: std::path::PathBuf,
+        #[clap(long, value_name = "FILE", help = "Where to write the plan")]
+        output: std::path::PathBuf,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Sign the plan with a key derived from this file's contents"
+        )]
+        key: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            help = "Compare hash-equal files byte-for-byte before reporting them as duplicates"
+        )]
+        verify: bool,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //carry out a plan from `rdedupe plan` after re-checking every file it touches
+    Apply {
+        plan: std::path::PathBuf,
+        #[clap(long, value_name = "FILE", help = "Key file the plan was signed with")]
+        key: Option<std::path::PathBuf>,
+        #[clap(long, help = "Print the planned actions without touching any file")]
+        dry_run: bool,
+        #[clap(
+            long,
+            help = "Allow actions outside the directories the plan was made from"
+        )]
+        force: bool,
+        #[clap(
+            long,
+            value_name = "DIR",
+            help = "Directory actions may modify (repeatable, required unless --dry-run)"
+        )]
+        allow_root: Vec<std::path::PathBuf>,
+    },
+
+    //browse and amend a plan in the terminal, then apply or export it
+    Review {
+        plan: std::path::PathBuf,
+        #[clap(long, value_name = "FILE", help = "Key file the plan was signed with")]
+        key: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Where to export the amended plan [default: the plan]"
+        )]
+        output: Option<std::path::PathBuf>,
+        #[clap(long, help = "Only print what applying would do")]
+        dry_run: bool,
+        #[clap(
+            long,
+            help = "Allow actions outside the directories the plan was made from"
+        )]
+        force: bool,
+        #[clap(
+            long,
+            value_name = "DIR",
+            help = "Directory actions may modify (repeatable, required to apply)"
+        )]
+        allow_root: Vec<std::path::PathBuf>,
+    },
+
+    //scan, then pick the copies to keep in each duplicate group in the terminal and apply the rest
+    Interactive {
+        #[clap(default_value = ".")]
+        path: String,
+        #[clap(
+            long,
+            help = "Compare hash-equal files byte-for-byte before reporting them as duplicates"
+        )]
+        verify: bool,
+        #[clap(long, help = "Only print what applying would do")]
+        dry_run: bool,
+        #[clap(long, help = "Allow actions outside the scanned directory")]
+        force: bool,
+        #[clap(
+            long,
+            value_name = "DIR",
+            help = "Directory actions may modify (repeatable, required to apply)"
+        )]
+        allow_root: Vec<std::path::PathBuf>,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //hash files for remote `dedupe --remote` clients, run where the data lives
+    Worker {
+        #[clap(long, default_value = "127.0.0.1:7878")]
+        listen: String,
+        #[clap(
+            long,
+            value_name = "TOKEN",
+            help = "Shared secret every client must present [default: $RDEDUPE_TOKEN]"
+        )]
+        token: Option<String>,
+        #[clap(long, help = "Directory the clients' scanned root corresponds to here")]
+        root: std::path::PathBuf,
+    },
+
+    //check that every source file has an identical copy in the backup
+    VerifyBackup {
+        #[clap(long)]
+        source: String,
+        #[clap(long)]
+        backup: String,
+        #[clap(long, help = "Write per-file verification results to CSV")]
+        csv: Option<String>,
+    },
+
+    //report files duplicated across container image layers
+    Layers {
+        #[clap(
+            long = "path",
+            required = true,
+            help = "Image tarball, layer tarball, OCI layout or directory of extracted layers (repeatable)"
+        )]
+        paths: Vec<String>,
+        #[clap(long, help = "Write duplicated layer files to CSV")]
         csv: Option<String>,
     },
 
+    //hardlink identical messages across maildir folders
+    Maildir {
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(long, help = "Print the planned hardlinks without touching any file")]
+        dry_run: bool,
+        #[clap(
+            long,
+            help = "Allow linking inside a git repository and outside the scanned directory"
+        )]
+        force: bool,
+        #[clap(
+            long,
+            value_name = "DIR",
+            help = "Directory links may be made in (repeatable, required unless --dry-run)"
+        )]
+        allow_root: Vec<std::path::PathBuf>,
+    },
+
+    //audit a directory of hardlinked snapshots (rsnapshot, rsync --link-dest)
+    Linkfarm {
+        #[clap(long, help = "Directory whose subdirectories are the snapshots")]
+        path: String,
+    },
+
+    //deduplicated chunk-store archiver
+    Archive {
+        #[clap(subcommand)]
+        command: ArchiveCommands,
+    },
+
+    //measure hash and IO throughput and recommend settings for this machine
+    Bench {
+        #[clap(
+            long,
+            default_value = ".",
+            help = "Directory on the volume to benchmark"
+        )]
+        path: String,
+        #[clap(long, default_value_t = 64, help = "Amount of synthetic data in MB")]
+        size_mb: usize,
+    },
+
+    //create a reproducible synthetic tree with controlled duplication
+    GenTree {
+        #[clap(help = "Directory to create (must be empty or missing)")]
+        dir: String,
+        #[clap(
+            long,
+            default_value = "1k",
+            value_parser = rdedupe::generate::parse_count,
+            help = "Number of files, e.g. 100k"
+        )]
+        files: u64,
+        #[clap(
+            long,
+            default_value_t = 0.3,
+            help = "Share of files that duplicate another file"
+        )]
+        dupe_ratio: f64,
+        #[clap(
+            long,
+            default_value = "4k",
+            value_parser = rdedupe::generate::parse_size,
+            help = "Average file size, e.g. 4k or 1m"
+        )]
+        size: u64,
+        #[clap(long, default_value_t = 1)]
+        seed: u64,
+    },
+
+    //maintain the local checksum cache
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommands,
+    },
+
+    //tag duplicate groups during review and act on them by tag later
+    Tag {
+        #[clap(subcommand)]
+        command: TagCommands,
+    },
+
+    //show what was done, from an audit log written with --audit-log
+    Audit {
+        #[clap(long)]
+        log: std::path::PathBuf,
+        #[clap(
+            long,
+            value_parser = rdedupe::audit::parse_date,
+            help = "Only actions on or after this day (YYYY-MM-DD, UTC)"
+        )]
+        since: Option<u64>,
+        #[clap(
+            long,
+            value_parser = rdedupe::audit::parse_date,
+            help = "Only actions before this day (YYYY-MM-DD, UTC)"
+        )]
+        until: Option<u64>,
+    },
+
+    //compare two saved scans: the duplicate groups of --parquet or --csv output, or every file of two snapshots
+    Diff {
+        #[clap(help = "Earlier scan: a --parquet scan or a --snapshot")]
+        before: std::path::PathBuf,
+        #[clap(help = "Later scan, of the same kind")]
+        after: std::path::PathBuf,
+    },
+
+    //list the built-in and user-defined presets
+    Profiles {
+        #[clap(long, help = "Config file with user presets")]
+        config: Option<std::path::PathBuf>,
+    },
+
+    //list the largest files and directories, without looking for duplicates
+    Largest {
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(long, default_value = "")]
+        pattern: String,
+        #[clap(
+            long,
+            default_value_t = 10,
+            help = "How many files and directories to list"
+        )]
+        top: usize,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //estimate duplicate waste from a random sample before committing to a full scan
+    Estimate {
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(long, default_value = "")]
+        pattern: String,
+        #[clap(
+            long,
+            default_value_t = 1000,
+            help = "How many size-matched files to sample"
+        )]
+        sample: usize,
+        #[clap(
+            long,
+            default_value_t = 64,
+            help = "Files of the same size each sampled file is compared with, at most"
+        )]
+        bucket_cap: usize,
+        #[clap(long, help = "Seed for the sample [default: random]")]
+        seed: Option<u64>,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //answer whether a file or pattern has duplicates, stopping at the first ones found
+    Exists {
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(
+            long,
+            required_unless_present = "pattern",
+            conflicts_with = "pattern",
+            help = "Look for copies of this file"
+        )]
+        file: Option<String>,
+        #[clap(
+            long,
+            help = "Look for duplicates among the files whose path contains this"
+        )]
+        pattern: Option<String>,
+        #[clap(
+            long,
+            default_value_t = 1,
+            help = "Stop after this many duplicates; exit 0 if they were found, 1 if not, 2 on error"
+        )]
+        count: usize,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
+    //scan for duplicates and write the summary statistics
+    Report {
+        #[clap(long, default_value = ".")]
+        path: String,
+        #[clap(long, default_value = "")]
+        pattern: String,
+        #[clap(long, value_name = "FILE", help = "Write the statistics here as CSV")]
+        output: Option<std::path::PathBuf>,
+        #[clap(
+            long,
+            value_name = "FILE",
+            help = "Write one row per duplicate group here as CSV: hash, file count, size, wasted bytes and example paths"
+        )]
+        groups: Option<std::path::PathBuf>,
+        #[clap(long, help = "Compare hash-equal files byte-for-byte")]
+        verify: bool,
+        #[clap(flatten)]
+        walk: WalkArgs,
+    },
+
     //create count with path and pattern defaults for both
     Count {
         #[clap(long, default_value = ".")]
         path: String,
         #[clap(long, default_value = "")]
         pattern: String,
+        #[clap(flatten)]
+        walk: WalkArgs,
     },
 }
 
+fn print_link_summary(summary: &rdedupe::resolve::LinkSummary, dry_run: bool, noun: &str) {
+    let verb = if dry_run { "Would link" } else { "Linked" };
+    println!(
+        "\n{} {} {}, {:.2} MB reclaimed, {} skipped",
+        verb,
+        summary.linked,
+        noun,
+        summary.bytes_saved as f64 / 1_048_576.0,
+        summary.skipped
+    );
+}
+
+// Confine file changes to the allowed roots, if any were given; a bad root ends the run
+fn install_guard(allow_root: &[std::path::PathBuf], roots: &[String], force: bool) {
+    if allow_root.is_empty() {
+        return;
+    }
+    match rdedupe::guard::Guard::new(allow_root, roots, force) {
+        Ok(guard) => rdedupe::guard::install(guard),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+fn print_action_summary(summary: &rdedupe::resolve::LinkSummary, dry_run: bool) {
+    let verb = if dry_run { "Would apply" } else { "Applied" };
+    println!(
+        "\n{}: {} linked, {} deleted, {} symlinked, {:.2} MB reclaimed, {} skipped",
+        verb,
+        summary.linked,
+        summary.deleted,
+        summary.symlinked,
+        summary.bytes_saved as f64 / 1_048_576.0,
+        summary.skipped
+    );
+}
+
+fn print_io_stats() {
+    if let Some(stats) = rdedupe::throttle::stats() {
+        println!(
+            "Adaptive IO: {} parallel reader(s) at the end (range {}-{} of {}) over {} reads",
+            stats.limit, stats.lowest, stats.highest, stats.max, stats.reads
+        );
+    }
+}
+
+fn print_cache_stats() {
+    if let Some(backend) = rdedupe::cache::describe() {
+        let stats = rdedupe::cache::stats();
+        println!(
+            "Checksum cache ({}): {} hit(s), {} miss(es)",
+            backend, stats.hits, stats.misses
+        );
+    }
+}
+
+// After a time-boxed scan: either say where it stopped and keep the checkpoint, or drop it
+fn finish_time_box(resume: Option<&std::path::Path>, analyzed: usize) {
+    if let Err(e) = rdedupe::timebox::finish() {
+        eprintln!("Error: checkpoint: {}", e);
+    }
+    let Some(stopped_at) = rdedupe::timebox::stopped_at() else {
+        return;
+    };
+    println!(
+        "\nOut of time at {}: scan incomplete, {} file(s) hashed",
+        stopped_at, analyzed
+    );
+    match resume {
+        Some(path) => println!(
+            "Checkpoint saved to: {} (rerun with --resume {} to continue)",
+            path.display(),
+            path.display()
+        ),
+        None => println!("Pass --resume FILE to keep the hashes for a later run"),
+    }
+}
+
+fn run_tag(command: TagCommands) -> Result<(), Box<dyn std::error::Error>> {
+    use rdedupe::tags::{default_path, TagStore};
+
+    let store_path = |store: Option<std::path::PathBuf>| {
+        store
+            .or_else(default_path)
+            .ok_or("no tag store, pass --store")
+    };
+
+    match command {
+        TagCommands::Add { group, tags, store } => {
+            let path = store_path(store)?;
+            let mut store = TagStore::load(&path)?;
+            for tag in &tags {
+                store.add(&group, tag);
+            }
+            store.save(&path)?;
+            println!("{}: {}", group, store.tags(&group).join(", "));
+        }
+        TagCommands::Remove { group, tags, store } => {
+            let path = store_path(store)?;
+            let mut store = TagStore::load(&path)?;
+            let removed = tags.iter().filter(|tag| store.remove(&group, tag)).count();
+            store.save(&path)?;
+            println!("Removed {} tag(s) from {}", removed, group);
+        }
+        TagCommands::List { tag, store } => {
+            let store = TagStore::load(&store_path(store)?)?;
+            let mut listed = 0;
+            for (group, tags) in store.entries() {
+                if tag.as_ref().is_some_and(|t| !tags.contains(&t.as_str())) {
+                    continue;
+                }
+                println!("{}: {}", group, tags.join(", "));
+                listed += 1;
+            }
+            println!("{} tagged group(s)", listed);
+        }
+    }
+
+    Ok(())
+}
+
+fn run_archive(command: ArchiveCommands) -> Result<(), Box<dyn std::error::Error>> {
+    use rdedupe::archive::Repository;
+    use std::path::Path;
+
+    match command {
+        ArchiveCommands::Create { repo, path, name } => {
+            let repository = Repository::init(Path::new(&repo))?;
+            let (snapshot, stats) = repository.create_snapshot(&path, name.as_deref())?;
+            println!(
+                "Snapshot '{}': {} files, {} chunks, {:.2} MB read, {:.2} MB stored",
+                snapshot.name,
+                stats.files,
+                stats.chunks,
+                stats.total_bytes as f64 / 1_048_576.0,
+                stats.stored_bytes as f64 / 1_048_576.0
+            );
+            if stats.skipped > 0 {
+                println!("Left out {} unreadable file(s)", stats.skipped);
+            }
+        }
+        ArchiveCommands::Extract {
+            repo,
+            snapshot,
+            dest,
+        } => {
+            let restored =
+                Repository::open(Path::new(&repo))?.extract(&snapshot, Path::new(&dest))?;
+            println!(
+                "Restored {} files from '{}' into {}",
+                restored, snapshot, dest
+            );
+        }
+        ArchiveCommands::List { repo } => {
+            for snapshot in Repository::open(Path::new(&repo))?.snapshots()? {
+                let size: u64 = snapshot.files.iter().map(|f| f.size_bytes).sum();
+                println!(
+                    "{}  {} files  {:.2} MB  from {}",
+                    snapshot.name,
+                    snapshot.files.len(),
+                    size as f64 / 1_048_576.0,
+                    snapshot.source
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Paths a scan had to leave out are warnings on the command line, the scan goes on without them
+struct PrintSkipped;
+
+impl rdedupe::diagnostics::DiagnosticSink for PrintSkipped {
+    fn report(&self, diagnostic: rdedupe::diagnostics::Diagnostic) {
+        use rdedupe::diagnostics::Diagnostic;
+        match diagnostic {
+            Diagnostic::UnreadablePath { path, reason }
+            | Diagnostic::SkippedFile { path, reason } => {
+                println!("Warning: skipped {}: {}", path, reason)
+            }
+            Diagnostic::WorkerFailed { address, reason } => {
+                println!("Warning: worker {} {}", address, reason)
+            }
+            _ => {}
+        }
+    }
+}
+
 fn main() {
     let cli = Cli::parse();
+    // Every line of an event stream is JSON, so diagnostics and progress become events too
+    let streaming = matches!(
+        &cli.command,
+        Some(Commands::Dedupe {
+            format: Some(rdedupe::report::Format::Ndjson),
+            output: None,
+            ..
+        })
+    );
+    if streaming {
+        rdedupe::diagnostics::set_sink(Box::new(rdedupe::events::Ndjson));
+        rdedupe::progress::set_sink(Box::new(rdedupe::events::Ndjson));
+    } else {
+        rdedupe::diagnostics::set_sink(Box::new(PrintSkipped));
+    }
+    // Bars redrawn into a log are noise, so only a terminal gets them
+    if !cli.quiet {
+        match std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+            true => rdedupe::progress::set_sink(Box::new(rdedupe::progress::Indicatif)),
+            false => rdedupe::progress::set_sink(Box::new(rdedupe::progress::Messages)),
+        }
+    }
+    rdedupe::throttle::set_enabled(!cli.static_io);
+    rdedupe::hasher::set_hasher(cli.hash);
+    if let Some(threads) = cli.threads {
+        if let Err(e) = rdedupe::set_threads(threads as usize) {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+    // Backup checks and paranoid scans hash what is on disk now, never a remembered hash
+    let verifying = matches!(&cli.command, Some(Commands::VerifyBackup { .. }))
+        || matches!(
+            &cli.command,
+            Some(Commands::Dedupe { verify: true, .. })
+                | Some(Commands::Dedupe {
+                    paranoia: Some(rdedupe::paranoia::Paranoia::Paranoid),
+                    ..
+                })
+        );
+    if let Some(url) = cli.shared_cache.as_ref().filter(|_| !verifying) {
+        match rdedupe::cache::open(url) {
+            Ok(backend) => rdedupe::cache::set_backend(backend),
+            Err(e) => {
+                eprintln!("Error: shared cache: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+    // The local cache is on unless a shared cache or a --resume checkpoint takes its place
+    let resuming = matches!(
+        &cli.command,
+        Some(Commands::Dedupe {
+            resume: Some(_),
+            ..
+        })
+    );
+    let maintaining = matches!(&cli.command, Some(Commands::Cache { .. }));
+    let local_cache = cli
+        .cache
+        .clone()
+        .or_else(rdedupe::cache::default_local_path);
+    if cli.shared_cache.is_none() && !cli.no_cache && !resuming && !maintaining && !verifying {
+        if let Some(path) = &local_cache {
+            match rdedupe::cache::LocalCache::open(path) {
+                Ok(cache) => rdedupe::cache::set_backend(Box::new(cache)),
+                Err(e) => eprintln!("Warning: checksum cache {}, hashing without it", e),
+            }
+        }
+    }
+    if let Some(path) = &cli.audit_log {
+        if let Err(e) = rdedupe::audit::open(path) {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    }
+    if cli.pin_threads {
+        match rdedupe::affinity::enable_pinning() {
+            Ok((nodes, workers)) => rdedupe::progress::message(&format!(
+                "Pinned {} hashing workers across {} NUMA node(s)",
+                workers, nodes
+            )),
+            Err(e) => eprintln!("Warning: could not pin threads: {}", e),
+        }
+    }
     match cli.command {
-        Some(Commands::Search { path, pattern }) => {
+        Some(Commands::Search {
+            path,
+            pattern,
+            walk,
+        }) => {
             println!("Searching for files in {} matching {}", path, pattern);
 
-            let files = rdedupe::walk(&path).unwrap();
-            let files = rdedupe::find(files, &pattern);
+            let files = match rdedupe::walk_with_options(&path, &walk.options_matching(&pattern)) {
+                Ok(files) => files,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
 
             //print count of files matching pattern
             println!("Found {} files matching {}", files.len(), pattern);
@@ -58,36 +1157,1071 @@ fn main() {
                 println!("{}", file);
             }
         }
-        Some(Commands::Dedupe { path, pattern, csv }) => {
+        Some(Commands::Dedupe {
+            roots,
+            path,
+            pattern: patterns,
+            csv,
+            parquet,
+            snapshot,
+            output,
+            format,
+            waste,
+            savings,
+            dirs,
+            perceptual,
+            perceptual_distance,
+            canonical_map,
+            per_home,
+            profile,
+            config,
+            rules,
+            action,
+            keep,
+            prefer_root,
+            older_than,
+            age_by,
+            memory_budget,
+            dry_run,
+            force,
+            allow_root,
+            only_tag,
+            tag_store,
+            verify,
+            paranoia,
+            remote,
+            remote_token,
+            max_duration,
+            resume,
+            decompress,
+            normalize,
+            staged,
+            walk,
+        }) => {
             //dedupe files matching a pattern with enhanced reporting
             //display the progress bar using indicatif
-            rdedupe::display_thread_info();
-            println!("Analyzing files in {} matching '{}'", path, pattern);
-            
+            if format.is_some() && output.is_none() && !streaming {
+                eprintln!("Error: --format csv, json and parquet need --output, only ndjson streams to stdout");
+                std::process::exit(2);
+            }
+            if !streaming {
+                rdedupe::display_thread_info();
+            }
+            if !remote.is_empty() {
+                let Some(token) = rdedupe::remote::token(remote_token) else {
+                    eprintln!("Error: --remote needs --remote-token or RDEDUPE_TOKEN, the token the workers were started with");
+                    std::process::exit(2);
+                };
+                rdedupe::remote::set_workers(rdedupe::remote::Workers {
+                    addresses: remote,
+                    token,
+                });
+            }
+            let paranoia = paranoia.unwrap_or(match verify {
+                true => rdedupe::paranoia::Paranoia::Paranoid,
+                false => rdedupe::paranoia::Paranoia::Standard,
+            });
+            if paranoia == rdedupe::paranoia::Paranoia::Quick
+                && (profile.is_some()
+                    || rules.is_some()
+                    || action.is_some()
+                    || memory_budget.is_some()
+                    || rdedupe::remote::enabled()
+                    || decompress
+                    || !normalize.is_empty())
+            {
+                eprintln!(
+                    "Error: --paranoia quick only reports, it can't be combined with actions, remote hashing, --memory-budget, --decompress or --normalize"
+                );
+                std::process::exit(2);
+            }
+            if paranoia == rdedupe::paranoia::Paranoia::Quick
+                && (dirs || snapshot.is_some() || perceptual.is_some())
+            {
+                eprintln!(
+                    "Error: --dirs, --snapshot and --perceptual need content hashes, they can't be combined with --paranoia quick"
+                );
+                std::process::exit(2);
+            }
+            #[cfg(not(feature = "perceptual"))]
+            if perceptual.is_some() || perceptual_distance.is_some() {
+                eprintln!(
+                    "Error: --perceptual needs rdedupe built with the perceptual feature (cargo build --features perceptual)"
+                );
+                std::process::exit(2);
+            }
+            rdedupe::paranoia::set_level(paranoia);
+            if let Some(edge) = staged {
+                if edge == 0 {
+                    eprintln!("Error: --staged needs a SIZE above zero");
+                    std::process::exit(2);
+                }
+                rdedupe::pipeline::set_staged(edge);
+            }
+            let verify = paranoia == rdedupe::paranoia::Paranoia::Paranoid;
+            rdedupe::compressed::set_enabled(decompress);
+            rdedupe::normalize::install(
+                normalize
+                    .iter()
+                    .filter_map(|name| rdedupe::normalize::builtin(name))
+                    .collect(),
+            );
+
+            if patterns.len() > 1 && (profile.is_some() || memory_budget.is_some()) {
+                eprintln!(
+                    "Error: several --pattern values can't be combined with --profile or --memory-budget"
+                );
+                std::process::exit(2);
+            }
+            let pattern = patterns.first().cloned().unwrap_or_default();
+
+            // Huge scans trade the DataFrame report for a bounded-memory group listing
+            let path = match roots.as_slice() {
+                [] => path,
+                [root] => Some(root.clone()),
+                _ => None,
+            };
+            // No report, no breakdowns and no actions: the bare scan, as events
+            if streaming {
+                if profile.is_some()
+                    || rules.is_some()
+                    || action.is_some()
+                    || memory_budget.is_some()
+                    || rdedupe::remote::enabled()
+                    || max_duration.is_some()
+                    || resume.is_some()
+                    || decompress
+                    || !normalize.is_empty()
+                    || csv.is_some()
+                    || parquet.is_some()
+                    || snapshot.is_some()
+                    || waste
+                    || savings
+                    || dirs
+                    || perceptual.is_some()
+                    || canonical_map.is_some()
+                    || per_home.is_some()
+                {
+                    rdedupe::events::emit_failure("--format ndjson only scans, it can't be combined with reports, profiles, actions, remote hashing, --memory-budget, --max-duration, --resume, --decompress or --normalize");
+                    std::process::exit(rdedupe::events::SCAN_ERRORS);
+                }
+                let mut options = rdedupe::scan::ScanOptions::for_run(".");
+                if !roots.is_empty() {
+                    options.roots = roots.clone();
+                } else if let Some(path) = &path {
+                    options.roots = vec![path.clone()];
+                }
+                options.walk = match patterns.is_empty() {
+                    true => walk.options(),
+                    false => walk
+                        .options()
+                        .filter(move |file: &str, _: &std::fs::Metadata| {
+                            rdedupe::patterns::matches_any(&patterns, file)
+                        }),
+                };
+                options.verify = verify;
+                options.edge_bytes = Some(staged.unwrap_or(rdedupe::pipeline::EDGE_BYTES));
+                match rdedupe::scan::scan(&options) {
+                    Ok(report) => {
+                        rdedupe::events::emit_report(&report);
+                        std::process::exit(rdedupe::events::exit_code(&report));
+                    }
+                    Err(e) => {
+                        rdedupe::events::emit_failure(&e.to_string());
+                        std::process::exit(rdedupe::events::SCAN_ERRORS);
+                    }
+                }
+            }
+            if let Some(budget) = memory_budget {
+                if roots.len() > 1 {
+                    eprintln!("Error: --memory-budget scans a single root");
+                    std::process::exit(2);
+                }
+                let path = path.unwrap_or_else(|| ".".to_string());
+                let budget = budget * 1_048_576;
+                match rdedupe::spill::run_with_budget(&path, &pattern, &walk.options(), budget) {
+                    Ok(_) => {
+                        print_io_stats();
+                        print_cache_stats();
+                    }
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(2);
+                    }
+                }
+                return;
+            }
+
+            let rules = match rules.as_deref().map(rdedupe::rules::load_rules) {
+                Some(Ok(rules)) => Some(rules),
+                Some(Err(e)) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+                None => None,
+            };
+
+            let only_groups = match &only_tag {
+                Some(tag) => {
+                    let store = tag_store.or_else(rdedupe::tags::default_path);
+                    match store.map(|path| rdedupe::tags::TagStore::load(&path)) {
+                        Some(Ok(store)) => {
+                            let groups = store.groups_with(tag);
+                            println!("Acting only on {} group(s) tagged '{}'", groups.len(), tag);
+                            Some(groups)
+                        }
+                        Some(Err(e)) => {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                        None => {
+                            eprintln!("Error: no tag store, pass --tag-store");
+                            std::process::exit(2);
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let config = config.or_else(rdedupe::profile::default_config_path);
+            let profile = match profile.as_deref() {
+                Some(name) => match rdedupe::profile::lookup(name, config.as_deref()) {
+                    Ok(Some(mut profile)) => {
+                        if older_than.is_some() {
+                            profile.older_than = older_than.clone();
+                        }
+                        if let Some(age_by) = age_by {
+                            profile.age_by = age_by;
+                        }
+                        if let Err(e) = profile.age_threshold() {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                        Some(profile)
+                    }
+                    Ok(None) => {
+                        eprintln!(
+                            "Error: unknown profile '{}', run `rdedupe profiles` to list them",
+                            name
+                        );
+                        std::process::exit(2);
+                    }
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(2);
+                    }
+                },
+                None => None,
+            };
+            // Profiles carry their own age rule; this one is for --action and --rules
+            let age_threshold = match older_than.as_deref().map(rdedupe::profile::parse_age) {
+                Some(Ok(threshold)) => Some(threshold),
+                Some(Err(e)) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+                None => None,
+            };
+
+            // Profiles that know their cache layout scan those directories by default
+            let paths = match (path, &profile) {
+                (Some(path), _) => vec![path],
+                (None, _) if roots.len() > 1 => roots,
+                (None, Some(profile)) if !profile.roots.is_empty() => profile.existing_roots(),
+                (None, _) => vec![".".to_string()],
+            };
+            // A root inside another would be walked twice, every file its own duplicate
+            let distinct = rdedupe::roots::distinct(&paths);
+            for root in paths.iter().filter(|root| !distinct.contains(root)) {
+                println!(
+                    "Warning: {} repeats or is inside another root, walking it once",
+                    root
+                );
+            }
+            let paths = distinct;
+            println!(
+                "Analyzing files in {} matching '{}'",
+                paths.join(", "),
+                patterns.join("', '")
+            );
+            install_guard(&allow_root, &paths, force);
+            // --action is a single rule over the whole scan
+            let rules = rules.or_else(|| {
+                action.map(|action| {
+                    let rules = rdedupe::rules::RuleSet::everywhere(
+                        &paths,
+                        action,
+                        keep.unwrap_or_default(),
+                    );
+                    match &prefer_root {
+                        Some(root) => rules.prefer(root),
+                        None => rules,
+                    }
+                })
+            });
+
+            if let Some(checkpoint) = &resume {
+                if cli.shared_cache.is_some() {
+                    eprintln!("Error: --resume can't be combined with --shared-cache");
+                    std::process::exit(2);
+                }
+                match rdedupe::timebox::resume(checkpoint) {
+                    Ok(Some(resumed)) => println!(
+                        "Resuming from {} ({} hash(es), stopped at {})",
+                        checkpoint.display(),
+                        resumed.hashes,
+                        resumed.stopped_at.as_deref().unwrap_or("the end")
+                    ),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(2);
+                    }
+                }
+            }
+            if let Some(budget) = max_duration {
+                rdedupe::timebox::set_max_duration(budget);
+            }
+
             // Always use enhanced DataFrame functionality for better progress reporting
-            let result = rdedupe::run_with_dataframe(&path, &pattern, csv.as_deref());
+            let result = match &profile {
+                Some(profile) => rdedupe::profile::run_profile(
+                    &paths,
+                    &pattern,
+                    profile,
+                    csv.as_deref(),
+                    &walk.options(),
+                    verify,
+                )
+                .and_then(|df| {
+                    if let Some(summary) = rdedupe::profile::apply_action(
+                        &df,
+                        profile,
+                        dry_run,
+                        force,
+                        only_groups.as_ref(),
+                    )? {
+                        print_link_summary(&summary, dry_run, "file(s)");
+                    }
+                    Ok(df)
+                }),
+                None => rdedupe::run_with_patterns(
+                    &paths,
+                    &patterns,
+                    csv.as_deref(),
+                    &walk.options(),
+                    verify,
+                )
+                .and_then(|df| {
+                    if let Some(rules) = &rules {
+                        let mut planned = rdedupe::rules::plan(&df, rules)?;
+                        if let Some(only) = &only_groups {
+                            planned.retain(|action| only.contains(&action.group));
+                        }
+                        if let Some(threshold) = age_threshold {
+                            let spared = rdedupe::profile::spare_recent_actions(
+                                &mut planned,
+                                threshold,
+                                age_by.unwrap_or_default(),
+                            );
+                            println!(
+                                "Age rule: {} duplicate(s) touched within {} are kept",
+                                spared,
+                                older_than.as_deref().unwrap_or_default()
+                            );
+                        }
+                        rdedupe::rules::print_plan(&planned);
+                        let summary = rdedupe::rules::apply(&planned, dry_run)?;
+                        print_action_summary(&summary, dry_run);
+                    }
+                    Ok(df)
+                }),
+            };
 
             match result {
                 Ok(df) => {
+                    // A report that failed doesn't stop the others, but the run still fails
+                    let mut failed = false;
+                    if waste {
+                        match rdedupe::waste::breakdown(&df, &paths) {
+                            Ok(waste) => rdedupe::waste::print_breakdown(&waste),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if savings {
+                        match rdedupe::savings::measure(&df) {
+                            Ok(savings) => rdedupe::savings::print_savings(&savings),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if dirs {
+                        match rdedupe::trees::duplicate_trees(&df, &paths) {
+                            Ok(trees) => rdedupe::trees::print_trees(&trees),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    #[cfg(feature = "perceptual")]
+                    if let Some(name) = &perceptual {
+                        let algorithm =
+                            rdedupe::perceptual::parse_algorithm(name).unwrap_or_default();
+                        let distance =
+                            perceptual_distance.unwrap_or(rdedupe::perceptual::DEFAULT_DISTANCE);
+                        match rdedupe::perceptual::near_duplicates(&df, algorithm, distance) {
+                            Ok(near) => rdedupe::perceptual::print_near_duplicates(
+                                &near, algorithm, distance,
+                            ),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if let Some(map_path) = canonical_map {
+                        let policy = profile.as_ref().map(|p| p.keep).unwrap_or_default();
+                        match rdedupe::canonical::elect(&df, policy).and_then(|elections| {
+                            rdedupe::canonical::write_mapping(&elections, &map_path)?;
+                            Ok(elections.len())
+                        }) {
+                            Ok(groups) => println!(
+                                "Canonical mapping for {} group(s) saved to: {}",
+                                groups,
+                                map_path.display()
+                            ),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
                     println!("\n=== Analysis Complete ===");
                     println!("Total files analyzed: {}", df.height());
+                    println!("Detection: {} ({})", paranoia.as_str(), paranoia.describe());
+                    print_io_stats();
+                    print_cache_stats();
+                    if rdedupe::affinity::pin_failures() > 0 {
+                        println!(
+                            "Warning: {} worker(s) could not be pinned to their CPU",
+                            rdedupe::affinity::pin_failures()
+                        );
+                    }
                     if let Some(csv_path) = csv {
                         println!("Detailed CSV report saved to: {}", csv_path);
                     }
+                    if let Some(output) = output {
+                        let format = format.unwrap_or(rdedupe::report::Format::of(&output));
+                        match rdedupe::report::write_report(&df, &output, format) {
+                            Ok(written) => println!(
+                                "{} report saved to: {} ({} {})",
+                                format.as_str().to_uppercase(),
+                                output.display(),
+                                written,
+                                match format {
+                                    rdedupe::report::Format::Json
+                                    | rdedupe::report::Format::Ndjson => "duplicate group(s)",
+                                    _ => "duplicate file(s)",
+                                }
+                            ),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if let Some(parquet_path) = parquet {
+                        match rdedupe::report::write_parquet(&mut df.clone(), &parquet_path) {
+                            Ok(()) => println!("Scan saved to: {}", parquet_path.display()),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if let Some(snapshot_path) = snapshot {
+                        match rdedupe::snapshot::from_scan(&df, &paths).and_then(|snapshot| {
+                            rdedupe::snapshot::write_snapshot(&snapshot, &snapshot_path)?;
+                            Ok(snapshot.files.len())
+                        }) {
+                            Ok(files) => println!(
+                                "Snapshot of {} file(s) saved to: {}",
+                                files,
+                                snapshot_path.display()
+                            ),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    if let Some(dir) = per_home {
+                        match rdedupe::partition::write_home_reports(&df, &paths, &dir) {
+                            Ok(written) => {
+                                println!("Per-home reports saved to: {}", dir);
+                                for (name, rows) in written {
+                                    println!("  {} ({} duplicate files)", name, rows);
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                failed = true;
+                            }
+                        }
+                    }
+                    finish_time_box(resume.as_deref(), df.height());
+                    if failed {
+                        std::process::exit(2);
+                    }
+                }
+
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Plan {
+            path,
+            pattern,
+            rules,
+            output,
+            key,
+            verify,
+            walk,
+        }) => {
+            let result = rdedupe::rules::load_rules(&rules).and_then(|rules| {
+                let df =
+                    rdedupe::run_with_dataframe(&path, &pattern, None, &walk.options(), verify)?;
+                let planned = rdedupe::rules::plan(&df, &rules)?;
+                rdedupe::rules::print_plan(&planned);
+                let plan =
+                    rdedupe::plan::build(planned, std::slice::from_ref(&path), key.as_deref())?;
+                rdedupe::plan::write_plan(&plan, &output)?;
+                Ok(plan)
+            });
+            match result {
+                Ok(plan) => println!(
+                    "Plan with {} action(s) saved to: {}, review it and run `rdedupe apply {}`",
+                    plan.entries.len(),
+                    output.display(),
+                    output.display()
+                ),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Apply {
+            plan,
+            key,
+            dry_run,
+            force,
+            allow_root,
+        }) => {
+            let plan = match rdedupe::plan::read_plan(&plan, key.as_deref()) {
+                Ok(plan) => plan,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            println!(
+                "Applying a plan of {} action(s) made on {} for {}",
+                plan.entries.len(),
+                rdedupe::audit::format_time(plan.created),
+                plan.roots.join(", ")
+            );
+            install_guard(&allow_root, &plan.roots, force);
+            match rdedupe::plan::apply(&plan, dry_run) {
+                Ok(summary) => print_action_summary(&summary, dry_run),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Review {
+            plan: plan_path,
+            key,
+            output,
+            dry_run,
+            force,
+            allow_root,
+        }) => {
+            let result = rdedupe::plan::read_plan(&plan_path, key.as_deref())
+                .and_then(rdedupe::tui::review_plan);
+            let (choice, mut plan) = match result {
+                Ok(reviewed) => reviewed,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            match choice {
+                rdedupe::tui::Choice::Quit => println!("Plan left unchanged"),
+                rdedupe::tui::Choice::Export => {
+                    let output = output.unwrap_or(plan_path);
+                    match rdedupe::plan::reseal(&mut plan, key.as_deref())
+                        .and_then(|()| rdedupe::plan::write_plan(&plan, &output))
+                    {
+                        Ok(()) => println!("Reviewed plan saved to: {}", output.display()),
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                    }
+                }
+                rdedupe::tui::Choice::Apply => {
+                    install_guard(&allow_root, &plan.roots, force);
+                    match rdedupe::plan::apply(&plan, dry_run) {
+                        Ok(summary) => print_action_summary(&summary, dry_run),
+                        Err(e) => {
+                            eprintln!("Error: {}", e);
+                            std::process::exit(2);
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(Commands::Interactive {
+            path,
+            verify,
+            dry_run,
+            force,
+            allow_root,
+            walk,
+        }) => {
+            let mut options = rdedupe::scan::ScanOptions::for_run(&path);
+            options.walk = walk.options();
+            options.verify = verify;
+            let report = match rdedupe::scan::scan(&options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            if report.groups.is_empty() {
+                println!("No duplicates found in {}", path);
+                return;
+            }
+            let (choice, queue) = match rdedupe::interactive::review_groups(report) {
+                Ok(reviewed) => reviewed,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+            if choice == rdedupe::tui::Choice::Quit {
+                println!("No changes made");
+                return;
+            }
+            install_guard(&allow_root, std::slice::from_ref(&path), force);
+            match rdedupe::rules::apply(&queue, dry_run) {
+                Ok(summary) => print_action_summary(&summary, dry_run),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Worker {
+            listen,
+            root,
+            token,
+        }) => {
+            let Some(token) = rdedupe::remote::token(token) else {
+                eprintln!(
+                    "Error: a worker needs --token or RDEDUPE_TOKEN, clients present it to connect"
+                );
+                std::process::exit(2);
+            };
+            if let Err(e) = rdedupe::remote::serve(&listen, &root, &token) {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::VerifyBackup {
+            source,
+            backup,
+            csv,
+        }) => {
+            rdedupe::display_thread_info();
+
+            let entries = match rdedupe::backup::verify_backup(&source, &backup) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            };
+
+            let problems = rdedupe::backup::print_backup_report(&entries);
+
+            if let Some(csv_path) = csv {
+                match rdedupe::backup::write_backup_csv(&entries, &csv_path) {
+                    Ok(()) => println!("Verification report saved to: {}", csv_path),
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        std::process::exit(2);
+                    }
+                }
+            }
+
+            if problems > 0 {
+                std::process::exit(1);
+            }
+        }
+
+        Some(Commands::Layers { paths, csv }) => {
+            let result =
+                rdedupe::layers::scan_layers(&paths).map(rdedupe::layers::find_layer_duplicates);
+
+            match result {
+                Ok(duplicates) => {
+                    rdedupe::layers::print_layer_report(&duplicates);
+                    if let Some(csv_path) = csv {
+                        match rdedupe::layers::write_layer_csv(&duplicates, &csv_path) {
+                            Ok(()) => println!("Layer report saved to: {}", csv_path),
+                            Err(e) => {
+                                eprintln!("Error: {}", e);
+                                std::process::exit(2);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Maildir {
+            path,
+            dry_run,
+            force,
+            allow_root,
+        }) => {
+            install_guard(&allow_root, std::slice::from_ref(&path), force);
+            if !dry_run && !force && rdedupe::git::inside_repository(std::path::Path::new(&path)) {
+                eprintln!(
+                    "Error: {} is inside a git repository, refusing to link (use --force)",
+                    path
+                );
+                std::process::exit(2);
+            }
+
+            let result = rdedupe::maildir::scan_maildir(&path).and_then(|groups| {
+                rdedupe::maildir::print_maildir_report(&groups);
+                rdedupe::maildir::link_groups(&groups, dry_run)
+            });
+
+            match result {
+                Ok(summary) => print_link_summary(&summary, dry_run, "message(s)"),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
                 }
-                
-                Err(e) => println!("Error: {}", e),
             }
         }
-        
-        Some(Commands::Count { path, pattern }) => {
+
+        Some(Commands::Linkfarm { path }) => {
+            rdedupe::display_thread_info();
+
+            match rdedupe::linkfarm::audit_farm(&path) {
+                Ok(audit) => {
+                    if rdedupe::linkfarm::print_farm_report(&audit) > 0 {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Cache {
+            command: CacheCommands::Prune,
+        }) => {
+            let Some(path) = local_cache else {
+                eprintln!("Error: no cache location, pass --cache");
+                std::process::exit(2);
+            };
+            match rdedupe::cache::LocalCache::open(&path).and_then(|cache| cache.prune()) {
+                Ok(pruned) => println!(
+                    "Pruned {} stale cache entries from {}, {} kept",
+                    pruned.removed,
+                    path.display(),
+                    pruned.kept
+                ),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Tag { command }) => {
+            if let Err(e) = run_tag(command) {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::Archive { command }) => {
+            if let Err(e) = run_archive(command) {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::Bench { path, size_mb }) => {
+            rdedupe::display_thread_info();
+
+            match rdedupe::bench::run_bench(std::path::Path::new(&path), size_mb * 1_048_576) {
+                Ok(report) => rdedupe::bench::print_bench_report(&report),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::GenTree {
+            dir,
+            files,
+            dupe_ratio,
+            size,
+            seed,
+        }) => {
+            let spec = rdedupe::generate::TreeSpec {
+                files,
+                dupe_ratio,
+                avg_size: size,
+                seed,
+            };
+
+            match rdedupe::generate::generate_tree(std::path::Path::new(&dir), &spec) {
+                Ok(summary) => println!(
+                    "Generated {} files ({:.2} MB) under {}: {} unique, {} duplicates of {} files",
+                    summary.files,
+                    summary.total_bytes as f64 / 1_048_576.0,
+                    dir,
+                    summary.unique,
+                    summary.duplicates,
+                    summary.groups
+                ),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Audit { log, since, until }) => {
+            match rdedupe::audit::print_log(&log, since, until) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        // Snapshots are compared file by file, Parquet scans by their duplicate groups
+        Some(Commands::Diff { before, after })
+            if rdedupe::snapshot::is_snapshot(&before)
+                || rdedupe::snapshot::is_snapshot(&after) =>
+        {
+            let result = rdedupe::snapshot::read_snapshot(&before).and_then(|before_snapshot| {
+                let after_snapshot = rdedupe::snapshot::read_snapshot(&after)?;
+                let diff = rdedupe::snapshot::diff(&before_snapshot, &after_snapshot)?;
+                rdedupe::snapshot::print_diff(&diff, &before_snapshot, &after_snapshot);
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::Diff { before, after }) => {
+            let scans = rdedupe::report::read_scan(&before)
+                .and_then(|b| Ok((b, rdedupe::report::read_scan(&after)?)));
+            let result = scans.and_then(|(before, after)| {
+                let diffs = rdedupe::diff::diff_scans(&before, &after)?;
+                rdedupe::diff::print_diff(&diffs, &before, &after)
+            });
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::Profiles { config }) => {
+            let config = config.or_else(rdedupe::profile::default_config_path);
+
+            match rdedupe::profile::available(config.as_deref()) {
+                Ok(profiles) => {
+                    for (profile, source) in profiles {
+                        println!("{:<12} {} ({})", profile.name, profile.description, source);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Largest {
+            path,
+            pattern,
+            top,
+            walk,
+        }) => match rdedupe::walk_with_options(&path, &walk.options_matching(&pattern)) {
+            Ok(files) => {
+                println!(
+                    "Found {} files matching '{}' in {}",
+                    files.len(),
+                    pattern,
+                    path
+                );
+                rdedupe::largest::print_largest(&rdedupe::largest::largest(&files, &path, top));
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        },
+
+        Some(Commands::Estimate {
+            path,
+            pattern,
+            sample,
+            bucket_cap,
+            seed,
+            walk,
+        }) => match rdedupe::walk_with_options(&path, &walk.options_matching(&pattern)) {
+            Ok(files) => {
+                let seed = seed.unwrap_or_else(|| {
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_nanos() as u64)
+                        .unwrap_or_default()
+                });
+                rdedupe::sample::print_estimate(&rdedupe::sample::estimate(
+                    &files, sample, bucket_cap, seed,
+                ));
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        },
+
+        Some(Commands::Exists {
+            path,
+            file,
+            pattern,
+            count,
+            walk,
+        }) => {
+            let target = match (file, pattern) {
+                (Some(file), _) => rdedupe::exists::Target::File(file),
+                (None, pattern) => rdedupe::exists::Target::Pattern(pattern.unwrap_or_default()),
+            };
+            let count = count.max(1);
+            let found = rdedupe::walk_with_options(&path, &walk.options())
+                .and_then(|files| rdedupe::exists::find_duplicates(&files, &target, count));
+            match found {
+                Ok(found) => {
+                    rdedupe::exists::print_found(&found, count);
+                    // Like grep: 0 when the duplicates were found, 1 when they weren't
+                    if found.len() < count {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
+        }
+
+        Some(Commands::Report {
+            path,
+            pattern,
+            output,
+            groups,
+            verify,
+            walk,
+        }) => {
+            let patterns = [pattern];
+            let result = rdedupe::analyze(
+                std::slice::from_ref(&path),
+                &patterns,
+                &walk.options(),
+                verify,
+            )
+            .and_then(|df| {
+                println!("\n=== File Analysis Summary ===");
+                println!("{}", rdedupe::generate_statistics(&df)?);
+                if let Some(output) = &output {
+                    rdedupe::write_statistics(&df, output)?;
+                    println!("Statistics written to: {}", output.display());
+                }
+                let mut group_stats = rdedupe::collect_statistics(&df)?;
+                println!("\n=== Duplicate Summary ===");
+                println!("{}", rdedupe::collect_summary(&df, &group_stats)?);
+                if let Some(groups) = &groups {
+                    rdedupe::report::write_csv(&mut group_stats, groups)?;
+                    println!(
+                        "Group statistics written to: {} ({} group(s))",
+                        groups.display(),
+                        group_stats.height()
+                    );
+                }
+                Ok(())
+            });
+            if let Err(e) = result {
+                eprintln!("Error: {}", e);
+                std::process::exit(2);
+            }
+        }
+
+        Some(Commands::Count {
+            path,
+            pattern,
+            walk,
+        }) => {
             //count files matching a pattern
             println!("Counting files in {} matching {}", path, pattern);
 
-            let files = rdedupe::walk(&path).unwrap();
-            let files = rdedupe::find(files, &pattern);
-
-            println!("Found {} files matching {}", files.len(), pattern);
+            match rdedupe::walk_with_options(&path, &walk.options_matching(&pattern)) {
+                Ok(files) => println!("Found {} files matching {}", files.len(), pattern),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(2);
+                }
+            }
         }
 
         None => {
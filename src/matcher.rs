@@ -0,0 +1,117 @@
+//path matchers for --include and --exclude: substrings, globs and regular expressions
+use crate::filter::FileFilter;
+use regex::Regex;
+use std::fs::Metadata;
+
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    // The path contains this text, like --pattern
+    Substring(String),
+    // A glob over the whole path: * and ? stay within one component, ** spans any number of them
+    Glob { pattern: String, regex: Regex },
+    Regex(Regex),
+}
+
+/*  Translate a glob into an anchored regex. A glob without a leading / may match from any
+directory boundary, so "*.jpg" matches every JPEG, and "photos" with "*.jpg" below it only those
+directly in a photos directory.
+*/
+pub(crate) fn glob_regex(glob: &str) -> Result<Regex, String> {
+    let mut regex = String::from(match glob.starts_with('/') {
+        true => "^",
+        false => "(?:^|/)",
+    });
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).map_err(|e| format!("invalid glob '{}': {}", glob, e))
+}
+
+impl Matcher {
+    pub fn glob(pattern: &str) -> Result<Self, String> {
+        Ok(Matcher::Glob {
+            pattern: pattern.to_string(),
+            regex: glob_regex(pattern)?,
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        match self {
+            Matcher::Substring(text) => path.contains(text.as_str()),
+            Matcher::Glob { regex, .. } | Matcher::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+/*  re:EXPR is a regular expression, glob:PATTERN a glob, and anything else containing *, ? or [
+a glob too; plain text matches as a substring.
+*/
+pub fn parse_matcher(value: &str) -> Result<Matcher, String> {
+    if let Some(expression) = value.strip_prefix("re:") {
+        return Regex::new(expression)
+            .map(Matcher::Regex)
+            .map_err(|e| format!("invalid regex '{}': {}", expression, e));
+    }
+    if let Some(pattern) = value.strip_prefix("glob:") {
+        return Matcher::glob(pattern);
+    }
+    if value.contains(['*', '?', '[']) {
+        return Matcher::glob(value);
+    }
+    Ok(Matcher::Substring(value.to_string()))
+}
+
+// Keeps the files at least one of the matchers accepts, as repeated --include does
+#[derive(Debug, Clone)]
+pub struct AnyOf(pub Vec<Matcher>);
+
+impl FileFilter for AnyOf {
+    fn accept(&self, path: &str, _metadata: &Metadata) -> bool {
+        self.0.iter().any(|matcher| matcher.is_match(path))
+    }
+
+    fn describe(&self) -> String {
+        let matchers: Vec<String> = self
+            .0
+            .iter()
+            .map(|matcher| match matcher {
+                Matcher::Substring(text) => text.clone(),
+                Matcher::Glob { pattern, .. } => format!("glob:{}", pattern),
+                Matcher::Regex(regex) => format!("re:{}", regex.as_str()),
+            })
+            .collect();
+        format!("include={}", matchers.join("|"))
+    }
+}
@@ -0,0 +1,185 @@
+//content normalizers applied before hashing, so semantically identical files group together
+use crate::hasher::{to_hex, Hasher};
+use crate::{compressed, photos, progress, throttle};
+use std::fs;
+use std::io;
+use std::sync::{Arc, OnceLock};
+
+static NORMALIZERS: OnceLock<Vec<Arc<dyn Normalizer>>> = OnceLock::new();
+
+/*  Rewrites a file's bytes into a canonical form before they are hashed. normalize returns None
+for content it doesn't apply to, which is then passed on unchanged. Normalizers run in the order
+they were installed, each on the previous one's output.
+*/
+pub trait Normalizer: Send + Sync {
+    // Part of the cache key, so it must change whenever the output would
+    fn name(&self) -> &str;
+    fn normalize(&self, path: &str, content: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl std::fmt::Debug for dyn Normalizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Text is a file without NUL bytes in its first block, the same test git uses
+fn is_text(content: &[u8]) -> bool {
+    !content[..content.len().min(8000)].contains(&0)
+}
+
+// CRLF and lone CR line endings become LF
+pub struct LineEndings;
+
+impl Normalizer for LineEndings {
+    fn name(&self) -> &str {
+        "eol"
+    }
+
+    fn normalize(&self, _path: &str, content: &[u8]) -> Option<Vec<u8>> {
+        if !is_text(content) || !content.contains(&b'\r') {
+            return None;
+        }
+        let mut normalized = Vec::with_capacity(content.len());
+        let mut bytes = content.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            if byte == b'\r' {
+                bytes.next_if_eq(&&b'\n');
+                normalized.push(b'\n');
+            } else {
+                normalized.push(byte);
+            }
+        }
+        Some(normalized)
+    }
+}
+
+// UTF-16 text with a byte order mark becomes UTF-8, and a UTF-8 byte order mark is dropped
+pub struct TextEncoding;
+
+fn decode_utf16(content: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<Vec<u8>> {
+    if !content.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = content
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+    let text: String = char::decode_utf16(units).collect::<Result<_, _>>().ok()?;
+    Some(text.into_bytes())
+}
+
+impl Normalizer for TextEncoding {
+    fn name(&self) -> &str {
+        "encoding"
+    }
+
+    fn normalize(&self, _path: &str, content: &[u8]) -> Option<Vec<u8>> {
+        match content {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => Some(rest.to_vec()),
+            [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+            _ => None,
+        }
+    }
+}
+
+// JPEG and PNG files without their EXIF, XMP, IPTC, comment and text metadata, as photos::image_hash
+pub struct ImageMetadata;
+
+impl Normalizer for ImageMetadata {
+    fn name(&self) -> &str {
+        "image-metadata"
+    }
+
+    fn normalize(&self, _path: &str, content: &[u8]) -> Option<Vec<u8>> {
+        photos::strip_metadata(content)
+    }
+}
+
+pub const BUILTIN: [&str; 3] = ["eol", "encoding", "image-metadata"];
+
+pub fn builtin(name: &str) -> Option<Box<dyn Normalizer>> {
+    match name {
+        "eol" => Some(Box::new(LineEndings)),
+        "encoding" => Some(Box::new(TextEncoding)),
+        "image-metadata" => Some(Box::new(ImageMetadata)),
+        _ => None,
+    }
+}
+
+// For clap: one built-in normalizer by name
+pub fn parse_builtin(name: &str) -> Result<String, String> {
+    match builtin(name) {
+        Some(_) => Ok(name.to_string()),
+        None => Err(format!(
+            "unknown normalizer '{}' (expected one of {})",
+            name,
+            BUILTIN.join(", ")
+        )),
+    }
+}
+
+// Only the first set of a run is used; library users can install their own normalizers here
+pub fn install(normalizers: Vec<Box<dyn Normalizer>>) {
+    if !normalizers.is_empty() {
+        let _ = NORMALIZERS.set(normalizers.into_iter().map(Arc::from).collect());
+    }
+}
+
+pub fn enabled() -> bool {
+    NORMALIZERS.get().is_some()
+}
+
+// The normalizers of the run, empty unless install was called
+pub fn installed() -> &'static [Arc<dyn Normalizer>] {
+    NORMALIZERS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+// The checksum cache algorithm: the base hash plus every normalizer, in order
+pub fn algorithm(
+    format: Option<compressed::Format>,
+    hasher: Hasher,
+    normalizers: &[Arc<dyn Normalizer>],
+) -> String {
+    let base = format
+        .map(|f| f.algorithm(hasher))
+        .unwrap_or_else(|| hasher.as_str().to_string());
+    let names: Vec<&str> = normalizers.iter().map(|n| n.name()).collect();
+    format!("{}+{}", base, names.join("+"))
+}
+
+/*  Hash of path's content after decompression (with --decompress) and every normalizer. Files from
+progress::LARGE_FILE on, on disk or once decompressed, are hashed as they are, rather than read
+into memory whole.
+*/
+pub fn hash_normalized(
+    path: &str,
+    format: Option<compressed::Format>,
+    hasher: Hasher,
+    normalizers: &[Arc<dyn Normalizer>],
+) -> io::Result<String> {
+    let streamed = || -> io::Result<String> {
+        let digest = match format {
+            Some(format) => compressed::hash_content(path, format, hasher)?,
+            None => hasher.hash_file(path)?,
+        };
+        Ok(to_hex(&digest))
+    };
+    if fs::metadata(path)?.len() >= progress::LARGE_FILE {
+        return streamed();
+    }
+
+    let content = match format {
+        Some(format) => compressed::read(path, format, progress::LARGE_FILE)?,
+        None => Some(throttle::read(path)?),
+    };
+    let Some(mut content) = content else {
+        return streamed();
+    };
+    for normalizer in normalizers {
+        if let Some(normalized) = normalizer.normalize(path, &content) {
+            content = normalized;
+        }
+    }
+    Ok(to_hex(&hasher.hash_bytes(&content)))
+}
@@ -0,0 +1,74 @@
+//detection rigor: how much evidence two files need before they are reported as duplicates
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static LEVEL: AtomicU8 = AtomicU8::new(Paranoia::Standard as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Paranoia {
+    // Same size is enough: nothing is read, so groups are only candidates
+    Quick,
+    // Same size and content hash
+    #[default]
+    Standard,
+    // Same size and content hash, confirmed byte for byte
+    Paranoid,
+}
+
+impl Paranoia {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Paranoia::Quick => "quick",
+            Paranoia::Standard => "standard",
+            Paranoia::Paranoid => "paranoid",
+        }
+    }
+
+    pub fn describe(self) -> String {
+        let hash = crate::hasher::selected().label();
+        match self {
+            Paranoia::Quick => "size only, groups are unconfirmed candidates".to_string(),
+            Paranoia::Standard => format!("size and {}", hash),
+            Paranoia::Paranoid => format!("size and {}, confirmed byte for byte", hash),
+        }
+    }
+
+    // The content hash the scan groups by
+    pub fn hash(self) -> fn(&str) -> io::Result<String> {
+        match self {
+            Paranoia::Quick => size_key,
+            Paranoia::Standard | Paranoia::Paranoid => crate::content_hash,
+        }
+    }
+}
+
+pub fn parse_level(value: &str) -> Result<Paranoia, String> {
+    match value {
+        "quick" => Ok(Paranoia::Quick),
+        "standard" => Ok(Paranoia::Standard),
+        "paranoid" => Ok(Paranoia::Paranoid),
+        _ => Err(format!(
+            "unknown paranoia level '{}' (expected quick, standard or paranoid)",
+            value
+        )),
+    }
+}
+
+// Recorded in every report of the run
+pub fn set_level(level: Paranoia) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Paranoia {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Paranoia::Quick,
+        2 => Paranoia::Paranoid,
+        _ => Paranoia::Standard,
+    }
+}
+
+// The quick level's stand-in for a hash, in the content_hash column of its reports
+pub fn size_key(path: &str) -> io::Result<String> {
+    Ok(format!("size:{}", fs::metadata(path)?.len()))
+}
@@ -0,0 +1,105 @@
+//per-home-directory partitioning of duplicate reports for multi-user servers
+use crate::report;
+use polars::prelude::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const GLOBAL_REPORT: &str = "_global.csv";
+
+// Top-level directory under the scanned root a path belongs to, e.g. alice for /home/alice/x
+pub fn home_of(roots: &[String], path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let relative = roots.iter().find_map(|root| path.strip_prefix(root).ok())?;
+
+    let mut components = relative.components();
+    let home = components.next()?;
+    // A file directly in the root belongs to nobody
+    components.next()?;
+
+    Some(home.as_os_str().to_string_lossy().to_string())
+}
+
+/*  Add a home column and, for each duplicate, the other homes holding a copy of the same content,
+so a user's report shows which of their duplicates are shared with other users
+*/
+pub fn with_home_columns(df: &DataFrame, roots: &[String]) -> Result<DataFrame, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let homes: Vec<Option<String>> = paths
+        .into_iter()
+        .map(|p| p.and_then(|p| home_of(roots, p)))
+        .collect();
+
+    let mut homes_by_group: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    for (group, home) in groups.into_iter().zip(&homes) {
+        if let (Some(group), Some(home)) = (group, home) {
+            homes_by_group.entry(group).or_default().insert(home);
+        }
+    }
+
+    let shared_with: Vec<Option<String>> = groups
+        .into_iter()
+        .zip(&homes)
+        .map(|(group, home)| {
+            let others: Vec<&str> = homes_by_group
+                .get(group?)?
+                .iter()
+                .copied()
+                .filter(|other| Some(*other) != home.as_deref())
+                .collect();
+            (!others.is_empty()).then(|| others.join(","))
+        })
+        .collect();
+
+    let mut df = df.clone();
+    df.with_column(Series::new("home", homes))?;
+    df.with_column(Series::new("shared_with", shared_with))?;
+
+    Ok(df)
+}
+
+/*  Write one duplicate report per home directory (<home>.csv) plus a global cross-user report
+into output_dir. Returns the number of duplicate rows written per report.
+*/
+pub fn write_home_reports(
+    df: &DataFrame,
+    roots: &[String],
+    output_dir: &str,
+) -> Result<BTreeMap<String, usize>, Box<dyn Error>> {
+    fs::create_dir_all(output_dir)?;
+
+    let duplicates = df
+        .clone()
+        .lazy()
+        .filter(col("is_duplicate").eq(lit(true)))
+        .collect()?;
+    let mut duplicates = with_home_columns(&duplicates, roots)?;
+
+    let mut written = BTreeMap::new();
+    report::write_csv(&mut duplicates, &Path::new(output_dir).join(GLOBAL_REPORT))?;
+    written.insert(GLOBAL_REPORT.to_string(), duplicates.height());
+
+    let homes: BTreeSet<String> = duplicates
+        .column("home")?
+        .utf8()?
+        .into_iter()
+        .flatten()
+        .map(|h| h.to_string())
+        .collect();
+
+    for home in homes {
+        let mut own = duplicates
+            .clone()
+            .lazy()
+            .filter(col("home").eq(lit(home.as_str())))
+            .collect()?;
+        let name = format!("{}.csv", home);
+        report::write_csv(&mut own, &Path::new(output_dir).join(&name))?;
+        written.insert(name, own.height());
+    }
+
+    Ok(written)
+}
@@ -0,0 +1,89 @@
+//several patterns in one scan, with the matches and duplicates of every pattern reported apart
+use polars::prelude::*;
+use std::error::Error;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PatternStats {
+    pub pattern: String,
+    pub files: usize,
+    pub bytes: u64,
+    // Files of the pattern with at least one copy anywhere in the scan
+    pub duplicated: usize,
+    pub duplicated_bytes: u64,
+}
+
+// The walk keeps a file matching any pattern; no patterns keeps everything
+pub fn matches_any(patterns: &[String], path: &str) -> bool {
+    patterns.is_empty()
+        || patterns
+            .iter()
+            .any(|pattern| path.contains(pattern.as_str()))
+}
+
+// Add a "pattern" column: the first pattern, in the order given, each file's path contains
+pub fn label(df: &DataFrame, patterns: &[String]) -> Result<DataFrame, Box<dyn Error>> {
+    let labels: Vec<Option<&str>> = df
+        .column("file_path")?
+        .utf8()?
+        .into_iter()
+        .map(|path| {
+            let path = path?;
+            patterns
+                .iter()
+                .find(|pattern| path.contains(pattern.as_str()))
+                .map(|pattern| pattern.as_str())
+        })
+        .collect();
+
+    let mut df = df.clone();
+    df.with_column(Series::new("pattern", labels))?;
+    Ok(df)
+}
+
+// A file containing several patterns counts under each of them
+pub fn breakdown(df: &DataFrame, patterns: &[String]) -> Result<Vec<PatternStats>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let duplicates = df.column("is_duplicate")?.bool()?;
+
+    let mut stats: Vec<PatternStats> = patterns
+        .iter()
+        .map(|pattern| PatternStats {
+            pattern: pattern.clone(),
+            ..Default::default()
+        })
+        .collect();
+    for index in 0..df.height() {
+        let Some(path) = paths.get(index) else {
+            continue;
+        };
+        let size = sizes.get(index).unwrap_or(0);
+        let duplicate = duplicates.get(index).unwrap_or(false);
+        for entry in stats
+            .iter_mut()
+            .filter(|s| path.contains(s.pattern.as_str()))
+        {
+            entry.files += 1;
+            entry.bytes += size;
+            if duplicate {
+                entry.duplicated += 1;
+                entry.duplicated_bytes += size;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+pub fn print_breakdown(stats: &[PatternStats]) {
+    println!("\n=== Matches by Pattern ===");
+    for entry in stats {
+        println!(
+            "  '{}': {} file(s), {:.2} MB; {} duplicated ({:.2} MB)",
+            entry.pattern,
+            entry.files,
+            entry.bytes as f64 / 1_048_576.0,
+            entry.duplicated,
+            entry.duplicated_bytes as f64 / 1_048_576.0
+        );
+    }
+}
@@ -0,0 +1,869 @@
+//near-duplicate images by perceptual hash: re-encodes and resizes that byte checksums miss
+use crate::photos;
+use polars::prelude::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+
+// Hashes at most this many bits apart are near-duplicates unless --perceptual-distance says otherwise
+pub const DEFAULT_DISTANCE: u32 = 10;
+
+// Larger images are refused rather than decoded, so a small file can't claim gigabytes of pixels
+pub const MAX_PIXELS: usize = 100_000_000;
+
+fn check_size(width: usize, height: usize) -> Result<(), Box<dyn Error>> {
+    match width.checked_mul(height) {
+        Some(0) => Err("image of no pixels".into()),
+        Some(pixels) if pixels <= MAX_PIXELS => Ok(()),
+        _ => Err(format!("{}x{} image is above {} pixels", width, height, MAX_PIXELS).into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    // Whether each pixel of a 9x8 thumbnail is darker than its right neighbour
+    #[default]
+    DHash,
+    // The signs of an 8x8 block of low DCT frequencies of a 32x32 thumbnail, against their median
+    PHash,
+}
+
+impl Algorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Algorithm::DHash => "dhash",
+            Algorithm::PHash => "phash",
+        }
+    }
+
+    pub fn hash(self, image: &Gray) -> u64 {
+        match self {
+            Algorithm::DHash => dhash(image),
+            Algorithm::PHash => phash(image),
+        }
+    }
+}
+
+pub fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    match value {
+        "dhash" => Ok(Algorithm::DHash),
+        "phash" => Ok(Algorithm::PHash),
+        _ => Err(format!(
+            "unknown perceptual hash '{}' (expected dhash or phash)",
+            value
+        )),
+    }
+}
+
+// A grayscale image, row by row, 0 to 255
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gray {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<f32>,
+}
+
+impl Gray {
+    fn at(&self, x: usize, y: usize) -> f32 {
+        self.pixels[y * self.width + x]
+    }
+
+    // Box average when shrinking, the nearest pixel when a target pixel covers none
+    fn resize(&self, width: usize, height: usize) -> Gray {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (top, bottom) = span(y, height, self.height);
+            for x in 0..width {
+                let (left, right) = span(x, width, self.width);
+                let mut sum = 0.0;
+                for row in top..bottom {
+                    for column in left..right {
+                        sum += self.at(column, row);
+                    }
+                }
+                pixels.push(sum / ((bottom - top) * (right - left)) as f32);
+            }
+        }
+        Gray {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+// The source pixels of target pixel index out of of, at least one
+fn span(index: usize, of: usize, source: usize) -> (usize, usize) {
+    let start = index * source / of;
+    let end = ((index + 1) * source / of).max(start + 1).min(source);
+    (start.min(source - 1), end)
+}
+
+pub fn dhash(image: &Gray) -> u64 {
+    let thumb = image.resize(9, 8);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = hash << 1 | (thumb.at(x, y) < thumb.at(x + 1, y)) as u64;
+        }
+    }
+    hash
+}
+
+pub fn phash(image: &Gray) -> u64 {
+    const SIZE: usize = 32;
+    let thumb = image.resize(SIZE, SIZE);
+    let cosines: Vec<f32> = (0..8 * SIZE)
+        .map(|i| {
+            let (u, x) = (i / SIZE, i % SIZE);
+            (std::f32::consts::PI * u as f32 * (2 * x + 1) as f32 / (2 * SIZE) as f32).cos()
+        })
+        .collect();
+    let mut low = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    sum += thumb.at(x, y) * cosines[u * SIZE + x] * cosines[v * SIZE + y];
+                }
+            }
+            low[v * 8 + u] = sum;
+        }
+    }
+    // The DC term is the overall brightness, it would swamp the median
+    let mut sorted: Vec<f32> = low[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    low.iter()
+        .fold(0u64, |hash, value| hash << 1 | (*value > median) as u64)
+}
+
+/*  The luma of a JPEG or PNG file, or None for other formats, lossless or arithmetic coded JPEGs,
+interlaced PNGs and images under 8x8 pixels. JPEGs are decoded from the DC coefficients
+alone, an image an eighth of the size that is plenty for a thumbnail hash and skips the inverse
+DCT. An Err is a file that couldn't be read, is corrupt or breaks the spec, or is above
+MAX_PIXELS; the scan skips it.
+*/
+pub fn luma(path: &str) -> Result<Option<Gray>, Box<dyn Error>> {
+    let image = match photos::sniff_mime(path) {
+        Some("image/jpeg") => jpeg_dc(&fs::read(path)?)?,
+        Some("image/png") => png(&fs::read(path)?)?,
+        _ => None,
+    };
+    Ok(image.filter(|image| image.width >= 8 && image.height >= 8))
+}
+
+// Canonical Huffman codes of one DHT table, decoded a bit at a time
+#[derive(Debug, Clone, Default)]
+struct Huffman {
+    // Per code length 1 to 16: the largest code, or -1, and where its symbols start
+    max_code: [i32; 17],
+    offset: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], symbols: &[u8]) -> Self {
+        let mut table = Huffman {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            symbols: symbols.to_vec(),
+        };
+        let (mut code, mut index) = (0i32, 0i32);
+        for length in 1..=16 {
+            let count = counts[length - 1] as i32;
+            table.offset[length] = index - code;
+            if count > 0 {
+                code += count;
+                index += count;
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u8, Box<dyn Error>> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = code << 1 | bits.bit() as i32;
+            if code <= self.max_code[length] {
+                return self
+                    .symbols
+                    .get((code + self.offset[length]) as usize)
+                    .copied()
+                    .ok_or_else(|| "corrupt JPEG Huffman table".into());
+            }
+        }
+        Err("corrupt JPEG entropy data".into())
+    }
+}
+
+// The entropy coded bytes of a scan, with stuffed zero bytes removed; reads zeros past a marker
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    byte: u8,
+    left: u8,
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> u8 {
+        if self.left == 0 {
+            self.byte = match self.data.get(self.pos) {
+                Some(0xFF) if self.data.get(self.pos + 1) == Some(&0) => {
+                    self.pos += 2;
+                    0xFF
+                }
+                Some(0xFF) | None => 0,
+                Some(byte) => {
+                    self.pos += 1;
+                    *byte
+                }
+            };
+            self.left = 8;
+        }
+        self.left -= 1;
+        self.byte >> self.left & 1
+    }
+
+    fn bits(&mut self, count: u8) -> i32 {
+        (0..count).fold(0, |value, _| value << 1 | self.bit() as i32)
+    }
+
+    // Drop the partial byte and an RSTn marker, if one comes next
+    fn restart(&mut self) {
+        self.left = 0;
+        if self.data.get(self.pos) == Some(&0xFF)
+            && self
+                .data
+                .get(self.pos + 1)
+                .is_some_and(|m| (0xD0..=0xD7).contains(m))
+        {
+            self.pos += 2;
+        }
+    }
+}
+
+// A coefficient of size bits, sign extended; 8-bit JPEGs code at most 11
+fn extend(value: i32, size: u8) -> Result<i32, Box<dyn Error>> {
+    match size {
+        0 => Ok(0),
+        12.. => Err("corrupt JPEG coefficient size".into()),
+        _ if value < 1 << (size - 1) => Ok(value - (1 << size) + 1),
+        _ => Ok(value),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+}
+
+fn be16(data: &[u8], at: usize) -> Result<usize, Box<dyn Error>> {
+    match data.get(at..at + 2) {
+        Some(bytes) => Ok((bytes[0] as usize) << 8 | bytes[1] as usize),
+        None => Err("truncated JPEG".into()),
+    }
+}
+
+// Where the entropy coded data starting at start ends: the first marker that isn't RSTn
+fn scan_end(data: &[u8], start: usize) -> usize {
+    let mut at = start;
+    while at + 1 < data.len() {
+        if data[at] == 0xFF && data[at + 1] != 0 && !(0xD0..=0xD7).contains(&data[at + 1]) {
+            return at;
+        }
+        at += 1;
+    }
+    data.len()
+}
+
+/*  The first component's block means of a sequential or progressive Huffman coded JPEG, one value
+per 8x8 block. A progressive file's first DC scan has them all, only less precise than after its
+refinement scans, so it is the only scan read.
+*/
+fn jpeg_dc(data: &[u8]) -> Result<Option<Gray>, Box<dyn Error>> {
+    let mut quant = [0u16; 4];
+    let mut dc_tables: [Option<Huffman>; 4] = Default::default();
+    let mut ac_tables: [Option<Huffman>; 4] = Default::default();
+    let mut components: Vec<Component> = Vec::new();
+    let (mut width, mut height, mut restart) = (0usize, 0usize, 0usize);
+    let mut progressive = false;
+    let mut means: Option<(usize, usize, Vec<f32>)> = None;
+
+    let mut at = 2;
+    while at + 4 <= data.len() {
+        if data[at] != 0xFF {
+            return Err("corrupt JPEG marker".into());
+        }
+        let marker = data[at + 1];
+        if marker == 0xFF {
+            at += 1;
+            continue;
+        }
+        if marker == 0xD9 {
+            break;
+        }
+        // Markers without a segment
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            at += 2;
+            continue;
+        }
+        let length = be16(data, at + 2)?;
+        let segment = data
+            .get(at + 4..at + 2 + length)
+            .ok_or("truncated JPEG segment")?;
+        match marker {
+            // Baseline, extended sequential and progressive Huffman coding
+            0xC0..=0xC2 => {
+                if segment.first() != Some(&8) {
+                    return Ok(None);
+                }
+                progressive = marker == 0xC2;
+                height = be16(segment, 1)?;
+                width = be16(segment, 3)?;
+                check_size(width, height)?;
+                let count = *segment.get(5).ok_or("truncated JPEG frame")? as usize;
+                for index in 0..count {
+                    let part = segment
+                        .get(6 + index * 3..9 + index * 3)
+                        .ok_or("truncated JPEG frame")?;
+                    let (h, v, quant) = (part[1] >> 4, part[1] & 15, part[2]);
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) || quant > 3 {
+                        return Err("corrupt JPEG frame".into());
+                    }
+                    components.push(Component {
+                        id: part[0],
+                        h: h as usize,
+                        v: v as usize,
+                        quant: quant as usize,
+                    });
+                }
+            }
+            // Lossless, hierarchical and arithmetic coded frames
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return Ok(None),
+            0xC4 => {
+                let mut rest = segment;
+                while rest.len() >= 17 {
+                    let (class, id) = (rest[0] >> 4, (rest[0] & 15) as usize);
+                    if class > 1 || id > 3 {
+                        return Err("corrupt JPEG Huffman table".into());
+                    }
+                    let counts = &rest[1..17];
+                    let total: usize = counts.iter().map(|c| *c as usize).sum();
+                    let symbols = rest.get(17..17 + total).ok_or("truncated JPEG table")?;
+                    let table = Huffman::new(counts, symbols);
+                    match class {
+                        0 => dc_tables[id] = Some(table),
+                        _ => ac_tables[id] = Some(table),
+                    }
+                    rest = &rest[17 + total..];
+                }
+            }
+            0xDB => {
+                let mut rest = segment;
+                while !rest.is_empty() {
+                    let (precision, id) = (rest[0] >> 4, (rest[0] & 15) as usize);
+                    if precision > 1 || id > 3 {
+                        return Err("corrupt JPEG quantization table".into());
+                    }
+                    let size = if precision == 0 { 64 } else { 128 };
+                    let table = rest.get(1..1 + size).ok_or("truncated JPEG table")?;
+                    quant[id] = match precision {
+                        0 => table[0] as u16,
+                        _ => (table[0] as u16) << 8 | table[1] as u16,
+                    };
+                    rest = &rest[1 + size..];
+                }
+            }
+            0xDD => restart = be16(segment, 0)?,
+            0xDA => {
+                let start = at + 2 + length;
+                let end = scan_end(data, start);
+                let Some(first) = components.first().copied() else {
+                    return Err("JPEG scan before its frame".into());
+                };
+                let count = *segment.first().ok_or("truncated JPEG scan")? as usize;
+                let mut members = Vec::new();
+                for index in 0..count {
+                    let part = segment
+                        .get(1 + index * 2..3 + index * 2)
+                        .ok_or("truncated JPEG scan")?;
+                    let component = components
+                        .iter()
+                        .copied()
+                        .find(|c| c.id == part[0])
+                        .ok_or("JPEG scan of an unknown component")?;
+                    let (dc, ac) = ((part[1] >> 4) as usize, (part[1] & 15) as usize);
+                    if dc > 3 || ac > 3 {
+                        return Err("corrupt JPEG scan table selector".into());
+                    }
+                    members.push((component, dc, ac));
+                }
+                // Spectral selection start, successive approximation high and low bits
+                let selection = segment
+                    .get(1 + count * 2..4 + count * 2)
+                    .ok_or("truncated JPEG scan")?;
+                let first_dc = selection[0] == 0 && selection[2] >> 4 == 0;
+                if members.iter().any(|(c, _, _)| c.id == first.id)
+                    && (!progressive || (first_dc && means.is_none()))
+                {
+                    means = Some(decode_scan(
+                        &data[start..end],
+                        &members,
+                        first,
+                        &components,
+                        (width, height),
+                        restart,
+                        (&dc_tables, &ac_tables),
+                        quant[first.quant] as f32,
+                        progressive.then_some(selection[2] & 15),
+                    )?);
+                }
+                at = end;
+                continue;
+            }
+            _ => {}
+        }
+        at += 2 + length;
+    }
+
+    Ok(means.map(|(width, height, pixels)| Gray {
+        width,
+        height,
+        pixels,
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn decode_scan(
+    data: &[u8],
+    members: &[(Component, usize, usize)],
+    first: Component,
+    components: &[Component],
+    (width, height): (usize, usize),
+    restart: usize,
+    (dc_tables, ac_tables): (&[Option<Huffman>; 4], &[Option<Huffman>; 4]),
+    dc_quant: f32,
+    // The low bit position of a progressive DC scan, which carries no AC coefficients
+    dc_only: Option<u8>,
+) -> Result<(usize, usize, Vec<f32>), Box<dyn Error>> {
+    let h_max = components.iter().map(|c| c.h).max().unwrap_or(1);
+    let v_max = components.iter().map(|c| c.v).max().unwrap_or(1);
+    // Blocks of the first component that hold image, and the MCU grid of the scan
+    let blocks_x = (width * first.h).div_ceil(h_max).div_ceil(8);
+    let blocks_y = (height * first.v).div_ceil(v_max).div_ceil(8);
+    let interleaved = members.len() > 1;
+    let (mcus_x, mcus_y) = match interleaved {
+        true => (width.div_ceil(8 * h_max), height.div_ceil(8 * v_max)),
+        false => (blocks_x, blocks_y),
+    };
+
+    // The tables of every member, an AC one only where AC coefficients follow
+    let mut tables = Vec::with_capacity(members.len());
+    for (_, dc, ac) in members {
+        let missing = || "JPEG scan uses a missing Huffman table";
+        let dc = dc_tables[*dc].as_ref().ok_or_else(missing)?;
+        let ac = match dc_only {
+            Some(_) => None,
+            None => Some(ac_tables[*ac].as_ref().ok_or_else(missing)?),
+        };
+        tables.push((dc, ac));
+    }
+
+    let mut pixels = vec![128f32; blocks_x * blocks_y];
+    let mut predictions = vec![0i32; members.len()];
+    let mut bits = Bits {
+        data,
+        pos: 0,
+        byte: 0,
+        left: 0,
+    };
+    for mcu in 0..mcus_x * mcus_y {
+        if restart > 0 && mcu > 0 && mcu % restart == 0 {
+            bits.restart();
+            predictions.iter_mut().for_each(|p| *p = 0);
+        }
+        let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+        for (index, ((component, _, _), (dc, ac))) in members.iter().zip(&tables).enumerate() {
+            let (h, v) = match interleaved {
+                true => (component.h, component.v),
+                false => (1, 1),
+            };
+            for block in 0..h * v {
+                let size = dc.decode(&mut bits)?;
+                let value = bits.bits(size);
+                predictions[index] = predictions[index].wrapping_add(extend(value, size)?);
+                let mut k = 1;
+                while let Some(ac) = ac.filter(|_| k < 64) {
+                    let run_size = ac.decode(&mut bits)?;
+                    let (run, size) = (run_size >> 4, run_size & 15);
+                    if size == 0 {
+                        if run != 15 {
+                            break;
+                        }
+                        k += 16;
+                        continue;
+                    }
+                    k += run as usize;
+                    bits.bits(size);
+                    k += 1;
+                }
+                if component.id != first.id {
+                    continue;
+                }
+                let (x, y) = (mx * h + block % h, my * v + block / h);
+                if x < blocks_x && y < blocks_y {
+                    // The DC coefficient is eight times the block's mean, level shifted by 128
+                    let dc = predictions[index] << dc_only.unwrap_or(0);
+                    pixels[y * blocks_x + x] =
+                        (dc as f32 * dc_quant / 8.0 + 128.0).clamp(0.0, 255.0);
+                }
+            }
+        }
+    }
+    Ok((blocks_x, blocks_y, pixels))
+}
+
+fn be32(data: &[u8], at: usize) -> Result<u32, Box<dyn Error>> {
+    match data.get(at..at + 4) {
+        Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        None => Err("truncated PNG".into()),
+    }
+}
+
+fn paeth(left: u8, up: u8, corner: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - corner as i16;
+    let (a, b, c) = (
+        (estimate - left as i16).abs(),
+        (estimate - up as i16).abs(),
+        (estimate - corner as i16).abs(),
+    );
+    if a <= b && a <= c {
+        left
+    } else if b <= c {
+        up
+    } else {
+        corner
+    }
+}
+
+fn png(data: &[u8]) -> Result<Option<Gray>, Box<dyn Error>> {
+    let mut at = 8;
+    let mut header = None;
+    let mut palette: Vec<u8> = Vec::new();
+    let mut compressed = Vec::new();
+    while at + 8 <= data.len() {
+        let length = be32(data, at)? as usize;
+        let kind = &data[at + 4..at + 8];
+        let body = data
+            .get(at + 8..at + 8 + length)
+            .ok_or("truncated PNG chunk")?;
+        match kind {
+            b"IHDR" if length >= 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        at += 12 + length;
+    }
+    let header = header.ok_or("PNG without a header")?;
+    let (width, height) = (be32(&header, 0)? as usize, be32(&header, 4)? as usize);
+    let (depth, color, interlace) = (header[8] as usize, header[9], header[12]);
+    // The depths the spec allows for each color type
+    let (channels, depths): (usize, &[usize]) = match color {
+        0 => (1, &[1, 2, 4, 8, 16]),
+        2 => (3, &[8, 16]),
+        3 => (1, &[1, 2, 4, 8]),
+        4 => (2, &[8, 16]),
+        6 => (4, &[8, 16]),
+        _ => return Err("PNG of an unknown color type".into()),
+    };
+    if !depths.contains(&depth) || header[10] != 0 || header[11] != 0 || interlace > 1 {
+        return Err("corrupt PNG header".into());
+    }
+    check_size(width, height)?;
+    if interlace != 0 {
+        return Ok(None);
+    }
+
+    let bits_per_pixel = channels * depth;
+    let stride = (width * bits_per_pixel).div_ceil(8);
+    let step = bits_per_pixel.div_ceil(8);
+    // Never inflate more than the image needs, whatever the stream holds
+    let expected = (stride + 1) * height;
+    let mut raw = Vec::new();
+    flate2::read::ZlibDecoder::new(compressed.as_slice())
+        .take(expected as u64)
+        .read_to_end(&mut raw)?;
+    if raw.len() < expected {
+        return Err("truncated PNG image data".into());
+    }
+
+    let mut previous = vec![0u8; stride];
+    let mut row = vec![0u8; stride];
+    let mut pixels = Vec::with_capacity(width * height);
+    let max = ((1u32 << depth.min(16)) - 1) as f32;
+    for y in 0..height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        for x in 0..stride {
+            let left = if x >= step { row[x - step] } else { 0 };
+            let corner = if x >= step { previous[x - step] } else { 0 };
+            row[x] = line[x + 1].wrapping_add(match line[0] {
+                1 => left,
+                2 => previous[x],
+                3 => ((left as u16 + previous[x] as u16) / 2) as u8,
+                4 => paeth(left, previous[x], corner),
+                0 => 0,
+                _ => return Err("corrupt PNG row filter".into()),
+            });
+        }
+        let sample = |index: usize| -> f32 {
+            let value = match depth {
+                16 => (row[index * 2] as u16) << 8 | row[index * 2 + 1] as u16,
+                8 => row[index] as u16,
+                _ => {
+                    let bit = index * depth;
+                    ((row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8) as u16
+                }
+            };
+            value as f32
+        };
+        for x in 0..width {
+            let base = x * channels;
+            let value = match color {
+                3 => {
+                    let entry = sample(base) as usize * 3;
+                    match palette.get(entry..entry + 3) {
+                        Some(rgb) => {
+                            0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32
+                        }
+                        None => 0.0,
+                    }
+                }
+                2 | 6 => {
+                    (0.299 * sample(base) + 0.587 * sample(base + 1) + 0.114 * sample(base + 2))
+                        * 255.0
+                        / max
+                }
+                _ => sample(base) * 255.0 / max,
+            };
+            pixels.push(value);
+        }
+        std::mem::swap(&mut previous, &mut row);
+    }
+    Ok(Some(Gray {
+        width,
+        height,
+        pixels,
+    }))
+}
+
+struct BkNode {
+    hash: u64,
+    file: usize,
+    // (distance, node) of every child
+    children: Vec<(u32, usize)>,
+}
+
+// A BK-tree over Hamming distance, so finding the hashes near one skips most of the others
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+impl BkTree {
+    fn insert(&mut self, hash: u64, file: usize) {
+        let new = self.nodes.len();
+        self.nodes.push(BkNode {
+            hash,
+            file,
+            children: Vec::new(),
+        });
+        if new == 0 {
+            return;
+        }
+        let mut at = 0;
+        loop {
+            let distance = (self.nodes[at].hash ^ hash).count_ones();
+            match self.nodes[at].children.iter().find(|(d, _)| *d == distance) {
+                Some((_, child)) => at = *child,
+                None => {
+                    self.nodes[at].children.push((distance, new));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn within(&self, hash: u64, max: u32, found: &mut Vec<usize>) {
+        let mut pending = match self.nodes.is_empty() {
+            true => vec![],
+            false => vec![0],
+        };
+        while let Some(at) = pending.pop() {
+            let node = &self.nodes[at];
+            let distance = (node.hash ^ hash).count_ones();
+            if distance <= max {
+                found.push(node.file);
+            }
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= max)
+                    .map(|(_, child)| *child),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NearImage {
+    pub path: String,
+    pub hash: u64,
+    // Bits between this image's hash and the first one's
+    pub distance: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NearDuplicates {
+    // Largest group first, then by the first path; each sorted by path
+    pub groups: Vec<Vec<NearImage>>,
+    pub images: usize,
+    // JPEG and PNG files that couldn't be read or decoded, and images in other formats
+    pub undecoded: usize,
+}
+
+/*  The JPEG and PNG files of an analyzed DataFrame grouped by perceptual hash: images are linked
+when their hashes are at most max_distance bits apart, and a group is everything linked to each
+other, so its ends can be further apart than that. Groups whose files are all exact copies of one
+another are left out, the regular report lists them already.
+*/
+pub fn near_duplicates(
+    df: &DataFrame,
+    algorithm: Algorithm,
+    max_distance: u32,
+) -> Result<NearDuplicates, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let hashes = df.column("content_hash")?.utf8()?;
+    let images: Vec<(&str, Option<&str>)> = paths
+        .into_iter()
+        .zip(hashes)
+        .filter_map(|(path, content)| Some((path?, content)))
+        .filter(|(path, _)| photos::sniff_mime(path).is_some())
+        .collect();
+
+    let hashed: Vec<Option<u64>> = images
+        .clone()
+        .into_par_iter()
+        .map(|(path, _)| match luma(path) {
+            Ok(Some(image)) => Some(algorithm.hash(&image)),
+            _ => None,
+        })
+        .collect();
+
+    let mut tree = BkTree::default();
+    let mut decoded = Vec::new();
+    for (index, hash) in hashed.iter().enumerate() {
+        if let Some(hash) = hash {
+            tree.insert(*hash, index);
+            decoded.push(index);
+        }
+    }
+
+    // Union-find over every pair within reach
+    let mut parent: Vec<usize> = (0..images.len()).collect();
+    fn find(parent: &mut [usize], mut at: usize) -> usize {
+        while parent[at] != at {
+            parent[at] = parent[parent[at]];
+            at = parent[at];
+        }
+        at
+    }
+    let mut near = Vec::new();
+    for &index in &decoded {
+        near.clear();
+        tree.within(hashed[index].unwrap_or_default(), max_distance, &mut near);
+        for &other in &near {
+            let (a, b) = (find(&mut parent, index), find(&mut parent, other));
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+
+    let mut members: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for &index in &decoded {
+        let root = find(&mut parent, index);
+        members.entry(root).or_default().push(index);
+    }
+    let mut groups: Vec<Vec<NearImage>> = members
+        .into_values()
+        .filter(|files| files.len() > 1)
+        .filter(|files| {
+            let mut contents: HashMap<Option<&str>, usize> = HashMap::new();
+            for &index in files {
+                *contents.entry(images[index].1).or_default() += 1;
+            }
+            contents.len() > 1 || contents.contains_key(&None)
+        })
+        .map(|mut files| {
+            files.sort_by_key(|index| images[*index].0);
+            let first = hashed[files[0]].unwrap_or_default();
+            files
+                .into_iter()
+                .map(|index| {
+                    let hash = hashed[index].unwrap_or_default();
+                    NearImage {
+                        path: images[index].0.to_string(),
+                        hash,
+                        distance: (hash ^ first).count_ones(),
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.len()
+            .cmp(&a.len())
+            .then_with(|| a[0].path.cmp(&b[0].path))
+    });
+
+    Ok(NearDuplicates {
+        groups,
+        images: images.len(),
+        undecoded: images.len() - decoded.len(),
+    })
+}
+
+pub fn print_near_duplicates(near: &NearDuplicates, algorithm: Algorithm, max_distance: u32) {
+    println!(
+        "\n=== Near-Duplicate Images ({}, up to {} bit(s) apart) ===",
+        algorithm.as_str(),
+        max_distance
+    );
+    if near.groups.is_empty() {
+        println!("No near-duplicate images found beyond the exact duplicates.");
+    }
+    for group in &near.groups {
+        println!("{} similar image(s):", group.len());
+        for image in group {
+            println!(
+                "  {:016x}  {} (distance {})",
+                image.hash, image.path, image.distance
+            );
+        }
+    }
+    println!(
+        "{} near-duplicate group(s) among {} image(s); {} couldn't be decoded (only Huffman coded JPEG and non-interlaced PNG are)",
+        near.groups.len(),
+        near.images,
+        near.undecoded
+    );
+}
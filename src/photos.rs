@@ -0,0 +1,155 @@
+//image helpers used by the photos profile: type sniffing, metadata-insensitive hashing and EXIF lookups
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+
+// Sniff an image MIME type from the first bytes of a file
+pub fn sniff_mime(path: &str) -> Option<&'static str> {
+    let mut header = [0u8; 16];
+    let read = File::open(path).ok()?.read(&mut header).ok()?;
+    mime_from_magic(&header[..read])
+}
+
+fn mime_from_magic(header: &[u8]) -> Option<&'static str> {
+    if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if header.starts_with(b"GIF8") {
+        Some("image/gif")
+    } else if header.starts_with(b"II*\0") || header.starts_with(b"MM\0*") {
+        // TIFF container, also used by most camera raw formats (DNG, NEF, CR2, ARW)
+        Some("image/tiff")
+    } else if header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if header.len() >= 12
+        && &header[4..8] == b"ftyp"
+        && matches!(
+            &header[8..12],
+            b"heic" | b"heix" | b"hevc" | b"mif1" | b"msf1"
+        )
+    {
+        Some("image/heic")
+    } else {
+        None
+    }
+}
+
+/*  Hash the image content while ignoring metadata that phones and photo tools rewrite
+(EXIF, XMP, IPTC, comments, PNG text chunks). Formats we can't parse are hashed whole.
+*/
+pub fn image_hash(path: &str) -> io::Result<String> {
+    let content = fs::read(path)?;
+
+    let mut context = md5::Context::new();
+    if !feed_image_data(&content, &mut |data| context.consume(data)) {
+        return Ok(format!("{:x}", md5::compute(&content)));
+    }
+
+    Ok(format!("{:x}", context.compute()))
+}
+
+// The bytes image_hash hashes, or None for formats it can't parse
+pub fn strip_metadata(content: &[u8]) -> Option<Vec<u8>> {
+    let mut stripped = Vec::with_capacity(content.len());
+    feed_image_data(content, &mut |data| stripped.extend_from_slice(data)).then_some(stripped)
+}
+
+fn feed_image_data(content: &[u8], feed: &mut impl FnMut(&[u8])) -> bool {
+    match mime_from_magic(content) {
+        Some("image/jpeg") => feed_jpeg(content, feed),
+        Some("image/png") => feed_png(content, feed),
+        _ => false,
+    }
+}
+
+// Feed every JPEG segment except the metadata ones; returns false on malformed input
+fn feed_jpeg(content: &[u8], feed: &mut impl FnMut(&[u8])) -> bool {
+    let mut pos = 2;
+
+    while pos + 4 <= content.len() {
+        if content[pos] != 0xFF {
+            return false;
+        }
+
+        let marker = content[pos + 1];
+
+        // Standalone markers carry no length
+        if marker == 0x01 || (0xD0..=0xD8).contains(&marker) {
+            feed(&content[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+
+        // Start of scan: everything from here on is image data
+        if marker == 0xDA {
+            feed(&content[pos..]);
+            return true;
+        }
+
+        let length = u16::from_be_bytes([content[pos + 2], content[pos + 3]]) as usize;
+        let end = pos + 2 + length;
+        if length < 2 || end > content.len() {
+            return false;
+        }
+
+        // APP1 (EXIF/XMP), APP13 (IPTC) and COM segments are metadata
+        if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+            feed(&content[pos..end]);
+        }
+
+        pos = end;
+    }
+
+    false
+}
+
+// Feed every PNG chunk except text, EXIF and timestamp chunks; returns false on malformed input
+fn feed_png(content: &[u8], feed: &mut impl FnMut(&[u8])) -> bool {
+    let mut pos = 8;
+
+    while pos + 12 <= content.len() {
+        let length = u32::from_be_bytes([
+            content[pos],
+            content[pos + 1],
+            content[pos + 2],
+            content[pos + 3],
+        ]) as usize;
+        let chunk_type = &content[pos + 4..pos + 8];
+        let end = pos + 12 + length;
+        if end > content.len() {
+            return false;
+        }
+
+        if !matches!(chunk_type, b"tEXt" | b"zTXt" | b"iTXt" | b"eXIf" | b"tIME") {
+            // Skip the CRC, it only protects the chunk itself
+            feed(&content[pos + 4..end - 4]);
+        }
+
+        if chunk_type == b"IEND" {
+            return true;
+        }
+
+        pos = end;
+    }
+
+    false
+}
+
+// EXIF capture time (DateTimeOriginal, falling back to DateTime)
+pub fn capture_time(path: &str) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .iter()
+        .find_map(|tag| exif.get_field(*tag, exif::In::PRIMARY))
+        .map(|field| field.display_value().to_string())
+}
+
+// Pixel dimensions read from the image header
+pub fn dimensions(path: &str) -> Option<(u32, u32)> {
+    let size = imagesize::size(path).ok()?;
+    Some((size.width as u32, size.height as u32))
+}
@@ -0,0 +1,253 @@
+//walk and hash concurrently: discovered files stream through a bounded channel into the hashing pool
+use crate::{affinity, diagnostics, hasher, progress, timebox, FileInfo, WalkOptions};
+use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use walkdir::WalkDir;
+
+// Paths in flight between the walker and the hashers; the walker blocks once this many are queued
+const CHANNEL_CAPACITY: usize = 4096;
+
+// Bytes compared at each end of a large file before it is hashed whole
+pub const EDGE_BYTES: u64 = 64 * 1024;
+
+// 0: `dedupe` hashes every file it walks, see set_staged
+static STAGED_EDGE: AtomicU64 = AtomicU64::new(0);
+
+/*  Walk every root and hash the files keep accepts while the walk is still running. Results come
+back in walk order, as if the file list had been collected first. Dropping reproducible files
+needs the complete list, and pinned NUMA pools partition a known list, so those fall back to
+walking first.
+*/
+pub fn walk_and_hash<F>(
+    roots: &[String],
+    options: &WalkOptions,
+    keep: F,
+    hash: fn(&str) -> io::Result<String>,
+) -> Result<Vec<FileInfo>, Box<dyn Error>>
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    if options.skip_reproducible || affinity::pinning_enabled() {
+        let mut files = Vec::new();
+        for root in roots {
+            files.extend(
+                crate::walk_with_options(root, options)?
+                    .into_iter()
+                    .filter(|f| keep(f)),
+            );
+        }
+        return crate::collect_file_info_with(files, hash);
+    }
+
+    progress::message(&format!(
+        "\nAnalyzing files with {} threads while walking...",
+        rayon::current_num_threads()
+    ));
+
+    let spinner = progress::walk_spinner();
+    let pb = progress::stage_bar(progress::Stage::Hash, 0);
+    pb.set_message("Computing MD5 hashes...");
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+    let current = progress::current_file_line();
+
+    let (sender, receiver) = mpsc::sync_channel::<(usize, String)>(CHANNEL_CAPACITY);
+
+    let (walked, mut hashed) = std::thread::scope(|scope| {
+        // The walker owns the sender, so the channel closes as soon as the walk ends
+        let (keep, spinner, queued) = (&keep, spinner.clone(), pb.clone());
+        // Returns the number of files queued
+        let walker = scope.spawn(move || -> Result<usize, String> {
+            let mut sequence = 0;
+            let mut inodes = crate::SeenInodes::default();
+            for root in roots {
+                let mut ignores = crate::ignores::Ignores::default();
+                let entries = WalkDir::new(root)
+                    .follow_links(options.follow_links)
+                    .into_iter()
+                    .filter_entry(|entry| {
+                        (options.include_git || entry.depth() == 0 || entry.file_name() != ".git")
+                            && !options.excludes(entry)
+                            && !ignores.skips(entry, options)
+                    });
+
+                for entry in entries {
+                    if options.capped(sequence) {
+                        return Ok(sequence);
+                    }
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(e) => {
+                            diagnostics::walk_error(e).map_err(|e| e.to_string())?;
+                            continue;
+                        }
+                    };
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+                    let path = entry.path().to_string_lossy().to_string();
+                    spinner.inc(1);
+                    if !options.filters.accepts_entry(&path, &entry)
+                        || !keep(&path)
+                        || !inodes.first_name(&entry, options)
+                    {
+                        continue;
+                    }
+                    // Out of time: queue nothing more, the hashers drop what is already queued
+                    if timebox::out_of_time(&path) {
+                        return Ok(sequence);
+                    }
+
+                    queued.inc_length(1);
+                    // The hashers hung up, nothing left to do
+                    if sender.send((sequence, path)).is_err() {
+                        return Ok(sequence);
+                    }
+                    sequence += 1;
+                }
+            }
+            Ok(sequence)
+        });
+
+        let hashed: Vec<(usize, FileInfo)> = receiver
+            .into_iter()
+            .par_bridge()
+            .filter_map(|(sequence, path)| {
+                if timebox::out_of_time(&path) {
+                    pb.inc(1);
+                    return None;
+                }
+                current.set_message(path.clone());
+                let info = diagnostics::skipped(&path, hash(&path))
+                    .and_then(|hash| diagnostics::skipped(&path, FileInfo::with_hash(&path, hash)));
+                pb.inc(1);
+                info.map(|info| (sequence, info))
+            })
+            .collect();
+
+        (walker.join().unwrap(), hashed)
+    });
+
+    spinner.finish_and_clear();
+    current.finish_and_clear();
+    pb.finish_with_message("✓ File analysis complete!");
+    progress::message("");
+    options.report_cap(walked?);
+
+    hashed.sort_by_key(|(sequence, _)| *sequence);
+    Ok(hashed.into_iter().map(|(_, info)| info).collect())
+}
+
+/*  Bucket files by size ahead of hashing. A file whose size no other file shares can't have a copy,
+so only buckets of two or more need to be read at all. Files that can't be stat'ed are reported
+and dropped.
+*/
+pub fn group_by_size(files: Vec<String>) -> HashMap<u64, Vec<String>> {
+    let sized: Vec<(u64, String)> = files
+        .into_par_iter()
+        .filter_map(|file| {
+            diagnostics::skipped(&file, fs::metadata(&file)).map(|m| (m.len(), file))
+        })
+        .collect();
+
+    let mut groups: HashMap<u64, Vec<String>> = HashMap::new();
+    for (size, file) in sized {
+        groups.entry(size).or_default().push(file);
+    }
+    groups
+}
+
+// Stage dedupe by size, then edges, then the full hash, comparing edge bytes at each end
+pub fn set_staged(edge: u64) {
+    STAGED_EDGE.store(edge, Ordering::Relaxed);
+}
+
+pub fn staged() -> Option<u64> {
+    Some(STAGED_EDGE.load(Ordering::Relaxed)).filter(|edge| *edge > 0)
+}
+
+// What is left of the same-size files once their edges are compared
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Staged {
+    // Still colliding, sorted by path: only these need a full hash
+    pub files: Vec<String>,
+    // Files large enough to have their edges hashed first
+    pub edge_hashed: usize,
+    // Files whose edges couldn't be read, also reported through diagnostics
+    pub unreadable: Vec<diagnostics::PathError>,
+}
+
+// The first and last edge bytes of a file of len bytes
+fn edges(path: &str, len: u64, edge: u64) -> io::Result<Vec<u8>> {
+    let mut file = fs::File::open(path)?;
+    let mut bytes = vec![0u8; 2 * edge as usize];
+    let (head, tail) = bytes.split_at_mut(edge as usize);
+    file.read_exact(head)?;
+    file.seek(SeekFrom::Start(len - edge))?;
+    file.read_exact(tail)?;
+    Ok(bytes)
+}
+
+/*  The second stage after group_by_size, as fdupes and jdupes do it: files of one size are told
+apart by a hash of their first and last edge bytes, so large files that differ anywhere near
+either end are never read whole. Buckets of files no longer than two edges skip the stage, their
+full hash costs no more.
+*/
+pub fn same_edges(groups: HashMap<u64, Vec<String>>, edge: u64, hasher: hasher::Hasher) -> Staged {
+    let mut staged = Staged::default();
+    let mut large = Vec::new();
+    for (size, bucket) in groups.into_iter().filter(|(_, bucket)| bucket.len() > 1) {
+        match size > 2 * edge {
+            true => large.extend(bucket.into_iter().map(|file| (size, file))),
+            false => staged.files.extend(bucket),
+        }
+    }
+    staged.edge_hashed = large.len();
+
+    let keyed: Vec<(io::Result<Vec<u8>>, u64, String)> = large
+        .into_par_iter()
+        .map(|(size, file)| {
+            let digest = edges(&file, size, edge).map(|bytes| hasher.hash_bytes(&bytes));
+            (digest, size, file)
+        })
+        .collect();
+    let mut buckets: HashMap<(u64, Vec<u8>), Vec<String>> = HashMap::new();
+    for (digest, size, file) in keyed {
+        match digest {
+            Ok(digest) => buckets.entry((size, digest)).or_default().push(file),
+            Err(e) => {
+                diagnostics::report(diagnostics::Diagnostic::SkippedFile {
+                    path: file.clone(),
+                    reason: e.to_string(),
+                });
+                staged.unreadable.push(diagnostics::PathError {
+                    path: file,
+                    reason: e.to_string(),
+                });
+            }
+        }
+    }
+    staged.files.extend(
+        buckets
+            .into_values()
+            .filter(|bucket| bucket.len() > 1)
+            .flatten(),
+    );
+    staged.files.sort();
+    staged
+}
+
+// The files of every bucket with more than one member, sorted by path
+pub fn same_size(groups: HashMap<u64, Vec<String>>) -> Vec<String> {
+    let mut files: Vec<String> = groups
+        .into_values()
+        .filter(|bucket| bucket.len() > 1)
+        .flatten()
+        .collect();
+    files.sort();
+    files
+}
@@ -0,0 +1,176 @@
+//plan/apply workflow: a signed, reviewable file of per-file actions, carried out in a later run
+use crate::resolve::LinkSummary;
+use crate::rules::{self, PlannedAction};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const VERSION: u32 = 1;
+const KEY_CONTEXT: &str = "rdedupe plan signing key v1";
+
+// What a file looked like when it was planned; apply skips files that no longer match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size_bytes: u64,
+    pub modified_ns: u128,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    #[serde(flatten)]
+    pub action: PlannedAction,
+    pub keep_state: FileState,
+    pub path_state: FileState,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub created: u64,
+    // Directories scanned to build the plan, the scope apply checks --allow-root against
+    pub roots: Vec<String>,
+    pub entries: Vec<PlanEntry>,
+    // "key" when signed with a key file, "none" when only sealed against accidental edits
+    pub signed_with: String,
+    pub signature: String,
+}
+
+pub fn file_state(path: &Path) -> std::io::Result<FileState> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(FileState {
+        size_bytes: metadata.len(),
+        modified_ns: modified.as_nanos(),
+    })
+}
+
+/*  blake3 over the plan with an empty signature. With a key file the hash is keyed by a key
+derived from its contents, so only holders of the key can produce a plan apply accepts; without
+one it still catches edits, but anyone can reseal an edited plan.
+*/
+fn sign(plan: &Plan, key: Option<&[u8]>) -> Result<String, Box<dyn Error>> {
+    let unsigned = Plan {
+        signature: String::new(),
+        ..plan.clone()
+    };
+    let data = serde_json::to_vec(&unsigned)?;
+    let hash = match key {
+        Some(key) => blake3::keyed_hash(&blake3::derive_key(KEY_CONTEXT, key), &data),
+        None => blake3::hash(&data),
+    };
+    Ok(hash.to_hex().to_string())
+}
+
+fn read_key(key: Option<&Path>) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    key.map(|path| fs::read(path).map_err(|e| format!("{}: {}", path.display(), e).into()))
+        .transpose()
+}
+
+// Record the current state of every file the actions touch
+pub fn build(
+    planned: Vec<PlannedAction>,
+    roots: &[String],
+    key: Option<&Path>,
+) -> Result<Plan, Box<dyn Error>> {
+    let mut entries = Vec::with_capacity(planned.len());
+    for action in planned {
+        entries.push(PlanEntry {
+            keep_state: file_state(Path::new(&action.keep))?,
+            path_state: file_state(Path::new(&action.path))?,
+            action,
+        });
+    }
+
+    let mut plan = Plan {
+        version: VERSION,
+        created: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        roots: roots.to_vec(),
+        entries,
+        signed_with: String::new(),
+        signature: String::new(),
+    };
+    reseal(&mut plan, key)?;
+    Ok(plan)
+}
+
+// Sign a plan again after its entries were amended
+pub fn reseal(plan: &mut Plan, key: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let key = read_key(key)?;
+    plan.signed_with = if key.is_some() { "key" } else { "none" }.to_string();
+    plan.signature = sign(plan, key.as_deref())?;
+    Ok(())
+}
+
+pub fn write_plan(plan: &Plan, path: &Path) -> Result<(), Box<dyn Error>> {
+    crate::report::write_atomic(path, |writer| {
+        serde_json::to_writer_pretty(&mut *writer, plan)?;
+        writeln!(writer)?;
+        Ok(())
+    })
+}
+
+// Load a plan and check its signature; a keyed plan needs its key
+pub fn read_plan(path: &Path, key: Option<&Path>) -> Result<Plan, Box<dyn Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let plan: Plan =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if plan.version != VERSION {
+        return Err(format!(
+            "{}: unsupported plan version {}",
+            path.display(),
+            plan.version
+        )
+        .into());
+    }
+
+    let key = read_key(key)?;
+    match (plan.signed_with.as_str(), &key) {
+        ("key", None) => {
+            return Err(format!("{} is signed with a key, pass --key", path.display()).into())
+        }
+        ("none", Some(_)) => {
+            return Err(format!("{} is not signed with a key", path.display()).into())
+        }
+        ("key" | "none", _) => {}
+        (other, _) => return Err(format!("{}: unknown signing '{}'", path.display(), other).into()),
+    }
+    if sign(&plan, key.as_deref())? != plan.signature {
+        return Err(format!(
+            "{} fails signature verification, it was modified after planning",
+            path.display()
+        )
+        .into());
+    }
+    Ok(plan)
+}
+
+/*  Carry out a verified plan. Actions whose files changed since planning are skipped; the rest are
+re-verified byte-for-byte and guarded exactly like `dedupe --rules`.
+*/
+pub fn apply(plan: &Plan, dry_run: bool) -> Result<LinkSummary, Box<dyn Error>> {
+    let mut current = Vec::with_capacity(plan.entries.len());
+    let mut changed = 0;
+    for entry in &plan.entries {
+        let unchanged = file_state(Path::new(&entry.action.keep)).ok() == Some(entry.keep_state)
+            && file_state(Path::new(&entry.action.path)).ok() == Some(entry.path_state);
+        if unchanged {
+            current.push(entry.action.clone());
+        } else {
+            println!(
+                "skip: {} (changed since the plan was made)",
+                entry.action.path
+            );
+            changed += 1;
+        }
+    }
+
+    let mut summary = rules::apply(&current, dry_run)?;
+    summary.skipped += changed;
+    Ok(summary)
+}
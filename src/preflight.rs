@@ -0,0 +1,169 @@
+//check every planned action can be carried out before the first file is changed
+use crate::guard;
+use crate::rules::{Disposition, PlannedAction};
+use std::error::Error;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub path: String,
+    pub reason: String,
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    fn c_path(path: &Path) -> Option<CString> {
+        CString::new(path.as_os_str().as_bytes()).ok()
+    }
+
+    pub fn read_only(path: &Path) -> bool {
+        let Some(path) = c_path(path) else {
+            return false;
+        };
+        unsafe {
+            let mut stat: libc::statvfs = std::mem::zeroed();
+            libc::statvfs(path.as_ptr(), &mut stat) == 0 && stat.f_flag & libc::ST_RDONLY != 0
+        }
+    }
+
+    // Access as the effective user, the one the actions run as
+    pub fn can(path: &Path, mode: libc::c_int) -> bool {
+        let Some(path) = c_path(path) else {
+            return false;
+        };
+        unsafe { libc::faccessat(libc::AT_FDCWD, path.as_ptr(), mode, libc::AT_EACCESS) == 0 }
+    }
+
+    pub fn can_write_dir(dir: &Path) -> bool {
+        can(dir, libc::W_OK | libc::X_OK)
+    }
+
+    pub fn can_read_write(file: &Path) -> bool {
+        can(file, libc::R_OK | libc::W_OK)
+    }
+
+    pub fn euid() -> u32 {
+        unsafe { libc::geteuid() }
+    }
+
+    pub fn owner(path: &Path) -> Option<u32> {
+        std::fs::metadata(path).ok().map(|m| m.uid())
+    }
+
+    pub fn sticky(dir: &Path) -> bool {
+        std::fs::metadata(dir).is_ok_and(|m| m.mode() & libc::S_ISVTX != 0)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    use std::path::Path;
+
+    pub fn read_only(_path: &Path) -> bool {
+        false
+    }
+
+    pub fn can_write_dir(dir: &Path) -> bool {
+        std::fs::metadata(dir).is_ok_and(|m| !m.permissions().readonly())
+    }
+
+    pub fn can_read_write(file: &Path) -> bool {
+        std::fs::metadata(file).is_ok_and(|m| !m.permissions().readonly())
+    }
+
+    pub fn euid() -> u32 {
+        0
+    }
+
+    pub fn owner(_path: &Path) -> Option<u32> {
+        None
+    }
+
+    pub fn sticky(_dir: &Path) -> bool {
+        false
+    }
+}
+
+fn problem(action: &PlannedAction) -> Option<String> {
+    let victim = Path::new(&action.path);
+    let dir = match victim.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    if os::read_only(dir) {
+        return Some("read-only filesystem".to_string());
+    }
+    // Both actions replace or remove a name in the victim's directory
+    if !os::can_write_dir(dir) {
+        return Some(format!("no write permission on {}", dir.display()));
+    }
+    let euid = os::euid();
+    if euid != 0
+        && os::sticky(dir)
+        && os::owner(victim) != Some(euid)
+        && os::owner(dir) != Some(euid)
+    {
+        return Some(format!(
+            "{} is sticky and the file belongs to another user",
+            dir.display()
+        ));
+    }
+    // With fs.protected_hardlinks, linking to another user's file needs read and write access to it
+    let keep = Path::new(&action.keep);
+    if action.action == Disposition::Hardlink
+        && euid != 0
+        && os::owner(keep) != Some(euid)
+        && !os::can_read_write(keep)
+    {
+        return Some(format!(
+            "cannot link to {}, it belongs to another user",
+            keep.display()
+        ));
+    }
+    None
+}
+
+// Problems of the actions a real run would attempt; paths outside the allowed roots are skipped anyway
+pub fn check(planned: &[PlannedAction], dry_run: bool) -> Vec<Problem> {
+    planned
+        .iter()
+        .filter(|action| action.action != Disposition::Keep)
+        .filter(|action| dry_run || guard::check(Path::new(&action.path)).is_ok())
+        .filter_map(|action| {
+            problem(action).map(|reason| Problem {
+                path: action.path.clone(),
+                reason,
+            })
+        })
+        .collect()
+}
+
+/*  Print the preflight result. A real run with problems fails here, before anything is changed; a
+dry run only reports them.
+*/
+pub fn run(planned: &[PlannedAction], dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let problems = check(planned, dry_run);
+    if problems.is_empty() {
+        println!("Preflight passed: every planned path is writable");
+        return Ok(());
+    }
+
+    println!("\nPreflight failed for {} path(s):", problems.len());
+    for problem in &problems {
+        println!("  {}: {}", problem.path, problem.reason);
+    }
+    if dry_run {
+        return Ok(());
+    }
+    Err(format!(
+        "preflight failed for {} path(s), nothing was changed",
+        problems.len()
+    )
+    .into())
+}
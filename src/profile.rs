@@ -0,0 +1,742 @@
+//preset profiles bundling filters, comparison and keep policy for common dedupe jobs
+use crate::audit;
+use crate::photos;
+use crate::progress::{self, Stage};
+use crate::resolve::{self, LinkSummary, Verdict};
+use crate::rules::{Disposition, PlannedAction};
+use polars::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// How file content is compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentMode {
+    // Hash the raw bytes
+    #[default]
+    Raw,
+    // Hash image data only, ignoring EXIF and other embedded metadata
+    Image,
+}
+
+// What to do with the copies that are not kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileAction {
+    // Only report
+    #[default]
+    Report,
+    // Replace extra copies with hardlinks to the kept one
+    Hardlink,
+}
+
+// Which copy of a duplicate group is marked as the one to keep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeepPolicy {
+    // First path in sorted order
+    #[default]
+    First,
+    // Largest width x height, then largest file, then first path
+    HighestResolution,
+}
+
+impl KeepPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeepPolicy::First => "first",
+            KeepPolicy::HighestResolution => "highest-resolution",
+        }
+    }
+}
+
+// Which timestamps decide whether a copy is old enough to act on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AgeField {
+    #[default]
+    Modified,
+    Accessed,
+    // Neither modified nor accessed within the threshold
+    Both,
+}
+
+pub fn parse_age_field(value: &str) -> Result<AgeField, String> {
+    match value {
+        "modified" => Ok(AgeField::Modified),
+        "accessed" => Ok(AgeField::Accessed),
+        "both" => Ok(AgeField::Both),
+        _ => Err(format!(
+            "unknown age field '{}' (expected modified, accessed or both)",
+            value
+        )),
+    }
+}
+
+// An age such as 90d, 12w or 2y (a year is 365 days, a bare number is days) in seconds
+pub fn parse_age(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "d"),
+    };
+    let days_per_unit = match unit {
+        "d" => 1,
+        "w" => 7,
+        "y" => 365,
+        _ => {
+            return Err(format!(
+                "invalid age '{}' (expected e.g. 90d, 12w or 2y)",
+                value
+            ))
+        }
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid age '{}' (expected e.g. 90d, 12w or 2y)", value))?;
+    Ok(number * days_per_unit * 86_400)
+}
+
+// Unset fields in a config file fall back to a report-only scan of everything
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    // Taken from the config table name
+    #[serde(skip)]
+    pub name: String,
+    pub description: String,
+    // Lowercase extensions without the dot; empty accepts everything
+    pub extensions: Vec<String>,
+    // Sniffed MIME types accepted even when the extension doesn't match
+    pub mime_types: Vec<String>,
+    pub content: ContentMode,
+    pub group_by_capture_time: bool,
+    pub keep: KeepPolicy,
+    // Only copies untouched for this long are acted on, e.g. "2y"; younger copies are kept too
+    pub older_than: Option<String>,
+    pub age_by: AgeField,
+    // Directories scanned when no path is given; a leading ~ is the home directory
+    pub roots: Vec<String>,
+    pub action: ProfileAction,
+}
+
+impl Profile {
+    // Minimum age in seconds for a copy to be acted on, if the profile has one
+    pub fn age_threshold(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(match &self.older_than {
+            Some(age) => Some(parse_age(age)?),
+            None => None,
+        })
+    }
+
+    // Default roots that exist on this machine, with ~ expanded
+    pub fn existing_roots(&self) -> Vec<String> {
+        self.roots
+            .iter()
+            .map(|root| expand_home(root))
+            .filter(|root| Path::new(root).is_dir())
+            .collect()
+    }
+
+    // Check a path against the extension and MIME filters
+    pub fn matches(&self, path: &str) -> bool {
+        if self.extensions.is_empty() && self.mime_types.is_empty() {
+            return true;
+        }
+
+        let extension = Path::new(path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        if self.extensions.contains(&extension) {
+            return true;
+        }
+
+        !self.mime_types.is_empty()
+            && photos::sniff_mime(path)
+                .is_some_and(|mime| self.mime_types.iter().any(|m| m == mime))
+    }
+}
+
+// Tuned for camera rolls merged from several phones
+pub fn photos() -> Profile {
+    let extensions = [
+        "jpg", "jpeg", "png", "heic", "heif", "gif", "webp", "tif", "tiff", "dng", "cr2", "cr3",
+        "nef", "arw", "orf", "rw2",
+    ];
+    let mime_types = [
+        "image/jpeg",
+        "image/png",
+        "image/heic",
+        "image/gif",
+        "image/webp",
+        "image/tiff",
+    ];
+
+    Profile {
+        name: "photos".to_string(),
+        description:
+            "Camera rolls: image files, EXIF-insensitive comparison, keep highest resolution"
+                .to_string(),
+        extensions: extensions.iter().map(|e| e.to_string()).collect(),
+        mime_types: mime_types.iter().map(|m| m.to_string()).collect(),
+        content: ContentMode::Image,
+        group_by_capture_time: true,
+        keep: KeepPolicy::HighestResolution,
+        older_than: None,
+        age_by: AgeField::Modified,
+        roots: Vec::new(),
+        action: ProfileAction::Report,
+    }
+}
+
+fn expand_home(path: &str) -> String {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Ok(home)) => format!("{}{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+/*  Package and build caches hold immutable, content-addressed artifacts, so identical files
+inside and across them can be hardlinked safely. Deleting is never offered.
+*/
+fn package_cache(name: &str, description: &str, roots: &[&str]) -> Profile {
+    Profile {
+        name: name.to_string(),
+        description: description.to_string(),
+        extensions: Vec::new(),
+        mime_types: Vec::new(),
+        content: ContentMode::Raw,
+        group_by_capture_time: false,
+        keep: KeepPolicy::First,
+        older_than: None,
+        age_by: AgeField::Modified,
+        roots: roots.iter().map(|r| r.to_string()).collect(),
+        action: ProfileAction::Hardlink,
+    }
+}
+
+const CARGO_ROOTS: [&str; 3] = [
+    "~/.cargo/registry/cache",
+    "~/.cargo/registry/src",
+    "~/.cargo/git/checkouts",
+];
+const NPM_ROOTS: [&str; 2] = ["~/.npm/_cacache/content-v2", "~/.cache/yarn"];
+const PIP_ROOTS: [&str; 2] = ["~/.cache/pip", "~/Library/Caches/pip"];
+const GRADLE_ROOTS: [&str; 2] = ["~/.gradle/caches/modules-2/files-2.1", "~/.m2/repository"];
+
+// Look up a built-in profile by name
+pub fn builtin(name: &str) -> Option<Profile> {
+    match name {
+        "photos" => Some(photos()),
+        "cargo" => Some(package_cache(
+            "cargo",
+            "Cargo registry and git caches, hardlink identical artifacts",
+            &CARGO_ROOTS,
+        )),
+        "npm" => Some(package_cache(
+            "npm",
+            "npm and yarn caches, hardlink identical artifacts",
+            &NPM_ROOTS,
+        )),
+        "pip" => Some(package_cache(
+            "pip",
+            "pip wheel and http caches, hardlink identical artifacts",
+            &PIP_ROOTS,
+        )),
+        "gradle" => Some(package_cache(
+            "gradle",
+            "Gradle and Maven artifact caches, hardlink identical artifacts",
+            &GRADLE_ROOTS,
+        )),
+        "caches" => {
+            let roots: Vec<&str> =
+                [&CARGO_ROOTS[..], &NPM_ROOTS, &PIP_ROOTS, &GRADLE_ROOTS].concat();
+            Some(package_cache(
+                "caches",
+                "All known package caches at once, hardlink identical artifacts across them",
+                &roots,
+            ))
+        }
+        _ => None,
+    }
+}
+
+pub fn builtin_names() -> Vec<&'static str> {
+    vec!["photos", "cargo", "npm", "pip", "gradle", "caches"]
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProfileConfig {
+    profiles: BTreeMap<String, Profile>,
+}
+
+// RDEDUPE_CONFIG, else $XDG_CONFIG_HOME/rdedupe/config.toml, else ~/.config/rdedupe/config.toml
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RDEDUPE_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| Path::new(&home).join(".config")))
+        .ok()?;
+
+    Some(config_home.join("rdedupe").join("config.toml"))
+}
+
+/*  Load user presets from the [profiles.<name>] tables of a TOML config file:
+
+    [profiles.videos]
+    description = "Team video dumps"
+    extensions = ["mp4", "mov"]
+    roots = ["~/Videos"]
+    content = "raw"          # raw | image
+    keep = "first"           # first | highest-resolution
+    older_than = "2y"        # only act on copies untouched this long
+    age_by = "modified"      # modified | accessed | both
+    action = "hardlink"      # report | hardlink
+
+A missing file simply means no user presets.
+*/
+pub fn load_user_profiles(path: &Path) -> Result<Vec<Profile>, Box<dyn Error>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let config: ProfileConfig =
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    Ok(config
+        .profiles
+        .into_iter()
+        .map(|(name, mut profile)| {
+            profile.name = name;
+            profile.extensions = profile
+                .extensions
+                .iter()
+                .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                .collect();
+            profile
+                .age_threshold()
+                .map_err(|e| format!("{}: profile '{}': {}", path.display(), profile.name, e))?;
+            Ok(profile)
+        })
+        .collect::<Result<_, String>>()?)
+}
+
+// Find a profile by name; user presets take precedence over built-ins of the same name
+pub fn lookup(name: &str, config: Option<&Path>) -> Result<Option<Profile>, Box<dyn Error>> {
+    if let Some(config) = config {
+        let user = load_user_profiles(config)?;
+        if let Some(profile) = user.into_iter().find(|p| p.name == name) {
+            return Ok(Some(profile));
+        }
+    }
+
+    Ok(builtin(name))
+}
+
+// Every selectable profile with where it comes from ("built-in" or the config path)
+pub fn available(config: Option<&Path>) -> Result<Vec<(Profile, String)>, Box<dyn Error>> {
+    let user = match config {
+        Some(config) => load_user_profiles(config)?
+            .into_iter()
+            .map(|p| (p, config.display().to_string()))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let mut profiles: Vec<(Profile, String)> = builtin_names()
+        .into_iter()
+        .filter(|name| !user.iter().any(|(p, _)| p.name == *name))
+        .filter_map(builtin)
+        .map(|p| (p, "built-in".to_string()))
+        .collect();
+    profiles.extend(user);
+
+    Ok(profiles)
+}
+
+// Run the DataFrame analysis over the given roots with a profile's filters and policies applied
+pub fn run_profile(
+    paths: &[String],
+    pattern: &str,
+    profile: &Profile,
+    output_csv: Option<&str>,
+    walk_options: &crate::WalkOptions,
+    verify: bool,
+) -> Result<DataFrame, Box<dyn Error>> {
+    for path in paths {
+        progress::message(&format!(
+            "Scanning directory: {} (profile '{}')",
+            path, profile.name
+        ));
+    }
+
+    let hash = match profile.content {
+        ContentMode::Raw => crate::content_hash,
+        ContentMode::Image => photos::image_hash,
+    };
+    let mut file_infos = crate::pipeline::walk_and_hash(
+        paths,
+        walk_options,
+        |file| file.contains(pattern) && profile.matches(file),
+        hash,
+    )?;
+
+    progress::message(&format!(
+        "Found {} files matching profile '{}'",
+        file_infos.len(),
+        profile.name
+    ));
+
+    if file_infos.is_empty() {
+        progress::message("No files found to analyze.");
+        return crate::empty_dataframe();
+    }
+
+    if verify {
+        crate::verify::report_split(crate::verify::split_mismatches(&mut file_infos));
+    }
+
+    let mut df = crate::create_dataframe(file_infos)?;
+    if paths.len() > 1 {
+        df = crate::roots::label(&df, paths)?;
+    }
+
+    let paths: Vec<String> = column_strings(&df, "file_path")?;
+    let mut dims: Vec<Option<(u32, u32)>> = vec![None; paths.len()];
+
+    if profile.content == ContentMode::Image || profile.keep == KeepPolicy::HighestResolution {
+        dims = paths.par_iter().map(|p| photos::dimensions(p)).collect();
+        df.with_column(Series::new(
+            "width",
+            dims.iter().map(|d| d.map(|(w, _)| w)).collect::<Vec<_>>(),
+        ))?;
+        df.with_column(Series::new(
+            "height",
+            dims.iter().map(|d| d.map(|(_, h)| h)).collect::<Vec<_>>(),
+        ))?;
+    }
+
+    if profile.group_by_capture_time {
+        add_capture_groups(&mut df, &paths)?;
+    }
+
+    let mut keep = elect_keepers(&df, &dims, profile.keep)?;
+    if let Some(threshold) = profile.age_threshold()? {
+        let spared = spare_recent(&df, &keep, threshold, profile.age_by)?;
+        progress::message(&format!(
+            "Age rule: {} duplicate(s) touched within {} are kept",
+            spared.iter().filter(|s| **s).count(),
+            profile.older_than.as_deref().unwrap_or_default()
+        ));
+        for (kept, spared) in keep.iter_mut().zip(&spared) {
+            *kept |= *spared;
+        }
+        df.with_column(Series::new("spared", spared))?;
+    }
+    df.with_column(Series::new("keep", keep))?;
+
+    crate::print_report(&df, output_csv)?;
+
+    Ok(df)
+}
+
+fn column_strings(df: &DataFrame, name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    Ok(df
+        .column(name)?
+        .utf8()?
+        .into_iter()
+        .map(|v| v.unwrap_or_default().to_string())
+        .collect())
+}
+
+/*  Mark one file per group as the keeper. Groups are the exact duplicate groups, widened to
+the capture group when capture-time grouping is on, so re-encodes of the same shot compete too.
+Files in no group are always kept.
+*/
+fn elect_keepers(
+    df: &DataFrame,
+    dims: &[Option<(u32, u32)>],
+    policy: KeepPolicy,
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let duplicate_groups: Vec<Option<&str>> =
+        df.column("duplicate_group")?.utf8()?.into_iter().collect();
+    let capture_groups: Vec<Option<&str>> = match df.column("capture_group") {
+        Ok(column) => column.utf8()?.into_iter().collect(),
+        Err(_) => vec![None; df.height()],
+    };
+
+    // An exact duplicate group joins the capture group of any of its members
+    let mut widened: HashMap<&str, &str> = HashMap::new();
+    for (duplicate, capture) in duplicate_groups.iter().zip(&capture_groups) {
+        if let (Some(duplicate), Some(capture)) = (duplicate, capture) {
+            widened.entry(duplicate).or_insert(capture);
+        }
+    }
+
+    let mut members: HashMap<String, Vec<usize>> = HashMap::new();
+    for index in 0..df.height() {
+        let cluster = match (capture_groups[index], duplicate_groups[index]) {
+            (Some(capture), _) => format!("capture:{}", capture),
+            (None, Some(duplicate)) => match widened.get(duplicate) {
+                Some(capture) => format!("capture:{}", capture),
+                None => format!("hash:{}", duplicate),
+            },
+            (None, None) => continue,
+        };
+        members.entry(cluster).or_default().push(index);
+    }
+
+    let mut keep = vec![true; df.height()];
+    for indices in members.values() {
+        let keeper = indices.iter().copied().min_by(|&a, &b| {
+            let by_path = paths.get(a).cmp(&paths.get(b));
+            match policy {
+                KeepPolicy::First => by_path,
+                KeepPolicy::HighestResolution => {
+                    let pixels = |i: usize| dims[i].map(|(w, h)| w as u64 * h as u64).unwrap_or(0);
+                    pixels(b)
+                        .cmp(&pixels(a))
+                        .then(sizes.get(b).cmp(&sizes.get(a)))
+                        .then(by_path)
+                }
+            }
+        });
+
+        for &index in indices {
+            keep[index] = Some(index) == keeper;
+        }
+    }
+
+    Ok(keep)
+}
+
+// Whether a copy is old enough to act on; unknown times count as recent, so we never act on a guess
+fn is_stale(path: &str, threshold: u64, by: AgeField) -> bool {
+    let now = std::time::SystemTime::now();
+    let age = |time: std::io::Result<std::time::SystemTime>| {
+        time.ok()
+            .map(|t| now.duration_since(t).map(|d| d.as_secs()).unwrap_or(0))
+    };
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            let modified = age(metadata.modified()).is_some_and(|a| a >= threshold);
+            let accessed = age(metadata.accessed()).is_some_and(|a| a >= threshold);
+            match by {
+                AgeField::Modified => modified,
+                AgeField::Accessed => accessed,
+                AgeField::Both => modified && accessed,
+            }
+        }
+        Err(_) => false,
+    }
+}
+
+/*  Mark the copies too young to act on, looking at the modification and/or access time. The
+elected keepers are never marked, so a group's keeper stays identifiable whatever its age.
+*/
+fn spare_recent(
+    df: &DataFrame,
+    keep: &[bool],
+    threshold: u64,
+    by: AgeField,
+) -> Result<Vec<bool>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut spared = vec![false; keep.len()];
+    for (index, &kept) in keep.iter().enumerate() {
+        let (Some(path), Some(_)) = (paths.get(index), groups.get(index)) else {
+            continue;
+        };
+        if kept {
+            continue;
+        }
+        spared[index] = !is_stale(path, threshold, by);
+    }
+    Ok(spared)
+}
+
+// The age rule for --action and --rules plans: drop the changes to copies too young, returning how many
+pub fn spare_recent_actions(
+    planned: &mut Vec<PlannedAction>,
+    threshold: u64,
+    by: AgeField,
+) -> usize {
+    let before = planned.len();
+    planned.retain(|action| {
+        action.action == Disposition::Keep || is_stale(&action.path, threshold, by)
+    });
+    before - planned.len()
+}
+
+/*  Add the EXIF capture time and a capture_group column for photos that share a capture time
+but not content, which usually means the same shot was re-encoded or edited on another device
+*/
+fn add_capture_groups(df: &mut DataFrame, paths: &[String]) -> Result<(), Box<dyn Error>> {
+    let capture_times: Vec<Option<String>> =
+        paths.par_iter().map(|p| photos::capture_time(p)).collect();
+
+    let hashes = column_strings(df, "content_hash")?;
+    let mut by_time: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (index, time) in capture_times.iter().enumerate() {
+        if let Some(time) = time {
+            by_time.entry(time).or_default().push(index);
+        }
+    }
+
+    let mut capture_group: Vec<Option<String>> = vec![None; paths.len()];
+    let mut reencodes = Vec::new();
+    for (time, indices) in &by_time {
+        let first_hash = &hashes[indices[0]];
+        if indices.iter().any(|&i| &hashes[i] != first_hash) {
+            for &i in indices {
+                capture_group[i] = Some(time.to_string());
+            }
+            reencodes.push((time.to_string(), indices.len()));
+        }
+    }
+
+    if !reencodes.is_empty() {
+        reencodes.sort();
+        progress::message("\n=== Photos Sharing A Capture Time (possible re-encodes) ===");
+        for (time, count) in &reencodes {
+            progress::message(&format!("  {} -> {} files", time, count));
+        }
+    }
+
+    df.with_column(Series::new("capture_time", capture_times))?;
+    df.with_column(Series::new("capture_group", capture_group))?;
+
+    Ok(())
+}
+
+// The elected keeper of every group of a DataFrame analyzed by run_profile
+pub fn keepers(df: &DataFrame) -> Result<HashMap<&str, &str>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+    let keep = df.column("keep")?.bool()?;
+    // Copies kept only because of the age rule don't count as their group's keeper
+    let spared: Vec<bool> = match df.column("spared") {
+        Ok(column) => column
+            .bool()?
+            .into_iter()
+            .map(|s| s == Some(true))
+            .collect(),
+        Err(_) => vec![false; df.height()],
+    };
+
+    let mut keepers = HashMap::new();
+    for (index, &spared) in spared.iter().enumerate() {
+        if let (Some(group), Some(true), Some(path), false) =
+            (groups.get(index), keep.get(index), paths.get(index), spared)
+        {
+            keepers.insert(group, path);
+        }
+    }
+    Ok(keepers)
+}
+
+/*  Apply the profile's action to an analyzed DataFrame: every duplicate not marked keep is
+hardlinked to its group's keeper. Files inside git repositories are skipped unless forced, and
+nothing is changed without allowed roots (see guard). With only_groups, other groups are left
+alone. Returns None for report-only profiles.
+*/
+pub fn apply_action(
+    df: &DataFrame,
+    profile: &Profile,
+    dry_run: bool,
+    force: bool,
+    only_groups: Option<&HashSet<String>>,
+) -> Result<Option<LinkSummary>, Box<dyn Error>> {
+    if profile.action == ProfileAction::Report || df.height() == 0 {
+        return Ok(None);
+    }
+    if !dry_run {
+        crate::guard::require()?;
+    }
+
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+    let keep = df.column("keep")?.bool()?;
+    let keepers = keepers(df)?;
+
+    let victims: Vec<(usize, &str, &str)> = (0..df.height())
+        .filter_map(
+            |index| match (groups.get(index), keep.get(index), paths.get(index)) {
+                (Some(group), _, _) if only_groups.is_some_and(|only| !only.contains(group)) => {
+                    None
+                }
+                (Some(group), Some(false), Some(path)) => {
+                    keepers.get(group).map(|keeper| (index, *keeper, path))
+                }
+                _ => None,
+            },
+        )
+        .collect();
+
+    // Each pair is verified right before it is acted on, so both bars move together
+    let verifying = progress::stage_bar(Stage::Verify, victims.len() as u64);
+    let acting = progress::stage_bar(Stage::Act, victims.len() as u64);
+
+    let policy = match &profile.older_than {
+        Some(age) => format!(
+            "profile {}: keep {:?}, act on copies older than {} by {:?}",
+            profile.name, profile.keep, age, profile.age_by
+        ),
+        None => format!("profile {}: keep {:?}", profile.name, profile.keep),
+    };
+    let mut summary = LinkSummary::default();
+    for (index, keeper, path) in victims {
+        let (keeper, victim) = (Path::new(keeper), Path::new(path));
+
+        if !force && crate::git::inside_repository(victim) {
+            progress::message(&format!(
+                "skip: {} (inside a git repository, use --force)",
+                path
+            ));
+            summary.skipped += 1;
+            verifying.inc(1);
+            acting.inc(1);
+            continue;
+        }
+
+        let verdict = resolve::verify_pair(keeper, victim)?;
+        verifying.inc(1);
+
+        verifying.suspend(|| match verdict {
+            Verdict::AlreadyLinked => {}
+            Verdict::Mismatch => resolve::skip_mismatch(victim, &mut summary),
+            Verdict::Ready => resolve::link_verified(
+                keeper,
+                victim,
+                sizes.get(index).unwrap_or(0),
+                dry_run,
+                &audit::Context {
+                    hash: groups.get(index).unwrap_or_default(),
+                    policy: &policy,
+                },
+                &mut summary,
+            ),
+        });
+        acting.inc(1);
+    }
+    verifying.finish_and_clear();
+    acting.finish_and_clear();
+
+    Ok(Some(summary))
+}
@@ -0,0 +1,166 @@
+//multi-stage progress display: one line per pipeline stage plus the file being hashed
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::sync::OnceLock;
+
+static MULTI: OnceLock<MultiProgress> = OnceLock::new();
+static SINK: OnceLock<Box<dyn ProgressSink>> = OnceLock::new();
+
+// Files at least this big get their own byte progress line while they are hashed
+pub const LARGE_FILE: u64 = 64 * 1_048_576;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Walk,
+    Hash,
+    Verify,
+    Act,
+}
+
+impl Stage {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stage::Walk => "walking",
+            Stage::Hash => "hashing",
+            Stage::Verify => "verifying",
+            Stage::Act => "acting",
+        }
+    }
+}
+
+/*  Progress events of a run, for embedders to show in their own UI. Every method does nothing by
+default and without a sink nothing is drawn at all, so library use stays silent. Called from the
+hashing threads, so it must be cheap and thread safe.
+*/
+pub trait ProgressSink: Send + Sync {
+    // A stage begins; total is None while it isn't known, as for the walk
+    fn on_phase_change(&self, _stage: Stage, _total: Option<u64>) {}
+
+    fn on_file_start(&self, _path: &str) {}
+
+    fn on_file_hashed(&self, _path: &str, _hash: &str) {}
+
+    // A line of commentary on the run, such as how many files a profile matched
+    fn on_message(&self, _message: &str) {}
+
+    // Whether the built-in indicatif stage bars are drawn to the terminal
+    fn draws_bars(&self) -> bool {
+        false
+    }
+}
+
+// The command line's sink: the indicatif stage bars below, drawn on stderr
+pub struct Indicatif;
+
+impl ProgressSink for Indicatif {
+    fn on_message(&self, message: &str) {
+        multi().suspend(|| println!("{}", message));
+    }
+
+    fn draws_bars(&self) -> bool {
+        true
+    }
+}
+
+// The command line's sink when stderr isn't a terminal: the messages on stdout, no bars
+pub struct Messages;
+
+impl ProgressSink for Messages {
+    fn on_message(&self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+// Only the first sink of a run is used, and only if set before the first bar
+pub fn set_sink(sink: Box<dyn ProgressSink>) {
+    let _ = SINK.set(sink);
+}
+
+pub fn phase(stage: Stage, total: Option<u64>) {
+    if let Some(sink) = SINK.get() {
+        sink.on_phase_change(stage, total);
+    }
+}
+
+pub fn file_started(path: &str) {
+    if let Some(sink) = SINK.get() {
+        sink.on_file_start(path);
+    }
+}
+
+pub fn file_hashed(path: &str, hash: &str) {
+    if let Some(sink) = SINK.get() {
+        sink.on_file_hashed(path, hash);
+    }
+}
+
+pub fn message(message: &str) {
+    if let Some(sink) = SINK.get() {
+        sink.on_message(message);
+    }
+}
+
+// Every stage bar of the run is drawn into this one display, hidden unless the sink draws bars
+pub fn multi() -> &'static MultiProgress {
+    MULTI.get_or_init(|| match SINK.get().is_some_and(|sink| sink.draws_bars()) {
+        true => MultiProgress::new(),
+        false => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    })
+}
+
+// A counter for the walk, whose total isn't known up front
+pub fn walk_spinner() -> ProgressBar {
+    phase(Stage::Walk, None);
+    let bar = multi().add(ProgressBar::new_spinner());
+    bar.set_style(
+        ProgressStyle::default_spinner()
+            .template("{prefix:>9} {spinner:.green} [{elapsed_precise}] {pos} files found")
+            .unwrap(),
+    );
+    bar.set_prefix(Stage::Walk.label());
+    bar.enable_steady_tick(std::time::Duration::from_millis(100));
+    bar
+}
+
+// A bar of len steps; 0 when the total grows as the stage runs
+pub fn stage_bar(stage: Stage, len: u64) -> ProgressBar {
+    phase(stage, Some(len).filter(|len| *len > 0));
+    let bar = multi().add(ProgressBar::new(len));
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{prefix:>9} {spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg}",
+            )
+            .unwrap()
+            .progress_chars("##-"),
+    );
+    bar.set_prefix(stage.label());
+    bar
+}
+
+// A line under the stage bars naming the file a worker is on
+pub fn current_file_line() -> ProgressBar {
+    let line = multi().add(ProgressBar::new_spinner());
+    line.set_style(
+        ProgressStyle::default_spinner()
+            .template("{prefix:>9} {wide_msg}")
+            .unwrap(),
+    );
+    line.set_prefix("file");
+    line
+}
+
+// Byte progress of one large file, removed again once it is hashed
+pub fn file_bytes_bar(path: &str, len: u64) -> ProgressBar {
+    let bar = multi().add(ProgressBar::new(len));
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{prefix:>9} [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta}) {wide_msg}",
+            )
+            .unwrap()
+            .progress_chars("##-"),
+    );
+    bar.set_prefix("large");
+    bar.set_message(path.to_string());
+    bar
+}
@@ -0,0 +1,446 @@
+//offload file hashing to worker processes that sit close to the data, e.g. on the NAS itself
+use crate::diagnostics::{self, Diagnostic};
+use crate::hasher::Hasher;
+use crate::progress::{self, Stage};
+use crate::{FileInfo, HashSettings};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::error::Error;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+// Paths sent in one request; a worker hashes a batch with all its threads
+const BATCH: usize = 64;
+// The largest batch a worker accepts, so a bad header can't make it reserve unbounded memory
+const MAX_BATCH: usize = 4 * BATCH;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+// Longer than any header or path a client sends, PATH_MAX being 4096 on Linux
+const MAX_LINE: u64 = 8192;
+// A worker drops a client that sends nothing, or reads nothing, for this long
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+// Every connection holds a thread, so a worker turns away clients beyond this many
+const MAX_CONNECTIONS: usize = 64;
+
+static WORKERS: OnceLock<Workers> = OnceLock::new();
+
+// The workers of a run and the token they were started with
+#[derive(Clone, Default)]
+pub struct Workers {
+    pub addresses: Vec<String>,
+    pub token: String,
+}
+
+impl std::fmt::Debug for Workers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Workers")
+            .field("addresses", &self.addresses)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RemoteStats {
+    pub workers: usize,
+    pub remote: usize,
+    // Files hashed here because their worker was unreachable, dropped the connection or couldn't read them
+    pub local: usize,
+}
+
+// Route the checksum stage of this run to these host:port workers
+pub fn set_workers(workers: Workers) {
+    let _ = WORKERS.set(workers);
+}
+
+pub fn enabled() -> bool {
+    WORKERS.get().is_some_and(|w| !w.addresses.is_empty())
+}
+
+// The workers of the run, for callers such as scan::ScanOptions that carry their own
+pub fn workers() -> Option<Workers> {
+    WORKERS.get().cloned()
+}
+
+// The shared secret of workers and clients: --token or --remote-token, else RDEDUPE_TOKEN
+pub fn token(given: Option<String>) -> Option<String> {
+    given
+        .or_else(|| std::env::var("RDEDUPE_TOKEN").ok())
+        .filter(|token| !token.is_empty())
+}
+
+// Compares every byte whatever the first difference, so the time taken doesn't leak the token
+fn same_token(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/*  Wire format, one line per message:
+
+    client: AUTH <token>                  worker: OK
+    client: BATCH <n> <algorithm>         worker: OK <hex digest>   (n replies, in request order)
+    client: <relative path>               worker: ERR <message>     (a file it couldn't hash)
+    ...n paths
+
+In place of any reply a worker may send REFUSED <reason> and hang up: a bad token, a malformed or
+oversized request, another algorithm, or too many clients connected already.
+
+A connection starts with AUTH, then carries any number of batches of at most MAX_BATCH paths. The
+algorithm is a hasher::Hasher name; a worker hashing with another one refuses the batch, so a
+client never mixes digests of two algorithms. Paths are relative to the scanned root on the client
+and to --root on the worker, so both sides may mount the data in different places. Lines are at
+most MAX_LINE bytes, and a client quiet for IDLE_TIMEOUT is dropped.
+*/
+fn resolve(root: &Path, relative: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(relative);
+    if relative
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err("path escapes the worker root".to_string());
+    }
+    // A symlink under the root may still point out of it; root is canonical already
+    let path = root
+        .join(relative)
+        .canonicalize()
+        .map_err(|e| e.to_string())?;
+    if !path.starts_with(root) {
+        return Err("path escapes the worker root".to_string());
+    }
+    Ok(path)
+}
+
+// Tell the client why it is turned away, then hang up
+fn refuse(writer: &mut impl Write, reason: &str) -> io::Result<()> {
+    writeln!(writer, "REFUSED {}", reason)?;
+    writer.flush()?;
+    Err(invalid(reason))
+}
+
+// One line into line, refusing a client whose line runs past MAX_LINE; 0 once it hangs up
+fn read_line(
+    reader: &mut BufReader<TcpStream>,
+    writer: &mut impl Write,
+    line: &mut String,
+) -> io::Result<usize> {
+    line.clear();
+    let read = reader.by_ref().take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        refuse(writer, &format!("line longer than {} bytes", MAX_LINE))?;
+    }
+    Ok(read)
+}
+
+fn serve_connection(stream: TcpStream, root: &Path, token: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(IDLE_TIMEOUT))?;
+    stream.set_write_timeout(Some(IDLE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    let mut line = String::new();
+
+    if read_line(&mut reader, &mut writer, &mut line)? == 0 {
+        return Ok(());
+    }
+    match line.trim_end().strip_prefix("AUTH ") {
+        Some(given) if same_token(given, token) => {
+            writeln!(writer, "OK")?;
+            writer.flush()?;
+        }
+        _ => return refuse(&mut writer, "bad token"),
+    }
+
+    let algorithm = crate::hasher::selected().as_str();
+    loop {
+        if read_line(&mut reader, &mut writer, &mut line)? == 0 {
+            return Ok(());
+        }
+        let Some((count, requested)) = line
+            .trim_end()
+            .strip_prefix("BATCH ")
+            .and_then(|header| header.split_once(' '))
+        else {
+            return refuse(&mut writer, "expected BATCH <n> <algorithm>");
+        };
+        let count: usize = match count.parse() {
+            Ok(count) if count <= MAX_BATCH => count,
+            _ => return refuse(&mut writer, &format!("batch size must be 0-{}", MAX_BATCH)),
+        };
+        if requested != algorithm {
+            return refuse(
+                &mut writer,
+                &format!("worker hashes with {}, not {}", algorithm, requested),
+            );
+        }
+
+        let mut paths = Vec::with_capacity(count);
+        for _ in 0..count {
+            if read_line(&mut reader, &mut writer, &mut line)? == 0 {
+                return Err(invalid("connection closed inside a batch"));
+            }
+            paths.push(line.trim_end_matches('\n').to_string());
+        }
+
+        let replies: Vec<String> = paths
+            .par_iter()
+            .map(|relative| {
+                let hashed = resolve(root, relative).and_then(|path| {
+                    crate::content_hash(&path.to_string_lossy()).map_err(|e| e.to_string())
+                });
+                match hashed {
+                    Ok(hash) => format!("OK {}\n", hash),
+                    Err(e) => format!("ERR {}\n", e.replace('\n', " ")),
+                }
+            })
+            .collect();
+        for reply in replies {
+            writer.write_all(reply.as_bytes())?;
+        }
+        writer.flush()?;
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Accept clients presenting token forever, one thread per connection up to MAX_CONNECTIONS
+pub fn serve(listen: &str, root: &Path, token: &str) -> Result<(), Box<dyn Error>> {
+    if !root.is_dir() {
+        return Err(format!("{} is not a directory", root.display()).into());
+    }
+    let root = root.canonicalize()?;
+    let listener = TcpListener::bind(listen)?;
+    println!(
+        "Hashing worker listening on {} for files under {}",
+        listener.local_addr()?,
+        root.display()
+    );
+
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Warning: failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            let _ = stream.set_write_timeout(Some(IDLE_TIMEOUT));
+            let _ = refuse(&mut stream, "too many connections");
+            continue;
+        }
+        let (root, token, active) = (root.clone(), token.to_string(), active.clone());
+        std::thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if let Err(e) = serve_connection(stream, &root, &token) {
+                println!("Warning: client {} dropped: {}", peer, e);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+    Ok(())
+}
+
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl Connection {
+    fn open(address: &str, token: &str) -> io::Result<Self> {
+        let address = std::net::ToSocketAddrs::to_socket_addrs(address)?
+            .next()
+            .ok_or_else(|| invalid("address did not resolve"))?;
+        let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        };
+        writeln!(connection.writer, "AUTH {}", token)?;
+        connection.writer.flush()?;
+        match connection.reply()?.as_str() {
+            "OK" => Ok(connection),
+            reply => Err(refused(reply)),
+        }
+    }
+
+    fn reply(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(invalid("worker closed the connection"));
+        }
+        Ok(line.trim_end().to_string())
+    }
+
+    /*  One hash per path; None where the worker could not read the file. A worker that refuses the
+    whole batch, say for hashing with another algorithm, replies REFUSED with the reason and hangs
+    up, which fails the batch.
+    */
+    fn hash_batch(
+        &mut self,
+        relative: &[String],
+        hasher: Hasher,
+    ) -> io::Result<Vec<Option<String>>> {
+        writeln!(self.writer, "BATCH {} {}", relative.len(), hasher.as_str())?;
+        for path in relative {
+            writeln!(self.writer, "{}", path)?;
+        }
+        self.writer.flush()?;
+
+        let mut hashes = Vec::with_capacity(relative.len());
+        for _ in relative {
+            let reply = self.reply()?;
+            if reply.starts_with("REFUSED ") {
+                return Err(refused(&reply));
+            }
+            hashes.push(reply.strip_prefix("OK ").map(|hash| hash.to_string()));
+        }
+        Ok(hashes)
+    }
+}
+
+fn refused(reply: &str) -> io::Error {
+    let reason = reply.strip_prefix("REFUSED ").unwrap_or(reply);
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("worker refused: {}", reason),
+    )
+}
+
+fn worker_failed(address: &str, reason: &str) {
+    diagnostics::report(Diagnostic::WorkerFailed {
+        address: address.to_string(),
+        reason: reason.to_string(),
+    });
+}
+
+fn relative_to(root: &str, file: &str) -> Option<String> {
+    let relative = Path::new(file).strip_prefix(root).ok()?.to_str()?;
+    (!relative.contains('\n')).then(|| relative.to_string())
+}
+
+/*  Hash files found under root on the configured workers, as collect_file_info, for the command
+line: it announces the work and leaves out the files that couldn't be hashed anywhere.
+*/
+pub fn collect_file_info(
+    files: Vec<String>,
+    root: &str,
+) -> Result<(Vec<FileInfo>, RemoteStats), Box<dyn Error>> {
+    let workers = WORKERS.get().cloned().unwrap_or_default();
+    println!(
+        "\nHashing {} files on {} remote worker(s)...",
+        files.len(),
+        workers.addresses.len()
+    );
+    let (hashes, stats) = hash_files(&files, root, &workers, &HashSettings::global());
+    let file_infos = files
+        .iter()
+        .zip(hashes)
+        .filter_map(|(file, hash)| {
+            let hash = diagnostics::skipped(file, hash)?;
+            diagnostics::skipped(file, FileInfo::with_hash(file, hash))
+        })
+        .collect();
+    println!();
+    Ok((file_infos, stats))
+}
+
+/*  Hash files found under root on workers, one connection each, handing out batches to whichever
+worker asks next. Batches a worker fails on, files it can't read and files that can't be
+expressed relative to root are hashed here with local, which must hash as the workers do. Results
+keep the order of files; a worker that fails is reported through diagnostics.
+*/
+pub fn hash_files(
+    files: &[String],
+    root: &str,
+    workers: &Workers,
+    local: &HashSettings,
+) -> (Vec<io::Result<String>>, RemoteStats) {
+    let batches: Vec<&[String]> = files.chunks(BATCH).collect();
+    let hashes: Vec<Mutex<Option<Vec<Option<String>>>>> =
+        batches.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+
+    let pb = progress::stage_bar(Stage::Hash, files.len() as u64);
+    pb.set_message("Waiting for remote workers...");
+
+    std::thread::scope(|scope| {
+        for address in &workers.addresses {
+            let (batches, hashes, next, pb) = (&batches, &hashes, &next, &pb);
+            let (token, hasher) = (&workers.token, local.hasher);
+            scope.spawn(move || {
+                let mut connection = match Connection::open(address, token) {
+                    Ok(connection) => connection,
+                    Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                        worker_failed(address, &e.to_string());
+                        return;
+                    }
+                    Err(e) => {
+                        worker_failed(address, &format!("unreachable: {}", e));
+                        return;
+                    }
+                };
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(batch) = batches.get(index) else {
+                        return;
+                    };
+                    let relative: Option<Vec<String>> =
+                        batch.iter().map(|f| relative_to(root, f)).collect();
+                    // Left for the local pass
+                    let Some(relative) = relative else {
+                        continue;
+                    };
+                    match connection.hash_batch(&relative, hasher) {
+                        Ok(batch_hashes) => {
+                            pb.inc(batch.len() as u64);
+                            *hashes[index].lock().unwrap() = Some(batch_hashes);
+                        }
+                        Err(e) => {
+                            worker_failed(address, &format!("failed: {}", e));
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    let mut stats = RemoteStats {
+        workers: workers.addresses.len(),
+        ..Default::default()
+    };
+    let mut results = Vec::with_capacity(files.len());
+    for (batch, hashes) in batches.iter().zip(hashes) {
+        let replies = hashes.into_inner().unwrap().unwrap_or_else(|| {
+            pb.inc(batch.len() as u64);
+            vec![None; batch.len()]
+        });
+        let remote = replies.iter().filter(|reply| reply.is_some()).count();
+        stats.remote += remote;
+        stats.local += batch.len() - remote;
+        // Files the worker couldn't read may still be readable from here
+        let hashed: Vec<io::Result<String>> = batch
+            .par_iter()
+            .zip(replies)
+            .map(|(file, reply)| match reply {
+                Some(hash) => Ok(hash),
+                None => local.content_hash(file),
+            })
+            .collect();
+        results.extend(hashed);
+    }
+
+    pb.finish_with_message("✓ File analysis complete!");
+    (results, stats)
+}
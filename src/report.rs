@@ -0,0 +1,213 @@
+//crash-safe report files: written under a temporary name and renamed into place once complete
+use polars::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.rdedupe-{}.tmp", name, std::process::id()))
+}
+
+/*  Write path through a temporary file next to it, then rename it over path. The data is synced
+before the rename and the directory after it, so a crash leaves either the previous report or the
+complete new one, never a truncated file. On error the temporary file is removed.
+*/
+pub fn write_atomic<F>(path: &Path, write: F) -> Result<(), Box<dyn Error>>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), Box<dyn Error>>,
+{
+    let temporary = temporary_path(path);
+    let result = (|| -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&temporary, path)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary);
+        return result;
+    }
+    sync_parent(path);
+    Ok(())
+}
+
+// Make the rename itself durable; best effort, some filesystems refuse to sync directories
+fn sync_parent(path: &Path) {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = File::open(parent) {
+        let _ = dir.sync_all();
+    }
+}
+
+pub fn write_csv(df: &mut DataFrame, path: &Path) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |writer| {
+        CsvWriter::new(writer).include_header(true).finish(df)?;
+        Ok(())
+    })
+}
+
+pub fn write_parquet(df: &mut DataFrame, path: &Path) -> Result<(), Box<dyn Error>> {
+    write_atomic(path, |writer| {
+        ParquetWriter::new(writer).finish(df)?;
+        Ok(())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Csv,
+    // One object per duplicate group
+    Json,
+    Parquet,
+    // One group per line; with no --output, the scan's events on stdout, see events
+    Ndjson,
+}
+
+impl Format {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Parquet => "parquet",
+            Format::Ndjson => "ndjson",
+        }
+    }
+
+    // By the output's extension, CSV when it says nothing
+    pub fn of(path: &Path) -> Format {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("json") => Format::Json,
+            Some(e) if e.eq_ignore_ascii_case("parquet") => Format::Parquet,
+            Some(e) if e.eq_ignore_ascii_case("ndjson") || e.eq_ignore_ascii_case("jsonl") => {
+                Format::Ndjson
+            }
+            _ => Format::Csv,
+        }
+    }
+}
+
+pub fn parse_format(value: &str) -> Result<Format, String> {
+    match value {
+        "csv" => Ok(Format::Csv),
+        "json" => Ok(Format::Json),
+        "parquet" => Ok(Format::Parquet),
+        "ndjson" => Ok(Format::Ndjson),
+        _ => Err(format!(
+            "unknown report format '{}' (expected csv, json, parquet or ndjson)",
+            value
+        )),
+    }
+}
+
+// A duplicate group of the JSON report
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupReport {
+    pub group: String,
+    pub size_bytes: u64,
+    pub count: usize,
+    // Bytes freed by keeping a single copy
+    pub wasted_bytes: u64,
+    pub files: Vec<String>,
+}
+
+// The duplicate groups of an analyzed DataFrame, largest waste first
+pub fn duplicate_groups(df: &DataFrame) -> PolarsResult<Vec<GroupReport>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut members: BTreeMap<&str, GroupReport> = BTreeMap::new();
+    for index in 0..df.height() {
+        let (Some(group), Some(path)) = (groups.get(index), paths.get(index)) else {
+            continue;
+        };
+        let entry = members.entry(group).or_insert_with(|| GroupReport {
+            group: group.to_string(),
+            size_bytes: sizes.get(index).unwrap_or(0),
+            count: 0,
+            wasted_bytes: 0,
+            files: Vec::new(),
+        });
+        entry.count += 1;
+        entry.files.push(path.to_string());
+    }
+
+    let mut reports: Vec<GroupReport> = members
+        .into_values()
+        .map(|mut group| {
+            group.files.sort();
+            group.wasted_bytes = group.size_bytes * (group.count as u64 - 1);
+            group
+        })
+        .collect();
+    reports.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then(a.group.cmp(&b.group))
+    });
+    Ok(reports)
+}
+
+/*  Write the duplicates of an analyzed DataFrame to path: their rows as CSV or Parquet, or the
+groups as a JSON array or as one JSON object per line. Returns how many rows or groups were written.
+*/
+pub fn write_report(df: &DataFrame, path: &Path, format: Format) -> Result<usize, Box<dyn Error>> {
+    if format == Format::Json || format == Format::Ndjson {
+        let groups = duplicate_groups(df)?;
+        write_atomic(path, |writer| {
+            match format {
+                Format::Json => {
+                    serde_json::to_writer_pretty(&mut *writer, &groups)?;
+                    writeln!(writer)?;
+                }
+                _ => {
+                    for group in &groups {
+                        writeln!(writer, "{}", serde_json::to_string(group)?)?;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        return Ok(groups.len());
+    }
+
+    let mut duplicates = df
+        .clone()
+        .lazy()
+        .filter(col("is_duplicate").eq(lit(true)))
+        .collect()?;
+    match format {
+        Format::Parquet => write_parquet(&mut duplicates, path)?,
+        _ => write_csv(&mut duplicates, path)?,
+    }
+    Ok(duplicates.height())
+}
+
+// A saved scan: Parquet from --parquet, or a CSV report by its .csv extension
+pub fn read_scan(path: &Path) -> Result<DataFrame, Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut df = if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+    {
+        CsvReader::new(file).has_header(true).finish()?
+    } else {
+        ParquetReader::new(file).finish()?
+    };
+    // Scans saved before content_hash was renamed, when every hash was MD5
+    if df.get_column_names().contains(&"md5_hash") {
+        df.rename("md5_hash", "content_hash")?;
+    }
+    Ok(df)
+}
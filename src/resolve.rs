@@ -0,0 +1,363 @@
+//filesystem actions that resolve duplicates
+use crate::{audit, guard};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct LinkSummary {
+    pub linked: usize,
+    pub deleted: usize,
+    pub symlinked: usize,
+    pub bytes_saved: u64,
+    // Copies left alone: other filesystem, content mismatch, guarded paths or I/O errors
+    pub skipped: usize,
+}
+
+// Both paths live on the same filesystem, so they can be hardlinked
+#[cfg(unix)]
+pub fn same_device(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(a)?.dev() == fs::metadata(b)?.dev())
+}
+
+#[cfg(not(unix))]
+pub fn same_device(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+// Both paths already point at the same inode
+#[cfg(unix)]
+pub fn already_linked(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (fs::metadata(a)?, fs::metadata(b)?);
+    Ok(a.dev() == b.dev() && a.ino() == b.ino())
+}
+
+#[cfg(not(unix))]
+pub fn already_linked(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/*  The victim is the keeper itself under another name, as through a bind mount or a symlinked
+directory, rather than a second hardlink to it. An inode with one link, or two paths that
+canonicalize to one, is a single copy: deleting or symlinking it would lose the only one.
+*/
+#[cfg(unix)]
+pub fn same_file(keep: &Path, victim: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    if fs::metadata(victim)?.nlink() < 2 {
+        return Ok(true);
+    }
+    Ok(keep.canonicalize()? == victim.canonicalize()?)
+}
+
+#[cfg(not(unix))]
+pub fn same_file(keep: &Path, victim: &Path) -> io::Result<bool> {
+    Ok(keep.canonicalize()? == victim.canonicalize()?)
+}
+
+// Byte-for-byte comparison, so a hash collision can never cost data
+pub fn files_identical(a: &Path, b: &Path) -> io::Result<bool> {
+    crate::verify::files_identical(a, b)
+}
+
+fn temporary_sibling(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.rdedupe-{}", name, std::process::id()))
+}
+
+/*  Replace victim with a hardlink to keep. The link is created under a temporary name next to
+the victim and renamed over it, so the victim path never goes missing, even on a crash.
+*/
+pub fn hardlink_replace(keep: &Path, victim: &Path) -> io::Result<()> {
+    let temporary = temporary_sibling(victim);
+    fs::hard_link(keep, &temporary)?;
+
+    if let Err(e) = fs::rename(&temporary, victim) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+// Which copy of a group survives when the others are deleted or linked to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Keep {
+    // First path in sorted order
+    #[default]
+    First,
+    // Earliest modification time, then first path
+    Oldest,
+    // Latest modification time, then first path
+    Newest,
+    // Fewest bytes in the path, then first path
+    ShortestPath,
+}
+
+impl Keep {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Keep::First => "first",
+            Keep::Oldest => "oldest",
+            Keep::Newest => "newest",
+            Keep::ShortestPath => "shortest-path",
+        }
+    }
+
+    /*  The keeper among candidates, sorted by path. Copies whose modification time can't be read
+    lose to every copy whose time is known.
+    */
+    pub fn choose<'a>(&self, candidates: &[&'a str]) -> Option<&'a str> {
+        let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+        match self {
+            Keep::First => candidates.first().copied(),
+            Keep::Oldest => candidates
+                .iter()
+                .map(|path| (modified(path), *path))
+                .min_by_key(|(time, path)| (time.is_none(), *time, *path))
+                .map(|(_, path)| path),
+            Keep::Newest => candidates
+                .iter()
+                .map(|path| (modified(path), *path))
+                .min_by_key(|(time, path)| (time.is_none(), std::cmp::Reverse(*time), *path))
+                .map(|(_, path)| path),
+            Keep::ShortestPath => candidates
+                .iter()
+                .copied()
+                .min_by_key(|path| (path.len(), *path)),
+        }
+    }
+}
+
+pub fn parse_keep(value: &str) -> Result<Keep, String> {
+    match value {
+        "first" => Ok(Keep::First),
+        "oldest" => Ok(Keep::Oldest),
+        "newest" => Ok(Keep::Newest),
+        "shortest-path" => Ok(Keep::ShortestPath),
+        _ => Err(format!(
+            "unknown keep policy '{}' (expected first, oldest, newest or shortest-path)",
+            value
+        )),
+    }
+}
+
+/*  Replace victim with a symlink to keep's absolute path, through the same temporary name and
+rename as hardlink_replace. Unlike a hardlink it can cross filesystems, but the copy now depends
+on keep staying where it is.
+*/
+pub fn symlink_replace(keep: &Path, victim: &Path) -> io::Result<()> {
+    let target = keep.canonicalize()?;
+    let temporary = temporary_sibling(victim);
+    make_symlink(&target, &temporary)?;
+
+    if let Err(e) = fs::rename(&temporary, victim) {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn make_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(not(unix))]
+fn make_symlink(_target: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "symlinks are only supported on unix",
+    ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    // Both paths already share an inode, nothing to do
+    AlreadyLinked,
+    // Other filesystem or the content changed since it was hashed
+    Mismatch,
+    Ready,
+}
+
+// Re-check a planned pair byte-for-byte and for being on the same filesystem
+pub fn verify_pair(keep: &Path, victim: &Path) -> io::Result<Verdict> {
+    if already_linked(keep, victim)? {
+        return Ok(Verdict::AlreadyLinked);
+    }
+    if !same_device(keep, victim)? || !files_identical(keep, victim)? {
+        return Ok(Verdict::Mismatch);
+    }
+    Ok(Verdict::Ready)
+}
+
+/*  Act on a pair verify_pair found Ready; with dry_run the planned link is only printed. Victims
+outside the allowed roots are skipped, and links made are recorded in the audit log, if one is open.
+*/
+pub fn link_verified(
+    keep: &Path,
+    victim: &Path,
+    size_bytes: u64,
+    dry_run: bool,
+    context: &audit::Context,
+    summary: &mut LinkSummary,
+) {
+    if dry_run {
+        println!("would link: {} -> {}", victim.display(), keep.display());
+    } else if let Err(reason) = guard::check(victim) {
+        println!("skip: {} ({})", victim.display(), reason);
+        summary.skipped += 1;
+        return;
+    } else if let Err(e) = hardlink_replace(keep, victim) {
+        println!("skip: {} ({})", victim.display(), e);
+        summary.skipped += 1;
+        return;
+    } else {
+        println!("linked: {} -> {}", victim.display(), keep.display());
+        if let Err(e) = audit::record("hardlink", victim, Some(keep), size_bytes, context) {
+            eprintln!("Warning: could not write the audit log: {}", e);
+        }
+    }
+
+    summary.linked += 1;
+    summary.bytes_saved += size_bytes;
+}
+
+/*  Delete victim, a verified copy of keep; with dry_run the deletion is only printed. The same
+allowed-root and audit rules as link_verified apply. A victim already hardlinked to keep frees
+no space, so only its name goes and no bytes are counted; one that is keep itself under another
+path is skipped.
+*/
+pub fn delete_verified(
+    keep: &Path,
+    victim: &Path,
+    size_bytes: u64,
+    verdict: Verdict,
+    dry_run: bool,
+    context: &audit::Context,
+    summary: &mut LinkSummary,
+) {
+    let freed = if verdict == Verdict::AlreadyLinked {
+        0
+    } else {
+        size_bytes
+    };
+    // Unknown counts as the same file, so a failed check never costs the only copy
+    if verdict == Verdict::AlreadyLinked && !matches!(same_file(keep, victim), Ok(false)) {
+        println!(
+            "skip: {} (the kept copy itself, reached through another path)",
+            victim.display()
+        );
+        summary.skipped += 1;
+        return;
+    }
+
+    if dry_run {
+        println!(
+            "would delete: {} (copy of {})",
+            victim.display(),
+            keep.display()
+        );
+    } else if let Err(reason) = guard::check(victim) {
+        println!("skip: {} ({})", victim.display(), reason);
+        summary.skipped += 1;
+        return;
+    } else if let Err(e) = fs::remove_file(victim) {
+        println!("skip: {} ({})", victim.display(), e);
+        summary.skipped += 1;
+        return;
+    } else {
+        println!("deleted: {} (copy of {})", victim.display(), keep.display());
+        if let Err(e) = audit::record("delete", victim, Some(keep), size_bytes, context) {
+            eprintln!("Warning: could not write the audit log: {}", e);
+        }
+    }
+
+    summary.deleted += 1;
+    summary.bytes_saved += freed;
+}
+
+/*  Replace victim, a verified copy of keep, with a symlink to it; with dry_run the link is only
+printed. Counted like delete_verified: a victim already hardlinked to keep frees nothing, and
+one that is keep itself under another path is skipped.
+*/
+pub fn symlink_verified(
+    keep: &Path,
+    victim: &Path,
+    size_bytes: u64,
+    verdict: Verdict,
+    dry_run: bool,
+    context: &audit::Context,
+    summary: &mut LinkSummary,
+) {
+    let freed = if verdict == Verdict::AlreadyLinked {
+        0
+    } else {
+        size_bytes
+    };
+    // Unknown counts as the same file, so a failed check never costs the only copy
+    if verdict == Verdict::AlreadyLinked && !matches!(same_file(keep, victim), Ok(false)) {
+        println!(
+            "skip: {} (the kept copy itself, reached through another path)",
+            victim.display()
+        );
+        summary.skipped += 1;
+        return;
+    }
+
+    if dry_run {
+        println!("would symlink: {} -> {}", victim.display(), keep.display());
+    } else if let Err(reason) = guard::check(victim) {
+        println!("skip: {} ({})", victim.display(), reason);
+        summary.skipped += 1;
+        return;
+    } else if let Err(e) = symlink_replace(keep, victim) {
+        println!("skip: {} ({})", victim.display(), e);
+        summary.skipped += 1;
+        return;
+    } else {
+        println!("symlinked: {} -> {}", victim.display(), keep.display());
+        if let Err(e) = audit::record("symlink", victim, Some(keep), size_bytes, context) {
+            eprintln!("Warning: could not write the audit log: {}", e);
+        }
+    }
+
+    summary.symlinked += 1;
+    summary.bytes_saved += freed;
+}
+
+// Record a pair verify_pair turned down
+pub fn skip_mismatch(victim: &Path, summary: &mut LinkSummary) {
+    println!(
+        "skip: {} (different filesystem or content changed)",
+        victim.display()
+    );
+    summary.skipped += 1;
+}
+
+/*  Hardlink one duplicate to the kept copy, recording the outcome in summary. The pair is
+re-checked byte-for-byte and for being on the same filesystem right before linking, and copies
+already sharing an inode are left alone. With dry_run the planned link is only printed.
+*/
+pub fn link_copy(
+    keep: &Path,
+    victim: &Path,
+    size_bytes: u64,
+    dry_run: bool,
+    context: &audit::Context,
+    summary: &mut LinkSummary,
+) -> Result<(), Box<dyn Error>> {
+    match verify_pair(keep, victim)? {
+        Verdict::AlreadyLinked => {}
+        Verdict::Mismatch => skip_mismatch(victim, summary),
+        Verdict::Ready => link_verified(keep, victim, size_bytes, dry_run, context, summary),
+    }
+
+    Ok(())
+}
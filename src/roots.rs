@@ -0,0 +1,119 @@
+//several scan roots in one run: overlapping roots walked once, and the root each copy lives under
+use polars::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RootStats {
+    pub root: String,
+    pub files: usize,
+    pub bytes: u64,
+    // Files of the root with at least one copy anywhere in the scan
+    pub duplicated: usize,
+    pub duplicated_bytes: u64,
+    // Of those, the files with a copy under another root
+    pub across: usize,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/*  The roots in the order given, without repeats and without roots inside another one, so no file
+is walked twice and reported as its own duplicate. Roots are compared by their canonical form, so
+`.` and the current directory's absolute path are one root.
+*/
+pub fn distinct<P: AsRef<Path>>(roots: &[P]) -> Vec<String> {
+    let canonical: Vec<PathBuf> = roots.iter().map(|root| canonical(root.as_ref())).collect();
+    roots
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| {
+            !canonical.iter().enumerate().any(|(other, outer)| {
+                other != *index
+                    && canonical[*index].starts_with(outer)
+                    && (canonical[*index] != *outer || other < *index)
+            })
+        })
+        .map(|(_, root)| root.as_ref().to_string_lossy().to_string())
+        .collect()
+}
+
+// The root a walked path lives under; walks return paths starting with the root as given
+pub fn root_of<'a>(path: &str, roots: &'a [String]) -> Option<&'a str> {
+    roots
+        .iter()
+        .find(|root| Path::new(path).starts_with(root))
+        .map(|root| root.as_str())
+}
+
+// Add a "root" column: the root, as given, each file was found under
+pub fn label(df: &DataFrame, roots: &[String]) -> Result<DataFrame, Box<dyn Error>> {
+    let labels: Vec<Option<&str>> = df
+        .column("file_path")?
+        .utf8()?
+        .into_iter()
+        .map(|path| root_of(path?, roots))
+        .collect();
+
+    let mut df = df.clone();
+    df.with_column(Series::new("root", labels))?;
+    Ok(df)
+}
+
+// Needs the "root" column from label
+pub fn breakdown(df: &DataFrame, roots: &[String]) -> Result<Vec<RootStats>, Box<dyn Error>> {
+    let labels = df.column("root")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut group_roots: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (group, root) in groups.into_iter().zip(labels) {
+        if let (Some(group), Some(root)) = (group, root) {
+            group_roots.entry(group).or_default().insert(root);
+        }
+    }
+
+    let mut stats: Vec<RootStats> = roots
+        .iter()
+        .map(|root| RootStats {
+            root: root.clone(),
+            ..Default::default()
+        })
+        .collect();
+    for index in 0..df.height() {
+        let Some(entry) = labels
+            .get(index)
+            .and_then(|root| stats.iter_mut().find(|s| s.root == root))
+        else {
+            continue;
+        };
+        let size = sizes.get(index).unwrap_or(0);
+        entry.files += 1;
+        entry.bytes += size;
+        if let Some(group) = groups.get(index) {
+            entry.duplicated += 1;
+            entry.duplicated_bytes += size;
+            if group_roots[group].len() > 1 {
+                entry.across += 1;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+pub fn print_breakdown(stats: &[RootStats]) {
+    println!("\n=== Duplicates by Root ===");
+    for entry in stats {
+        println!(
+            "  {}: {} file(s), {:.2} MB; {} duplicated ({:.2} MB), {} with a copy under another root",
+            entry.root,
+            entry.files,
+            entry.bytes as f64 / 1_048_576.0,
+            entry.duplicated,
+            entry.duplicated_bytes as f64 / 1_048_576.0,
+            entry.across
+        );
+    }
+}
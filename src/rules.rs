@@ -0,0 +1,330 @@
+//declarative per-group dispositions: a TOML rules file turns duplicate groups into an action plan
+use crate::audit;
+use crate::guard;
+use crate::preflight;
+use crate::resolve::{self, Keep, LinkSummary, Verdict};
+use crate::space;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+// Which copies of a group a rule's path has to hold for the rule to apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Condition {
+    AllUnder,
+    AnyUnder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Disposition {
+    Keep,
+    Hardlink,
+    Delete,
+    Symlink,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Keep => "keep",
+            Disposition::Hardlink => "hardlink",
+            Disposition::Delete => "delete",
+            Disposition::Symlink => "symlink",
+        }
+    }
+}
+
+// The actions --action takes; keeping every copy is no action
+pub fn parse_action(value: &str) -> Result<Disposition, String> {
+    match value {
+        "delete" => Ok(Disposition::Delete),
+        "hardlink" => Ok(Disposition::Hardlink),
+        "symlink" => Ok(Disposition::Symlink),
+        _ => Err(format!(
+            "unknown action '{}' (expected delete, hardlink or symlink)",
+            value
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default)]
+    pub name: String,
+    pub when: Condition,
+    pub path: PathBuf,
+    // What happens to the copies that are not kept
+    pub action: Disposition,
+    // Which of the eligible copies is kept
+    #[serde(default)]
+    pub keep: Keep,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSet {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /*  Rules acting on every group under roots, as `dedupe --action` does. A group with copies
+    under several roots keeps one under the earliest root given.
+    */
+    pub fn everywhere<P: AsRef<Path>>(roots: &[P], action: Disposition, keep: Keep) -> Self {
+        RuleSet {
+            rules: roots
+                .iter()
+                .map(|root| Rule {
+                    name: "--action".to_string(),
+                    when: Condition::AnyUnder,
+                    path: root.as_ref().to_path_buf(),
+                    action,
+                    keep,
+                })
+                .collect(),
+        }
+    }
+
+    // Keep a copy under root whenever a group has one, ahead of the other rules
+    pub fn prefer(mut self, root: &Path) -> Self {
+        if let Some(first) = self.rules.first() {
+            let rule = Rule {
+                name: "--prefer-root".to_string(),
+                when: Condition::AnyUnder,
+                path: root.to_path_buf(),
+                ..first.clone()
+            };
+            self.rules.insert(0, rule);
+        }
+        self
+    }
+}
+
+// One file's fate: the copy it duplicates and what to do with it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    pub group: String,
+    pub keep: String,
+    pub path: String,
+    pub action: Disposition,
+    pub size_bytes: u64,
+    pub rule: String,
+}
+
+/*  Load rules from a TOML file, evaluated in order, first match wins:
+
+    [[rule]]
+    name = "scratch"
+    when = "all-under"       # all-under | any-under
+    path = "/scratch"
+    action = "delete"        # delete | hardlink | symlink | keep
+    keep = "oldest"          # first | oldest | newest | shortest-path
+
+    [[rule]]
+    name = "archive"
+    when = "any-under"
+    path = "/archive"
+    action = "hardlink"
+
+all-under applies when every copy lives under path and chooses the keeper among all of them.
+any-under applies when at least one copy does and chooses among those. keep defaults to the first
+copy by path. Groups no rule matches are left alone.
+*/
+pub fn load_rules(path: &Path) -> Result<RuleSet, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let mut rules: RuleSet =
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    for (index, rule) in rules.rules.iter_mut().enumerate() {
+        if rule.name.is_empty() {
+            rule.name = format!("rule {}", index + 1);
+        }
+    }
+    Ok(rules)
+}
+
+// Compare canonical forms where possible, so relative scan paths match absolute rule paths
+fn is_under(file: &str, root: &Path) -> bool {
+    let file = Path::new(file);
+    if file.starts_with(root) {
+        return true;
+    }
+    match (file.canonicalize(), root.canonicalize()) {
+        (Ok(file), Ok(root)) => file.starts_with(root),
+        _ => false,
+    }
+}
+
+impl Rule {
+    // The copy to keep when the rule applies to members (sorted by path)
+    fn keeper<'a>(&self, members: &[&'a str]) -> Option<&'a str> {
+        match self.when {
+            Condition::AllUnder => {
+                if !members.iter().all(|m| is_under(m, &self.path)) {
+                    return None;
+                }
+                self.keep.choose(members)
+            }
+            Condition::AnyUnder => {
+                let under: Vec<&str> = members
+                    .iter()
+                    .copied()
+                    .filter(|m| is_under(m, &self.path))
+                    .collect();
+                self.keep.choose(&under)
+            }
+        }
+    }
+}
+
+// Evaluate the rules against every duplicate group of an analyzed DataFrame
+pub fn plan(df: &DataFrame, rules: &RuleSet) -> Result<Vec<PlannedAction>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut members: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for index in 0..df.height() {
+        if let (Some(group), Some(path)) = (groups.get(index), paths.get(index)) {
+            members
+                .entry(group)
+                .or_default()
+                .push((path, sizes.get(index).unwrap_or(0)));
+        }
+    }
+
+    let mut planned = Vec::new();
+    for (group, mut copies) in members {
+        copies.sort();
+        let names: Vec<&str> = copies.iter().map(|(path, _)| *path).collect();
+        let Some((rule, keep)) = rules
+            .rules
+            .iter()
+            .find_map(|rule| rule.keeper(&names).map(|keep| (rule, keep)))
+        else {
+            continue;
+        };
+
+        for (path, size_bytes) in copies.iter().filter(|(path, _)| *path != keep) {
+            planned.push(PlannedAction {
+                group: group.to_string(),
+                keep: keep.to_string(),
+                path: path.to_string(),
+                action: rule.action,
+                size_bytes: *size_bytes,
+                rule: rule.name.clone(),
+            });
+        }
+    }
+
+    Ok(planned)
+}
+
+pub fn print_plan(planned: &[PlannedAction]) {
+    println!("\n=== Action Plan ===");
+    for action in planned {
+        println!(
+            "{:<8} {} (keep {}, {})",
+            action.action.as_str(),
+            action.path,
+            action.keep,
+            action.rule
+        );
+    }
+    let count =
+        |disposition: Disposition| planned.iter().filter(|a| a.action == disposition).count();
+    println!(
+        "{} to delete, {} to hardlink, {} to symlink, {} kept by rule",
+        count(Disposition::Delete),
+        count(Disposition::Hardlink),
+        count(Disposition::Symlink),
+        count(Disposition::Keep)
+    );
+}
+
+// Deleting or symlinking a copy only needs the content to match; hardlinking also needs one filesystem
+fn verify(action: Disposition, keep: &Path, victim: &Path) -> std::io::Result<Verdict> {
+    if action == Disposition::Hardlink {
+        return resolve::verify_pair(keep, victim);
+    }
+    if resolve::already_linked(keep, victim)? {
+        return Ok(Verdict::AlreadyLinked);
+    }
+    if !resolve::files_identical(keep, victim)? {
+        return Ok(Verdict::Mismatch);
+    }
+    Ok(Verdict::Ready)
+}
+
+/*  Carry out a plan. The whole plan passes a permission preflight first; every pair is then
+re-verified byte-for-byte right before it is acted on and must sit inside the allowed roots. With
+dry_run nothing is touched.
+*/
+pub fn apply(planned: &[PlannedAction], dry_run: bool) -> Result<LinkSummary, Box<dyn Error>> {
+    if !dry_run {
+        guard::require()?;
+    }
+    space::print_projection(&space::project(planned));
+    preflight::run(planned, dry_run)?;
+
+    let mut summary = LinkSummary::default();
+    for action in planned {
+        if action.action == Disposition::Keep {
+            continue;
+        }
+        let (keep, victim) = (Path::new(&action.keep), Path::new(&action.path));
+        let policy = format!("rules: {}", action.rule);
+        let context = audit::Context {
+            hash: &action.group,
+            policy: &policy,
+        };
+
+        let verdict = match verify(action.action, keep, victim) {
+            Ok(verdict) => verdict,
+            Err(e) => {
+                println!("skip: {} ({})", victim.display(), e);
+                summary.skipped += 1;
+                continue;
+            }
+        };
+
+        match (action.action, verdict) {
+            (_, Verdict::Mismatch) => resolve::skip_mismatch(victim, &mut summary),
+            (Disposition::Hardlink, Verdict::AlreadyLinked) => {}
+            (Disposition::Hardlink, Verdict::Ready) => resolve::link_verified(
+                keep,
+                victim,
+                action.size_bytes,
+                dry_run,
+                &context,
+                &mut summary,
+            ),
+            (Disposition::Delete, verdict) => resolve::delete_verified(
+                keep,
+                victim,
+                action.size_bytes,
+                verdict,
+                dry_run,
+                &context,
+                &mut summary,
+            ),
+            (Disposition::Symlink, verdict) => resolve::symlink_verified(
+                keep,
+                victim,
+                action.size_bytes,
+                verdict,
+                dry_run,
+                &context,
+                &mut summary,
+            ),
+            (Disposition::Keep, _) => {}
+        }
+    }
+
+    Ok(summary)
+}
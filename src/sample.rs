@@ -0,0 +1,170 @@
+//estimate duplicate waste from a random sample of size-matched files, before committing to a full scan
+use crate::diagnostics;
+use crate::generate::Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+// Two-sided 95% normal quantile
+const Z_95: f64 = 1.96;
+
+#[derive(Debug, Clone, Default)]
+pub struct Estimate {
+    pub files: u64,
+    pub total_bytes: u64,
+    // Files sharing their size with at least one other file, the only ones that can be copies
+    pub candidates: u64,
+    pub candidate_bytes: u64,
+    pub sampled: u64,
+    pub hashed: u64,
+    pub wasted_bytes: f64,
+    // 95% confidence bounds on wasted_bytes
+    pub low: f64,
+    pub high: f64,
+    pub seed: u64,
+}
+
+// Pick count distinct items at random (a partial Fisher-Yates shuffle)
+fn pick<T: Clone>(items: &[T], count: usize, rng: &mut Rng) -> Vec<T> {
+    let mut items = items.to_vec();
+    let count = count.min(items.len());
+    for i in 0..count {
+        let j = i + rng.below((items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+    items.truncate(count);
+    items
+}
+
+/*  Each file's share of the waste is size * (1 - 1/k) for the k copies of its content, which sums
+to the exact waste over every file. A simple random sample of the candidates estimates that sum;
+for each sampled file only its size bucket is hashed, and at most bucket_cap of the bucket, its
+copy count being extrapolated from those. The bounds are the normal interval of the sample mean
+with the finite population correction, so sampling every candidate gives the exact waste.
+*/
+pub fn estimate(files: &[String], sample: usize, bucket_cap: usize, seed: u64) -> Estimate {
+    let mut buckets: HashMap<u64, Vec<String>> = HashMap::new();
+    let mut estimate = Estimate {
+        seed,
+        ..Default::default()
+    };
+    for file in files {
+        let Some(metadata) = diagnostics::skipped(file, fs::symlink_metadata(file)) else {
+            continue;
+        };
+        estimate.files += 1;
+        estimate.total_bytes += metadata.len();
+        buckets
+            .entry(metadata.len())
+            .or_default()
+            .push(file.clone());
+    }
+    buckets.retain(|_, bucket| bucket.len() > 1);
+
+    let mut candidates: Vec<(String, u64)> = buckets
+        .iter()
+        .flat_map(|(size, bucket)| bucket.iter().map(|file| (file.clone(), *size)))
+        .collect();
+    candidates.sort();
+    estimate.candidates = candidates.len() as u64;
+    estimate.candidate_bytes = candidates.iter().map(|(_, size)| size).sum();
+
+    let mut rng = Rng(seed);
+    let sampled = pick(&candidates, sample.max(1), &mut rng);
+    // For each sampled file, the others of its size it is compared with
+    let compared: Vec<(String, u64, Vec<String>)> = sampled
+        .into_iter()
+        .map(|(file, size)| {
+            let others: Vec<String> = buckets[&size]
+                .iter()
+                .filter(|other| **other != file)
+                .cloned()
+                .collect();
+            let others = pick(&others, bucket_cap.max(1), &mut rng);
+            (file, size, others)
+        })
+        .collect();
+
+    let to_hash: HashSet<&String> = compared
+        .iter()
+        .flat_map(|(file, _, others)| std::iter::once(file).chain(others))
+        .collect();
+    estimate.hashed = to_hash.len() as u64;
+    let hashes: HashMap<&String, String> = to_hash
+        .into_par_iter()
+        .filter_map(|file| diagnostics::skipped(file, crate::content_hash(file)).map(|h| (file, h)))
+        .collect();
+
+    let mut shares = Vec::with_capacity(compared.len());
+    for (file, size, others) in &compared {
+        let Some(hash) = hashes.get(file) else {
+            continue;
+        };
+        let hashed: Vec<&String> = others.iter().filter_map(|o| hashes.get(o)).collect();
+        let bucket_others = (buckets[size].len() - 1) as f64;
+        let copies = match hashed.len() {
+            0 => 1.0,
+            n => {
+                let matches = hashed.iter().filter(|h| **h == hash).count() as f64;
+                1.0 + matches / n as f64 * bucket_others
+            }
+        };
+        shares.push(*size as f64 * (1.0 - 1.0 / copies));
+    }
+    estimate.sampled = shares.len() as u64;
+    if shares.is_empty() {
+        return estimate;
+    }
+
+    let n = shares.len() as f64;
+    let population = estimate.candidates as f64;
+    let mean = shares.iter().sum::<f64>() / n;
+    let variance = match shares.len() {
+        1 => 0.0,
+        _ => shares.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1.0),
+    };
+    let correction = (1.0 - n / population).max(0.0);
+    let error = population * (variance / n * correction).sqrt();
+
+    estimate.wasted_bytes = population * mean;
+    estimate.low = (estimate.wasted_bytes - Z_95 * error).max(0.0);
+    estimate.high = (estimate.wasted_bytes + Z_95 * error).min(estimate.candidate_bytes as f64);
+    estimate
+}
+
+pub fn print_estimate(estimate: &Estimate) {
+    let mb = |bytes: f64| bytes / 1_048_576.0;
+    println!("\n=== Duplication Estimate ===");
+    println!(
+        "Files walked: {} ({:.2} MB)",
+        estimate.files,
+        mb(estimate.total_bytes as f64)
+    );
+    println!(
+        "Size-matched candidates: {} ({:.2} MB)",
+        estimate.candidates,
+        mb(estimate.candidate_bytes as f64)
+    );
+    println!(
+        "Sampled {} candidate(s), hashing {} file(s)",
+        estimate.sampled, estimate.hashed
+    );
+
+    let share = match estimate.total_bytes {
+        0 => 0.0,
+        total => estimate.wasted_bytes / total as f64 * 100.0,
+    };
+    println!(
+        "Estimated waste: {:.2} MB (95% confidence {:.2}-{:.2} MB), {:.1}% of the total",
+        mb(estimate.wasted_bytes),
+        mb(estimate.low),
+        mb(estimate.high),
+        share
+    );
+    if estimate.sampled < estimate.candidates {
+        println!(
+            "Seed {}: pass --seed {} to draw the same sample again",
+            estimate.seed, estimate.seed
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//dedup already achieved by hardlinks and reflinked extents, next to what is left to reclaim
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, Metadata};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Savings {
+    pub files: usize,
+    pub inodes: usize,
+    // Names beyond the first of inodes seen more than once in the scan
+    pub extra_names: usize,
+    pub hardlink_bytes: u64,
+    // Inodes with links the scan didn't reach
+    pub linked_outside: usize,
+    // Files sharing at least one extent with another file (reflinks, clones, block dedup)
+    pub reflinked_files: usize,
+    pub reflink_bytes: u64,
+    pub remaining_bytes: u64,
+}
+
+// An inode of the scan, by the first of its names seen
+struct Inode<'a> {
+    path: &'a str,
+    size: u64,
+    links: u64,
+    names: u64,
+    group: Option<&'a str>,
+}
+
+// (device, inode, link count)
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> (u64, u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.dev(), metadata.ino(), metadata.nlink())
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> (u64, u64, u64) {
+    (0, 0, 1)
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    // _IOWR('f', 11, struct fiemap)
+    const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+    // Extents fetched per ioctl
+    const BATCH: usize = 256;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; BATCH],
+    }
+
+    /*  The (physical offset, length) of every extent the filesystem reports as shared with another
+    file. Filesystems without FIEMAP (tmpfs, NFS) report none.
+    */
+    pub fn shared_extents(path: &str) -> Vec<(u64, u64)> {
+        let Ok(file) = File::open(path) else {
+            return Vec::new();
+        };
+        let mut shared = Vec::new();
+        let mut start = 0;
+        loop {
+            let mut map = Box::new(Fiemap {
+                start,
+                length: u64::MAX - start,
+                flags: FIEMAP_FLAG_SYNC,
+                mapped_extents: 0,
+                extent_count: BATCH as u32,
+                reserved: 0,
+                extents: [Extent::default(); BATCH],
+            });
+            if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut *map) } != 0 {
+                return shared;
+            }
+            let extents = &map.extents[..map.mapped_extents as usize];
+            for extent in extents {
+                if extent.flags & FIEMAP_EXTENT_SHARED != 0 {
+                    shared.push((extent.physical, extent.length));
+                }
+            }
+            match extents.last() {
+                Some(last) if last.flags & FIEMAP_EXTENT_LAST == 0 => {
+                    start = last.logical + last.length
+                }
+                _ => return shared,
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod os {
+    pub fn shared_extents(_path: &str) -> Vec<(u64, u64)> {
+        Vec::new()
+    }
+}
+
+/*  Hardlink savings are the extra names of inodes seen several times; reflink savings are the extra
+references to a shared physical extent among distinct inodes, so a hardlinked reflink is not
+counted twice. What remains is every distinct inode beyond the first of a duplicate group, minus
+what those copies already share through extents.
+*/
+pub fn measure(df: &DataFrame) -> Result<Savings, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut savings = Savings::default();
+    let mut inodes: HashMap<(u64, u64), Inode> = HashMap::new();
+    for index in 0..df.height() {
+        let Some(path) = paths.get(index) else {
+            continue;
+        };
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            continue;
+        };
+        savings.files += 1;
+        let (device, ino, links) = inode(&metadata);
+        inodes
+            .entry((device, ino))
+            .or_insert(Inode {
+                path,
+                size: sizes.get(index).unwrap_or(0),
+                links,
+                names: 0,
+                group: groups.get(index),
+            })
+            .names += 1;
+    }
+
+    savings.inodes = inodes.len();
+    // (device, physical offset, length) -> the distinct inodes referencing it
+    let mut extents: HashMap<(u64, u64, u64), Vec<(u64, u64)>> = HashMap::new();
+    let mut group_inodes: BTreeMap<&str, Vec<(u64, u64)>> = BTreeMap::new();
+    for (&key, seen) in &inodes {
+        if seen.names > 1 {
+            savings.extra_names += seen.names as usize - 1;
+            savings.hardlink_bytes += (seen.names - 1) * seen.size;
+        }
+        if seen.links > seen.names {
+            savings.linked_outside += 1;
+        }
+        for (physical, length) in os::shared_extents(seen.path) {
+            extents
+                .entry((key.0, physical, length))
+                .or_default()
+                .push(key);
+        }
+        if let Some(group) = seen.group {
+            group_inodes.entry(group).or_default().push(key);
+        }
+    }
+
+    // Bytes each inode shares with an earlier inode, whoever that is
+    let mut shared_by: HashMap<(u64, u64), u64> = HashMap::new();
+    for ((_, _, length), mut owners) in extents {
+        owners.sort();
+        for owner in owners.into_iter().skip(1) {
+            *shared_by.entry(owner).or_default() += length;
+            savings.reflink_bytes += length;
+        }
+    }
+    savings.reflinked_files = shared_by.len();
+
+    for mut members in group_inodes.into_values() {
+        members.sort();
+        for key in members.into_iter().skip(1) {
+            let size = inodes[&key].size;
+            savings.remaining_bytes +=
+                size.saturating_sub(shared_by.get(&key).copied().unwrap_or(0));
+        }
+    }
+    Ok(savings)
+}
+
+pub fn print_savings(savings: &Savings) {
+    let mb = |bytes: u64| bytes as f64 / 1_048_576.0;
+    println!("\n=== Dedup State ===");
+    println!("{} file(s) on {} inode(s)", savings.files, savings.inodes);
+    println!(
+        "Already saved by hardlinks: {:.2} MB ({} extra name(s))",
+        mb(savings.hardlink_bytes),
+        savings.extra_names
+    );
+    println!(
+        "Already saved by shared extents (reflinks, clones): {:.2} MB ({} file(s))",
+        mb(savings.reflink_bytes),
+        savings.reflinked_files
+    );
+    println!("Remaining potential: {:.2} MB", mb(savings.remaining_bytes));
+    if savings.linked_outside > 0 {
+        println!(
+            "{} inode(s) have links outside the scan, their space is only freed once those go too",
+            savings.linked_outside
+        );
+    }
+}
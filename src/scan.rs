@@ -0,0 +1,339 @@
+//typed scan results for library callers: the scan prints nothing and returns everything
+use crate::cache::{self, HashCache};
+use crate::diagnostics::{self, Diagnostic, PathError};
+use crate::hasher::{self, Hasher};
+use crate::normalize::{self, Normalizer};
+use crate::paranoia::{self, Paranoia};
+use crate::remote::{self, Workers};
+use crate::{compressed, filter, pipeline, progress, roots, verify};
+use crate::{FileInfo, HashSettings, WalkOptions};
+use polars::prelude::DataFrame;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // Duplicates are found across all roots; a root inside another is walked once
+    pub roots: Vec<String>,
+    pub walk: WalkOptions,
+    // Inclusive size bounds, applied before anything is hashed
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    // Stop after this many files across all roots
+    pub max_files: Option<usize>,
+    // Compare this many bytes at each end of large same-size files before hashing them whole
+    pub edge_bytes: Option<u64>,
+    // Confirm every group byte for byte, splitting off files that only share a hash
+    pub verify: bool,
+    // The content hash to group by, also used for the edges
+    pub hasher: Hasher,
+    // Quick groups by size without reading anything; Paranoid confirms groups like verify
+    pub paranoia: Paranoia,
+    // Hash compressed files by their decompressed content, see compressed
+    pub decompress: bool,
+    // Applied before hashing, in order, see normalize
+    pub normalizers: Vec<Arc<dyn Normalizer>>,
+    // Where hashes are remembered between scans
+    pub cache: Option<Arc<dyn HashCache>>,
+    // Hash on these remote::serve workers instead of locally; they serve a single root
+    pub workers: Option<Workers>,
+}
+
+impl ScanOptions {
+    // MD5 at the standard level, with no cache, decompression, normalizers or workers
+    pub fn new(root: &str) -> Self {
+        ScanOptions {
+            roots: vec![root.to_string()],
+            walk: WalkOptions::default(),
+            min_size: None,
+            max_size: None,
+            max_files: None,
+            edge_bytes: Some(pipeline::EDGE_BYTES),
+            verify: false,
+            hasher: Hasher::default(),
+            paranoia: Paranoia::default(),
+            decompress: false,
+            normalizers: Vec::new(),
+            cache: None,
+            workers: None,
+        }
+    }
+
+    // Like new, with the hashing settings the command line set for the whole run
+    pub fn for_run(root: &str) -> Self {
+        ScanOptions {
+            hasher: hasher::selected(),
+            paranoia: paranoia::level(),
+            decompress: compressed::enabled(),
+            normalizers: normalize::installed().to_vec(),
+            cache: cache::backend(),
+            workers: remote::workers(),
+            ..ScanOptions::new(root)
+        }
+    }
+
+    fn hash_settings(&self) -> HashSettings<'_> {
+        HashSettings {
+            hasher: self.hasher,
+            decompress: self.decompress,
+            normalizers: &self.normalizers,
+            cache: self.cache.as_deref(),
+        }
+    }
+
+    // The analyzed DataFrame of file_infos, labelled with these options' hash and paranoia level
+    pub fn dataframe(&self, file_infos: Vec<FileInfo>) -> Result<DataFrame, Box<dyn Error>> {
+        crate::create_dataframe_with(file_infos, self.hasher, self.paranoia)
+    }
+
+    // Decompressed or normalized content can match across files of different sizes on disk
+    fn transforms_content(&self) -> bool {
+        self.decompress || !self.normalizers.is_empty()
+    }
+
+    // The walk options with the size bounds as one more filter
+    fn walk_options(&self) -> WalkOptions {
+        let mut walk = self.walk.clone();
+        if let Some(size) = filter::Size::within(self.min_size, self.max_size) {
+            walk = walk.filter(size);
+        }
+        walk
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub path: String,
+    pub size_bytes: u64,
+    pub modified: Option<SystemTime>,
+    // The root, as given in ScanOptions, the file was found under
+    pub root: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    pub size_bytes: u64,
+    // Sorted by path
+    pub files: Vec<FileEntry>,
+}
+
+impl DuplicateGroup {
+    // Bytes freed by keeping a single copy
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * (self.files.len() as u64).saturating_sub(1)
+    }
+
+    // Copies live under more than one root
+    pub fn spans_roots(&self) -> bool {
+        self.files
+            .iter()
+            .any(|file| file.root != self.files[0].root)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    pub files: usize,
+    pub bytes: u64,
+    // Files sharing their size with another one, the only ones read; all of them with decompress or normalizers
+    pub same_size: usize,
+    // Of those, the large ones compared by their edges first, see pipeline::same_edges
+    pub edge_hashed: usize,
+    // Files hashed in full: the same-size files whose edges, if compared, matched another's
+    pub hashed: usize,
+    // Of those, the ones hashed on ScanOptions::workers
+    pub remote_hashed: usize,
+    // Files that vanished or couldn't be read, see diagnostics
+    pub skipped: usize,
+    // The walk stopped at max_files
+    pub capped: bool,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupeReport {
+    // Largest waste first
+    pub groups: Vec<DuplicateGroup>,
+    pub wasted_bytes: u64,
+    pub stats: ScanStats,
+    // Directories the walk couldn't enter and files that couldn't be hashed, in the order met
+    pub errors: Vec<PathError>,
+}
+
+/*  Walk every root, hash only the files whose size and, for large files, edges another file shares
+and group them by content under the options' hash and paranoia level. With decompress or
+normalizers every file is hashed, since their content can match across sizes. Unreadable files
+are counted as skipped, reported through diagnostics and listed in the report's errors, never
+fatal; only an unreadable root, or workers given for more than one root or together with
+decompress or normalizers, is an error.
+*/
+pub fn scan(options: &ScanOptions) -> Result<DedupeReport, Box<dyn Error>> {
+    let started = Instant::now();
+    let workers = options
+        .workers
+        .as_ref()
+        .filter(|w| !w.addresses.is_empty() && options.paranoia != Paranoia::Quick);
+    // Workers hash the raw bytes, so their hashes wouldn't match the ones made locally
+    if workers.is_some() && options.transforms_content() {
+        return Err(
+            "remote workers hash raw bytes, they can't be combined with decompress or normalizers"
+                .into(),
+        );
+    }
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut walk = options.walk_options();
+    let roots = roots::distinct(&options.roots);
+    // A cap already on the walk options, such as the command line's, holds unless max_files is set
+    let max_files = options.max_files.or(options.walk.max_files);
+    for root in &roots {
+        walk.max_files = max_files.map(|max| max.saturating_sub(files.len()));
+        if walk.capped(0) {
+            break;
+        }
+        files.extend(crate::walk_collecting(root, &walk, &mut errors)?);
+    }
+
+    let mut stats = ScanStats {
+        files: files.len(),
+        capped: max_files.is_some_and(|max| files.len() >= max),
+        ..Default::default()
+    };
+    let by_size = pipeline::group_by_size(files);
+    let sized: usize = by_size.values().map(Vec::len).sum();
+    stats.skipped = stats.files - sized;
+    stats.bytes = by_size
+        .iter()
+        .map(|(size, bucket)| size * bucket.len() as u64)
+        .sum();
+
+    let candidates = match options.edge_bytes {
+        _ if options.transforms_content() => {
+            let candidates: Vec<String> = by_size.into_values().flatten().collect();
+            stats.same_size = candidates.len();
+            candidates
+        }
+        Some(edge) => {
+            stats.same_size = by_size.values().filter(|b| b.len() > 1).map(Vec::len).sum();
+            let staged = pipeline::same_edges(by_size, edge, options.hasher);
+            stats.edge_hashed = staged.edge_hashed;
+            stats.skipped += staged.unreadable.len();
+            errors.extend(staged.unreadable);
+            staged.files
+        }
+        None => {
+            let candidates = pipeline::same_size(by_size);
+            stats.same_size = candidates.len();
+            candidates
+        }
+    };
+    let read = candidates.len();
+    progress::phase(progress::Stage::Hash, Some(candidates.len() as u64));
+    let settings = options.hash_settings();
+    let digests: Vec<std::io::Result<String>> = match workers {
+        Some(workers) => {
+            // Workers resolve paths relative to the one root they serve
+            let [root] = roots.as_slice() else {
+                return Err("remote hashing scans a single root".into());
+            };
+            let (digests, remote) = remote::hash_files(&candidates, root, workers, &settings);
+            stats.remote_hashed = remote.remote;
+            digests
+        }
+        None => candidates
+            .par_iter()
+            .map(|path| match options.paranoia {
+                Paranoia::Quick => paranoia::size_key(path),
+                _ => settings.content_hash(path),
+            })
+            .collect(),
+    };
+    let results: Vec<Result<(FileInfo, Option<SystemTime>), PathError>> = candidates
+        .into_par_iter()
+        .zip(digests)
+        .map(|(path, digest)| {
+            let fail = |e: std::io::Error| {
+                diagnostics::report(Diagnostic::SkippedFile {
+                    path: path.clone(),
+                    reason: e.to_string(),
+                });
+                PathError {
+                    path: path.clone(),
+                    reason: e.to_string(),
+                }
+            };
+            let digest = digest.map_err(fail)?;
+            let metadata = fs::metadata(&path).map_err(fail)?;
+            let info = FileInfo::with_metadata(
+                &path,
+                digest,
+                metadata.len(),
+                metadata.created().ok(),
+                metadata.modified().ok(),
+            );
+            Ok((info, metadata.modified().ok()))
+        })
+        .collect();
+    let mut hashed = Vec::new();
+    let mut modified = Vec::new();
+    for result in results {
+        match result {
+            Ok((info, time)) => {
+                hashed.push(info);
+                modified.push(time);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    stats.hashed = hashed.len();
+    stats.skipped += read - hashed.len();
+    // Only renames the hashes of files it splits off, so modified stays aligned
+    if options.verify || options.paranoia == Paranoia::Paranoid {
+        verify::split_mismatches(&mut hashed);
+    }
+
+    let mut members: HashMap<String, Vec<FileEntry>> = HashMap::new();
+    for (info, modified) in hashed.into_iter().zip(modified) {
+        members.entry(info.hash).or_default().push(FileEntry {
+            size_bytes: info.size_bytes,
+            modified,
+            root: roots::root_of(&info.path, &roots)
+                .unwrap_or_default()
+                .to_string(),
+            path: info.path,
+        });
+    }
+
+    let mut groups: Vec<DuplicateGroup> = members
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(hash, mut files)| {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            DuplicateGroup {
+                hash,
+                size_bytes: files[0].size_bytes,
+                files,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.files[0].path.cmp(&b.files[0].path))
+    });
+
+    stats.elapsed = started.elapsed();
+    Ok(DedupeReport {
+        wasted_bytes: groups.iter().map(DuplicateGroup::wasted_bytes).sum(),
+        groups,
+        stats,
+        errors,
+    })
+}
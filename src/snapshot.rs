@@ -0,0 +1,238 @@
+//scan snapshots: every file's path, size, hash and mtime in a stable file, and what changed between two
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*  The format: JSON lines, a header first and then one line per file, sorted by path.
+
+    {"format":"rdedupe-snapshot","version":1,"created":1760400000,"hash":"md5","roots":["/mnt/archive"]}
+    {"path":"/mnt/archive/a.tar","size_bytes":10240,"hash":"9e10...","modified":1760300000}
+
+Times are seconds since the epoch, modified is null when it couldn't be read. Readers reject a
+newer version and ignore fields they don't know, so fields can be added without a version bump.
+*/
+pub const FORMAT: &str = "rdedupe-snapshot";
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    created: u64,
+    hash: String,
+    roots: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub size_bytes: u64,
+    pub hash: String,
+    pub modified: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub created: u64,
+    // The content hash the scan used, see hasher::Hasher::as_str
+    pub hash: String,
+    pub roots: Vec<String>,
+    // Sorted by path
+    pub files: Vec<Entry>,
+}
+
+fn seconds(time: SystemTime) -> Option<u64> {
+    time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+}
+
+// Every file of an analyzed scan, with the mtime it has now
+pub fn from_scan(df: &DataFrame, roots: &[String]) -> Result<Snapshot, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let hashes = df.column("content_hash")?.utf8()?;
+
+    let mut files = Vec::with_capacity(df.height());
+    for index in 0..df.height() {
+        let (Some(path), Some(hash)) = (paths.get(index), hashes.get(index)) else {
+            continue;
+        };
+        files.push(Entry {
+            path: path.to_string(),
+            size_bytes: sizes.get(index).unwrap_or(0),
+            hash: hash.to_string(),
+            modified: fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(seconds),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Snapshot {
+        created: seconds(SystemTime::now()).unwrap_or(0),
+        hash: crate::hasher::selected().as_str().to_string(),
+        roots: roots.to_vec(),
+        files,
+    })
+}
+
+pub fn write_snapshot(snapshot: &Snapshot, path: &Path) -> Result<(), Box<dyn Error>> {
+    let header = Header {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        created: snapshot.created,
+        hash: snapshot.hash.clone(),
+        roots: snapshot.roots.clone(),
+    };
+    crate::report::write_atomic(path, |writer| {
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+        for entry in &snapshot.files {
+            writeln!(writer, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    })
+}
+
+fn header(line: &str) -> Option<Header> {
+    serde_json::from_str::<Header>(line)
+        .ok()
+        .filter(|header| header.format == FORMAT)
+}
+
+// Whether path starts with a snapshot header, of any version
+pub fn is_snapshot(path: &Path) -> bool {
+    let Ok(file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).is_ok() && header(&line).is_some()
+}
+
+pub fn read_snapshot(path: &Path) -> Result<Snapshot, Box<dyn Error>> {
+    let file = fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut lines = BufReader::new(file).lines();
+    let first = lines.next().transpose()?.unwrap_or_default();
+    let Some(header) = header(&first) else {
+        return Err(format!("{}: not an rdedupe snapshot", path.display()).into());
+    };
+    if header.version > VERSION {
+        return Err(format!(
+            "{}: snapshot version {} is newer than this rdedupe reads ({})",
+            path.display(),
+            header.version,
+            VERSION
+        )
+        .into());
+    }
+
+    let mut files = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(&line)
+            .map_err(|e| format!("{}: line {}: {}", path.display(), index + 2, e))?;
+        files.push(entry);
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Snapshot {
+        created: header.created,
+        hash: header.hash,
+        roots: header.roots,
+        files,
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub added: Vec<Entry>,
+    pub removed: Vec<Entry>,
+    // (before, after) of paths whose size or content changed; a new mtime alone is no change
+    pub changed: Vec<(Entry, Entry)>,
+    // Files of the later scan with a copy there that had none, or didn't exist, in the earlier one
+    pub newly_duplicated: Vec<Entry>,
+}
+
+fn copies(snapshot: &Snapshot) -> HashMap<&str, usize> {
+    let mut copies = HashMap::new();
+    for entry in &snapshot.files {
+        *copies.entry(entry.hash.as_str()).or_default() += 1;
+    }
+    copies
+}
+
+// Every list sorted by path
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Result<SnapshotDiff, Box<dyn Error>> {
+    if before.hash != after.hash {
+        return Err(format!(
+            "the snapshots were hashed with {} and {}, their contents can't be compared",
+            before.hash, after.hash
+        )
+        .into());
+    }
+    let old: BTreeMap<&str, &Entry> = before.files.iter().map(|e| (e.path.as_str(), e)).collect();
+    let new: BTreeMap<&str, &Entry> = after.files.iter().map(|e| (e.path.as_str(), e)).collect();
+    let (old_copies, new_copies) = (copies(before), copies(after));
+
+    let mut diff = SnapshotDiff::default();
+    for (path, entry) in &old {
+        match new.get(path) {
+            None => diff.removed.push((*entry).clone()),
+            Some(now) if now.hash != entry.hash || now.size_bytes != entry.size_bytes => {
+                diff.changed.push(((*entry).clone(), (*now).clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, entry) in &new {
+        let was = old.get(path);
+        if was.is_none() {
+            diff.added.push((*entry).clone());
+        }
+        let duplicated = |copies: &HashMap<&str, usize>, hash: &str| copies[hash] > 1;
+        if duplicated(&new_copies, &entry.hash)
+            && !was.is_some_and(|was| duplicated(&old_copies, &was.hash))
+        {
+            diff.newly_duplicated.push((*entry).clone());
+        }
+    }
+    Ok(diff)
+}
+
+pub fn print_diff(diff: &SnapshotDiff, before: &Snapshot, after: &Snapshot) {
+    let time = |snapshot: &Snapshot| crate::audit::format_time(snapshot.created);
+    println!(
+        "=== Snapshot Diff ({} -> {}) ===",
+        time(before),
+        time(after)
+    );
+    for entry in &diff.added {
+        println!("added       {} ({} bytes)", entry.path, entry.size_bytes);
+    }
+    for entry in &diff.removed {
+        println!("removed     {} ({} bytes)", entry.path, entry.size_bytes);
+    }
+    for (old, new) in &diff.changed {
+        println!(
+            "changed     {} ({} -> {} bytes, {} -> {})",
+            new.path, old.size_bytes, new.size_bytes, old.hash, new.hash
+        );
+    }
+    for entry in &diff.newly_duplicated {
+        println!("duplicated  {}", entry.path);
+    }
+    println!(
+        "{} added, {} removed, {} changed, {} newly duplicated ({} -> {} file(s))",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.newly_duplicated.len(),
+        before.files.len(),
+        after.files.len()
+    );
+}
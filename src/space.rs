@@ -0,0 +1,154 @@
+//projected free space per filesystem before a plan is carried out
+use crate::rules::{Disposition, PlannedAction};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, Metadata};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct Projection {
+    pub mount: PathBuf,
+    // None where the platform can't tell
+    pub free_bytes: Option<u64>,
+    pub total_bytes: Option<u64>,
+    pub reclaim_bytes: u64,
+    pub actions: usize,
+    // Actions that free nothing: cross-device links, copies already linked, inodes with other names
+    pub no_gain: usize,
+}
+
+// (device, inode, link count, bytes allocated on disk)
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> (u64, u64, u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (
+        metadata.dev(),
+        metadata.ino(),
+        metadata.nlink(),
+        metadata.blocks() * 512,
+    )
+}
+
+#[cfg(not(unix))]
+fn inode(metadata: &Metadata) -> (u64, u64, u64, u64) {
+    (0, 0, 1, metadata.len())
+}
+
+// Free and total bytes of the filesystem holding path, as an unprivileged user sees them
+#[cfg(target_os = "linux")]
+fn free_space(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let mut stat: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stat) != 0 {
+            return None;
+        }
+        let block = stat.f_frsize as u64;
+        Some((stat.f_bavail as u64 * block, stat.f_blocks as u64 * block))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn free_space(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+// The topmost directory above path still on the same device
+fn mount_point(path: &Path, device: u64) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let mut mount = path.clone();
+    for ancestor in path.ancestors().skip(1) {
+        match fs::metadata(ancestor) {
+            Ok(metadata) if inode(&metadata).0 == device => mount = ancestor.to_path_buf(),
+            _ => break,
+        }
+    }
+    mount
+}
+
+/*  What the plan would free on each filesystem. A hardlink or delete only frees blocks when the
+victim's inode loses its last name, so copies with links outside the plan, copies already linked
+to their keeper and links across devices (which are refused) free nothing. Sizes are the blocks
+actually allocated, so sparse files count for what they occupy.
+*/
+pub fn project(planned: &[PlannedAction]) -> Vec<Projection> {
+    let mut devices: BTreeMap<u64, Projection> = BTreeMap::new();
+    // Names of an inode the plan removes, with its link count and allocation
+    let mut removed: HashMap<(u64, u64), (usize, u64, u64)> = HashMap::new();
+
+    for action in planned {
+        if action.action == Disposition::Keep {
+            continue;
+        }
+        let (Ok(victim), Ok(keep)) = (fs::metadata(&action.path), fs::metadata(&action.keep))
+        else {
+            continue;
+        };
+        let (device, ino, links, allocated) = inode(&victim);
+        let (keep_device, keep_ino, _, _) = inode(&keep);
+
+        let projection = devices.entry(device).or_insert_with(|| Projection {
+            mount: mount_point(Path::new(&action.path), device),
+            ..Default::default()
+        });
+        projection.actions += 1;
+
+        let cross_device = action.action == Disposition::Hardlink && keep_device != device;
+        let already_linked = keep_device == device && keep_ino == ino;
+        if cross_device || already_linked {
+            projection.no_gain += 1;
+            continue;
+        }
+        removed
+            .entry((device, ino))
+            .or_insert((0, links, allocated))
+            .0 += 1;
+    }
+
+    for ((device, _), (names, links, allocated)) in removed {
+        let projection = devices.get_mut(&device).expect("device seen above");
+        if names as u64 >= links {
+            projection.reclaim_bytes += allocated;
+        } else {
+            projection.no_gain += names;
+        }
+    }
+
+    devices
+        .into_values()
+        .map(|mut projection| {
+            if let Some((free, total)) = free_space(&projection.mount) {
+                projection.free_bytes = Some(free);
+                projection.total_bytes = Some(total);
+            }
+            projection
+        })
+        .collect()
+}
+
+pub fn print_projection(projections: &[Projection]) {
+    if projections.is_empty() {
+        return;
+    }
+    println!("\n=== Projected Free Space ===");
+    for projection in projections {
+        let reclaim = projection.reclaim_bytes as f64 / 1_048_576.0;
+        let space = match (projection.free_bytes, projection.total_bytes) {
+            (Some(free), Some(total)) => format!(
+                "{:.2} MB free of {:.2} MB -> {:.2} MB free",
+                free as f64 / 1_048_576.0,
+                total as f64 / 1_048_576.0,
+                free as f64 / 1_048_576.0 + reclaim
+            ),
+            _ => "free space unknown".to_string(),
+        };
+        println!(
+            "{}: {} (+{:.2} MB from {} action(s), {} freeing nothing)",
+            projection.mount.display(),
+            space,
+            reclaim,
+            projection.actions,
+            projection.no_gain
+        );
+    }
+}
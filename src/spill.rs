@@ -0,0 +1,215 @@
+//checksum aggregation under a memory budget: overflow is spilled to hash-prefix partition runs on disk
+use crate::index::{self, ChecksumIndex, Digest, PathId, PathTable};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// 256 partitions keyed by the first byte of the digest, so merging needs ~1/256 of the data in memory
+const PARTITIONS: usize = 256;
+// Files hashed in parallel before their digests are handed to the index
+const BATCH: usize = 64 * 1024;
+
+// Paths sharing one digest, one Vec per duplicate group
+pub type DuplicateGroups = Vec<Vec<String>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct SpillStats {
+    pub entries: u64,
+    pub spilled_entries: u64,
+    pub spills: usize,
+}
+
+/*  Groups paths by digest like checksum(), but once the digest groups grow past budget_bytes they
+are spilled into partition files. The walk's path table stays in memory and only ids are spilled.
+Every partition holds all ids for its hash prefix, so each one can be merged on its own.
+*/
+pub struct SpillingIndex {
+    budget_bytes: usize,
+    index: ChecksumIndex,
+    dir: PathBuf,
+    runs: Vec<Option<BufWriter<File>>>,
+    stats: SpillStats,
+}
+
+fn run_path(dir: &Path, partition: usize) -> PathBuf {
+    dir.join(format!("run-{:02x}", partition))
+}
+
+// Fixed 37-byte records: the digest length, the digest zero-padded to 32 bytes and the path id
+const RECORD: usize = 37;
+
+fn write_record(out: &mut impl Write, digest: &Digest, id: PathId) -> io::Result<()> {
+    let bytes = digest.as_bytes();
+    let mut padded = [0u8; 32];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    out.write_all(&[bytes.len() as u8])?;
+    out.write_all(&padded)?;
+    out.write_all(&id.to_le_bytes())
+}
+
+fn read_record(input: &mut impl Read) -> io::Result<Option<(Digest, PathId)>> {
+    let mut record = [0u8; RECORD];
+    match input.read_exact(&mut record) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        other => other?,
+    }
+
+    let len = (record[0] as usize).min(32);
+    let digest = Digest::from_bytes(&record[1..1 + len]).expect("at most 32 bytes");
+    let id = PathId::from_le_bytes(record[33..].try_into().unwrap());
+    Ok(Some((digest, id)))
+}
+
+impl SpillingIndex {
+    pub fn new(paths: PathTable, budget_bytes: usize) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("rdedupe-spill-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        Ok(SpillingIndex {
+            budget_bytes,
+            index: ChecksumIndex::from_paths(paths),
+            dir,
+            runs: (0..PARTITIONS).map(|_| None).collect(),
+            stats: SpillStats::default(),
+        })
+    }
+
+    pub fn insert(&mut self, digest: Digest, id: PathId) -> io::Result<()> {
+        self.index.insert_id(digest, id);
+        self.stats.entries += 1;
+
+        if self.index.groups_memory_bytes() > self.budget_bytes {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        for (digest, id) in self.index.entry_ids() {
+            let partition = digest.as_bytes()[0] as usize;
+            if self.runs[partition].is_none() {
+                let run = File::create(run_path(&self.dir, partition))?;
+                self.runs[partition] = Some(BufWriter::new(run));
+            }
+            write_record(self.runs[partition].as_mut().unwrap(), digest, id)?;
+            self.stats.spilled_entries += 1;
+        }
+
+        self.stats.spills += 1;
+        self.index.clear_groups();
+        Ok(())
+    }
+
+    /*  Every group with more than one path. Without a spill this is just the in-memory index;
+    otherwise what is left in memory is spilled too and each partition run is merged in turn.
+    */
+    pub fn finish(mut self) -> io::Result<(DuplicateGroups, SpillStats)> {
+        if self.stats.spills == 0 {
+            return Ok((self.index.duplicate_groups(), self.stats.clone()));
+        }
+
+        self.spill()?;
+        for run in self.runs.iter_mut().flatten() {
+            run.flush()?;
+        }
+
+        let mut duplicates = Vec::new();
+        for partition in 0..PARTITIONS {
+            if self.runs[partition].take().is_none() {
+                continue;
+            }
+
+            let mut merged: HashMap<Digest, Vec<PathId>> = HashMap::new();
+            let mut input = BufReader::new(File::open(run_path(&self.dir, partition))?);
+            while let Some((digest, id)) = read_record(&mut input)? {
+                merged.entry(digest).or_default().push(id);
+            }
+            duplicates.extend(
+                merged
+                    .into_values()
+                    .filter(|ids| ids.len() > 1)
+                    .map(|ids| ids.into_iter().map(|id| self.index.path(id)).collect()),
+            );
+        }
+
+        Ok((duplicates, self.stats.clone()))
+    }
+}
+
+impl Drop for SpillingIndex {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// Hash files in parallel batches and group them within budget_bytes of memory
+pub fn checksum_with_budget(
+    files: PathTable,
+    budget_bytes: usize,
+) -> Result<(DuplicateGroups, SpillStats), Box<dyn Error>> {
+    println!(
+        "Computing checksums with {} threads within a {:.2} MB index budget...",
+        rayon::current_num_threads(),
+        budget_bytes as f64 / 1_048_576.0
+    );
+
+    let pb = crate::progress::stage_bar(crate::progress::Stage::Hash, files.len() as u64);
+
+    let total = files.len() as PathId;
+    let mut index = SpillingIndex::new(files, budget_bytes)?;
+    for start in (0..total).step_by(BATCH) {
+        let batch = start..total.min(start + BATCH as PathId);
+        let paths = index.index.paths();
+        let hashed: Vec<(Digest, PathId)> = batch
+            .clone()
+            .into_par_iter()
+            .map_init(String::new, |buffer, id| {
+                paths.write_path(id, buffer);
+                index::digest(buffer).ok().map(|digest| (digest, id))
+            })
+            .flatten()
+            .collect();
+        pb.inc(batch.len() as u64);
+
+        for (digest, id) in hashed {
+            index.insert(digest, id)?;
+        }
+    }
+    pb.finish_and_clear();
+
+    Ok(index.finish()?)
+}
+
+// Like run(), but the checksum index stays within budget_bytes of memory
+pub fn run_with_budget(
+    path: &str,
+    pattern: &str,
+    walk_options: &crate::WalkOptions,
+    budget_bytes: usize,
+) -> Result<DuplicateGroups, Box<dyn Error>> {
+    let files = crate::walk_table(path, pattern, walk_options)?;
+    println!("Found {} files matching {}", files.len(), pattern);
+
+    let (mut duplicates, stats) = checksum_with_budget(files, budget_bytes)?;
+    if stats.spills > 0 {
+        println!(
+            "Spilled {} of {} index entries to disk in {} run(s)",
+            stats.spilled_entries, stats.entries, stats.spills
+        );
+    }
+
+    for group in &mut duplicates {
+        group.sort();
+    }
+    duplicates.sort();
+
+    println!("Found {} duplicate(s)", duplicates.len());
+    for duplicate in &duplicates {
+        println!("{:?}", duplicate);
+    }
+
+    Ok(duplicates)
+}
@@ -0,0 +1,108 @@
+//review tags on duplicate groups, kept across runs and used to filter later actions
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Tags per group, keyed by the group ID shown in reports (the content hash)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TagStore {
+    groups: BTreeMap<String, BTreeSet<String>>,
+}
+
+// RDEDUPE_TAGS, else $XDG_DATA_HOME/rdedupe/tags.json, else ~/.local/share/rdedupe/tags.json
+pub fn default_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RDEDUPE_TAGS") {
+        return Some(PathBuf::from(path));
+    }
+
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .ok()?;
+
+    Some(data_home.join("rdedupe").join("tags.json"))
+}
+
+// Tags are short lowercase words such as keep, delete-later or ask-owner
+pub fn validate_tag(tag: &str) -> Result<(), String> {
+    let valid = !tag.is_empty()
+        && tag
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid tag '{}' (use lowercase letters, digits, - and _)",
+            tag
+        ))
+    }
+}
+
+impl TagStore {
+    // A missing file is an empty store
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(
+                serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(TagStore::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        crate::report::write_atomic(path, |writer| {
+            writeln!(writer, "{}", json)?;
+            Ok(())
+        })
+    }
+
+    // Returns whether the tag is new for this group
+    pub fn add(&mut self, group: &str, tag: &str) -> bool {
+        self.groups
+            .entry(group.to_string())
+            .or_default()
+            .insert(tag.to_string())
+    }
+
+    // Returns whether the group had the tag
+    pub fn remove(&mut self, group: &str, tag: &str) -> bool {
+        let Some(tags) = self.groups.get_mut(group) else {
+            return false;
+        };
+        let removed = tags.remove(tag);
+        if tags.is_empty() {
+            self.groups.remove(group);
+        }
+        removed
+    }
+
+    pub fn tags(&self, group: &str) -> Vec<&str> {
+        self.groups
+            .get(group)
+            .map(|tags| tags.iter().map(|t| t.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn groups_with(&self, tag: &str) -> HashSet<String> {
+        self.groups
+            .iter()
+            .filter(|(_, tags)| tags.contains(tag))
+            .map(|(group, _)| group.clone())
+            .collect()
+    }
+
+    // Every tagged group with its tags, ordered by group ID
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Vec<&str>)> {
+        self.groups
+            .iter()
+            .map(|(group, tags)| (group.as_str(), tags.iter().map(|t| t.as_str()).collect()))
+    }
+}
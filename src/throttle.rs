@@ -0,0 +1,182 @@
+//adaptive limit on parallel file reads, driven by the read latency actually observed
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// Reads between two limit adjustments
+const WINDOW: u64 = 16;
+// Latency this far above the best seen means the device is saturated
+const BACK_OFF: f64 = 2.0;
+// Latency this close to the best seen means there is headroom
+const SPEED_UP: f64 = 1.3;
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static LIMITER: OnceLock<IoLimiter> = OnceLock::new();
+
+#[derive(Debug, Clone, Default)]
+pub struct LimiterStats {
+    pub reads: u64,
+    pub limit: usize,
+    pub lowest: usize,
+    pub highest: usize,
+    pub max: usize,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    active: usize,
+    // Exponentially weighted read latency, in microseconds per MB read (at least one per read)
+    latency: f64,
+    // Best smoothed latency seen so far, the device's unloaded speed
+    baseline: f64,
+    window: u64,
+    stats: LimiterStats,
+}
+
+/*  A counting semaphore whose size follows the device: it starts at half the worker count, adds a
+reader while latency stays near the best observed and cuts readers by a quarter once it climbs
+well above it (spinning disks, saturated NAS). Hashing threads stay busy; only reads wait.
+*/
+#[derive(Debug)]
+pub struct IoLimiter {
+    max: usize,
+    state: Mutex<State>,
+    freed: Condvar,
+}
+
+impl IoLimiter {
+    pub fn new(max: usize) -> Self {
+        let max = max.max(1);
+        let start = (max / 2).max(1);
+
+        IoLimiter {
+            max,
+            state: Mutex::new(State {
+                limit: start,
+                active: 0,
+                latency: 0.0,
+                baseline: f64::MAX,
+                window: 0,
+                stats: LimiterStats {
+                    limit: start,
+                    lowest: start,
+                    highest: start,
+                    max,
+                    ..Default::default()
+                },
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub fn acquire(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.active >= state.limit {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.active += 1;
+    }
+
+    // Give the permit back and feed the read's latency into the controller
+    pub fn release(&self, elapsed: Duration, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+
+        let sample = elapsed.as_micros() as f64 / (1.0 + bytes as f64 / 1_048_576.0);
+        state.latency = if state.stats.reads == 0 {
+            sample
+        } else {
+            0.8 * state.latency + 0.2 * sample
+        };
+        state.stats.reads += 1;
+        state.window += 1;
+
+        if state.window >= WINDOW {
+            state.window = 0;
+            state.baseline = state.baseline.min(state.latency);
+
+            if state.latency > state.baseline * BACK_OFF {
+                state.limit = (state.limit * 3 / 4).max(1);
+            } else if state.latency < state.baseline * SPEED_UP && state.limit < self.max {
+                state.limit += 1;
+            }
+
+            state.stats.limit = state.limit;
+            state.stats.lowest = state.stats.lowest.min(state.limit);
+            state.stats.highest = state.stats.highest.max(state.limit);
+        }
+
+        drop(state);
+        self.freed.notify_all();
+    }
+
+    pub fn stats(&self) -> LimiterStats {
+        self.state.lock().unwrap().stats.clone()
+    }
+}
+
+// Turn the adaptive limit off, so every worker thread reads whenever it likes
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn limiter() -> &'static IoLimiter {
+    LIMITER.get_or_init(|| IoLimiter::new(rayon::current_num_threads()))
+}
+
+// fs::read under the shared limiter
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return fs::read(path);
+    }
+
+    let limiter = limiter();
+    limiter.acquire();
+    let started = Instant::now();
+    let result = fs::read(path);
+    limiter.release(
+        started.elapsed(),
+        result.as_ref().map(|c| c.len()).unwrap_or(0),
+    );
+    result
+}
+
+// Block size of read_chunks
+pub const CHUNK: usize = 1_048_576;
+
+/*  Stream path through chunk in CHUNK sized blocks under the shared limiter, for files too big to
+read at once. The permit is held for the whole file.
+*/
+pub fn read_chunks(path: &str, mut chunk: impl FnMut(&[u8])) -> io::Result<u64> {
+    let mut stream = || -> io::Result<u64> {
+        let mut file = fs::File::open(path)?;
+        let mut buffer = vec![0u8; CHUNK];
+        let mut total = 0;
+        loop {
+            let read = io::Read::read(&mut file, &mut buffer)?;
+            if read == 0 {
+                return Ok(total);
+            }
+            chunk(&buffer[..read]);
+            total += read as u64;
+        }
+    };
+    if !ENABLED.load(Ordering::Relaxed) {
+        return stream();
+    }
+
+    let limiter = limiter();
+    limiter.acquire();
+    let started = Instant::now();
+    let result = stream();
+    limiter.release(started.elapsed(), *result.as_ref().unwrap_or(&0) as usize);
+    result
+}
+
+// How the limit moved during this run, if any read went through it
+pub fn stats() -> Option<LimiterStats> {
+    LIMITER.get().map(|l| l.stats()).filter(|s| s.reads > 0)
+}
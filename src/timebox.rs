@@ -0,0 +1,165 @@
+//time-boxed scans: stop hashing once a budget is spent and checkpoint the hashes for a later run
+use crate::cache::{self, HashCache};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+// The first file left unhashed once the budget ran out
+static STOPPED_AT: Mutex<Option<String>> = Mutex::new(None);
+static CHECKPOINT: OnceLock<Checkpoint> = OnceLock::new();
+
+// A duration such as 90s, 30m, 2h or 1d (a bare number is seconds)
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let invalid = || {
+        format!(
+            "invalid duration '{}' (expected e.g. 90s, 30m or 2h)",
+            value
+        )
+    };
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => value.split_at(split),
+        None => (value, "s"),
+    };
+    let seconds_per_unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    Ok(Duration::from_secs(number * seconds_per_unit))
+}
+
+// Start the clock; only the first budget of a run counts
+pub fn set_max_duration(budget: Duration) {
+    let _ = DEADLINE.set(Instant::now() + budget);
+}
+
+/*  True once the budget is spent, so path is left for a later run. The walk and the hashers both
+ask before each file: nothing new is queued, and queued files are dropped instead of hashed.
+*/
+pub fn out_of_time(path: &str) -> bool {
+    if DEADLINE
+        .get()
+        .is_none_or(|deadline| Instant::now() < *deadline)
+    {
+        return false;
+    }
+    let mut stopped_at = STOPPED_AT.lock().unwrap();
+    if stopped_at.is_none() {
+        *stopped_at = Some(path.to_string());
+    }
+    true
+}
+
+// Where the scan stopped, if the budget ran out
+pub fn stopped_at() -> Option<String> {
+    STOPPED_AT.lock().unwrap().clone()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CheckpointFile {
+    stopped_at: Option<String>,
+    // cache::key -> hash, so files changed since the checkpoint are hashed again
+    hashes: HashMap<String, String>,
+}
+
+struct Checkpoint {
+    path: PathBuf,
+    hashes: Mutex<HashMap<String, String>>,
+}
+
+// Serves the checkpoint's hashes through the checksum cache and collects every new one
+struct CheckpointCache;
+
+impl HashCache for CheckpointCache {
+    fn get(&self, key: &str) -> Option<String> {
+        CHECKPOINT.get()?.hashes.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, hash: &str) {
+        if let Some(checkpoint) = CHECKPOINT.get() {
+            checkpoint
+                .hashes
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), hash.to_string());
+        }
+    }
+
+    fn describe(&self) -> String {
+        CHECKPOINT
+            .get()
+            .map(|c| format!("checkpoint {}", c.path.display()))
+            .unwrap_or_default()
+    }
+}
+
+// What a previous, time-boxed run left in its checkpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resumed {
+    pub stopped_at: Option<String>,
+    pub hashes: usize,
+}
+
+/*  Continue from path if it exists and collect this run's hashes into it. Returns where the
+previous run stopped and how many hashes it left. Takes the checksum cache slot, so it can't be
+combined with --shared-cache.
+*/
+pub fn resume(path: &Path) -> Result<Option<Resumed>, Box<dyn Error>> {
+    let previous: Option<CheckpointFile> = if path.exists() {
+        let text = fs::read_to_string(path)?;
+        Some(serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?)
+    } else {
+        None
+    };
+
+    let (resumed, hashes) = match previous {
+        Some(previous) => (
+            Some(Resumed {
+                stopped_at: previous.stopped_at,
+                hashes: previous.hashes.len(),
+            }),
+            previous.hashes,
+        ),
+        None => (None, HashMap::new()),
+    };
+    CHECKPOINT
+        .set(Checkpoint {
+            path: path.to_path_buf(),
+            hashes: Mutex::new(hashes),
+        })
+        .map_err(|_| "a checkpoint is already open")?;
+    cache::set_backend(Box::new(CheckpointCache));
+    Ok(resumed)
+}
+
+// Write the checkpoint after a run that stopped early; a complete run removes it instead
+pub fn finish() -> Result<(), Box<dyn Error>> {
+    let Some(checkpoint) = CHECKPOINT.get() else {
+        return Ok(());
+    };
+    let stopped_at = stopped_at();
+    if stopped_at.is_none() {
+        if checkpoint.path.exists() {
+            fs::remove_file(&checkpoint.path)?;
+        }
+        return Ok(());
+    }
+
+    let file = CheckpointFile {
+        stopped_at,
+        hashes: checkpoint.hashes.lock().unwrap().clone(),
+    };
+    crate::report::write_atomic(&checkpoint.path, |writer| {
+        serde_json::to_writer(&mut *writer, &file)?;
+        Ok(())
+    })
+}
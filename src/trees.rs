@@ -0,0 +1,191 @@
+//whole duplicated directory trees: a Merkle hash per directory from its children's names and hashes
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateTree {
+    pub hash: String,
+    // Sorted by path
+    pub dirs: Vec<String>,
+    // Files and bytes in each copy
+    pub files: usize,
+    pub size_bytes: u64,
+    /*  Copies that can go beyond those inside another returned tree, which are counted there: all
+    but one, or all the others when one of the copies sits inside a duplicated tree
+    */
+    pub redundant: usize,
+}
+
+impl DuplicateTree {
+    // Bytes freed by the redundant copies
+    pub fn wasted_bytes(&self) -> u64 {
+        self.size_bytes * self.redundant as u64
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    // (name, "f" or "d", hash) of every child
+    children: Vec<(String, char, String)>,
+    files: usize,
+    bytes: u64,
+    hash: String,
+}
+
+impl Node {
+    fn seal(&mut self) {
+        self.children.sort();
+        let mut context = md5::Context::new();
+        for (name, kind, hash) in &self.children {
+            context.consume(format!("{}\0{}\0{}\n", kind, name, hash));
+        }
+        self.hash = format!("{:x}", context.compute());
+    }
+}
+
+fn name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/*  Every directory below and including the roots gets a hash of its children's names and content
+or directory hashes, so two directories match when their trees hold the same names and content
+whatever they are called themselves. Only the files the scan kept count: a directory whose
+filtered out files differ still matches. Only the highest duplicated directories are returned,
+not the duplicated directories inside them. Needs the content hashes of a standard or paranoid
+scan of every file.
+*/
+pub fn duplicate_trees(
+    df: &DataFrame,
+    roots: &[String],
+) -> Result<Vec<DuplicateTree>, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let hashes = df.column("content_hash")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let roots: HashSet<PathBuf> = roots.iter().map(PathBuf::from).collect();
+
+    let mut nodes: HashMap<PathBuf, Node> = HashMap::new();
+    for index in 0..df.height() {
+        let (Some(path), Some(hash)) = (paths.get(index), hashes.get(index)) else {
+            continue;
+        };
+        let path = Path::new(path);
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+        let node = nodes.entry(parent.to_path_buf()).or_default();
+        node.children.push((name_of(path), 'f', hash.to_string()));
+        node.files += 1;
+        node.bytes += sizes.get(index).unwrap_or(0);
+        // Register the directories up to the root, which may hold no file of their own
+        let mut dir = parent;
+        while !roots.contains(dir) {
+            match dir.parent() {
+                Some(up) if !up.as_os_str().is_empty() => {
+                    nodes.entry(up.to_path_buf()).or_default();
+                    dir = up;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Deepest first, so every child is sealed before its parent takes its hash
+    let mut order: Vec<PathBuf> = nodes.keys().cloned().collect();
+    order.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in &order {
+        let mut node = nodes
+            .remove(dir)
+            .expect("every directory in order has a node");
+        node.seal();
+        if !roots.contains(dir) {
+            if let Some(parent) = dir.parent().and_then(|up| nodes.get_mut(up)) {
+                parent.children.push((name_of(dir), 'd', node.hash.clone()));
+                parent.files += node.files;
+                parent.bytes += node.bytes;
+            }
+        }
+        nodes.insert(dir.clone(), node);
+    }
+
+    let mut by_hash: BTreeMap<&str, Vec<&PathBuf>> = BTreeMap::new();
+    for (dir, node) in &nodes {
+        if node.files > 0 {
+            by_hash.entry(&node.hash).or_default().push(dir);
+        }
+    }
+    let duplicated: HashSet<&PathBuf> = by_hash
+        .values()
+        .filter(|dirs| dirs.len() > 1)
+        .flatten()
+        .copied()
+        .collect();
+
+    let mut trees: Vec<DuplicateTree> = by_hash
+        .into_iter()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        .filter_map(|(hash, dirs)| {
+            // A copy inside a duplicated directory is reported through that directory
+            let inside = |dir: &&PathBuf| {
+                !roots.contains(*dir)
+                    && dir
+                        .parent()
+                        .is_some_and(|up| duplicated.contains(&up.to_path_buf()))
+            };
+            let outside = dirs.iter().filter(|dir| !inside(dir)).count();
+            if outside == 0 {
+                return None;
+            }
+            let node = &nodes[dirs[0]];
+            let redundant = match outside == dirs.len() {
+                true => outside - 1,
+                false => outside,
+            };
+            let mut dirs: Vec<String> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+            dirs.sort();
+            Some(DuplicateTree {
+                hash: hash.to_string(),
+                dirs,
+                files: node.files,
+                size_bytes: node.bytes,
+                redundant,
+            })
+        })
+        .collect();
+    trees.sort_by(|a, b| {
+        b.wasted_bytes()
+            .cmp(&a.wasted_bytes())
+            .then_with(|| a.dirs.cmp(&b.dirs))
+    });
+    Ok(trees)
+}
+
+pub fn print_trees(trees: &[DuplicateTree]) {
+    println!("\n=== Duplicate Directories ===");
+    if trees.is_empty() {
+        println!("No duplicated directory trees found.");
+        return;
+    }
+    for tree in trees {
+        println!(
+            "{} copies of {} file(s), {:.2} MB each:",
+            tree.dirs.len(),
+            tree.files,
+            tree.size_bytes as f64 / 1_048_576.0
+        );
+        for dir in &tree.dirs {
+            println!("  {}", dir);
+        }
+    }
+    let files: usize = trees.iter().map(|t| t.files * t.redundant).sum();
+    let wasted: u64 = trees.iter().map(DuplicateTree::wasted_bytes).sum();
+    println!(
+        "{} duplicated tree(s) hold {} redundant file(s), {:.2} MB reclaimable",
+        trees.len(),
+        files,
+        wasted as f64 / 1_048_576.0
+    );
+}
@@ -0,0 +1,257 @@
+//terminal interface for reviewing and amending a plan before it is applied or exported
+use crate::audit;
+use crate::plan::{Plan, PlanEntry};
+use crate::rules::Disposition;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::error::Error;
+use std::io::IsTerminal;
+
+const HELP: &str =
+    "↑/↓ move  d delete  h hardlink  s symlink  x keep  space cycle  w export  a apply  q quit";
+
+// What the user chose to do with the reviewed plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Quit,
+    Export,
+    Apply,
+}
+
+pub struct PlanReview {
+    plan: Plan,
+    // Dispositions as loaded, to mark and count amendments
+    original: Vec<Disposition>,
+    state: ListState,
+    // A choice waiting for y/n
+    pending: Option<Choice>,
+}
+
+fn next_disposition(action: Disposition) -> Disposition {
+    match action {
+        Disposition::Delete => Disposition::Hardlink,
+        Disposition::Hardlink => Disposition::Keep,
+        Disposition::Keep => Disposition::Symlink,
+        Disposition::Symlink => Disposition::Delete,
+    }
+}
+
+impl PlanReview {
+    pub fn new(plan: Plan) -> Self {
+        let original = plan.entries.iter().map(|e| e.action.action).collect();
+        let selected = (!plan.entries.is_empty()).then_some(0);
+        PlanReview {
+            plan,
+            original,
+            state: ListState::default().with_selected(selected),
+            pending: None,
+        }
+    }
+
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    pub fn into_plan(self) -> Plan {
+        self.plan
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.state.selected()
+    }
+
+    pub fn amended(&self) -> usize {
+        self.plan
+            .entries
+            .iter()
+            .zip(&self.original)
+            .filter(|(entry, original)| entry.action.action != **original)
+            .count()
+    }
+
+    fn set(&mut self, change: impl FnOnce(Disposition) -> Disposition) {
+        if let Some(entry) = self
+            .state
+            .selected()
+            .and_then(|index| self.plan.entries.get_mut(index))
+        {
+            entry.action.action = change(entry.action.action);
+        }
+    }
+
+    fn confirm(&mut self, choice: Choice) -> Option<Choice> {
+        // Quitting loses nothing when the plan wasn't amended
+        if choice == Choice::Quit && self.amended() == 0 {
+            return Some(Choice::Quit);
+        }
+        self.pending = Some(choice);
+        None
+    }
+
+    // Returns the user's choice once one is made
+    pub fn handle(&mut self, key: KeyCode) -> Option<Choice> {
+        if let Some(choice) = self.pending.take() {
+            return matches!(key, KeyCode::Char('y') | KeyCode::Char('Y')).then_some(choice);
+        }
+
+        let last = self.plan.entries.len().saturating_sub(1);
+        match key {
+            KeyCode::Down | KeyCode::Char('j') => self.state.select_next(),
+            KeyCode::Up | KeyCode::Char('k') => self.state.select_previous(),
+            KeyCode::PageDown => self.state.scroll_down_by(10),
+            KeyCode::PageUp => self.state.scroll_up_by(10),
+            KeyCode::Home => self.state.select_first(),
+            KeyCode::End => self.state.select(Some(last)),
+            KeyCode::Char('d') => self.set(|_| Disposition::Delete),
+            KeyCode::Char('h') => self.set(|_| Disposition::Hardlink),
+            KeyCode::Char('s') => self.set(|_| Disposition::Symlink),
+            KeyCode::Char('x') => self.set(|_| Disposition::Keep),
+            KeyCode::Char(' ') => self.set(next_disposition),
+            KeyCode::Char('w') => return Some(Choice::Export),
+            KeyCode::Char('a') => return self.confirm(Choice::Apply),
+            KeyCode::Char('q') | KeyCode::Esc => return self.confirm(Choice::Quit),
+            _ => {}
+        }
+        // select_next can run one past the end until the list is rendered
+        if self.state.selected().is_some_and(|index| index > last) {
+            self.state.select(Some(last));
+        }
+        None
+    }
+
+    fn item(&self, entry: &PlanEntry, original: Disposition) -> ListItem<'static> {
+        let marker = if entry.action.action != original {
+            "*"
+        } else {
+            " "
+        };
+        let line = format!(
+            "{}{:<8} {}",
+            marker,
+            entry.action.action.as_str(),
+            entry.action.path
+        );
+        match entry.action.action {
+            Disposition::Delete => ListItem::new(line).red(),
+            Disposition::Hardlink => ListItem::new(line).yellow(),
+            Disposition::Symlink => ListItem::new(line).cyan(),
+            Disposition::Keep => ListItem::new(line),
+        }
+    }
+
+    fn details(&self) -> Vec<Line<'static>> {
+        let Some(index) = self.state.selected() else {
+            return vec![Line::from("The plan has no actions")];
+        };
+        let entry = &self.plan.entries[index];
+        let action = &entry.action;
+        vec![
+            Line::from(format!("file:     {}", action.path)),
+            Line::from(format!("keep:     {}", action.keep)),
+            Line::from(format!(
+                "size:     {:.2} MB, modified {}",
+                action.size_bytes as f64 / 1_048_576.0,
+                audit::format_time((entry.path_state.modified_ns / 1_000_000_000) as u64)
+            )),
+            Line::from(format!("group:    {}", action.group)),
+            Line::from(format!(
+                "planned:  {} by {}",
+                self.original[index].as_str(),
+                action.rule
+            )),
+        ]
+    }
+
+    fn status(&self) -> String {
+        let count = |disposition: Disposition| {
+            self.plan
+                .entries
+                .iter()
+                .filter(|e| e.action.action == disposition)
+                .count()
+        };
+        match self.pending {
+            Some(Choice::Apply) => format!(
+                "Apply {} delete(s), {} hardlink(s) and {} symlink(s) now? y/n",
+                count(Disposition::Delete),
+                count(Disposition::Hardlink),
+                count(Disposition::Symlink)
+            ),
+            Some(_) => format!("Discard {} amendment(s) and quit? y/n", self.amended()),
+            None => format!(
+                "{} to delete, {} to hardlink, {} to symlink, {} kept, {} amended",
+                count(Disposition::Delete),
+                count(Disposition::Hardlink),
+                count(Disposition::Symlink),
+                count(Disposition::Keep),
+                self.amended()
+            ),
+        }
+    }
+
+    pub fn render(&mut self, frame: &mut Frame) {
+        let [list_area, details_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(7),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let items: Vec<ListItem> = self
+            .plan
+            .entries
+            .iter()
+            .zip(&self.original)
+            .map(|(entry, original)| self.item(entry, *original))
+            .collect();
+        let title = format!(
+            " Plan for {} ({} action(s)) ",
+            self.plan.roots.join(", "),
+            self.plan.entries.len()
+        );
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        frame.render_stateful_widget(list, list_area, &mut self.state);
+
+        frame.render_widget(
+            Paragraph::new(self.details()).block(Block::bordered().title(" Selected ")),
+            details_area,
+        );
+        frame.render_widget(
+            Paragraph::new(vec![Line::from(self.status()), Line::from(HELP).dim()]),
+            status_area,
+        );
+    }
+}
+
+// Run the review until the user exports, applies or quits; returns the amended plan
+pub fn review_plan(plan: Plan) -> Result<(Choice, Plan), Box<dyn Error>> {
+    if !std::io::stdout().is_terminal() {
+        return Err("review needs an interactive terminal".into());
+    }
+
+    let mut review = PlanReview::new(plan);
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<Choice, Box<dyn Error>> {
+        loop {
+            terminal.draw(|frame| review.render(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(choice) = review.handle(key.code) {
+                    return Ok(choice);
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+
+    Ok((result?, review.into_plan()))
+}
@@ -0,0 +1,205 @@
+//filesystem abstraction, so trees that aren't on disk (in memory, provided by an application) can be deduped
+use crate::diagnostics;
+use crate::index::{ChecksumIndex, Digest};
+use crate::FileInfo;
+use polars::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VfsMetadata {
+    pub len: u64,
+    pub is_dir: bool,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+/*  What walk and checksum need from a file source. Paths are '/'-separated strings; list returns
+the full paths of a directory's entries. Symlinks are the source's business: RealFs does not
+follow them, like the disk walk.
+*/
+pub trait Vfs: Send + Sync {
+    fn list(&self, dir: &str) -> io::Result<Vec<String>>;
+    fn stat(&self, path: &str) -> io::Result<VfsMetadata>;
+    fn open_read(&self, path: &str) -> io::Result<Box<dyn Read + Send + '_>>;
+}
+
+// The real filesystem, through std::fs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RealFs;
+
+impl Vfs for RealFs {
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path().to_string_lossy().to_string()))
+            .collect()
+    }
+
+    fn stat(&self, path: &str) -> io::Result<VfsMetadata> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(VfsMetadata {
+            len: metadata.len(),
+            is_dir: metadata.is_dir(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+        })
+    }
+
+    fn open_read(&self, path: &str) -> io::Result<Box<dyn Read + Send + '_>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+}
+
+// An in-memory tree; directories exist implicitly above every file
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    files: BTreeMap<String, (Vec<u8>, Option<SystemTime>)>,
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{}: no such file", path))
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, content: impl Into<Vec<u8>>) {
+        self.files.insert(path.to_string(), (content.into(), None));
+    }
+
+    pub fn insert_with_time(
+        &mut self,
+        path: &str,
+        content: impl Into<Vec<u8>>,
+        modified: SystemTime,
+    ) {
+        self.files
+            .insert(path.to_string(), (content.into(), Some(modified)));
+    }
+
+    fn prefix(dir: &str) -> String {
+        format!("{}/", dir.trim_end_matches('/'))
+    }
+}
+
+impl Vfs for MemoryFs {
+    fn list(&self, dir: &str) -> io::Result<Vec<String>> {
+        let prefix = Self::prefix(dir);
+        let mut entries: Vec<String> = self
+            .files
+            .keys()
+            .filter_map(|path| {
+                let rest = path.strip_prefix(&prefix)?;
+                let name = rest.split('/').next()?;
+                Some(format!("{}{}", prefix, name))
+            })
+            .collect();
+        entries.sort();
+        entries.dedup();
+        if entries.is_empty() && !self.files.contains_key(dir) {
+            return Err(not_found(dir));
+        }
+        Ok(entries)
+    }
+
+    fn stat(&self, path: &str) -> io::Result<VfsMetadata> {
+        if let Some((content, modified)) = self.files.get(path) {
+            return Ok(VfsMetadata {
+                len: content.len() as u64,
+                is_dir: false,
+                created: None,
+                modified: *modified,
+            });
+        }
+        let prefix = Self::prefix(path);
+        if self.files.keys().any(|file| file.starts_with(&prefix)) {
+            return Ok(VfsMetadata {
+                is_dir: true,
+                ..Default::default()
+            });
+        }
+        Err(not_found(path))
+    }
+
+    fn open_read(&self, path: &str) -> io::Result<Box<dyn Read + Send + '_>> {
+        let (content, _) = self.files.get(path).ok_or_else(|| not_found(path))?;
+        Ok(Box::new(content.as_slice()))
+    }
+}
+
+// Every file under root whose path contains pattern, in sorted order; .git directories are skipped
+pub fn walk(vfs: &dyn Vfs, root: &str, pattern: &str) -> io::Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_string()];
+    while let Some(dir) = pending.pop() {
+        for entry in vfs.list(&dir)? {
+            if vfs.stat(&entry)?.is_dir {
+                if Path::new(&entry)
+                    .file_name()
+                    .is_some_and(|name| name != ".git")
+                {
+                    pending.push(entry);
+                }
+            } else if entry.contains(pattern) {
+                files.push(entry);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// A file's content hash under the selected algorithm, read in blocks
+pub fn digest(vfs: &dyn Vfs, path: &str) -> io::Result<Digest> {
+    let bytes = crate::hasher::selected().hash_reader(vfs.open_read(path)?)?;
+    Digest::from_bytes(&bytes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "digest too long"))
+}
+
+// Like crate::checksum over a Vfs: files that can't be read are left out
+pub fn checksum(vfs: &dyn Vfs, files: &[String]) -> ChecksumIndex {
+    let digests: Vec<(Digest, &String)> = files
+        .par_iter()
+        .filter_map(|file| digest(vfs, file).ok().map(|digest| (digest, file)))
+        .collect();
+
+    let mut checksums = ChecksumIndex::default();
+    for (digest, file) in digests {
+        checksums.insert(digest, file);
+    }
+    checksums
+}
+
+pub fn collect_file_info(vfs: &dyn Vfs, files: &[String]) -> Vec<FileInfo> {
+    files
+        .par_iter()
+        .filter_map(|file| {
+            let metadata = diagnostics::skipped(file, vfs.stat(file))?;
+            let digest = diagnostics::skipped(file, digest(vfs, file))?;
+            Some(FileInfo::with_metadata(
+                file,
+                crate::index::to_hex(&digest),
+                metadata.len,
+                metadata.created,
+                metadata.modified,
+            ))
+        })
+        .collect()
+}
+
+// The analyzed DataFrame of crate::run_with_dataframe, for a tree behind a Vfs
+pub fn scan(vfs: &dyn Vfs, root: &str, pattern: &str) -> Result<DataFrame, Box<dyn Error>> {
+    let files = walk(vfs, root, pattern)?;
+    let file_infos = collect_file_info(vfs, &files);
+    if file_infos.is_empty() {
+        return crate::empty_dataframe();
+    }
+    crate::create_dataframe(file_infos)
+}
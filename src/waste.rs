@@ -0,0 +1,125 @@
+//where reclaimable space sits: by directory depth and by top-level directory under the scanned root
+use crate::partition;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+// Label for copies directly in a scanned root
+const ROOT: &str = "(root)";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bucket {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl Bucket {
+    fn add(&mut self, bytes: u64) {
+        self.files += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Waste {
+    // Depth 0 is a file directly in the root, 1 one directory down, ...
+    pub by_depth: BTreeMap<usize, Bucket>,
+    pub by_top_level: BTreeMap<String, Bucket>,
+    pub total: Bucket,
+}
+
+fn depth_of(roots: &[String], path: &str) -> usize {
+    let path = Path::new(path);
+    roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .map(|relative| relative.components().count().saturating_sub(1))
+        .unwrap_or(0)
+}
+
+/*  Every copy but one of a group is reclaimable; the copy kept is the first by path, the same one
+the rules file keeps for all-under groups.
+*/
+pub fn breakdown(df: &DataFrame, roots: &[String]) -> Result<Waste, Box<dyn Error>> {
+    let paths = df.column("file_path")?.utf8()?;
+    let sizes = df.column("size_bytes")?.u64()?;
+    let groups = df.column("duplicate_group")?.utf8()?;
+
+    let mut members: BTreeMap<&str, Vec<(&str, u64)>> = BTreeMap::new();
+    for index in 0..df.height() {
+        if let (Some(group), Some(path)) = (groups.get(index), paths.get(index)) {
+            members
+                .entry(group)
+                .or_default()
+                .push((path, sizes.get(index).unwrap_or(0)));
+        }
+    }
+
+    let mut waste = Waste::default();
+    for mut copies in members.into_values() {
+        copies.sort();
+        for (path, size) in copies.into_iter().skip(1) {
+            let top_level = partition::home_of(roots, path).unwrap_or_else(|| ROOT.to_string());
+            waste
+                .by_depth
+                .entry(depth_of(roots, path))
+                .or_default()
+                .add(size);
+            waste.by_top_level.entry(top_level).or_default().add(size);
+            waste.total.add(size);
+        }
+    }
+    Ok(waste)
+}
+
+fn share(bucket: &Bucket, total: &Bucket) -> f64 {
+    if total.bytes == 0 {
+        return 0.0;
+    }
+    bucket.bytes as f64 * 100.0 / total.bytes as f64
+}
+
+pub fn print_breakdown(waste: &Waste) {
+    println!("\n=== Reclaimable Space by Depth ===");
+    for (depth, bucket) in &waste.by_depth {
+        println!(
+            "depth {:<3} {:>8} file(s) {:>12.2} MB {:>6.1}%",
+            depth,
+            bucket.files,
+            bucket.bytes as f64 / 1_048_576.0,
+            share(bucket, &waste.total)
+        );
+    }
+
+    println!("\n=== Reclaimable Space by Top-Level Directory ===");
+    let mut top_levels: Vec<(&String, &Bucket)> = waste.by_top_level.iter().collect();
+    top_levels.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(b.0)));
+    for (name, bucket) in &top_levels {
+        println!(
+            "{:<24} {:>8} file(s) {:>12.2} MB {:>6.1}%",
+            name,
+            bucket.files,
+            bucket.bytes as f64 / 1_048_576.0,
+            share(bucket, &waste.total)
+        );
+    }
+
+    println!(
+        "\nReclaimable: {} file(s), {:.2} MB",
+        waste.total.files,
+        waste.total.bytes as f64 / 1_048_576.0
+    );
+    // More than half the waste in one place is worth cleaning there first
+    if let Some((name, bucket)) = top_levels.first() {
+        let share = share(bucket, &waste.total);
+        if share > 50.0 {
+            println!("Concentrated: {:.1}% of it is under {}", share, name);
+        } else {
+            println!(
+                "Diffuse: no top-level directory holds more than {:.1}% of it",
+                share
+            );
+        }
+    }
+}
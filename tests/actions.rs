@@ -0,0 +1,54 @@
+//library API: carrying out planned actions on real files
+mod common;
+use common::{noise, tree};
+use rdedupe::rules::{Disposition, PlannedAction};
+
+fn planned(keep: &std::path::Path, path: &std::path::Path, action: Disposition) -> PlannedAction {
+    PlannedAction {
+        group: "group".to_string(),
+        keep: keep.display().to_string(),
+        path: path.display().to_string(),
+        action,
+        size_bytes: 4096,
+        rule: "test".to_string(),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn actions_never_drop_the_keeper_through_an_alias() {
+    let dir = tree("alias-keeper", &[("data/a.dat", noise(4096, 21))]);
+    let keep = dir.join("data/a.dat");
+    std::fs::hard_link(&keep, dir.join("data/b.dat")).unwrap();
+    // alias/a.dat is data/a.dat again, not a second copy
+    std::os::unix::fs::symlink(dir.join("data"), dir.join("alias")).unwrap();
+    let alias = dir.join("alias/a.dat");
+    rdedupe::guard::install(
+        rdedupe::guard::Guard::new(
+            std::slice::from_ref(&dir),
+            &[dir.display().to_string()],
+            false,
+        )
+        .unwrap(),
+    );
+
+    for action in [Disposition::Delete, Disposition::Symlink] {
+        let summary = rdedupe::rules::apply(&[planned(&keep, &alias, action)], false).unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.deleted + summary.symlinked, 0);
+        assert_eq!(std::fs::read(&keep).unwrap(), noise(4096, 21));
+        assert!(!std::fs::symlink_metadata(&keep).unwrap().is_symlink());
+    }
+
+    // A real second link to the keeper still goes
+    let summary = rdedupe::rules::apply(
+        &[planned(&keep, &dir.join("data/b.dat"), Disposition::Delete)],
+        false,
+    )
+    .unwrap();
+    assert_eq!(summary.deleted, 1);
+    assert!(!dir.join("data/b.dat").exists());
+    assert_eq!(std::fs::read(&keep).unwrap(), noise(4096, 21));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -2,6 +2,9 @@
 use assert_cmd::Command;
 use predicates::prelude::*;
 
+mod common;
+use common::{noise, scratch_dir};
+
 const PRG: &str = "rdedupe";
 const DUPE1: &str = "tests/inputs/one.txt";
 const DUPE2: &str = "tests/inputs/same-one.txt";
@@ -30,3 +33,3376 @@ fn search() {
         .stdout(predicate::str::contains(DUPE2))
         .stdout(predicate::str::contains(NOTDUPE));
 }
+
+#[test]
+fn dedupe_photos_profile() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/photos")
+        .arg("--profile")
+        .arg("photos")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 4 files matching profile 'photos'",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ))
+        .stdout(predicate::str::contains("possible re-encodes"));
+
+    // --quiet silences the profile's progress messages, not the report
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--quiet")
+        .arg("dedupe")
+        .arg("--path")
+        .arg("tests/photos")
+        .arg("--profile")
+        .arg("photos")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("matching profile").not())
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+}
+
+#[test]
+fn verify_backup_identical_tree() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("verify-backup")
+        .arg("--source")
+        .arg("tests/inputs")
+        .arg("--backup")
+        .arg("tests/inputs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("missing:   0"));
+}
+
+#[test]
+fn verify_backup_reports_missing() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("verify-backup")
+        .arg("--source")
+        .arg("tests/photos")
+        .arg("--backup")
+        .arg("tests/inputs")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains(
+            "missing: tests/photos/phone1/notes.txt",
+        ));
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn git_aware_scanning() {
+    let repo = scratch_dir("git");
+    git(&repo, &["init", "-q"]);
+    std::fs::write(repo.join("committed.txt"), "same").unwrap();
+    std::fs::write(repo.join("edited.txt"), "before").unwrap();
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "-q", "-m", "init"]);
+    std::fs::write(repo.join("edited.txt"), "after").unwrap();
+    std::fs::write(repo.join("untracked.txt"), "same").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("search")
+        .arg("--path")
+        .arg(&repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found 3 files"))
+        .stdout(predicate::str::contains(".git").not());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("search")
+        .arg("--path")
+        .arg(&repo)
+        .arg("--skip-reproducible")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found 2 files"))
+        .stdout(predicate::str::contains("committed.txt").not());
+
+    std::fs::remove_dir_all(&repo).unwrap();
+}
+
+fn tar_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *content).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+#[test]
+fn layers_reports_cross_layer_duplicates() {
+    let dir = scratch_dir("layers");
+    let shared = b"shared library".repeat(64);
+    let base = tar_bytes(&[("usr/lib/libbig.so", &shared), ("etc/base", b"base")]);
+    let app = tar_bytes(&[
+        ("usr/lib/libbig.so", &shared),
+        ("etc/.wh.base", b""),
+        ("app/main", b"app"),
+    ]);
+    let image = tar_bytes(&[
+        ("base/layer.tar", &base),
+        ("app/layer.tar", &app),
+        ("manifest.json", b"[]"),
+    ]);
+    let image_path = dir.join("image.tar");
+    std::fs::write(&image_path, image).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("layers")
+        .arg("--path")
+        .arg(&image_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 duplicated file(s)"))
+        .stdout(predicate::str::contains(
+            "image.tar :: app/layer.tar :: usr/lib/libbig.so",
+        ))
+        .stdout(predicate::str::contains("manifest.json").not());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn maildir_hardlinks_identical_messages() {
+    use std::os::unix::fs::MetadataExt;
+
+    let maildir = scratch_dir("maildir");
+    for folder in ["cur", "new", "tmp", ".Archive/cur", ".Archive/new"] {
+        std::fs::create_dir_all(maildir.join(folder)).unwrap();
+    }
+    let message = "Subject: hello\n\nsame body\n";
+    let inbox = maildir.join("cur/1700000000.M1P1.host,S=26:2,S");
+    let archived = maildir.join(".Archive/cur/1700000000.M1P1.host,S=26:2,RS");
+    std::fs::write(&inbox, message).unwrap();
+    std::fs::write(&archived, message).unwrap();
+    std::fs::write(maildir.join("new/1700000001.M2P1.host"), "other").unwrap();
+    std::fs::write(maildir.join("tmp/1700000002.M3P1.host"), message).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found 3 messages"))
+        .stdout(predicate::str::contains("also in [INBOX] flags=S"))
+        .stdout(predicate::str::contains("Would link 1 message(s)"));
+    assert_ne!(
+        std::fs::metadata(&inbox).unwrap().ino(),
+        std::fs::metadata(&archived).unwrap().ino()
+    );
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .arg("--allow-root")
+        .arg(&maildir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Linked 1 message(s)"));
+    assert_eq!(
+        std::fs::metadata(&inbox).unwrap().ino(),
+        std::fs::metadata(&archived).unwrap().ino()
+    );
+
+    std::fs::remove_dir_all(&maildir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dedupe_cargo_profile_hardlinks_cache() {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = scratch_dir("cargo-home");
+    let cache = home.join(".cargo/registry/cache/index-a");
+    let other = home.join(".cargo/registry/cache/index-b");
+    std::fs::create_dir_all(&cache).unwrap();
+    std::fs::create_dir_all(&other).unwrap();
+    std::fs::write(cache.join("serde-1.0.0.crate"), "crate bytes").unwrap();
+    std::fs::write(other.join("serde-1.0.0.crate"), "crate bytes").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("HOME", &home)
+        .arg("dedupe")
+        .arg("--profile")
+        .arg("cargo")
+        .arg("--allow-root")
+        .arg(&home)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Linked 1 file(s)"));
+    assert_eq!(
+        std::fs::metadata(cache.join("serde-1.0.0.crate"))
+            .unwrap()
+            .ino(),
+        std::fs::metadata(other.join("serde-1.0.0.crate"))
+            .unwrap()
+            .ino()
+    );
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[test]
+fn user_defined_profiles() {
+    let dir = scratch_dir("config");
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        "[profiles.notes]\ndescription = \"Plain text notes\"\nextensions = [\".TXT\"]\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("profiles")
+        .arg("--config")
+        .arg(&config)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("photos"))
+        .stdout(predicate::str::contains("Plain text notes"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--profile")
+        .arg("notes")
+        .arg("--config")
+        .arg(&config)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 3 files matching profile 'notes'",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_per_home_reports() {
+    let root = scratch_dir("homes");
+    for home in ["alice", "bob"] {
+        std::fs::create_dir_all(root.join(home)).unwrap();
+    }
+    std::fs::write(root.join("alice/song.mp3"), "shared").unwrap();
+    std::fs::write(root.join("bob/song-copy.mp3"), "shared").unwrap();
+    std::fs::write(root.join("alice/a.txt"), "mine").unwrap();
+    std::fs::write(root.join("alice/b.txt"), "mine").unwrap();
+    let reports = root.join("reports");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&root)
+        .arg("--per-home")
+        .arg(&reports)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("_global.csv (4 duplicate files)"))
+        .stdout(predicate::str::contains("alice.csv (3 duplicate files)"))
+        .stdout(predicate::str::contains("bob.csv (1 duplicate files)"));
+
+    let bob = std::fs::read_to_string(reports.join("bob.csv")).unwrap();
+    assert!(bob.contains("song-copy.mp3"));
+    assert!(bob.contains(",bob,alice"));
+    assert!(!bob.contains("a.txt"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn archive_create_and_extract() {
+    let dir = scratch_dir("archive");
+    let source = dir.join("source");
+    std::fs::create_dir_all(source.join("nested")).unwrap();
+    let big = noise(600 * 1024, 7);
+    let mut shifted = b"prefix that shifts every byte".to_vec();
+    shifted.extend_from_slice(&big);
+    std::fs::write(source.join("big.bin"), &big).unwrap();
+    std::fs::write(source.join("nested/shifted.bin"), &shifted).unwrap();
+    std::fs::write(source.join("small.txt"), "small").unwrap();
+    let repo = dir.join("repo");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let output = cmd
+        .arg("archive")
+        .arg("create")
+        .arg("--repo")
+        .arg(&repo)
+        .arg("--path")
+        .arg(&source)
+        .arg("--name")
+        .arg("first")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Snapshot 'first': 3 files"))
+        .get_output()
+        .stdout
+        .clone();
+    // The shifted copy shares almost every chunk with the original
+    let stored = String::from_utf8(output).unwrap();
+    let stored: f64 = stored
+        .split("MB read, ")
+        .nth(1)
+        .unwrap()
+        .split(" MB stored")
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(stored < 0.8, "stored {} MB", stored);
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("archive")
+        .arg("list")
+        .arg("--repo")
+        .arg(&repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("first  3 files"));
+
+    let restored = dir.join("restored");
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("archive")
+        .arg("extract")
+        .arg("--repo")
+        .arg(&repo)
+        .arg("--snapshot")
+        .arg("first")
+        .arg("--dest")
+        .arg(&restored)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Restored 3 files"));
+    assert_eq!(std::fs::read(restored.join("big.bin")).unwrap(), big);
+    assert_eq!(
+        std::fs::read(restored.join("nested/shifted.bin")).unwrap(),
+        shifted
+    );
+
+    let extract = |snapshot: &str| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.args(["archive", "extract", "--repo"])
+            .arg(&repo)
+            .args(["--snapshot", snapshot, "--dest"])
+            .arg(dir.join("unsafe"))
+            .assert()
+    };
+    // Names and chunk ids from outside never leave the repository
+    extract("../first")
+        .code(2)
+        .stderr(predicate::str::contains("invalid snapshot name '../first'"));
+    let index = std::fs::read_to_string(repo.join("snapshots/first.json")).unwrap();
+    for (name, id) in [("short", "a"), ("escape", "../../../outside")] {
+        let mut snapshot: serde_json::Value = serde_json::from_str(&index).unwrap();
+        snapshot["files"][0]["chunks"][0] = id.into();
+        std::fs::write(
+            repo.join(format!("snapshots/{}.json", name)),
+            snapshot.to_string(),
+        )
+        .unwrap();
+        extract(name)
+            .code(2)
+            .stderr(predicate::str::contains(format!(
+                "invalid chunk id '{}'",
+                id
+            )));
+    }
+
+    // An unreadable file is reported and left out, the rest is archived
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let locked = source.join("locked.txt");
+        std::fs::write(&locked, "locked").unwrap();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        let assert = cmd
+            .args(["archive", "create", "--repo"])
+            .arg(&repo)
+            .arg("--path")
+            .arg(&source)
+            .args(["--name", "second"])
+            .assert()
+            .success();
+        // Root reads it regardless
+        if std::fs::read(&locked).is_err() {
+            assert
+                .stdout(predicate::str::contains("Warning: skipped"))
+                .stdout(predicate::str::contains("Snapshot 'second': 3 files"))
+                .stdout(predicate::str::contains("Left out 1 unreadable file(s)"));
+        }
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn linkfarm_audits_snapshots() {
+    let dir = scratch_dir("linkfarm");
+    let (old, new) = (dir.join("daily.1"), dir.join("daily.0"));
+    std::fs::create_dir_all(&old).unwrap();
+    std::fs::create_dir_all(new.join("moved")).unwrap();
+
+    // Unchanged and still shared
+    std::fs::write(old.join("linked.txt"), "shared content").unwrap();
+    std::fs::hard_link(old.join("linked.txt"), new.join("linked.txt")).unwrap();
+    // Same content, separate inodes: a lost link, once in place and once after a move
+    std::fs::write(old.join("copy.txt"), "copied content").unwrap();
+    std::fs::write(new.join("copy.txt"), "copied content").unwrap();
+    std::fs::write(old.join("report.txt"), "moved content").unwrap();
+    std::fs::write(new.join("moved/report.txt"), "moved content").unwrap();
+    // Same size and mtime, different bytes: broken sharing
+    let stamp = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    for (snapshot, content) in [(&old, "version-a"), (&new, "version-b")] {
+        std::fs::write(snapshot.join("rotted.txt"), content).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(snapshot.join("rotted.txt"))
+            .unwrap()
+            .set_modified(stamp)
+            .unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("linkfarm")
+        .arg("--path")
+        .arg(&dir)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("linked:   1"))
+        .stdout(predicate::str::contains("unlinked: 1"))
+        .stdout(predicate::str::contains("diverged: 1"))
+        .stdout(predicate::str::contains("diverged: rotted.txt"))
+        .stdout(predicate::str::contains(
+            "Linking opportunities: 2 group(s)",
+        ))
+        .stdout(predicate::str::contains("moved/report.txt"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_memory_budget_spills_to_disk() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--memory-budget")
+        .arg("0")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("index entries to disk"))
+        .stdout(predicate::str::contains("Found 1 duplicate(s)"))
+        .stdout(predicate::str::contains(DUPE1))
+        .stdout(predicate::str::contains(DUPE2))
+        .stdout(predicate::str::contains(NOTDUPE).not());
+}
+
+#[test]
+fn bench_recommends_settings() {
+    let dir = scratch_dir("bench");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("bench")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--size-mb")
+        .arg("2")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("md5"))
+        .stdout(predicate::str::contains("blake3"))
+        .stdout(predicate::str::contains("write (synced):"))
+        .stdout(predicate::str::contains("Recommendation: "));
+
+    // The scratch file is cleaned up
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Every file under dir, relative path -> content
+fn tree_contents(dir: &std::path::Path) -> std::collections::BTreeMap<String, Vec<u8>> {
+    let mut contents = std::collections::BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let relative = path
+                    .strip_prefix(dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string();
+                contents.insert(relative, std::fs::read(&path).unwrap());
+            }
+        }
+    }
+    contents
+}
+
+#[test]
+fn gen_tree_is_reproducible() {
+    let dir = scratch_dir("gen-tree");
+    let (first, second) = (dir.join("first"), dir.join("second"));
+
+    for target in [&first, &second] {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("gen-tree")
+            .arg(target)
+            .arg("--files")
+            .arg("0.25k")
+            .arg("--dupe-ratio")
+            .arg("0.2")
+            .arg("--size")
+            .arg("64")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Generated 250 files (0.02 MB)"))
+            .stdout(predicate::str::contains("200 unique, 50 duplicates of"));
+    }
+    assert_eq!(tree_contents(&first), tree_contents(&second));
+
+    // Exactly the requested share is duplicated
+    let contents = tree_contents(&first);
+    let distinct: std::collections::HashSet<&Vec<u8>> = contents.values().collect();
+    assert_eq!(contents.len(), 250);
+    assert_eq!(distinct.len(), 200);
+
+    // Generating into a non-empty directory is refused
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("gen-tree")
+        .arg(&first)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("is not empty"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_hashes_while_walking() {
+    let dir = scratch_dir("pipeline");
+    let tree = dir.join("tree");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("gen-tree")
+        .arg(&tree)
+        .arg("--files")
+        .arg("250")
+        .arg("--size")
+        .arg("64")
+        .assert()
+        .success();
+
+    // Every generated file reaches the hashers through the channel, in walk order
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--pattern")
+        .arg(".bin")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("while walking"))
+        .stdout(predicate::str::contains(
+            "Found 250 files matching pattern '.bin'",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_adapts_io_readers() {
+    // A warm checksum cache would leave nothing to read
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--no-cache")
+        .arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Adaptive IO: "))
+        .stdout(predicate::str::contains("over 3 reads"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--static-io")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Adaptive IO").not());
+}
+
+#[test]
+fn dedupe_with_pinned_threads() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--pin-threads")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("NUMA node(s)"))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ))
+        .stdout(predicate::str::contains("could not be pinned").not());
+}
+
+#[test]
+fn dedupe_verify_compares_bytes() {
+    let dir = scratch_dir("verify");
+    // Larger than one comparison block, with an odd tail
+    let content = noise(3 * 1024 * 1024 + 77, 5);
+    std::fs::write(dir.join("a.bin"), &content).unwrap();
+    std::fs::write(dir.join("b.bin"), &content).unwrap();
+    let mut changed = content.clone();
+    changed[2 * 1024 * 1024] ^= 1;
+    std::fs::write(dir.join("c.bin"), &changed).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--verify")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Verified all duplicate candidates byte-for-byte",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// A worker on an ephemeral port, with the address it printed
+fn spawn_worker(root: &std::path::Path, extra: &[&str]) -> (std::process::Child, String) {
+    use std::io::BufRead;
+
+    let mut worker = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .args(extra)
+        .args([
+            "worker",
+            "--listen",
+            "127.0.0.1:0",
+            "--token",
+            "s3cret",
+            "--root",
+        ])
+        .arg(root)
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut banner = String::new();
+    let mut stdout = std::io::BufReader::new(worker.stdout.take().unwrap());
+    stdout.read_line(&mut banner).unwrap();
+    // Keep reading, so the worker's warnings about refused clients have somewhere to go
+    std::thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+    let address = banner
+        .split_whitespace()
+        .find(|word| word.starts_with("127.0.0.1:"))
+        .unwrap()
+        .to_string();
+    (worker, address)
+}
+
+#[test]
+fn dedupe_offloads_hashing_to_worker() {
+    let dedupe = |address: &str, token: &str, extra: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.args(extra)
+            .arg("dedupe")
+            .arg("--path")
+            .arg("tests/inputs")
+            .arg("--remote")
+            .arg(address)
+            .arg("--remote-token")
+            .arg(token)
+            .arg("--no-cache")
+            .assert()
+    };
+
+    let (mut worker, address) = spawn_worker(std::path::Path::new("tests/inputs"), &[]);
+    let assert = dedupe(&address, "s3cret", &[]);
+    // Every connection must present the worker's token
+    let wrong_token = dedupe(&address, "guess", &[]);
+    // And hash with the worker's algorithm
+    let wrong_hash = dedupe(&address, "s3cret", &["--hash", "blake3"]);
+    worker.kill().unwrap();
+    worker.wait().unwrap();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains(
+            "Remote hashing: 3 file(s) on 1 worker(s), 0 hashed locally",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+    wrong_token
+        .success()
+        .stdout(predicate::str::contains("worker refused: bad token"))
+        .stdout(predicate::str::contains(
+            "Remote hashing: 0 file(s) on 1 worker(s), 3 hashed locally",
+        ));
+    wrong_hash
+        .success()
+        .stdout(predicate::str::contains(
+            "worker refused: worker hashes with md5, not blake3",
+        ))
+        .stdout(predicate::str::contains(
+            "Remote hashing: 0 file(s) on 1 worker(s), 3 hashed locally",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    // An unreachable worker leaves the hashing to this machine
+    dedupe(&address, "s3cret", &[])
+        .success()
+        .stdout(predicate::str::contains("unreachable"))
+        .stdout(predicate::str::contains(
+            "Remote hashing: 0 file(s) on 1 worker(s), 3 hashed locally",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env_remove("RDEDUPE_TOKEN")
+        .arg("worker")
+        .arg("--root")
+        .arg("tests/inputs")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("a worker needs --token"));
+}
+
+#[test]
+fn dedupe_rehashes_files_a_worker_cannot_read() {
+    // The worker's copy of the tree lacks one file, which is hashed here instead
+    let dir = scratch_dir("worker-missing");
+    for name in ["one.txt", "same-one.txt"] {
+        std::fs::copy(
+            std::path::Path::new("tests/inputs").join(name),
+            dir.join(name),
+        )
+        .unwrap();
+    }
+    let (mut worker, address) = spawn_worker(&dir, &[]);
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let assert = cmd
+        .env("RDEDUPE_TOKEN", "s3cret")
+        .arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--remote")
+        .arg(&address)
+        .arg("--no-cache")
+        .assert();
+    worker.kill().unwrap();
+    worker.wait().unwrap();
+
+    assert
+        .success()
+        .stdout(predicate::str::contains(
+            "Remote hashing: 2 file(s) on 1 worker(s), 1 hashed locally",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn worker_bounds_what_clients_can_ask() {
+    use std::io::{BufRead, Write};
+    let dir = scratch_dir("worker-bounds");
+    std::fs::create_dir_all(dir.join("root")).unwrap();
+    std::fs::write(dir.join("root/inside.txt"), "inside").unwrap();
+    std::fs::write(dir.join("outside.txt"), "outside").unwrap();
+    let connect = |address: &str| {
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        (std::io::BufReader::new(stream.try_clone().unwrap()), stream)
+    };
+    let reply = |reader: &mut std::io::BufReader<std::net::TcpStream>| {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    };
+
+    let (mut worker, address) = spawn_worker(&dir.join("root"), &[]);
+    // Not even the AUTH line may run on without end
+    let (mut reader, mut stream) = connect(&address);
+    stream.write_all(&[b'A'; 8192]).unwrap();
+    assert_eq!(reply(&mut reader), "REFUSED line longer than 8192 bytes");
+
+    // A refused one-path batch reads as a refusal, not as a file the worker couldn't hash
+    let (mut reader, mut stream) = connect(&address);
+    stream.write_all(b"AUTH s3cret\n").unwrap();
+    assert_eq!(reply(&mut reader), "OK");
+    stream.write_all(b"BATCH 1 blake3\n").unwrap();
+    assert_eq!(
+        reply(&mut reader),
+        "REFUSED worker hashes with md5, not blake3"
+    );
+
+    // A symlink out of the root is no way out of it
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.join("outside.txt"), dir.join("root/link.txt")).unwrap();
+        let (mut reader, mut stream) = connect(&address);
+        stream
+            .write_all(b"AUTH s3cret\nBATCH 2 md5\ninside.txt\nlink.txt\n")
+            .unwrap();
+        assert_eq!(reply(&mut reader), "OK");
+        assert!(reply(&mut reader).starts_with("OK "));
+        assert_eq!(reply(&mut reader), "ERR path escapes the worker root");
+    }
+    worker.kill().unwrap();
+    worker.wait().unwrap();
+
+    // Past 64 open connections a fresh worker turns clients away
+    let (mut worker, address) = spawn_worker(&dir.join("root"), &[]);
+    let held: Vec<_> = (0..64).map(|_| connect(&address)).collect();
+    let (mut reader, _stream) = connect(&address);
+    assert_eq!(reply(&mut reader), "REFUSED too many connections");
+    drop(held);
+    worker.kill().unwrap();
+    worker.wait().unwrap();
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// A RESP server holding GET/SET in memory, enough to stand in for Redis
+fn fake_redis() -> String {
+    use std::io::{BufRead, Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let store = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::<
+        Vec<u8>,
+        Vec<u8>,
+    >::new()));
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let (stream, store) = (stream.unwrap(), store.clone());
+            std::thread::spawn(move || {
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    let count: usize = line.trim()[1..].parse().unwrap();
+                    let mut args = Vec::new();
+                    for _ in 0..count {
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                        let mut arg = vec![0u8; line.trim()[1..].parse::<usize>().unwrap() + 2];
+                        reader.read_exact(&mut arg).unwrap();
+                        arg.truncate(arg.len() - 2);
+                        args.push(arg);
+                    }
+                    line.clear();
+
+                    let reply = match args[0].as_slice() {
+                        b"GET" => match store.lock().unwrap().get(&args[1]) {
+                            Some(value) => {
+                                let mut reply = format!("${}\r\n", value.len()).into_bytes();
+                                reply.extend_from_slice(value);
+                                reply.extend_from_slice(b"\r\n");
+                                reply
+                            }
+                            None => b"$-1\r\n".to_vec(),
+                        },
+                        b"SET" => {
+                            store
+                                .lock()
+                                .unwrap()
+                                .insert(args[1].clone(), args[2].clone());
+                            b"+OK\r\n".to_vec()
+                        }
+                        _ => b"+OK\r\n".to_vec(),
+                    };
+                    writer.write_all(&reply).unwrap();
+                }
+            });
+        }
+    });
+
+    address
+}
+
+#[test]
+fn dedupe_shares_checksums_through_cache() {
+    let url = format!("redis://{}/1", fake_redis());
+
+    // The first host hashes and fills the cache, the next one only reads it
+    for expected in ["0 hit(s), 3 miss(es)", "3 hit(s), 0 miss(es)"] {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("dedupe")
+            .arg("--path")
+            .arg("tests/inputs")
+            .arg("--shared-cache")
+            .arg(&url)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(expected))
+            .stdout(predicate::str::contains(
+                "Found 2 files in 1 duplicate groups",
+            ));
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--shared-cache")
+        .arg("memcached://localhost")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("unsupported cache URL"));
+}
+
+#[test]
+fn dedupe_replaces_report_atomically() {
+    let dir = scratch_dir("atomic-report");
+    let report = dir.join("report.csv");
+    std::fs::write(&report, "previous report\n").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--csv")
+        .arg(&report)
+        .assert()
+        .success();
+
+    let written = std::fs::read_to_string(&report).unwrap();
+    assert!(written.starts_with("file_path,"));
+    assert!(written.contains(DUPE1));
+    // Only the finished report is left in the directory
+    let names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(names, vec!["report.csv".to_string()]);
+
+    // A report that can't be renamed into place leaves the target alone
+    let blocked = dir.join("blocked.csv");
+    std::fs::create_dir_all(blocked.join("keep")).unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .arg("--csv")
+        .arg(&blocked)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Error: "));
+    assert!(blocked.join("keep").is_dir());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn audit_log_records_links() {
+    let dir = scratch_dir("audit");
+    let maildir = dir.join("mail");
+    for folder in ["cur", ".Archive/cur"] {
+        std::fs::create_dir_all(maildir.join(folder)).unwrap();
+    }
+    let message = "Subject: audit\n\nsame body\n";
+    std::fs::write(maildir.join("cur/1700000000.M1P1.host:2,S"), message).unwrap();
+    std::fs::write(
+        maildir.join(".Archive/cur/1700000000.M1P1.host:2,S"),
+        message,
+    )
+    .unwrap();
+    let log = dir.join("audit.log");
+
+    // Dry runs change nothing, so they log nothing
+    for dry_run in [true, false] {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--audit-log")
+            .arg(&log)
+            .arg("maildir")
+            .arg("--path")
+            .arg(&maildir)
+            .arg("--allow-root")
+            .arg(&maildir);
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+        cmd.assert().success();
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("audit")
+        .arg("--log")
+        .arg(&log)
+        .arg("--since")
+        .arg("2020-01-01")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("hardlink"))
+        .stdout(predicate::str::contains("maildir: keep the first copy"))
+        .stdout(predicate::str::contains("1 action(s)"))
+        .stdout(predicate::str::contains("Chain verified"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("audit")
+        .arg("--log")
+        .arg(&log)
+        .arg("--until")
+        .arg("2020-01-01")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "0 action(s), 0.00 MB, out of 1 logged",
+        ));
+
+    // Any edit breaks the chain, and a broken log is never appended to
+    let edited = std::fs::read_to_string(&log)
+        .unwrap()
+        .replace("hardlink", "hardlinK");
+    std::fs::write(&log, edited).unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("audit")
+        .arg("--log")
+        .arg(&log)
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("fails verification at entry 1"));
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--audit-log")
+        .arg(&log)
+        .arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .assert()
+        .code(2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn actions_require_allowed_roots() {
+    let dir = scratch_dir("allow-root");
+    let maildir = dir.join("mail");
+    std::fs::create_dir_all(maildir.join("cur")).unwrap();
+    std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+    let message = "Subject: guard\n\nsame body\n";
+    std::fs::write(maildir.join("cur/1700000000.M1P1.host:2,S"), message).unwrap();
+    std::fs::write(maildir.join("cur/1700000000.M1P2.host:2,S"), message).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "refusing to change files without --allow-root",
+        ));
+
+    // Copies outside every allowed root are left alone
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .arg("--allow-root")
+        .arg(dir.join("elsewhere"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(outside the allowed roots)"))
+        .stdout(predicate::str::contains("Linked 0 message(s)"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("maildir")
+        .arg("--path")
+        .arg(&maildir)
+        .arg("--allow-root")
+        .arg(dir.join("missing"))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("allowed root"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dedupe_age_rule_spares_recent_copies() {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = scratch_dir("age-home");
+    let cache = home.join(".cargo/registry/cache");
+    for index in ["index-a", "index-b", "index-c"] {
+        std::fs::create_dir_all(cache.join(index)).unwrap();
+        std::fs::write(cache.join(index).join("log-0.4.0.crate"), "crate bytes").unwrap();
+    }
+    let three_years_ago =
+        std::time::SystemTime::now() - std::time::Duration::from_secs(3 * 365 * 86_400);
+    for index in ["index-a", "index-b"] {
+        std::fs::File::options()
+            .write(true)
+            .open(cache.join(index).join("log-0.4.0.crate"))
+            .unwrap()
+            .set_modified(three_years_ago)
+            .unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("HOME", &home)
+        .arg("dedupe")
+        .arg("--profile")
+        .arg("cargo")
+        .arg("--older-than")
+        .arg("2y")
+        .arg("--allow-root")
+        .arg(&home)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Age rule: 1 duplicate(s) touched within 2y are kept",
+        ))
+        .stdout(predicate::str::contains("Linked 1 file(s)"));
+
+    let inode = |index: &str| {
+        std::fs::metadata(cache.join(index).join("log-0.4.0.crate"))
+            .unwrap()
+            .ino()
+    };
+    assert_eq!(inode("index-a"), inode("index-b"));
+    assert_ne!(inode("index-a"), inode("index-c"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("HOME", &home)
+        .arg("dedupe")
+        .arg("--profile")
+        .arg("cargo")
+        .arg("--older-than")
+        .arg("2 years")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("invalid age '2 years'"));
+
+    // The same rule holds for a plain --action without a profile
+    std::fs::write(cache.join("index-c/log-0.4.0.crate"), "fresh bytes").unwrap();
+    std::fs::write(cache.join("index-d.crate"), "fresh bytes").unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(cache.join("index-d.crate"))
+        .unwrap()
+        .set_modified(three_years_ago)
+        .unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&cache)
+        .arg("--no-cache")
+        .arg("--action")
+        .arg("delete")
+        .arg("--keep")
+        .arg("oldest")
+        .arg("--older-than")
+        .arg("2y")
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Age rule: 1 duplicate(s) touched within 2y are kept",
+        ))
+        .stdout(predicate::str::contains(
+            "1 to delete, 0 to hardlink, 0 to symlink",
+        ));
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dedupe_acts_only_on_tagged_groups() {
+    use std::os::unix::fs::MetadataExt;
+
+    let home = scratch_dir("tag-home");
+    let cache = home.join(".cargo/registry/cache");
+    for index in ["index-a", "index-b"] {
+        std::fs::create_dir_all(cache.join(index)).unwrap();
+        std::fs::write(cache.join(index).join("rand-0.8.crate"), "rand bytes").unwrap();
+        std::fs::write(cache.join(index).join("libc-0.2.crate"), "libc bytes").unwrap();
+    }
+    let store = home.join("tags.json");
+    let rand_group = format!("{:x}", md5::compute(b"rand bytes"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("tag")
+        .arg("add")
+        .arg(&rand_group)
+        .arg("delete-later")
+        .arg("ask-owner")
+        .arg("--store")
+        .arg(&store)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ask-owner, delete-later"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("tag")
+        .arg("add")
+        .arg(&rand_group)
+        .arg("Delete Later")
+        .arg("--store")
+        .arg(&store)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid tag"));
+
+    // A later session only touches the groups tagged for it
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("HOME", &home)
+        .arg("dedupe")
+        .arg("--profile")
+        .arg("cargo")
+        .arg("--only-tag")
+        .arg("delete-later")
+        .arg("--tag-store")
+        .arg(&store)
+        .arg("--allow-root")
+        .arg(&home)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Acting only on 1 group(s) tagged 'delete-later'",
+        ))
+        .stdout(predicate::str::contains("Linked 1 file(s)"));
+
+    let inode = |index: &str, name: &str| {
+        std::fs::metadata(cache.join(index).join(name))
+            .unwrap()
+            .ino()
+    };
+    assert_eq!(
+        inode("index-a", "rand-0.8.crate"),
+        inode("index-b", "rand-0.8.crate")
+    );
+    assert_ne!(
+        inode("index-a", "libc-0.2.crate"),
+        inode("index-b", "libc-0.2.crate")
+    );
+
+    // A plain --action plan is narrowed the same way
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&cache)
+        .arg("--no-cache")
+        .arg("--action")
+        .arg("delete")
+        .arg("--only-tag")
+        .arg("delete-later")
+        .arg("--tag-store")
+        .arg(&store)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "1 to delete, 0 to hardlink, 0 to symlink",
+        ))
+        .stdout(predicate::str::contains("libc-0.2.crate (keep").not());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("tag")
+        .arg("remove")
+        .arg(&rand_group)
+        .arg("ask-owner")
+        .arg("--store")
+        .arg(&store)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 tag(s)"));
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("tag")
+        .arg("list")
+        .arg("--tag")
+        .arg("ask-owner")
+        .arg("--store")
+        .arg(&store)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 tagged group(s)"));
+
+    std::fs::remove_dir_all(&home).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dedupe_rules_file_plans_dispositions() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = scratch_dir("rules");
+    for sub in ["scratch", "archive", "work", "other"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    std::fs::write(dir.join("scratch/a.dat"), "scratch copy").unwrap();
+    std::fs::write(dir.join("scratch/b.dat"), "scratch copy").unwrap();
+    std::fs::write(dir.join("work/report.pdf"), "archived").unwrap();
+    std::fs::write(dir.join("archive/report.pdf"), "archived").unwrap();
+    std::fs::write(dir.join("other/x.txt"), "no rule").unwrap();
+    std::fs::write(dir.join("work/x.txt"), "no rule").unwrap();
+
+    let rules = dir.join("rules.toml");
+    std::fs::write(
+        &rules,
+        format!(
+            "[[rule]]\nname = \"scratch\"\nwhen = \"all-under\"\npath = \"{}\"\naction = \"delete\"\n\n\
+             [[rule]]\nname = \"archive\"\nwhen = \"any-under\"\npath = \"{}\"\naction = \"hardlink\"\n",
+            dir.join("scratch").display(),
+            dir.join("archive").display()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--pattern")
+        .arg("/")
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 to delete, 1 to hardlink"))
+        .stdout(predicate::str::contains("would delete: "))
+        .stdout(predicate::str::contains("Would apply: 1 linked, 1 deleted"));
+    assert!(dir.join("scratch/b.dat").exists());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--allow-root")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Applied: 1 linked, 1 deleted"));
+
+    assert!(dir.join("scratch/a.dat").exists());
+    assert!(!dir.join("scratch/b.dat").exists());
+    assert_eq!(
+        std::fs::metadata(dir.join("archive/report.pdf"))
+            .unwrap()
+            .ino(),
+        std::fs::metadata(dir.join("work/report.pdf"))
+            .unwrap()
+            .ino()
+    );
+    assert_ne!(
+        std::fs::metadata(dir.join("other/x.txt")).unwrap().ino(),
+        std::fs::metadata(dir.join("work/x.txt")).unwrap().ino()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diff_reports_group_changes_between_scans() {
+    let dir = scratch_dir("diff");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    for name in ["a1", "a2", "a3"] {
+        std::fs::write(tree.join(name), noise(4096, 1)).unwrap();
+    }
+    std::fs::write(tree.join("b1"), noise(2048, 2)).unwrap();
+    std::fs::write(tree.join("b2"), noise(2048, 2)).unwrap();
+
+    let scan = |name: &str| {
+        let saved = dir.join(name);
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("dedupe")
+            .arg("--path")
+            .arg(&tree)
+            .arg("--parquet")
+            .arg(&saved)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Scan saved to: "));
+        saved
+    };
+
+    let before = scan("before.parquet");
+    std::fs::remove_file(tree.join("a3")).unwrap();
+    std::fs::remove_file(tree.join("b2")).unwrap();
+    std::fs::write(tree.join("c1"), noise(1024, 3)).unwrap();
+    std::fs::write(tree.join("c2"), noise(1024, 3)).unwrap();
+    let after = scan("after.parquet");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("diff")
+        .arg(&before)
+        .arg(&after)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "appeared    {:x}",
+            md5::compute(noise(1024, 3))
+        )))
+        .stdout(predicate::str::contains(format!(
+            "shrank      {:x} (3 -> 2 copies",
+            md5::compute(noise(4096, 1))
+        )))
+        .stdout(predicate::str::contains(
+            "1 appeared, 1 disappeared, 0 grew, 1 shrank",
+        ))
+        .stdout(predicate::str::contains("Wasted space: 0.01 MB -> 0.00 MB"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn largest_lists_top_files_and_directories() {
+    let dir = scratch_dir("largest");
+    std::fs::create_dir_all(dir.join("big/nested")).unwrap();
+    std::fs::create_dir_all(dir.join("small")).unwrap();
+    std::fs::write(dir.join("big/nested/huge.bin"), noise(300_000, 1)).unwrap();
+    std::fs::write(dir.join("big/medium.bin"), noise(200_000, 2)).unwrap();
+    std::fs::write(dir.join("small/tiny.txt"), "tiny").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let output = cmd
+        .arg("largest")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--top")
+        .arg("2")
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).unwrap();
+
+    let files = stdout.split("=== Largest Directories ===").next().unwrap();
+    let huge = files.find("huge.bin").unwrap();
+    assert!(huge < files.find("medium.bin").unwrap());
+    assert!(!files.contains("tiny.txt"));
+
+    let directories = stdout.split("=== Largest Directories ===").nth(1).unwrap();
+    assert!(directories.contains(&format!("0.48 MB  {}\n", dir.display())));
+    assert!(directories.contains(&format!("0.48 MB  {}\n", dir.join("big").display())));
+    assert!(!directories.contains("small"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_waste_breaks_down_by_depth_and_top_level() {
+    let dir = scratch_dir("waste");
+    std::fs::create_dir_all(dir.join("photos/2023/raw")).unwrap();
+    std::fs::create_dir_all(dir.join("docs")).unwrap();
+    // Kept copy first by path: docs/report.pdf; the photos copies are reclaimable
+    std::fs::write(dir.join("docs/report.pdf"), noise(300_000, 1)).unwrap();
+    std::fs::write(dir.join("photos/2023/raw/report.pdf"), noise(300_000, 1)).unwrap();
+    std::fs::write(dir.join("photos/report.pdf"), noise(300_000, 1)).unwrap();
+    std::fs::write(dir.join("a.txt"), noise(100_000, 2)).unwrap();
+    std::fs::write(dir.join("b.txt"), noise(100_000, 2)).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--waste")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "=== Reclaimable Space by Depth ===",
+        ))
+        .stdout(predicate::str::is_match(r"depth 0 +1 file\(s\) +0\.10 MB +14\.3%").unwrap())
+        .stdout(predicate::str::is_match(r"depth 1 +1 file\(s\) +0\.29 MB +42\.9%").unwrap())
+        .stdout(predicate::str::is_match(r"depth 3 +1 file\(s\) +0\.29 MB +42\.9%").unwrap())
+        .stdout(predicate::str::is_match(r"photos +2 file\(s\) +0\.57 MB +85\.7%").unwrap())
+        .stdout(predicate::str::is_match(r"\(root\) +1 file\(s\)").unwrap())
+        .stdout(predicate::str::contains("Reclaimable: 3 file(s), 0.67 MB"))
+        .stdout(predicate::str::contains(
+            "Concentrated: 85.7% of it is under photos",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_canonical_map_follows_keep_policy() {
+    let dir = scratch_dir("canonical");
+    std::fs::create_dir_all(dir.join("b")).unwrap();
+    std::fs::create_dir_all(dir.join("a")).unwrap();
+    std::fs::write(dir.join("b/copy.dat"), noise(1000, 1)).unwrap();
+    std::fs::write(dir.join("a/copy.dat"), noise(1000, 1)).unwrap();
+    std::fs::write(dir.join("a/unique.dat"), noise(1000, 2)).unwrap();
+    let map = dir.join("canonical.json");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--pattern")
+        .arg(".dat")
+        .arg("--canonical-map")
+        .arg(&map)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Canonical mapping for 1 group(s) saved to: ",
+        ));
+
+    let elections: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&map).unwrap()).unwrap();
+    let elections = elections.as_array().unwrap();
+    assert_eq!(elections.len(), 1);
+    assert_eq!(
+        elections[0]["group"],
+        format!("{:x}", md5::compute(noise(1000, 1)))
+    );
+    assert_eq!(
+        elections[0]["canonical"],
+        dir.join("a/copy.dat").to_str().unwrap()
+    );
+    assert_eq!(
+        elections[0]["duplicates"],
+        serde_json::json!([dir.join("b/copy.dat").to_str().unwrap()])
+    );
+    assert_eq!(elections[0]["policy"], "first");
+    assert_eq!(elections[0]["size_bytes"], 1000);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn plan_then_apply_rechecks_signature_and_file_state() {
+    let dir = scratch_dir("plan");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(tree.join("scratch")).unwrap();
+    std::fs::write(tree.join("scratch/a.dat"), "first copy").unwrap();
+    std::fs::write(tree.join("scratch/b.dat"), "first copy").unwrap();
+    std::fs::write(tree.join("scratch/c.log"), "second copy").unwrap();
+    std::fs::write(tree.join("scratch/d.log"), "second copy").unwrap();
+    let rules = dir.join("rules.toml");
+    std::fs::write(
+        &rules,
+        format!(
+            "[[rule]]\nwhen = \"all-under\"\npath = \"{}\"\naction = \"delete\"\n",
+            tree.join("scratch").display()
+        ),
+    )
+    .unwrap();
+    let key = dir.join("plan.key");
+    std::fs::write(&key, "secret").unwrap();
+    let plan = dir.join("plan.json");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("plan")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--output")
+        .arg(&plan)
+        .arg("--key")
+        .arg(&key)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Plan with 2 action(s) saved to: "));
+    assert!(tree.join("scratch/b.dat").exists());
+
+    // Signed plans need their key, and any edit breaks the signature
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("apply")
+        .arg(&plan)
+        .arg("--dry-run")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("is signed with a key, pass --key"));
+    let original = std::fs::read_to_string(&plan).unwrap();
+    let tampered = dir.join("tampered.json");
+    std::fs::write(&tampered, original.replace("b.dat", "a.dat")).unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("apply")
+        .arg(&tampered)
+        .arg("--key")
+        .arg(&key)
+        .arg("--dry-run")
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("fails signature verification"));
+
+    // A file modified after planning is skipped, the rest is applied
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    std::fs::write(tree.join("scratch/d.log"), "second copy").unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("apply")
+        .arg(&plan)
+        .arg("--key")
+        .arg(&key)
+        .arg("--allow-root")
+        .arg(&tree)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "(changed since the plan was made)",
+        ))
+        .stdout(predicate::str::contains("Applied: 0 linked, 1 deleted"))
+        .stdout(predicate::str::contains("1 skipped"));
+    assert!(tree.join("scratch/a.dat").exists());
+    assert!(!tree.join("scratch/b.dat").exists());
+    assert!(tree.join("scratch/d.log").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn review_amends_plan_dispositions() {
+    use ratatui::crossterm::event::KeyCode;
+    use rdedupe::rules::{Disposition, PlannedAction};
+
+    let dir = scratch_dir("review");
+    let mut planned = Vec::new();
+    for (name, action) in [("b", Disposition::Delete), ("d", Disposition::Hardlink)] {
+        std::fs::write(dir.join(format!("{}.keep", name)), name).unwrap();
+        std::fs::write(dir.join(name), name).unwrap();
+        planned.push(PlannedAction {
+            group: format!("group-{}", name),
+            keep: dir.join(format!("{}.keep", name)).display().to_string(),
+            path: dir.join(name).display().to_string(),
+            action,
+            size_bytes: 1,
+            rule: "rule 1".to_string(),
+        });
+    }
+    let roots = [dir.display().to_string()];
+    let plan = rdedupe::plan::build(planned, &roots, None).unwrap();
+
+    let mut review = rdedupe::tui::PlanReview::new(plan.clone());
+    assert_eq!(review.handle(KeyCode::Char('x')), None);
+    assert_eq!(review.handle(KeyCode::Down), None);
+    assert_eq!(review.handle(KeyCode::Down), None);
+    assert_eq!(review.selected(), Some(1));
+    assert_eq!(review.handle(KeyCode::Char(' ')), None);
+    assert_eq!(review.amended(), 2);
+
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(100, 20)).unwrap();
+    terminal.draw(|frame| review.render(frame)).unwrap();
+    let screen: String = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect();
+    assert!(screen.contains("*keep"));
+    assert!(screen.contains("0 to delete, 0 to hardlink, 0 to symlink, 2 kept, 2 amended"));
+    assert!(screen.contains("planned:  hardlink by rule 1"));
+
+    // Quitting with amendments asks first; anything but y goes back to the review
+    assert_eq!(review.handle(KeyCode::Char('q')), None);
+    assert_eq!(review.handle(KeyCode::Char('n')), None);
+    assert_eq!(review.handle(KeyCode::Char('a')), None);
+    assert_eq!(
+        review.handle(KeyCode::Char('y')),
+        Some(rdedupe::tui::Choice::Apply)
+    );
+
+    // Amended plans are resealed; the edited plan is what apply sees
+    let mut amended = review.into_plan();
+    let path = dir.join("plan.json");
+    rdedupe::plan::reseal(&mut amended, None).unwrap();
+    rdedupe::plan::write_plan(&amended, &path).unwrap();
+    let reread = rdedupe::plan::read_plan(&path, None).unwrap();
+    assert!(reread
+        .entries
+        .iter()
+        .all(|e| e.action.action == Disposition::Keep));
+    assert_ne!(reread.signature, plan.signature);
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("review")
+        .arg(&path)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "review needs an interactive terminal",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn rules_project_free_space_per_filesystem() {
+    let dir = scratch_dir("space");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("a1"), noise(65536, 1)).unwrap();
+    std::fs::write(tree.join("a2"), noise(65536, 1)).unwrap();
+    std::fs::write(tree.join("b1"), noise(65536, 2)).unwrap();
+    std::fs::write(tree.join("b2"), noise(65536, 2)).unwrap();
+    // b2 keeps a name outside the scan, so dropping it frees nothing
+    std::fs::hard_link(tree.join("b2"), dir.join("b2-elsewhere")).unwrap();
+    let rules = dir.join("rules.toml");
+    std::fs::write(
+        &rules,
+        format!(
+            "[[rule]]\nwhen = \"all-under\"\npath = \"{}\"\naction = \"delete\"\n",
+            tree.display()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("=== Projected Free Space ==="))
+        .stdout(predicate::str::contains(
+            "(+0.06 MB from 2 action(s), 1 freeing nothing)",
+        ))
+        .stdout(predicate::str::is_match(r"MB free of [0-9.]+ MB -> [0-9.]+ MB free").unwrap());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn rules_preflight_checks_directory_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("preflight");
+    let locked = dir.join("locked");
+    std::fs::create_dir_all(&locked).unwrap();
+    std::fs::write(locked.join("a"), "same").unwrap();
+    std::fs::write(locked.join("b"), "same").unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+    let rules = dir.join("rules.toml");
+    std::fs::write(
+        &rules,
+        format!(
+            "[[rule]]\nwhen = \"all-under\"\npath = \"{}\"\naction = \"delete\"\n",
+            locked.display()
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let assert = cmd
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--rules")
+        .arg(&rules)
+        .arg("--allow-root")
+        .arg(&dir)
+        .assert()
+        .success();
+    // Root may write anywhere; everyone else is stopped before the first change
+    if unsafe { libc::geteuid() } == 0 {
+        assert.stdout(predicate::str::contains(
+            "Preflight passed: every planned path is writable",
+        ));
+    } else {
+        assert
+            .stdout(predicate::str::contains(format!(
+                "{}: no write permission on {}",
+                locked.join("b").display(),
+                locked.display()
+            )))
+            .stdout(predicate::str::contains(
+                "preflight failed for 1 path(s), nothing was changed",
+            ));
+        assert!(locked.join("b").exists());
+    }
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_streams_large_files() {
+    let dir = scratch_dir("large-files");
+    // Just over the size at which files are streamed with their own byte progress
+    let content = noise(64 * 1_048_576 + 4096, 7);
+    std::fs::write(dir.join("big.img"), &content).unwrap();
+    std::fs::write(dir.join("big-copy.img"), &content).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--csv")
+        .arg(dir.join("report.csv"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    let report = std::fs::read_to_string(dir.join("report.csv")).unwrap();
+    assert!(report.contains(&format!("{:x}", md5::compute(&content))));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_max_duration_checkpoints_and_resumes() {
+    let dir = scratch_dir("max-duration");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("a.txt"), b"same").unwrap();
+    std::fs::write(tree.join("b.txt"), b"same").unwrap();
+    let checkpoint = dir.join("scan.checkpoint");
+
+    // A budget that is spent before the first file
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--max-duration")
+        .arg("0s")
+        .arg("--resume")
+        .arg(&checkpoint)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "scan incomplete, 0 file(s) hashed",
+        ))
+        .stdout(predicate::str::contains("Checkpoint saved to:"));
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert!(saved["stopped_at"].as_str().unwrap().contains("tree"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--resume")
+        .arg(&checkpoint)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Resuming from"))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ))
+        .stdout(predicate::str::contains("scan incomplete").not());
+    assert!(!checkpoint.exists());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--max-duration")
+        .arg("soon")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid duration"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exists_stops_at_first_duplicates() {
+    let dir = scratch_dir("exists");
+    std::fs::write(dir.join("a.conf"), b"same").unwrap();
+    std::fs::write(dir.join("b.conf"), b"same").unwrap();
+    std::fs::write(dir.join("c.conf"), b"same").unwrap();
+    std::fs::write(dir.join("other.txt"), b"diff").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("exists")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--file")
+        .arg(dir.join("a.conf"))
+        .assert()
+        .code(0)
+        .stdout(predicate::str::contains(
+            "Found 1 duplicate(s), stopped early",
+        ));
+
+    // Only two copies of a.conf exist besides itself
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("exists")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--file")
+        .arg(dir.join("a.conf"))
+        .arg("--count")
+        .arg("3")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("Found only 2 of 3 duplicate(s)"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("exists")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--pattern")
+        .arg(".txt")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::contains("No duplicates found"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("exists")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--file")
+        .arg(dir.join("missing"))
+        .assert()
+        .code(2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_decompress_groups_compressed_variants() {
+    use std::io::Write;
+
+    let dir = scratch_dir("decompress");
+    let content = noise(200_000, 11);
+    std::fs::write(dir.join("report.csv"), &content).unwrap();
+
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&content).unwrap();
+    std::fs::write(dir.join("report.csv.gz"), gzip.finish().unwrap()).unwrap();
+    std::fs::write(
+        dir.join("report.csv.zst"),
+        zstd::encode_all(content.as_slice(), 3).unwrap(),
+    )
+    .unwrap();
+    let mut bzip = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bzip.write_all(&content).unwrap();
+    std::fs::write(dir.join("report.csv.bz2"), bzip.finish().unwrap()).unwrap();
+    // Not really gzip: hashed as it is, alongside its raw twin
+    std::fs::write(dir.join("notes.txt"), b"plain").unwrap();
+    std::fs::write(dir.join("notes.gz"), b"plain").unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--decompress")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 6 files in 2 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_normalize_groups_equivalent_text() {
+    let dir = scratch_dir("normalize");
+    std::fs::write(dir.join("unix.txt"), b"first\nsecond\n").unwrap();
+    std::fs::write(dir.join("dos.txt"), b"first\r\nsecond\r\n").unwrap();
+    let mut utf16 = vec![0xFF, 0xFE];
+    for unit in "first\r\nsecond\r\n".encode_utf16() {
+        utf16.extend_from_slice(&unit.to_le_bytes());
+    }
+    std::fs::write(dir.join("utf16.txt"), utf16).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 0 files in 0 duplicate groups",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--normalize")
+        .arg("eol")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    // Decoding runs first, so the UTF-16 copy's line endings are normalized too
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--normalize")
+        .arg("encoding,eol")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 3 files in 1 duplicate groups",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--normalize")
+        .arg("whitespace")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown normalizer"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn estimate_extrapolates_waste_from_a_sample() {
+    let dir = scratch_dir("estimate");
+    let content = noise(1_048_576, 3);
+    for copy in 0..4 {
+        std::fs::write(dir.join(format!("copy{}.bin", copy)), &content).unwrap();
+    }
+    // Same size, different content: candidates that turn out unique
+    std::fs::write(dir.join("lookalike.bin"), noise(1_048_576, 4)).unwrap();
+    std::fs::write(dir.join("small.bin"), b"unique").unwrap();
+
+    // Sampling every candidate gives the exact waste
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("estimate")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--sample")
+        .arg("100")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Size-matched candidates: 5 (5.00 MB)",
+        ))
+        .stdout(predicate::str::contains(
+            "Estimated waste: 3.00 MB (95% confidence 3.00-3.00 MB)",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("estimate")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--sample")
+        .arg("2")
+        .arg("--seed")
+        .arg("7")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Sampled 2 candidate(s)"))
+        .stdout(predicate::str::contains("pass --seed 7"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_paranoia_levels_are_recorded() {
+    let dir = scratch_dir("paranoia");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("a.txt"), b"same").unwrap();
+    std::fs::write(tree.join("b.txt"), b"same").unwrap();
+    std::fs::write(tree.join("c.txt"), b"diff").unwrap();
+
+    let scan = |level: &str| {
+        let report = dir.join(format!("{}.csv", level));
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        let assert = cmd
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&tree)
+            .arg("--paranoia")
+            .arg(level)
+            .arg("--csv")
+            .arg(&report)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("Detection: {}", level)));
+        (assert, std::fs::read_to_string(report).unwrap())
+    };
+
+    // Same size only: all three look alike
+    let (assert, report) = scan("quick");
+    assert.stdout(predicate::str::contains(
+        "Found 3 files in 1 duplicate groups",
+    ));
+    assert!(report.lines().next().unwrap().ends_with(",paranoia"));
+    assert!(report.contains("size:4"));
+    assert!(report.lines().skip(1).all(|line| line.ends_with(",quick")));
+
+    let (assert, report) = scan("paranoid");
+    assert.stdout(predicate::str::contains(
+        "Found 2 files in 1 duplicate groups",
+    ));
+    assert!(report
+        .lines()
+        .skip(1)
+        .all(|line| line.ends_with(",paranoid")));
+
+    // A size match is never enough to act on
+    std::fs::write(dir.join("rules.toml"), "[[rule]]\naction = \"delete\"\n").unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--paranoia")
+        .arg("quick")
+        .arg("--rules")
+        .arg(dir.join("rules.toml"))
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("--paranoia quick only reports"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn walk_filters_chain_during_the_walk() {
+    let dir = scratch_dir("filters");
+    std::fs::write(dir.join("big.log"), vec![b'x'; 4096]).unwrap();
+    std::fs::write(dir.join("big-copy.log"), vec![b'x'; 4096]).unwrap();
+    std::fs::write(dir.join("small.log"), b"x").unwrap();
+    std::fs::write(dir.join("small-copy.log"), b"x").unwrap();
+    std::fs::write(dir.join("big.txt"), vec![b'y'; 4096]).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("search")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--pattern")
+        .arg(".log")
+        .arg("--filter")
+        .arg("size>1k")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found 2 files matching .log"))
+        .stdout(predicate::str::contains("small.log").not());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--filter")
+        .arg("size<1k")
+        .arg("--filter")
+        .arg("age<1d")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ))
+        .stdout(predicate::str::contains("Total files analyzed: 2"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("search")
+        .arg("--filter")
+        .arg("colour=blue")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid filter"));
+
+    // The same chain from the library, with a closure as one of the filters
+    let options = rdedupe::WalkOptions::default()
+        .filter(rdedupe::filter::Pattern("big".to_string()))
+        .filter(|path: &str, _metadata: &std::fs::Metadata| !path.ends_with(".txt"));
+    let mut files = rdedupe::walk_with_options(dir.to_str().unwrap(), &options).unwrap();
+    files.sort();
+    assert_eq!(
+        files,
+        vec![
+            dir.join("big-copy.log").to_string_lossy().to_string(),
+            dir.join("big.log").to_string_lossy().to_string(),
+        ]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_reports_matches_per_pattern() {
+    let dir = scratch_dir("patterns");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("notes.bak"), b"draft").unwrap();
+    std::fs::write(tree.join("notes.txt~"), b"draft").unwrap();
+    std::fs::write(tree.join("old.bak"), b"older").unwrap();
+    std::fs::write(tree.join("scratch.tmp"), b"temp").unwrap();
+    std::fs::write(tree.join("keep.txt"), b"draft").unwrap();
+    let report = dir.join("report.csv");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--pattern")
+        .arg(".bak")
+        .arg("--pattern")
+        .arg(".tmp")
+        .arg("--pattern")
+        .arg("~")
+        .arg("--csv")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("=== Matches by Pattern ==="))
+        .stdout(predicate::str::contains("'.bak': 2 file(s)"))
+        .stdout(predicate::str::contains(
+            "'.tmp': 1 file(s), 0.00 MB; 0 duplicated",
+        ))
+        .stdout(predicate::str::contains(
+            "'~': 1 file(s), 0.00 MB; 1 duplicated",
+        ))
+        // keep.txt matches none of the patterns, so it isn't scanned
+        .stdout(predicate::str::contains("Total files analyzed: 4"));
+
+    let written = std::fs::read_to_string(&report).unwrap();
+    assert!(written.lines().next().unwrap().ends_with(",pattern"));
+    assert!(written
+        .lines()
+        .any(|line| line.contains("notes.txt~") && line.ends_with(",~")));
+    assert!(written
+        .lines()
+        .any(|line| line.contains("notes.bak") && line.ends_with(",.bak")));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_savings_reports_achieved_and_remaining() {
+    let dir = scratch_dir("savings");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    let content = noise(1_048_576, 5);
+    std::fs::write(tree.join("a.bin"), &content).unwrap();
+    std::fs::hard_link(tree.join("a.bin"), tree.join("a-link.bin")).unwrap();
+    std::fs::write(tree.join("b.bin"), &content).unwrap();
+    // Another name for b.bin the scan doesn't see
+    std::fs::hard_link(tree.join("b.bin"), dir.join("b-outside.bin")).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--savings")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 file(s) on 2 inode(s)"))
+        .stdout(predicate::str::contains(
+            "Already saved by hardlinks: 1.00 MB (1 extra name(s))",
+        ))
+        .stdout(predicate::str::contains("Already saved by shared extents"))
+        .stdout(predicate::str::contains("Remaining potential: 1.00 MB"))
+        .stdout(predicate::str::contains(
+            "1 inode(s) have links outside the scan",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn action_mode_keeps_newest_and_symlinks_the_rest() {
+    let dir = scratch_dir("action-mode");
+    std::fs::create_dir_all(dir.join("old")).unwrap();
+    std::fs::create_dir_all(dir.join("new")).unwrap();
+    std::fs::write(dir.join("old/photo.jpg"), noise(4096, 7)).unwrap();
+    std::fs::write(dir.join("new/photo.jpg"), noise(4096, 7)).unwrap();
+    let old = std::fs::File::options()
+        .write(true)
+        .open(dir.join("old/photo.jpg"))
+        .unwrap();
+    old.set_modified(std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86_400))
+        .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--action")
+        .arg("symlink")
+        .arg("--keep")
+        .arg("newest")
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "0 to delete, 0 to hardlink, 1 to symlink",
+        ))
+        .stdout(predicate::str::contains("would symlink: "))
+        .stdout(predicate::str::contains(
+            "Would apply: 0 linked, 0 deleted, 1 symlinked",
+        ));
+    assert!(!std::fs::symlink_metadata(dir.join("old/photo.jpg"))
+        .unwrap()
+        .is_symlink());
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--action")
+        .arg("symlink")
+        .arg("--keep")
+        .arg("newest")
+        .arg("--allow-root")
+        .arg(&dir)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Applied: 0 linked, 0 deleted, 1 symlinked",
+        ));
+    assert!(std::fs::symlink_metadata(dir.join("old/photo.jpg"))
+        .unwrap()
+        .is_symlink());
+    assert_eq!(
+        std::fs::read(dir.join("old/photo.jpg")).unwrap(),
+        noise(4096, 7)
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_hash_algorithm_is_selectable() {
+    let dir = scratch_dir("hash-algorithm");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("a.bin"), noise(5000, 3)).unwrap();
+    std::fs::write(tree.join("b.bin"), noise(5000, 3)).unwrap();
+    std::fs::write(tree.join("c.bin"), noise(5000, 4)).unwrap();
+
+    let blake3 = blake3::hash(&noise(5000, 3)).to_hex().to_string();
+    for (hash, label, digest) in [
+        ("blake3", "BLAKE3", Some(blake3)),
+        ("sha256", "SHA-256", None),
+        ("xxhash64", "xxHash64", None),
+    ] {
+        let report = dir.join(format!("{}.csv", hash));
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--hash")
+            .arg(hash)
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&tree)
+            .arg("--csv")
+            .arg(&report)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "Found 2 files in 1 duplicate groups",
+            ))
+            .stdout(predicate::str::contains(format!(
+                "Detection: standard (size and {})",
+                label
+            )));
+        let csv = std::fs::read_to_string(report).unwrap();
+        let header = csv.lines().next().unwrap();
+        assert!(header.contains(",content_hash,hash_algorithm,"));
+        assert!(csv.lines().nth(1).unwrap().contains(&format!(",{},", hash)));
+        if let Some(digest) = digest {
+            assert!(csv.contains(&digest));
+        }
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--hash")
+        .arg("crc32")
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unknown hash 'crc32'"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn local_cache_skips_unchanged_files() {
+    let dir = scratch_dir("local-cache");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    let cache = dir.join("checksums.jsonl");
+    // Freshly written files are never cached, their timestamps could still change unseen
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    for (name, seed) in [("a.bin", 1), ("b.bin", 1), ("c.bin", 2)] {
+        std::fs::write(tree.join(name), noise(2000, seed)).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(tree.join(name))
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+    }
+
+    let scan = |extra: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.env("RDEDUPE_CACHE", &cache)
+            .args(extra)
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&tree)
+            .assert()
+            .success()
+    };
+    scan(&[]).stdout(predicate::str::contains("0 hit(s), 3 miss(es)"));
+    scan(&[])
+        .stdout(predicate::str::contains("3 hit(s), 0 miss(es)"))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+    scan(&["--no-cache"]).stdout(predicate::str::contains("Checksum cache").not());
+
+    // Keyed on the canonical path, so another spelling of the same files still hits
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("RDEDUPE_CACHE", &cache)
+        .arg("dedupe")
+        .arg("--path")
+        .arg(tree.join("..").join("tree"))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 hit(s), 0 miss(es)"));
+    // A byte-for-byte scan hashes everything afresh
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.env("RDEDUPE_CACHE", &cache)
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--verify")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Checksum cache").not());
+
+    std::fs::remove_file(tree.join("c.bin")).unwrap();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("cache")
+        .arg("prune")
+        .arg("--cache")
+        .arg(&cache)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Pruned 1 stale cache entries"))
+        .stdout(predicate::str::contains("2 kept"));
+    assert_eq!(std::fs::read_to_string(&cache).unwrap().lines().count(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn report_writes_statistics_for_large_enough_files() {
+    let dir = scratch_dir("report-command");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("a.bin"), noise(500, 1)).unwrap();
+    std::fs::write(tree.join("b.bin"), noise(500, 1)).unwrap();
+    std::fs::write(tree.join("tiny.txt"), "tiny").unwrap();
+    std::fs::write(tree.join("tiny-copy.txt"), "tiny").unwrap();
+    let output = dir.join("stats.csv");
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--threads")
+        .arg("2")
+        .arg("report")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--min-size")
+        .arg("100")
+        .arg("--output")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("=== File Analysis Summary ==="))
+        .stdout(predicate::str::contains("Statistics written to: "));
+
+    let stats = std::fs::read_to_string(&output).unwrap();
+    assert!(stats.starts_with("metric,value\n"));
+    assert!(stats.contains("total_files,2.0"));
+    assert!(stats.contains("duplicate_files,2.0"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn search_includes_globs_and_excludes_directories() {
+    let dir = scratch_dir("include-exclude");
+    std::fs::create_dir_all(dir.join("photos/2024")).unwrap();
+    std::fs::create_dir_all(dir.join("node_modules/pkg")).unwrap();
+    for name in [
+        "photos/a.jpg",
+        "photos/2024/b.jpg",
+        "photos/2024/b.jpeg",
+        "photos/notes.txt",
+        "node_modules/pkg/logo.jpg",
+        "photos/2024/draft-1.jpg",
+    ] {
+        std::fs::write(dir.join(name), name).unwrap();
+    }
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let output = cmd
+        .arg("search")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--include")
+        .arg("**/*.jpg")
+        .arg("--include")
+        .arg("re:\\.jpeg$")
+        .arg("--exclude")
+        .arg("node_modules")
+        .arg("--exclude")
+        .arg("draft-?.jpg")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Found 3 files"), "{}", stdout);
+    assert!(stdout.contains("photos/a.jpg"));
+    assert!(stdout.contains("2024/b.jpg"));
+    assert!(stdout.contains("2024/b.jpeg"));
+    assert!(!stdout.contains("logo.jpg"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("search")
+        .arg("--include")
+        .arg("re:(")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid regex"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_writes_json_and_parquet_reports() {
+    let dir = scratch_dir("report-formats");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    for name in ["a.bin", "b.bin", "c.bin"] {
+        std::fs::write(tree.join(name), noise(1000, 1)).unwrap();
+    }
+    std::fs::write(tree.join("x.txt"), "pair").unwrap();
+    std::fs::write(tree.join("y.txt"), "pair").unwrap();
+    std::fs::write(tree.join("unique.txt"), "alone").unwrap();
+
+    let json = dir.join("groups.json");
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--output")
+        .arg(&json)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("JSON report saved to: "))
+        .stdout(predicate::str::contains("(2 duplicate group(s))"));
+    let groups: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json).unwrap()).unwrap();
+    let groups = groups.as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0]["count"], 3);
+    assert_eq!(groups[0]["wasted_bytes"], 2000);
+    assert_eq!(groups[0]["files"].as_array().unwrap().len(), 3);
+    assert_eq!(groups[1]["size_bytes"], 4);
+
+    let parquet = dir.join("duplicates.out");
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("dedupe")
+        .arg("--path")
+        .arg(&tree)
+        .arg("--output")
+        .arg(&parquet)
+        .arg("--format")
+        .arg("parquet")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(5 duplicate file(s))"));
+    let df = rdedupe::report::read_scan(&parquet).unwrap();
+    assert_eq!(df.height(), 5);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn walk_handles_links_and_unreadable_paths() {
+    let dir = scratch_dir("walk-links");
+    let tree = dir.join("tree");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+    std::fs::write(tree.join("a.bin"), noise(2048, 3)).unwrap();
+    std::fs::hard_link(tree.join("a.bin"), tree.join("a-link.bin")).unwrap();
+    std::fs::write(dir.join("elsewhere/b.bin"), noise(2048, 4)).unwrap();
+    std::fs::write(tree.join("b.bin"), noise(2048, 4)).unwrap();
+    std::os::unix::fs::symlink(dir.join("elsewhere"), tree.join("out")).unwrap();
+    std::os::unix::fs::symlink(tree.join("a.bin"), tree.join("a-sym.bin")).unwrap();
+    std::os::unix::fs::symlink(dir.join("nowhere"), tree.join("dangling")).unwrap();
+
+    let dedupe = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--no-cache")
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&tree)
+            .args(args)
+            .assert()
+            .success()
+    };
+    // By default symlinks aren't followed and both names of a.bin are duplicates
+    dedupe(&[]).stdout(predicate::str::contains(
+        "Found 2 files in 1 duplicate groups",
+    ));
+    dedupe(&["--skip-hardlinks"]).stdout(predicate::str::contains(
+        "Found 0 files in 0 duplicate groups",
+    ));
+    // a-sym.bin and a-link.bin are a.bin again; b.bin has a real copy behind out/
+    dedupe(&["--follow-symlinks"])
+        .stdout(predicate::str::contains("Warning: skipped"))
+        .stdout(predicate::str::contains("dangling"))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_bounds_and_file_cap() {
+    let dir = scratch_dir("size-bounds");
+    for name in ["tiny-1", "tiny-2"] {
+        std::fs::write(dir.join(name), b"# config").unwrap();
+    }
+    for name in ["big-1", "big-2", "big-3"] {
+        std::fs::write(dir.join(name), noise(20_000, 6)).unwrap();
+    }
+
+    let dedupe = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--no-cache")
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&dir)
+            .args(args)
+            .assert()
+            .success()
+    };
+    dedupe(&["--min-size", "10KB"]).stdout(predicate::str::contains(
+        "Found 3 files in 1 duplicate groups",
+    ));
+    dedupe(&["--max-size", "1KiB"]).stdout(predicate::str::contains(
+        "Found 2 files in 1 duplicate groups",
+    ));
+    dedupe(&["--max-files", "4"])
+        .stdout(predicate::str::contains(
+            "File cap reached: stopped walking after 4 file(s)",
+        ))
+        .stdout(predicate::str::contains("Found 4 files matching"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn staged_comparison_reads_edges_first() {
+    let dir = scratch_dir("staged");
+    let base = noise(1024 * 1024, 7);
+    std::fs::write(dir.join("a.mkv"), &base).unwrap();
+    std::fs::write(dir.join("b.mkv"), &base).unwrap();
+    // Same edges, different middle: only the full hash tells it apart
+    let mut middle = base.clone();
+    middle[500_000] ^= 1;
+    std::fs::write(dir.join("middle.mkv"), &middle).unwrap();
+    // Differs near the start: dropped after its edges
+    let mut head = base.clone();
+    head[10] ^= 1;
+    std::fs::write(dir.join("head.mkv"), &head).unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--no-cache")
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--staged")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Staged comparison: 3 of 4 file(s) collide on size and their first and last 64 KB (4 compared by edges)",
+        ))
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn collect_statistics_has_a_row_per_group() {
+    let dir = scratch_dir("group-stats");
+    for name in ["big-1", "big-2", "big-3", "big-4"] {
+        std::fs::write(dir.join(name), noise(8000, 8)).unwrap();
+    }
+    for name in ["small-1", "small-2"] {
+        std::fs::write(dir.join(name), noise(100, 9)).unwrap();
+    }
+    std::fs::write(dir.join("unique"), noise(100, 10)).unwrap();
+
+    let df = rdedupe::analyze(
+        &[dir.display().to_string()],
+        &[String::new()],
+        &rdedupe::WalkOptions::default(),
+        false,
+    )
+    .unwrap();
+    let groups = rdedupe::collect_statistics(&df).unwrap();
+    assert_eq!(groups.height(), 2);
+    let counts: Vec<Option<u64>> = groups
+        .column("file_count")
+        .unwrap()
+        .u64()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(counts, [Some(4), Some(2)]);
+    let wasted: Vec<Option<u64>> = groups
+        .column("wasted_bytes")
+        .unwrap()
+        .u64()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(wasted, [Some(24_000), Some(100)]);
+    let examples = groups.column("example_paths").unwrap();
+    assert_eq!(
+        examples
+            .utf8()
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .matches("big-")
+            .count(),
+        3
+    );
+
+    let summary = rdedupe::collect_summary(&df, &groups).unwrap();
+    let values: Vec<Option<u64>> = summary
+        .column("value")
+        .unwrap()
+        .u64()
+        .unwrap()
+        .into_iter()
+        .collect();
+    assert_eq!(values, [Some(7), Some(6), Some(2), Some(24_100)]);
+
+    let output = dir.join("groups.csv");
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("report")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--groups")
+        .arg(&output)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("reclaimable_bytes"))
+        .stdout(predicate::str::contains("Group statistics written to"));
+    let csv = std::fs::read_to_string(&output).unwrap();
+    assert!(csv.starts_with("group_hash,file_count,size_bytes,wasted_bytes,example_paths\n"));
+    assert_eq!(csv.lines().count(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interactive_review_queues_actions() {
+    use ratatui::crossterm::event::KeyCode;
+    use rdedupe::rules::Disposition;
+
+    let dir = scratch_dir("interactive");
+    for name in ["a1", "a2", "a3"] {
+        std::fs::write(dir.join(name), noise(4000, 11)).unwrap();
+    }
+    for name in ["b1", "b2"] {
+        std::fs::write(dir.join(name), noise(1000, 12)).unwrap();
+    }
+    let report =
+        rdedupe::scan::scan(&rdedupe::scan::ScanOptions::new(dir.to_str().unwrap())).unwrap();
+
+    // Groups come largest waste first, everything is left alone until an action is picked
+    let mut review = rdedupe::interactive::GroupReview::new(report);
+    assert!(review.queue().is_empty());
+    assert_eq!(review.handle(KeyCode::Char('d')), None);
+    assert_eq!(review.queue().len(), 2);
+    // Keep a2 as well, then hardlink the second group
+    assert_eq!(review.handle(KeyCode::Tab), None);
+    assert_eq!(review.handle(KeyCode::Down), None);
+    assert_eq!(review.selected(), Some((0, 1)));
+    assert_eq!(review.handle(KeyCode::Char(' ')), None);
+    assert_eq!(review.handle(KeyCode::Tab), None);
+    assert_eq!(review.handle(KeyCode::Down), None);
+    assert_eq!(review.selected(), Some((1, 0)));
+    assert_eq!(review.handle(KeyCode::Char('h')), None);
+
+    let queue = review.queue();
+    let name = |path: &str| path.rsplit('/').next().unwrap().to_string();
+    let queued: Vec<(Disposition, String, String)> = queue
+        .iter()
+        .map(|a| (a.action, name(&a.keep), name(&a.path)))
+        .collect();
+    assert_eq!(
+        queued,
+        [
+            (Disposition::Delete, "a1".to_string(), "a3".to_string()),
+            (Disposition::Hardlink, "b1".to_string(), "b2".to_string())
+        ]
+    );
+
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(120, 20)).unwrap();
+    terminal.draw(|frame| review.render(frame)).unwrap();
+    let screen: String = terminal
+        .backend()
+        .buffer()
+        .content()
+        .iter()
+        .map(|cell| cell.symbol())
+        .collect();
+    assert!(screen.contains("2 duplicate group(s)"));
+    assert!(screen.contains("1 to delete, 1 to hardlink, 0 to symlink"));
+
+    assert_eq!(review.handle(KeyCode::Char('q')), None);
+    assert_eq!(review.handle(KeyCode::Char('n')), None);
+    assert_eq!(review.handle(KeyCode::Char('a')), None);
+    assert_eq!(
+        review.handle(KeyCode::Char('y')),
+        Some(rdedupe::tui::Choice::Apply)
+    );
+    rdedupe::rules::apply(&queue, true).unwrap();
+    assert!(dir.join("a3").exists() && dir.join("b2").exists());
+
+    // Without a terminal there is nothing to review in
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--no-cache")
+        .arg("interactive")
+        .arg(&dir)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "interactive mode needs an interactive terminal",
+        ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn quiet_dedupe_still_reports() {
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--quiet")
+        .arg("dedupe")
+        .arg("--path")
+        .arg("tests/inputs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+}
+
+#[test]
+fn roots_are_scanned_together() {
+    let dir = scratch_dir("multi-root");
+    for root in ["backup1/nested", "backup2", "photos"] {
+        std::fs::create_dir_all(dir.join(root)).unwrap();
+    }
+    std::fs::write(dir.join("backup1/a.jpg"), noise(2000, 14)).unwrap();
+    std::fs::write(dir.join("backup2/a.jpg"), noise(2000, 14)).unwrap();
+    std::fs::write(dir.join("photos/a.jpg"), noise(2000, 14)).unwrap();
+    std::fs::write(dir.join("backup1/nested/b"), noise(500, 15)).unwrap();
+    let root = |name: &str| dir.join(name).display().to_string();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--no-cache")
+        .arg("dedupe")
+        .arg(root("backup1"))
+        .arg(root("backup2"))
+        .arg(root("photos"))
+        .arg(root("backup1/nested"))
+        .arg("--action")
+        .arg("delete")
+        .arg("--prefer-root")
+        .arg(root("photos"))
+        .arg("--allow-root")
+        .arg(&dir)
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Warning: {} repeats or is inside another root, walking it once",
+            root("backup1/nested")
+        )))
+        .stdout(predicate::str::contains(
+            "Found 3 files in 1 duplicate groups",
+        ))
+        .stdout(predicate::str::contains(format!(
+            "{}: 1 file(s), 0.00 MB; 1 duplicated (0.00 MB), 1 with a copy under another root",
+            root("backup2")
+        )))
+        .stdout(predicate::str::contains(format!(
+            "keep {}, --prefer-root",
+            dir.join("photos/a.jpg").display()
+        )));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dirs_reports_highest_duplicated_trees() {
+    let dir = scratch_dir("duplicate-trees");
+    for copy in ["extract-1", "extract-2"] {
+        let tree = dir.join(copy).join("release");
+        std::fs::create_dir_all(tree.join("bin")).unwrap();
+        std::fs::write(tree.join("README"), noise(300, 16)).unwrap();
+        std::fs::write(tree.join("bin/tool"), noise(4000, 17)).unwrap();
+        std::fs::write(tree.join("bin/helper"), noise(900, 18)).unwrap();
+    }
+    // Same content, one file renamed: not the same tree
+    std::fs::create_dir_all(dir.join("other")).unwrap();
+    std::fs::write(dir.join("other/README"), noise(300, 16)).unwrap();
+    std::fs::write(dir.join("other/tool.old"), noise(4000, 17)).unwrap();
+    let release = |copy: &str| dir.join(copy).join("release").display().to_string();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--no-cache")
+        .arg("dedupe")
+        .arg(&dir)
+        .arg("--dirs")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "2 copies of 3 file(s), 0.00 MB each:\n  {}\n  {}\n",
+            dir.join("extract-1").display(),
+            dir.join("extract-2").display()
+        )))
+        .stdout(predicate::str::contains(format!("  {}\n", release("extract-1"))).not())
+        .stdout(predicate::str::contains(format!("  {}/bin\n", release("extract-1"))).not())
+        .stdout(predicate::str::contains(format!("  {}\n", dir.join("other").display())).not())
+        .stdout(predicate::str::contains(
+            "1 duplicated tree(s) hold 3 redundant file(s), 0.00 MB reclaimable",
+        ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshots_diff_file_by_file() {
+    let dir = scratch_dir("snapshot-diff");
+    let tree = dir.join("archive");
+    std::fs::create_dir_all(&tree).unwrap();
+    std::fs::write(tree.join("kept"), noise(700, 19)).unwrap();
+    std::fs::write(tree.join("gone"), noise(700, 20)).unwrap();
+    std::fs::write(tree.join("edited"), noise(700, 21)).unwrap();
+    let (old, new) = (dir.join("old.snap"), dir.join("new.snap"));
+    let snapshot = |path: &std::path::Path| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--no-cache")
+            .arg("dedupe")
+            .arg(&tree)
+            .arg("--snapshot")
+            .arg(path)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Snapshot of"));
+    };
+    snapshot(&old);
+
+    std::fs::remove_file(tree.join("gone")).unwrap();
+    std::fs::write(tree.join("edited"), noise(800, 22)).unwrap();
+    std::fs::write(tree.join("copy"), noise(700, 19)).unwrap();
+    snapshot(&new);
+
+    let before = rdedupe::snapshot::read_snapshot(&old).unwrap();
+    assert_eq!(before.files.len(), 3);
+    assert_eq!(before.hash, "md5");
+    assert!(before.files.iter().all(|entry| entry.modified.is_some()));
+
+    let path = |name: &str| tree.join(name).display().to_string();
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("diff")
+        .arg(&old)
+        .arg(&new)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "added       {} (700 bytes)",
+            path("copy")
+        )))
+        .stdout(predicate::str::contains(format!(
+            "removed     {} (700 bytes)",
+            path("gone")
+        )))
+        .stdout(predicate::str::contains(format!(
+            "changed     {} (700 -> 800 bytes",
+            path("edited")
+        )))
+        .stdout(predicate::str::contains(format!(
+            "duplicated  {}\n",
+            path("kept")
+        )))
+        .stdout(predicate::str::contains(
+            "1 added, 1 removed, 1 changed, 2 newly duplicated (3 -> 3 file(s))",
+        ));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("diff")
+        .arg(&old)
+        .arg("Cargo.toml")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Cargo.toml: not an rdedupe snapshot",
+        ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn walk_honors_ignore_files_and_hidden() {
+    let dir = scratch_dir("ignore-files");
+    for sub in [
+        "node_modules/pkg",
+        "build",
+        "src/build",
+        "src/vendor",
+        ".cache",
+    ] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    std::fs::write(
+        dir.join(".gitignore"),
+        "# deps\nnode_modules/\n*.log\n!keep.log\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join(".rdedupeignore"), "/build\n").unwrap();
+    std::fs::write(dir.join("src/.gitignore"), "vendor\n").unwrap();
+    for file in [
+        "main.rs",
+        "keep.log",
+        "debug.log",
+        "node_modules/pkg/index.js",
+        "build/out",
+        "src/build/generated.rs",
+        "src/vendor/lib.rs",
+        ".cache/blob",
+    ] {
+        std::fs::write(dir.join(file), "same").unwrap();
+    }
+
+    let found = |flags: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        let output = cmd
+            .arg("search")
+            .arg("--path")
+            .arg(&dir)
+            .args(flags)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let mut files: Vec<String> = stdout
+            .lines()
+            .filter_map(|line| line.strip_prefix(dir.to_str().unwrap()))
+            .map(|file| file.trim_start_matches('/').to_string())
+            .collect();
+        files.sort();
+        files
+    };
+    // Root-anchored /build only ignores the top one; vendor is ignored by the nested file
+    assert_eq!(
+        found(&[]),
+        ["keep.log", "main.rs", "src/build/generated.rs"]
+    );
+    assert_eq!(found(&["--hidden"]).len(), 7);
+    assert!(found(&["--hidden"]).contains(&".cache/blob".to_string()));
+    assert_eq!(found(&["--no-ignore"]).len(), 7);
+    assert_eq!(found(&["--no-ignore", "--hidden"]).len(), 11);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn ndjson_streams_events_and_exit_codes() {
+    let dir = scratch_dir("ndjson-events");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.bin"), noise(4096, 1)).unwrap();
+    std::fs::write(dir.join("b.bin"), noise(4096, 2)).unwrap();
+
+    let events_with = |path: &std::path::Path, extra: &[&str]| {
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        let output = cmd
+            .args(["--no-cache", "dedupe", "--format", "ndjson", "--path"])
+            .arg(path)
+            .args(extra)
+            .output()
+            .unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (output.status.code(), lines)
+    };
+    let events = |path: &std::path::Path| events_with(path, &[]);
+    let kinds = |lines: &[serde_json::Value], kind: &str| {
+        lines.iter().filter(|line| line["event"] == kind).count()
+    };
+
+    // Two same-size files are hashed but differ
+    let (code, lines) = events(&dir);
+    assert_eq!(code, Some(0));
+    assert_eq!(kinds(&lines, "file"), 2);
+    assert_eq!(kinds(&lines, "group"), 0);
+    assert_eq!(lines.last().unwrap()["event"], "summary");
+
+    std::fs::write(dir.join("c.bin"), noise(4096, 1)).unwrap();
+    let (code, lines) = events(&dir);
+    assert_eq!(code, Some(1));
+    let group = lines.iter().find(|line| line["event"] == "group").unwrap();
+    assert_eq!(group["files"].as_array().unwrap().len(), 2);
+    assert_eq!(group["wasted_bytes"], 4096);
+    assert_eq!(lines.last().unwrap()["groups"], 1);
+
+    // The walk stops at --max-files here too
+    let (_, lines) = events_with(&dir, &["--max-files", "2"]);
+    assert_eq!(lines.last().unwrap()["files"], 2);
+
+    // Pinning and git checks have their say off stdout, every line still parses
+    let (code, lines) = events_with(&dir, &["--pin-threads", "--skip-reproducible"]);
+    assert_eq!(code, Some(1));
+    assert_eq!(lines.last().unwrap()["groups"], 1);
+
+    let (code, lines) = events(&dir.join("missing"));
+    assert_eq!(code, Some(2));
+    assert_eq!(lines.last().unwrap()["event"], "error");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// A grayscale PNG of pixel(x, y), the way an encoder of another quality would write it
+#[cfg(feature = "perceptual")]
+fn write_png(path: &std::path::Path, size: u32, pixel: impl Fn(u32, u32) -> u8) {
+    use std::io::Write;
+    let mut raw = Vec::new();
+    for y in 0..size {
+        raw.push(0);
+        raw.extend((0..size).map(|x| pixel(x, y)));
+    }
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&raw).unwrap();
+    let mut header = Vec::new();
+    header.extend(size.to_be_bytes());
+    header.extend(size.to_be_bytes());
+    header.extend([8, 0, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (kind, body) in [
+        (&b"IHDR"[..], header),
+        (b"IDAT", encoder.finish().unwrap()),
+        (b"IEND", Vec::new()),
+    ] {
+        png.extend((body.len() as u32).to_be_bytes());
+        png.extend(kind);
+        png.extend(&body);
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(&body);
+        png.extend(crc.sum().to_be_bytes());
+    }
+    std::fs::write(path, png).unwrap();
+}
+
+#[cfg(feature = "perceptual")]
+#[test]
+fn perceptual_groups_near_duplicate_images() {
+    let dir = scratch_dir("perceptual");
+    std::fs::create_dir_all(&dir).unwrap();
+    let scene = |x: u32, y: u32| ((x * 3 + y * 2) % 256) as u8;
+    write_png(&dir.join("original.png"), 64, scene);
+    // Brighter, with a little noise: other bytes, the same picture
+    write_png(&dir.join("reencoded.png"), 64, |x, y| {
+        scene(x, y).saturating_add(6 + ((x * 7 + y * 13) % 3) as u8)
+    });
+    write_png(&dir.join("other.png"), 64, |x, y| {
+        if (x / 8 + y / 16) % 2 == 0 {
+            40
+        } else {
+            220
+        }
+    });
+    std::fs::copy(dir.join("other.png"), dir.join("other-copy.png")).unwrap();
+    // Corrupt headers are skipped, not fatal: bit depth 3, 60000x60000 pixels, a bad table selector
+    let mut png = std::fs::read(dir.join("other.png")).unwrap();
+    png[24] = 3;
+    std::fs::write(dir.join("depth.png"), &png).unwrap();
+    png[24] = 8;
+    png[16..24].copy_from_slice(&[0, 0, 0xEA, 0x60, 0, 0, 0xEA, 0x60]);
+    std::fs::write(dir.join("huge.png"), &png).unwrap();
+    std::fs::write(
+        dir.join("selector.jpg"),
+        [
+            &[
+                0xFF, 0xD8, 0xFF, 0xC0, 0, 11, 8, 0, 16, 0, 16, 1, 1, 0x11, 0,
+            ][..],
+            &[
+                0xFF, 0xDA, 0, 8, 1, 1, 0xF0, 0, 63, 0, 0x12, 0x34, 0xFF, 0xD9,
+            ],
+        ]
+        .concat(),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    let output = cmd
+        .args(["--no-cache", "dedupe", "--perceptual", "--path"])
+        .arg(&dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let near = stdout.split("=== Near-Duplicate Images").nth(1).unwrap();
+    // The exact copies stay in the regular report only
+    assert!(near.contains("2 similar image(s):"));
+    assert!(near.contains("original.png (distance 0)"));
+    assert!(near.contains("reencoded.png"));
+    assert!(!near.contains("other"));
+    assert!(near.contains("1 near-duplicate group(s) among 7 image(s); 3 couldn't be decoded"));
+
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.args([
+        "--no-cache",
+        "dedupe",
+        "--perceptual=phash",
+        "--perceptual-distance",
+        "0",
+        "--path",
+    ])
+    .arg(&dir)
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("(phash, up to 0 bit(s) apart)"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_decompress_and_normalize_use_selected_hash() {
+    use std::io::Write;
+
+    let dir = scratch_dir("decompress-hash");
+    let content = noise(20_000, 12);
+    std::fs::write(dir.join("data.bin"), &content).unwrap();
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&content).unwrap();
+    std::fs::write(dir.join("data.bin.gz"), gzip.finish().unwrap()).unwrap();
+    let blake3 = blake3::hash(&content).to_hex().to_string();
+
+    for normalize in [None, Some("eol")] {
+        let report = dir.join("report.csv");
+        let mut cmd = Command::cargo_bin(PRG).unwrap();
+        cmd.arg("--hash")
+            .arg("blake3")
+            .arg("dedupe")
+            .arg("--path")
+            .arg(&dir)
+            .arg("--decompress")
+            .arg("--no-cache")
+            .arg("--csv")
+            .arg(&report);
+        if let Some(normalize) = normalize {
+            cmd.arg("--normalize").arg(normalize);
+        }
+        cmd.assert().success().stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+        let csv = std::fs::read_to_string(&report).unwrap();
+        assert!(csv.contains(&blake3), "{}", csv);
+        std::fs::remove_file(&report).unwrap();
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dedupe_normalize_streams_content_that_inflates_past_large_file() {
+    use std::io::Write;
+
+    let dir = scratch_dir("normalize-bomb");
+    // A few hundred KB on disk, 70 MB once decompressed
+    let content = vec![0u8; 70 * 1_048_576];
+    for (name, level) in [("a.bin.gz", 1), ("b.bin.gz", 9)] {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(level));
+        gzip.write_all(&content).unwrap();
+        std::fs::write(dir.join(name), gzip.finish().unwrap()).unwrap();
+    }
+    let blake3 = blake3::hash(&content).to_hex().to_string();
+
+    let report = dir.join("report.csv");
+    let mut cmd = Command::cargo_bin(PRG).unwrap();
+    cmd.arg("--hash")
+        .arg("blake3")
+        .arg("dedupe")
+        .arg("--path")
+        .arg(&dir)
+        .arg("--decompress")
+        .arg("--normalize")
+        .arg("eol")
+        .arg("--no-cache")
+        .arg("--csv")
+        .arg(&report)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Found 2 files in 1 duplicate groups",
+        ));
+    assert!(std::fs::read_to_string(&report).unwrap().contains(&blake3));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
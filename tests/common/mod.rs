@@ -0,0 +1,36 @@
+//fixtures shared by the test binaries
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+// Fresh scratch directory under the system temp dir
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rdedupe-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A scratch directory holding files, given as paths relative to it and their contents
+pub fn tree(name: &str, files: &[(&str, Vec<u8>)]) -> PathBuf {
+    let dir = scratch_dir(name);
+    for (path, contents) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+// Deterministic pseudo-random bytes for content that doesn't compress into tiny chunks
+pub fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        })
+        .collect()
+}
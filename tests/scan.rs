@@ -0,0 +1,497 @@
+//library API: scans, in-memory trees and the sinks, driven without the command line
+mod common;
+use common::{noise, scratch_dir, tree};
+
+#[test]
+fn scan_returns_typed_report() {
+    let dir = tree(
+        "scan-api",
+        &[
+            ("a.bin", noise(4096, 1)),
+            ("b.bin", noise(4096, 1)),
+            ("c.bin", noise(4096, 2)),
+            ("small.txt", b"unique".to_vec()),
+        ],
+    );
+
+    let options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    let report = rdedupe::scan::scan(&options).unwrap();
+
+    assert_eq!(report.stats.files, 4);
+    assert_eq!(report.stats.hashed, 3);
+    assert_eq!(report.stats.skipped, 0);
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.wasted_bytes, 4096);
+    let group = &report.groups[0];
+    assert_eq!(group.size_bytes, 4096);
+    let names: Vec<&str> = group
+        .files
+        .iter()
+        .map(|f| f.path.rsplit('/').next().unwrap())
+        .collect();
+    assert_eq!(names, ["a.bin", "b.bin"]);
+    assert!(group.files.iter().all(|f| f.modified.is_some()));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn scan_reports_dangling_links() {
+    let dir = tree(
+        "scan-links",
+        &[
+            ("tree/a.bin", noise(2048, 3)),
+            ("tree/b.bin", noise(2048, 4)),
+            ("elsewhere/b.bin", noise(2048, 4)),
+        ],
+    );
+    let tree = dir.join("tree");
+    std::os::unix::fs::symlink(dir.join("elsewhere"), tree.join("out")).unwrap();
+    std::os::unix::fs::symlink(dir.join("nowhere"), tree.join("dangling")).unwrap();
+
+    let mut options = rdedupe::scan::ScanOptions::new(tree.to_str().unwrap());
+    options.walk.follow_links = true;
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].path.ends_with("dangling"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_honours_size_bounds_and_file_cap() {
+    let dir = tree(
+        "scan-size-bounds",
+        &[
+            ("tiny-1", b"# config".to_vec()),
+            ("tiny-2", b"# config".to_vec()),
+            ("big-1", noise(20_000, 6)),
+            ("big-2", noise(20_000, 6)),
+            ("big-3", noise(20_000, 6)),
+        ],
+    );
+
+    let mut options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    options.min_size = Some(rdedupe::generate::parse_size("10kb").unwrap());
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.stats.files, 3);
+    assert_eq!(report.wasted_bytes, 40_000);
+
+    options.min_size = None;
+    options.max_files = Some(2);
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.stats.files, 2);
+    assert!(report.stats.capped);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_compares_edges_before_full_hashes() {
+    let base = noise(1024 * 1024, 7);
+    // Same edges, different middle: only the full hash tells it apart
+    let mut middle = base.clone();
+    middle[500_000] ^= 1;
+    // Differs near the start: dropped after its edges
+    let mut head = base.clone();
+    head[10] ^= 1;
+    let dir = tree(
+        "scan-staged",
+        &[
+            ("a.mkv", base.clone()),
+            ("b.mkv", base),
+            ("middle.mkv", middle),
+            ("head.mkv", head),
+        ],
+    );
+
+    let report =
+        rdedupe::scan::scan(&rdedupe::scan::ScanOptions::new(dir.to_str().unwrap())).unwrap();
+    assert_eq!(report.stats.same_size, 4);
+    assert_eq!(report.stats.edge_hashed, 4);
+    assert_eq!(report.stats.hashed, 3);
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.groups[0].files.len(), 2);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_records_the_root_of_each_copy() {
+    let dir = tree(
+        "scan-roots",
+        &[
+            ("backup2/a.jpg", noise(2000, 14)),
+            ("photos/a.jpg", noise(2000, 14)),
+        ],
+    );
+    let root = |name: &str| dir.join(name).display().to_string();
+
+    let mut options = rdedupe::scan::ScanOptions::new(&root("photos"));
+    options.roots.push(root("backup2"));
+    let report = rdedupe::scan::scan(&options).unwrap();
+    let group = &report.groups[0];
+    assert!(group.spans_roots());
+    let roots: Vec<&str> = group.files.iter().map(|f| f.root.as_str()).collect();
+    assert_eq!(roots, [root("backup2"), root("photos")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn size_pre_pass_only_keeps_shared_sizes() {
+    let dir = tree(
+        "size-pre-pass",
+        &[
+            ("a.dat", noise(300, 1)),
+            ("b.dat", noise(300, 2)),
+            ("c.dat", noise(301, 3)),
+        ],
+    );
+    let files: Vec<String> = ["a.dat", "b.dat", "c.dat", "missing.dat"]
+        .iter()
+        .map(|name| dir.join(name).display().to_string())
+        .collect();
+
+    let groups = rdedupe::pipeline::group_by_size(files.clone());
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[&301], vec![files[2].clone()]);
+    assert_eq!(
+        rdedupe::pipeline::same_size(groups),
+        vec![files[0].clone(), files[1].clone()]
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn checksum_groups_stream_in_path_order() {
+    let mut contents: Vec<(String, Vec<u8>)> = (0..300)
+        .map(|index| (format!("{:03}", index), noise(256, index % 3)))
+        .collect();
+    contents.push(("unique".to_string(), noise(256, 99)));
+    let entries: Vec<(&str, Vec<u8>)> = contents
+        .iter()
+        .map(|(name, bytes)| (name.as_str(), bytes.clone()))
+        .collect();
+    let dir = tree("checksum-stream", &entries);
+    let files: Vec<String> = contents
+        .iter()
+        .map(|(name, _)| dir.join(name).display().to_string())
+        .collect();
+
+    let checksums = rdedupe::checksum(files.clone()).unwrap();
+    assert_eq!(checksums.len(), 301);
+    assert_eq!(checksums.unique(), 4);
+    let mut groups = checksums.duplicate_groups();
+    groups.sort();
+    for (seed, group) in groups.iter().enumerate() {
+        let expected: Vec<String> = files[..300].iter().skip(seed).step_by(3).cloned().collect();
+        assert_eq!(*group, expected);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn vfs_dedupes_in_memory_tree() {
+    use rdedupe::vfs::{MemoryFs, RealFs, Vfs};
+
+    let mut memory = MemoryFs::new();
+    memory.insert("/data/a/report.txt", "same");
+    memory.insert("/data/b/deep/report.txt", "same");
+    memory.insert("/data/b/other.txt", "different");
+    memory.insert("/data/.git/objects/x", "same");
+
+    let files = rdedupe::vfs::walk(&memory, "/data", "").unwrap();
+    assert_eq!(
+        files,
+        [
+            "/data/a/report.txt",
+            "/data/b/deep/report.txt",
+            "/data/b/other.txt"
+        ]
+    );
+    assert!(memory.stat("/data/b").unwrap().is_dir);
+    assert!(memory.list("/missing").is_err());
+
+    let groups = rdedupe::vfs::checksum(&memory, &files).duplicate_groups();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].len(), 2);
+
+    let df = rdedupe::vfs::scan(&memory, "/data", ".txt").unwrap();
+    assert_eq!(df.height(), 3);
+    let duplicates = df
+        .column("is_duplicate")
+        .unwrap()
+        .bool()
+        .unwrap()
+        .into_iter()
+        .filter(|d| *d == Some(true))
+        .count();
+    assert_eq!(duplicates, 2);
+
+    // The disk implementation finds what the regular walk finds
+    let mut on_disk = rdedupe::walk(&["tests/inputs"]).unwrap();
+    on_disk.sort();
+    assert_eq!(
+        rdedupe::vfs::walk(&RealFs, "tests/inputs", "").unwrap(),
+        on_disk
+    );
+}
+
+#[test]
+fn scan_options_carry_their_own_hash_settings() {
+    struct Memory(std::sync::Mutex<Vec<String>>);
+    impl rdedupe::cache::HashCache for Memory {
+        fn get(&self, _key: &str) -> Option<String> {
+            None
+        }
+        fn put(&self, key: &str, _hash: &str) {
+            self.0.lock().unwrap().push(key.to_string());
+        }
+        fn describe(&self) -> String {
+            "memory".to_string()
+        }
+    }
+
+    let dir = tree(
+        "scan-settings",
+        &[
+            ("a.bin", noise(3000, 8)),
+            ("b.bin", noise(3000, 8)),
+            ("c.bin", noise(3000, 9)),
+        ],
+    );
+    // Old enough for the cache to take them
+    let past = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    for name in ["a.bin", "b.bin", "c.bin"] {
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join(name))
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+    }
+
+    // Two scans of one process, with nothing set globally
+    let cache = std::sync::Arc::new(Memory(Default::default()));
+    let mut options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    options.hasher = rdedupe::hasher::Hasher::Blake3;
+    options.cache = Some(cache.clone());
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(
+        report.groups[0].hash,
+        blake3::hash(&noise(3000, 8)).to_hex().to_string()
+    );
+    let keys = cache.0.lock().unwrap().clone();
+    assert_eq!(keys.len(), 3);
+    assert!(keys.iter().all(|key| key.starts_with("rdedupe:blake3:")));
+
+    let mut options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    options.paranoia = rdedupe::paranoia::Paranoia::Quick;
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.groups[0].hash, "size:3000");
+    assert_eq!(report.groups[0].files.len(), 3);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_groups_decompressed_and_normalized_content_across_sizes() {
+    use std::io::Write;
+
+    let csv = b"id,name\r\n1,alpha\r\n2,beta\r\n".repeat(40);
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(&csv).unwrap();
+    let dir = tree(
+        "scan-transforms",
+        &[
+            ("r.csv", csv.clone()),
+            ("r.csv.gz", gzip.finish().unwrap()),
+            ("notes-crlf.txt", b"one\r\ntwo\r\n".to_vec()),
+            ("notes-lf.txt", b"one\ntwo\n".to_vec()),
+        ],
+    );
+    let hashed_alone = rdedupe::scan::scan(&rdedupe::scan::ScanOptions::new(dir.to_str().unwrap()));
+    assert!(hashed_alone.unwrap().groups.is_empty());
+
+    let mut options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    options.decompress = true;
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    assert_eq!(report.groups[0].files.len(), 2);
+    assert_eq!(report.stats.same_size, 4);
+
+    options.normalizers = vec![rdedupe::normalize::builtin("eol").unwrap().into()];
+    let report = rdedupe::scan::scan(&options).unwrap();
+    assert_eq!(report.groups.len(), 2);
+
+    // Workers hash raw bytes, so they can't stand in for the local hash here
+    options.workers = Some(rdedupe::remote::Workers {
+        addresses: vec!["127.0.0.1:1".to_string()],
+        token: "secret".to_string(),
+    });
+    assert!(rdedupe::scan::scan(&options)
+        .unwrap_err()
+        .to_string()
+        .contains("can't be combined with decompress or normalizers"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn scan_options_label_their_dataframe() {
+    let dir = tree(
+        "scan-dataframe",
+        &[("a.bin", noise(100, 1)), ("b.bin", noise(100, 1))],
+    );
+    let files = [dir.join("a.bin"), dir.join("b.bin")];
+    let infos: Vec<rdedupe::FileInfo> = files
+        .iter()
+        .map(|path| {
+            let path = path.to_str().unwrap();
+            rdedupe::FileInfo::with_hash(path, "h".to_string()).unwrap()
+        })
+        .collect();
+
+    let mut options = rdedupe::scan::ScanOptions::new(dir.to_str().unwrap());
+    options.hasher = rdedupe::hasher::Hasher::Blake3;
+    options.paranoia = rdedupe::paranoia::Paranoia::Paranoid;
+    let df = options.dataframe(infos).unwrap();
+    let column = |name: &str| {
+        df.column(name)
+            .unwrap()
+            .utf8()
+            .unwrap()
+            .get(0)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(column("hash_algorithm"), "blake3");
+    assert_eq!(column("paranoia"), "paranoid");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn progress_events_reach_library_sink() {
+    use rdedupe::progress::{ProgressSink, Stage};
+    use std::sync::{Arc, Mutex};
+
+    let dir = tree(
+        "progress-sink",
+        &[
+            ("a", noise(3000, 13)),
+            ("b", noise(3000, 13)),
+            ("c", noise(3000, 13)),
+        ],
+    );
+
+    // Other tests share the process, so only events under dir count
+    struct Recorder(String, Arc<Mutex<Vec<String>>>);
+    impl ProgressSink for Recorder {
+        fn on_phase_change(&self, stage: Stage, _total: Option<u64>) {
+            self.1
+                .lock()
+                .unwrap()
+                .push(format!("phase {}", stage.label()));
+        }
+        fn on_file_start(&self, path: &str) {
+            if path.starts_with(&self.0) {
+                self.1.lock().unwrap().push("start".to_string());
+            }
+        }
+        fn on_file_hashed(&self, path: &str, hash: &str) {
+            if path.starts_with(&self.0) {
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(format!("hashed {}", hash.len()));
+            }
+        }
+    }
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let root = dir.to_str().unwrap().to_string();
+    rdedupe::progress::set_sink(Box::new(Recorder(root.clone(), events.clone())));
+
+    let report = rdedupe::scan::scan(&rdedupe::scan::ScanOptions::new(&root)).unwrap();
+    assert_eq!(report.groups.len(), 1);
+    let events = events.lock().unwrap().clone();
+    assert!(events.contains(&"phase walking".to_string()));
+    assert!(events.contains(&"phase hashing".to_string()));
+    assert_eq!(events.iter().filter(|e| *e == "start").count(), 3);
+    assert_eq!(events.iter().filter(|e| *e == "hashed 32").count(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diagnostics_reach_library_sink() {
+    use rdedupe::diagnostics::Diagnostic;
+    use rdedupe::vfs::{MemoryFs, Vfs, VfsMetadata};
+    use std::io::Read;
+
+    // A tree whose one file can be listed but not read
+    struct Unreadable(MemoryFs);
+    impl Vfs for Unreadable {
+        fn list(&self, dir: &str) -> std::io::Result<Vec<String>> {
+            self.0.list(dir)
+        }
+        fn stat(&self, path: &str) -> std::io::Result<VfsMetadata> {
+            self.0.stat(path)
+        }
+        fn open_read(&self, path: &str) -> std::io::Result<Box<dyn Read + Send + '_>> {
+            if path.ends_with("locked") {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::PermissionDenied,
+                    "permission denied",
+                ));
+            }
+            self.0.open_read(path)
+        }
+    }
+
+    struct Memory(std::sync::Mutex<std::collections::HashMap<String, String>>);
+    impl rdedupe::cache::HashCache for Memory {
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+        fn put(&self, key: &str, hash: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), hash.to_string());
+        }
+        fn describe(&self) -> String {
+            "memory".to_string()
+        }
+    }
+
+    let (sink, diagnostics) = rdedupe::diagnostics::channel();
+    rdedupe::diagnostics::set_sink(Box::new(sink));
+
+    let mut memory = MemoryFs::new();
+    memory.insert("/t/open", "readable");
+    memory.insert("/t/locked", "secret");
+    let df = rdedupe::vfs::scan(&Unreadable(memory), "/t", "").unwrap();
+    assert_eq!(df.height(), 1);
+
+    let dir = scratch_dir("diagnostics");
+    let file = dir.join("cached.txt").display().to_string();
+    std::fs::write(&file, "cache me").unwrap();
+    rdedupe::cache::set_backend(Box::new(Memory(Default::default())));
+    rdedupe::content_hash(&file).unwrap();
+    rdedupe::content_hash(&file).unwrap();
+
+    let received: Vec<Diagnostic> = diagnostics.try_iter().collect();
+    assert!(received.contains(&Diagnostic::SkippedFile {
+        path: "/t/locked".to_string(),
+        reason: "permission denied".to_string(),
+    }));
+    let misses: Vec<&Diagnostic> = received
+        .iter()
+        .filter(|d| matches!(d, Diagnostic::CacheMiss { path } if *path == file))
+        .collect();
+    assert_eq!(misses.len(), 1);
+    assert_eq!(misses[0].kind(), "cache-miss");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}